@@ -66,6 +66,22 @@ impl LightningDecode for AssetList {
     }
 }
 
+/// Amount of an RGB asset, used for asset allocations in channel funding and
+/// for asset transfers with HTLCs.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[display("{amount} of {asset_id}")]
+pub struct AssetAmount {
+    pub asset_id: AssetId,
+    pub amount: u64,
+}
+
 /// Once authentication is complete, the first message reveals the features
 /// supported or required by this node, even if this is a reconnection.
 ///
@@ -13,12 +13,46 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use bitcoin_scripts::hlc::HashLock;
+use bitcoin_scripts::hlc::{HashLock, HashPreimage};
 use internet2::addr::NodeId;
 
+use crate::bolt::{InitFeatures, ShortChannelId};
+
+/// Hop of a private route to the payment destination, as provided in the `r`
+/// field of BOLT-11 invoice. Describes the channel which the node forwards
+/// payments through towards the next hop of the route hint (or the payment
+/// destination, for the last hop).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{node_id}->{short_channel_id}")]
+pub struct RouteHintHop {
+    /// Node forwarding payment through the channel
+    pub node_id: NodeId,
+
+    /// Channel used for forwarding the payment
+    pub short_channel_id: ShortChannelId,
+
+    /// Base forwarding fee of the channel
+    pub fee_base_msat: u32,
+
+    /// Proportional forwarding fee of the channel, in millionths
+    pub fee_proportional_millionths: u32,
+
+    /// CLTV expiry delta required by the forwarding node
+    pub cltv_expiry_delta: u16,
+}
+
+/// Private route to the payment destination, as provided in the `r` field
+/// of BOLT-11 invoice
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
+pub struct RouteHint {
+    /// Route hops, ordered from the first forwarding node towards the
+    /// payment destination
+    pub hops: Vec<RouteHintHop>,
+}
+
 /// Payment request as it may be extracted from BOLT-11 invoice and used for
 /// route construction.
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[display("pay {amount_msat} msat to {node_id} locked by {payment_hash}")]
 pub struct PaymentRequest {
     /// Amount to pay
@@ -35,4 +69,15 @@ pub struct PaymentRequest {
     /// The actual CLTV used in the offered HTLC may be larger due to
     /// `cltv_delay`s on a route.
     pub min_final_cltv_expiry: u32,
+
+    /// Private routes to the destination, which may be used in addition to
+    /// the channels known from the gossip
+    pub route_hints: Vec<RouteHint>,
+
+    /// Features supported by the destination, if provided by the invoice
+    pub features: Option<InitFeatures>,
+
+    /// Payment secret, which has to be passed to the destination in the
+    /// onion payload, if provided by the invoice
+    pub payment_secret: Option<HashPreimage>,
 }
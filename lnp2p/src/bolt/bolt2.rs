@@ -17,18 +17,19 @@ use std::io;
 use std::str::FromStr;
 
 use amplify::flags::FlagVec;
-use amplify::{DumbDefault, Slice32};
+use amplify::{DumbDefault, Slice32, Wrapper};
 use bitcoin::hashes::sha256;
-use bitcoin::Txid;
+use bitcoin::{consensus, OutPoint, Script, Transaction, Txid, Witness};
 use bitcoin_scripts::hlc::{HashLock, HashPreimage};
 use bitcoin_scripts::PubkeyScript;
 use internet2::presentation::sphinx::Onion;
+use internet2::presentation::EvenOdd;
 use internet2::tlv;
-use lightning_encoding::Error;
+use lightning_encoding::{Error, TlvError};
 use secp256k1::ecdsa::Signature;
 use secp256k1::{PublicKey, SecretKey};
 
-use super::{ChannelId, TempChannelId};
+use super::{AssetAmount, ChannelId, ShortChannelId, TempChannelId};
 use crate::bolt::PaymentOnion;
 
 /// Total length of payment Sphinx package
@@ -62,6 +63,11 @@ pub enum ChannelType {
     /// and 12)
     #[display("anchored_zero_fee")]
     AnchorsZeroFeeHtlcTxStaticRemotekey,
+
+    /// option_simple_taproot, option_anchors_zero_fee_htlc_tx and
+    /// option_static_remotekey (bits 80, 22 and 12)
+    #[display("simple_taproot")]
+    SimpleTaproot,
 }
 
 impl ChannelType {
@@ -83,6 +89,20 @@ impl ChannelType {
         self == ChannelType::AnchorsZeroFeeHtlcTxStaticRemotekey
     }
 
+    /// Detects whether channel has `option_simple_taproot` set, i.e. uses
+    /// MuSig2 funding output and taproot commitment outputs
+    #[inline]
+    pub fn has_simple_taproot(self) -> bool {
+        self == ChannelType::SimpleTaproot
+    }
+
+    /// Detects whether channel has any form of anchor outputs, i.e. either
+    /// `option_anchor_outputs` or `option_anchors_zero_fee_htlc_tx` set
+    #[inline]
+    pub fn has_anchors(self) -> bool {
+        self.has_anchor_outputs() || self.has_anchors_zero_fee_htlc_tx()
+    }
+
     /// Converts default channel type into `None` and non-default into
     /// `Some(ChannelType)`
     #[inline]
@@ -107,14 +127,17 @@ impl TryFrom<FlagVec> for ChannelType {
         }
 
         let mut iter = flags.iter();
-        match (iter.next(), iter.next(), iter.next()) {
-            (Some(12), None, None) => Ok(ChannelType::StaticRemotekey),
-            (Some(12), Some(20), None) => {
+        match (iter.next(), iter.next(), iter.next(), iter.next()) {
+            (Some(12), None, None, None) => Ok(ChannelType::StaticRemotekey),
+            (Some(12), Some(20), None, None) => {
                 Ok(ChannelType::AnchorOutputsStaticRemotekey)
             }
-            (Some(12), Some(22), None) => {
+            (Some(12), Some(22), None, None) => {
                 Ok(ChannelType::AnchorsZeroFeeHtlcTxStaticRemotekey)
             }
+            (Some(12), Some(22), Some(80), None) => {
+                Ok(ChannelType::SimpleTaproot)
+            }
             _ => Err(lightning_encoding::Error::DataIntegrityError(s!(
                 "invalid combination of channel type flags"
             ))),
@@ -138,6 +161,7 @@ impl FromStr for ChannelType {
             "anchored_zero_fee" => {
                 ChannelType::AnchorsZeroFeeHtlcTxStaticRemotekey
             }
+            "simple_taproot" => ChannelType::SimpleTaproot,
             _ => return Err(ChannelTypeParseError(s.to_owned())),
         })
     }
@@ -171,6 +195,11 @@ impl lightning_encoding::LightningEncode for ChannelType {
                 flags.set(12);
                 flags.set(22);
             }
+            ChannelType::SimpleTaproot => {
+                flags.set(12);
+                flags.set(22);
+                flags.set(80);
+            }
         }
         let mut vec = flags.as_inner().to_vec();
         vec.reverse();
@@ -294,6 +323,18 @@ pub struct OpenChannel {
     #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
     pub channel_type: Option<ChannelType>,
 
+    /// MuSig2 public nonce which the sender will use for signing its next
+    /// commitment transaction in simple taproot channels
+    #[lightning_encoding(tlv = 4)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 4))]
+    pub next_local_nonce: Option<PublicNonce>,
+
+    /// RGB extension: assets allocated by the funder to the channel. The
+    /// assets must be present in the `init` asset lists of both peers.
+    #[lightning_encoding(tlv = 65537)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 65537))]
+    pub funding_assets: Option<Vec<AssetAmount>>,
+
     /// The rest of TLVs with unknown odd type ids
     #[lightning_encoding(unknown_tlvs)]
     #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
@@ -405,6 +446,12 @@ pub struct AcceptChannel {
     #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
     pub channel_type: Option<ChannelType>,
 
+    /// MuSig2 public nonce which the sender will use for signing its next
+    /// commitment transaction in simple taproot channels
+    #[lightning_encoding(tlv = 4)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 4))]
+    pub next_local_nonce: Option<PublicNonce>,
+
     /// The rest of TLVs with unknown odd type ids
     #[lightning_encoding(unknown_tlvs)]
     #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
@@ -438,7 +485,12 @@ impl AcceptChannel {
 /// `funding_signed`, it will broadcast the funding transaction.
 #[derive(Clone, PartialEq, Eq, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
 #[display(
     "funding_created({temporary_channel_id}, \
      {funding_txid}:{funding_output_index}, ...signature)"
@@ -456,6 +508,17 @@ pub struct FundingCreated {
     /// The signature of the channel initiator (funder) on the funding
     /// transaction
     pub signature: Signature,
+
+    /// MuSig2 partial signature of the sender together with its just-in-time
+    /// nonce, used instead of `signature` in simple taproot channels
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub partial_signature_with_nonce: Option<PartialSigWithNonce>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
 /// This message gives the funder the signature it needs for the first
@@ -465,7 +528,12 @@ pub struct FundingCreated {
 /// This message introduces the `channel_id` to identify the channel.
 #[derive(Clone, PartialEq, Eq, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
 #[display("funding_signed({channel_id}, ...signature)")]
 pub struct FundingSigned {
     /// The channel ID
@@ -473,21 +541,56 @@ pub struct FundingSigned {
 
     /// The signature of the channel acceptor on the funding transaction
     pub signature: Signature,
+
+    /// MuSig2 partial signature of the sender together with its just-in-time
+    /// nonce, used instead of `signature` in simple taproot channels
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub partial_signature_with_nonce: Option<PartialSigWithNonce>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
-/// This message indicates that the funding transaction has reached the
-/// `minimum_depth` asked for in `accept_channel`. Once both nodes have sent
-/// this, the channel enters normal operating mode.
+/// This message (named `channel_ready` in the recent BOLT-2 revisions)
+/// indicates that the funding transaction has reached the `minimum_depth` asked
+/// for in `accept_channel`. Once both nodes have sent this, the channel enters
+/// normal operating mode.
 #[derive(Clone, PartialEq, Eq, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
-#[display("funding_locked({channel_id}, {next_per_commitment_point})")]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display("funding_locked({channel_id}, {next_per_commitment_point}, ...)")]
 pub struct FundingLocked {
     /// The channel ID
     pub channel_id: ChannelId,
 
     /// The per-commitment point of the second commitment transaction
     pub next_per_commitment_point: PublicKey,
+
+    /// Alias for the channel short id, which the sender will recognize for
+    /// the HTLCs routed through this channel. Required for zero-conf channels
+    /// and channels negotiated with `option_scid_alias`.
+    #[lightning_encoding(tlv = 1)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
+    pub short_channel_id: Option<ShortChannelId>,
+
+    /// MuSig2 public nonce which the sender will use for signing its next
+    /// commitment transaction in simple taproot channels
+    #[lightning_encoding(tlv = 4)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 4))]
+    pub next_local_nonce: Option<PublicNonce>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Display)]
@@ -553,6 +656,11 @@ pub struct UpdateAddHtlc {
     /// onion_routing_packet with a different payment_hash.
     pub onion_routing_packet: Onion<PaymentOnion, PAYMENT_SPHINX_LEN>,
 
+    /// RGB extension: asset amount transferred with the HTLC
+    #[lightning_encoding(tlv = 65537)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 65537))]
+    pub asset: Option<AssetAmount>,
+
     /// The rest of TLVs with unknown odd type ids
     #[lightning_encoding(unknown_tlvs)]
     #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
@@ -623,11 +731,23 @@ pub struct CommitmentSigned {
 
     /// Signatures on the HTLC transactions
     pub htlc_signatures: Vec<Signature>,
+
+    /// MuSig2 partial signature of the sender together with its just-in-time
+    /// nonce, used instead of `signature` in simple taproot channels
+    pub partial_signature_with_nonce: Option<PartialSigWithNonce>,
+
+    /// The rest of TLVs with unknown odd type ids
+    pub unknown_tlvs: tlv::Stream,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
 #[display(
     "revoke_and_ack({channel_id}, {next_per_commitment_point}, \
      ...per_commitment_secret)"
@@ -641,6 +761,17 @@ pub struct RevokeAndAck {
 
     /// The next sender-broadcast commitment transaction's per-commitment point
     pub next_per_commitment_point: PublicKey,
+
+    /// MuSig2 public nonce which the sender will use for signing its next
+    /// commitment transaction in simple taproot channels
+    #[lightning_encoding(tlv = 4)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 4))]
+    pub next_local_nonce: Option<PublicNonce>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Display)]
@@ -682,171 +813,964 @@ pub struct ChannelReestablish {
     pub my_current_per_commitment_point: PublicKey,
 }
 
-impl DumbDefault for OpenChannel {
-    fn dumb_default() -> Self {
-        OpenChannel {
-            chain_hash: none!(),
-            temporary_channel_id: TempChannelId::dumb_default(),
-            funding_satoshis: 0,
-            push_msat: 0,
-            dust_limit_satoshis: 0,
-            max_htlc_value_in_flight_msat: 0,
-            channel_reserve_satoshis: 0,
-            htlc_minimum_msat: 0,
-            feerate_per_kw: 0,
-            to_self_delay: 0,
-            max_accepted_htlcs: 0,
-            funding_pubkey: dumb_pubkey!(),
-            revocation_basepoint: dumb_pubkey!(),
-            payment_point: dumb_pubkey!(),
-            delayed_payment_basepoint: dumb_pubkey!(),
-            htlc_basepoint: dumb_pubkey!(),
-            first_per_commitment_point: dumb_pubkey!(),
-            channel_flags: 0,
-            shutdown_scriptpubkey: None,
-            channel_type: None,
-            unknown_tlvs: none!(),
-        }
-    }
-}
-
-impl DumbDefault for AcceptChannel {
-    fn dumb_default() -> Self {
-        AcceptChannel {
-            temporary_channel_id: TempChannelId::dumb_default(),
-            dust_limit_satoshis: 0,
-            max_htlc_value_in_flight_msat: 0,
-            channel_reserve_satoshis: 0,
-            htlc_minimum_msat: 0,
-            minimum_depth: 0,
-            to_self_delay: 0,
-            max_accepted_htlcs: 0,
-            funding_pubkey: dumb_pubkey!(),
-            revocation_basepoint: dumb_pubkey!(),
-            payment_point: dumb_pubkey!(),
-            delayed_payment_basepoint: dumb_pubkey!(),
-            htlc_basepoint: dumb_pubkey!(),
-            first_per_commitment_point: dumb_pubkey!(),
-            shutdown_scriptpubkey: None,
-            channel_type: None,
-            unknown_tlvs: none!(),
-        }
-    }
-}
+/// MuSig2 public nonce, consisting of two compressed curve points, which is
+/// used in simple taproot channels
+#[derive(
+    Wrapper,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
+    From,
+    LightningEncode,
+    LightningDecode
+)]
+#[cfg_attr(feature = "strict_encoding", derive(StrictEncode, StrictDecode))]
+pub struct PublicNonce([u8; 66]);
+
+/// MuSig2 partial signature, which is used in simple taproot channels
+#[derive(
+    Wrapper,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
+    From,
+    LightningEncode,
+    LightningDecode
+)]
+#[cfg_attr(feature = "strict_encoding", derive(StrictEncode, StrictDecode))]
+pub struct PartialSignature([u8; 32]);
 
-// TODO: Replace this custom implementation with derivation in v0.10
-impl lightning_encoding::LightningEncode for CommitmentSigned {
-    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
-        let mut len = 0;
-        len += self.channel_id.lightning_encode(&mut e)?;
-        len += self.signature.lightning_encode(&mut e)?;
+/// MuSig2 partial signature together with the just-in-time nonce of the signer
+/// used to produce it
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+pub struct PartialSigWithNonce {
+    /// MuSig2 partial signature
+    pub partial_sig: PartialSignature,
 
-        let num_htlcs = self.htlc_signatures.len() as u16;
-        len += num_htlcs.lightning_encode(&mut e)?;
-        for index in 0..num_htlcs {
-            let sig: Signature = self.htlc_signatures[index as usize];
-            len += sig.lightning_encode(&mut e)?;
-        }
-        Ok(len)
-    }
+    /// Public nonce of the signer
+    pub nonce: PublicNonce,
 }
 
-impl lightning_encoding::LightningDecode for CommitmentSigned {
-    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
-        let channel_id = ChannelId::lightning_decode(&mut d)?;
-        let signature = Signature::lightning_decode(&mut d)?;
-        let num_htlcs = u16::lightning_decode(&mut d)?;
+/// Marker value for TLV records which carry no data and signal some
+/// requirement or feature just by their presence (like
+/// `require_confirmed_inputs`).
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[display("present")]
+pub struct TlvFlag;
+
+/// Amount, in satoshis, which a party adds to (positive values) or removes
+/// from (negative values) the funding output during RBF of the interactively
+/// constructed transaction or channel splicing.
+#[derive(
+    Wrapper, Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display, From
+)]
+#[display(inner)]
+pub struct FundingContribution(i64);
 
-        let mut htlc_signatures = Vec::with_capacity(num_htlcs as usize);
-        for _ in 0..num_htlcs {
-            let sig = Signature::lightning_decode(&mut d)?;
-            htlc_signatures.push(sig);
-        }
+/// This message initiates the v2 channel establishment workflow, in which the
+/// funding transaction is constructed interactively by both peers.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display(
+    "open_channel2({chain_hash}, {temporary_channel_id}, {funding_satoshis}, \
+     {channel_flags}, ...)"
+)]
+pub struct OpenChannel2 {
+    /// The genesis hash of the blockchain where the channel is to be opened
+    pub chain_hash: Slice32,
 
-        Ok(CommitmentSigned {
-            channel_id,
-            signature,
-            htlc_signatures,
-        })
-    }
-}
+    /// A temporary channel ID derived from the sender's revocation basepoint
+    pub temporary_channel_id: TempChannelId,
 
-#[cfg(test)]
-mod test {
-    use lightning_encoding::{LightningDecode, LightningEncode};
+    /// The fee rate per 1000-weight which the opener will use for the funding
+    /// transaction
+    pub funding_feerate_perkw: u32,
 
-    use super::*;
-    use crate::bolt::Messages;
+    /// The fee rate per 1000-weight of sender generated commitment
+    /// transactions, until updated by update_fee
+    pub commitment_feerate_perkw: u32,
 
-    #[test]
-    fn real_clightning_open_channel() {
-        // Real open_channel message sent by clightning
-        let msg_recv = [
-            0u8, 32, 6, 34, 110, 70, 17, 26, 11, 89, 202, 175, 18, 96, 67, 235,
-            91, 191, 40, 195, 79, 58, 94, 51, 42, 31, 199, 178, 183, 60, 241,
-            136, 145, 15, 55, 163, 222, 247, 199, 217, 62, 176, 50, 239, 35, 1,
-            82, 129, 198, 46, 117, 47, 78, 64, 130, 130, 167, 89, 107, 148,
-            190, 121, 88, 127, 175, 82, 0, 0, 0, 0, 0, 1, 134, 160, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 34, 255, 255, 255, 255, 255, 255,
-            255, 255, 0, 0, 0, 0, 0, 0, 3, 232, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 253, 0, 6, 1, 227, 3, 33, 98, 70, 252, 48, 195, 103, 238, 233,
-            231, 193, 79, 109, 137, 240, 0, 34, 234, 4, 191, 125, 249, 102, 44,
-            137, 141, 152, 246, 118, 166, 205, 60, 3, 96, 241, 203, 115, 211,
-            19, 224, 138, 23, 92, 68, 226, 196, 234, 61, 226, 143, 211, 90, 92,
-            44, 147, 5, 89, 185, 117, 71, 57, 241, 139, 196, 28, 3, 252, 250,
-            227, 188, 85, 7, 237, 113, 4, 18, 45, 7, 192, 165, 147, 18, 113,
-            191, 216, 125, 175, 201, 118, 225, 63, 243, 29, 155, 194, 235, 167,
-            20, 3, 12, 61, 69, 17, 92, 121, 215, 107, 192, 35, 192, 160, 214,
-            235, 86, 202, 92, 206, 239, 201, 48, 28, 215, 9, 43, 255, 250, 80,
-            32, 129, 98, 29, 3, 57, 9, 153, 179, 206, 248, 130, 112, 219, 32,
-            69, 209, 220, 105, 18, 211, 2, 165, 247, 245, 245, 1, 170, 100,
-            208, 34, 98, 123, 207, 130, 10, 66, 2, 21, 90, 74, 135, 143, 98,
-            75, 173, 210, 81, 201, 99, 45, 76, 125, 176, 84, 187, 222, 90, 218,
-            87, 5, 11, 119, 191, 75, 185, 108, 124, 8, 32, 1, 0, 0, 1, 2, 16,
-            0,
-        ];
-        Messages::lightning_deserialize(&msg_recv).unwrap();
-    }
+    /// Amount the opener contributes to the channel
+    pub funding_satoshis: u64,
 
-    #[test]
-    fn real_clightning_accept_message() {
-        // Real accept_channel message sent by clightning
-        let msg_recv = [
-            0u8, 33, 117, 72, 156, 134, 70, 5, 93, 232, 6, 166, 206, 185, 243,
-            33, 125, 57, 230, 233, 235, 59, 255, 0, 23, 127, 91, 135, 129, 43,
-            74, 208, 254, 247, 0, 0, 0, 0, 0, 0, 2, 34, 255, 255, 255, 255,
-            255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 3, 232, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 1, 0, 6, 1, 227, 3, 147, 217, 39, 113, 17, 182, 164,
-            198, 126, 180, 51, 123, 215, 81, 65, 205, 222, 78, 101, 98, 199, 9,
-            5, 82, 67, 253, 162, 180, 223, 72, 98, 66, 2, 128, 65, 61, 107,
-            193, 243, 6, 121, 64, 101, 217, 132, 255, 102, 24, 104, 82, 231,
-            85, 38, 41, 202, 139, 32, 111, 38, 234, 127, 68, 163, 60, 140, 2,
-            39, 52, 86, 138, 94, 124, 142, 9, 235, 164, 16, 181, 217, 161, 26,
-            12, 8, 130, 181, 137, 220, 99, 201, 127, 201, 112, 190, 163, 193,
-            106, 156, 37, 2, 190, 147, 103, 247, 7, 229, 100, 68, 242, 62, 188,
-            34, 207, 164, 62, 66, 28, 7, 175, 210, 8, 124, 194, 36, 83, 236,
-            44, 127, 223, 168, 157, 68, 3, 14, 128, 103, 81, 154, 149, 202,
-            159, 71, 124, 151, 73, 105, 239, 176, 47, 156, 129, 14, 188, 71,
-            184, 153, 30, 177, 53, 89, 69, 99, 111, 56, 131, 3, 199, 31, 18,
-            222, 84, 187, 107, 58, 128, 108, 91, 102, 62, 231, 232, 67, 121,
-            29, 89, 1, 3, 82, 96, 15, 23, 248, 232, 249, 141, 149, 229, 70, 1,
-            0,
-        ];
+    /// The threshold below which outputs on transactions broadcast by sender
+    /// will be omitted
+    pub dust_limit_satoshis: u64,
 
-        Messages::lightning_deserialize(&msg_recv).unwrap();
-    }
+    /// The maximum inbound HTLC value in flight towards sender, in
+    /// millisatoshi
+    pub max_htlc_value_in_flight_msat: u64,
 
-    #[test]
-    fn real_clightning_close_message() {
-        // Real close_channel message sent by clightning
-        let msg_recv = [
-            0u8, 38, 240, 6, 9, 251, 176, 118, 10, 79, 144, 36, 249, 193, 225,
-            103, 87, 223, 185, 26, 36, 177, 75, 202, 215, 227, 75, 79, 49, 101,
-            79, 167, 93, 206, 0, 22, 0, 20, 42, 238, 172, 27, 222, 161, 61,
-            181, 251, 208, 97, 79, 71, 255, 98, 8, 213, 205, 114, 94,
-        ];
-        Messages::lightning_deserialize(&msg_recv).unwrap();
-    }
+    /// The minimum HTLC size incoming to sender, in milli-satoshi
+    pub htlc_minimum_msat: u64,
+
+    /// The number of blocks which the counterparty will have to wait to claim
+    /// on-chain funds if they broadcast a commitment transaction
+    pub to_self_delay: u16,
+
+    /// The maximum number of inbound HTLCs towards sender
+    pub max_accepted_htlcs: u16,
+
+    /// The locktime for the funding transaction
+    pub locktime: u32,
+
+    /// The sender's key controlling the funding transaction
+    pub funding_pubkey: PublicKey,
+
+    /// Used to derive a revocation key for transactions broadcast by
+    /// counterparty
+    pub revocation_basepoint: PublicKey,
+
+    /// A payment key to sender for transactions broadcast by counterparty
+    pub payment_basepoint: PublicKey,
+
+    /// Used to derive a payment key to sender for transactions broadcast by
+    /// sender
+    pub delayed_payment_basepoint: PublicKey,
+
+    /// Used to derive an HTLC payment key to sender
+    pub htlc_basepoint: PublicKey,
+
+    /// The first to-be-broadcast-by-sender transaction's per commitment point
+    pub first_per_commitment_point: PublicKey,
+
+    /// The second to-be-broadcast-by-sender transaction's per commitment point
+    pub second_per_commitment_point: PublicKey,
+
+    /// Channel flags; see [`OpenChannel::channel_flags`]
+    pub channel_flags: u8,
+
+    /// Optionally, a request to pre-set the to-sender output's scriptPubkey
+    /// for when we collaboratively close
+    #[lightning_encoding(tlv = 0)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 0))]
+    pub shutdown_scriptpubkey: Option<PubkeyScript>,
+
+    /// Channel type, see [`ChannelType`]
+    #[lightning_encoding(tlv = 1)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
+    pub channel_type: Option<ChannelType>,
+
+    /// Requirement for the counterparty to use only confirmed inputs
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub require_confirmed_inputs: Option<TlvFlag>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+impl OpenChannel2 {
+    /// Detects whether channel should be announced
+    #[inline]
+    pub fn should_announce_channel(&self) -> bool {
+        self.channel_flags & 0x01 == 0x01
+    }
+}
+
+/// This message contains information about a node and indicates its acceptance
+/// of the new channel opened with v2 channel establishment protocol.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display("accept_channel2({temporary_channel_id}, {funding_satoshis}, ...)")]
+pub struct AcceptChannel2 {
+    /// The same `temporary_channel_id` as in `open_channel2`
+    pub temporary_channel_id: TempChannelId,
+
+    /// Amount the accepter contributes to the channel
+    pub funding_satoshis: u64,
+
+    /// The threshold below which outputs on transactions broadcast by sender
+    /// will be omitted
+    pub dust_limit_satoshis: u64,
+
+    /// The maximum inbound HTLC value in flight towards sender, in
+    /// milli-satoshi
+    pub max_htlc_value_in_flight_msat: u64,
+
+    /// The minimum HTLC size incoming to sender, in milli-satoshi
+    pub htlc_minimum_msat: u64,
+
+    /// Minimum depth of the funding transaction before the channel is
+    /// considered open
+    pub minimum_depth: u32,
+
+    /// The number of blocks which the counterparty will have to wait to claim
+    /// on-chain funds if they broadcast a commitment transaction
+    pub to_self_delay: u16,
+
+    /// The maximum number of inbound HTLCs towards sender
+    pub max_accepted_htlcs: u16,
+
+    /// The sender's key controlling the funding transaction
+    pub funding_pubkey: PublicKey,
+
+    /// Used to derive a revocation key for transactions broadcast by
+    /// counterparty
+    pub revocation_basepoint: PublicKey,
+
+    /// A payment key to sender for transactions broadcast by counterparty
+    pub payment_basepoint: PublicKey,
+
+    /// Used to derive a payment key to sender for transactions broadcast by
+    /// sender
+    pub delayed_payment_basepoint: PublicKey,
+
+    /// Used to derive an HTLC payment key to sender
+    pub htlc_basepoint: PublicKey,
+
+    /// The first to-be-broadcast-by-sender transaction's per commitment point
+    pub first_per_commitment_point: PublicKey,
+
+    /// The second to-be-broadcast-by-sender transaction's per commitment point
+    pub second_per_commitment_point: PublicKey,
+
+    /// Optionally, a request to pre-set the to-sender output's scriptPubkey
+    /// for when we collaboratively close
+    #[lightning_encoding(tlv = 0)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 0))]
+    pub shutdown_scriptpubkey: Option<PubkeyScript>,
+
+    /// Channel type, see [`ChannelType`]
+    #[lightning_encoding(tlv = 1)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
+    pub channel_type: Option<ChannelType>,
+
+    /// Requirement for the counterparty to use only confirmed inputs
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub require_confirmed_inputs: Option<TlvFlag>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+/// Adds an input to the interactively constructed transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display(
+    "tx_add_input({channel_id}, {serial_id}, {prevtx_vout}, {sequence:#x})"
+)]
+pub struct TxAddInput {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Identifier of the input, defining its position in the constructed
+    /// transaction. Even for the initiator, odd for the non-initiator.
+    pub serial_id: u64,
+
+    /// The transaction containing the spent output
+    pub prevtx: Transaction,
+
+    /// Index of the spent output within `prevtx`
+    pub prevtx_vout: u32,
+
+    /// Sequence number of the input
+    pub sequence: u32,
+}
+
+impl TxAddInput {
+    /// Returns outpoint spent by the input
+    #[inline]
+    pub fn prevout(&self) -> OutPoint {
+        OutPoint::new(self.prevtx.txid(), self.prevtx_vout)
+    }
+}
+
+/// Adds an output to the interactively constructed transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("tx_add_output({channel_id}, {serial_id}, {sats}, {script})")]
+pub struct TxAddOutput {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Identifier of the output, defining its position in the constructed
+    /// transaction. Even for the initiator, odd for the non-initiator.
+    pub serial_id: u64,
+
+    /// Output value in satoshis
+    pub sats: u64,
+
+    /// Output scriptPubkey
+    pub script: PubkeyScript,
+}
+
+/// Removes previously added input from the interactively constructed
+/// transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("tx_remove_input({channel_id}, {serial_id})")]
+pub struct TxRemoveInput {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Identifier of the input which has to be removed
+    pub serial_id: u64,
+}
+
+/// Removes previously added output from the interactively constructed
+/// transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("tx_remove_output({channel_id}, {serial_id})")]
+pub struct TxRemoveOutput {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Identifier of the output which has to be removed
+    pub serial_id: u64,
+}
+
+/// Signals the conclusion of a peer's transaction contributions. The
+/// negotiation ends once both peers have sent this message consecutively.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("tx_complete({channel_id})")]
+pub struct TxComplete {
+    /// The channel ID
+    pub channel_id: ChannelId,
+}
+
+/// Provides witnesses for the inputs which were contributed by the sender to
+/// the interactively constructed transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("tx_signatures({channel_id}, {txid}, ...witnesses)")]
+pub struct TxSignatures {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Transaction id of the constructed transaction
+    pub txid: Txid,
+
+    /// Witnesses for the sender's inputs, ordered by input serial id
+    pub witnesses: Vec<Witness>,
+}
+
+/// Initiates replacement of the interactively constructed transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display("tx_init_rbf({channel_id}, {locktime}, {feerate}, ...)")]
+pub struct TxInitRbf {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// The locktime for the replacement transaction
+    pub locktime: u32,
+
+    /// The fee rate per 1000-weight for the replacement transaction
+    pub feerate: u32,
+
+    /// Change of the sender contribution to the funding output
+    #[lightning_encoding(tlv = 0)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 0))]
+    pub funding_output_contribution: Option<FundingContribution>,
+
+    /// Requirement for the counterparty to use only confirmed inputs
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub require_confirmed_inputs: Option<TlvFlag>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+/// Acknowledges replacement of the interactively constructed transaction.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display("tx_ack_rbf({channel_id}, ...)")]
+pub struct TxAckRbf {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Change of the sender contribution to the funding output
+    #[lightning_encoding(tlv = 0)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 0))]
+    pub funding_output_contribution: Option<FundingContribution>,
+
+    /// Requirement for the counterparty to use only confirmed inputs
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub require_confirmed_inputs: Option<TlvFlag>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+/// Aborts the interactive transaction construction, providing an optional
+/// reason.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("tx_abort({channel_id}, ...)")]
+pub struct TxAbort {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Abort reason; usually an ASCII string
+    pub data: Vec<u8>,
+}
+
+/// Requests the channel to become quiescent, i.e. stop sending any updates to
+/// the channel state. Required before splicing the channel.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("stfu({channel_id}, {initiator})")]
+pub struct Stfu {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Set to 1 if the sender is the quiescence initiator, 0 otherwise
+    pub initiator: u8,
+}
+
+impl Stfu {
+    /// Detects whether the sender of the message is quiescence initiator
+    #[inline]
+    pub fn is_initiator(&self) -> bool {
+        self.initiator == 1
+    }
+}
+
+/// Initiates splicing of the quiescent channel, i.e. replacement of the
+/// channel funding output with a new one, adding or removing funds.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display(
+    "splice_init({channel_id}, {funding_contribution_satoshis}, \
+     {funding_feerate_perkw}, {locktime}, ...)"
+)]
+pub struct SpliceInit {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Amount the sender adds to (positive value) or removes from (negative
+    /// value) its channel balance
+    pub funding_contribution_satoshis: FundingContribution,
+
+    /// The fee rate per 1000-weight for the splice transaction
+    pub funding_feerate_perkw: u32,
+
+    /// The locktime for the splice transaction
+    pub locktime: u32,
+
+    /// The sender's key controlling the new funding output
+    pub funding_pubkey: PublicKey,
+
+    /// Requirement for the counterparty to use only confirmed inputs
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub require_confirmed_inputs: Option<TlvFlag>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+/// Accepts splicing of the channel, providing acceptor's contribution.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display("splice_ack({channel_id}, {funding_contribution_satoshis}, ...)")]
+pub struct SpliceAck {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Amount the sender adds to (positive value) or removes from (negative
+    /// value) its channel balance
+    pub funding_contribution_satoshis: FundingContribution,
+
+    /// The sender's key controlling the new funding output
+    pub funding_pubkey: PublicKey,
+
+    /// Requirement for the counterparty to use only confirmed inputs
+    #[lightning_encoding(tlv = 2)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 2))]
+    pub require_confirmed_inputs: Option<TlvFlag>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+/// Signals that the splice transaction has reached enough confirmations from
+/// the sender's point of view.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("splice_locked({channel_id}, {splice_txid})")]
+pub struct SpliceLocked {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// The transaction id of the confirmed splice transaction
+    pub splice_txid: Txid,
+}
+
+impl DumbDefault for OpenChannel {
+    fn dumb_default() -> Self {
+        OpenChannel {
+            chain_hash: none!(),
+            temporary_channel_id: TempChannelId::dumb_default(),
+            funding_satoshis: 0,
+            push_msat: 0,
+            dust_limit_satoshis: 0,
+            max_htlc_value_in_flight_msat: 0,
+            channel_reserve_satoshis: 0,
+            htlc_minimum_msat: 0,
+            feerate_per_kw: 0,
+            to_self_delay: 0,
+            max_accepted_htlcs: 0,
+            funding_pubkey: dumb_pubkey!(),
+            revocation_basepoint: dumb_pubkey!(),
+            payment_point: dumb_pubkey!(),
+            delayed_payment_basepoint: dumb_pubkey!(),
+            htlc_basepoint: dumb_pubkey!(),
+            first_per_commitment_point: dumb_pubkey!(),
+            channel_flags: 0,
+            shutdown_scriptpubkey: None,
+            channel_type: None,
+            next_local_nonce: None,
+            funding_assets: None,
+            unknown_tlvs: none!(),
+        }
+    }
+}
+
+impl DumbDefault for AcceptChannel {
+    fn dumb_default() -> Self {
+        AcceptChannel {
+            temporary_channel_id: TempChannelId::dumb_default(),
+            dust_limit_satoshis: 0,
+            max_htlc_value_in_flight_msat: 0,
+            channel_reserve_satoshis: 0,
+            htlc_minimum_msat: 0,
+            minimum_depth: 0,
+            to_self_delay: 0,
+            max_accepted_htlcs: 0,
+            funding_pubkey: dumb_pubkey!(),
+            revocation_basepoint: dumb_pubkey!(),
+            payment_point: dumb_pubkey!(),
+            delayed_payment_basepoint: dumb_pubkey!(),
+            htlc_basepoint: dumb_pubkey!(),
+            first_per_commitment_point: dumb_pubkey!(),
+            shutdown_scriptpubkey: None,
+            channel_type: None,
+            next_local_nonce: None,
+            unknown_tlvs: none!(),
+        }
+    }
+}
+
+// TODO: Replace this custom implementation with derivation in v0.10
+impl lightning_encoding::LightningEncode for CommitmentSigned {
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.channel_id.lightning_encode(&mut e)?;
+        len += self.signature.lightning_encode(&mut e)?;
+
+        let num_htlcs = self.htlc_signatures.len() as u16;
+        len += num_htlcs.lightning_encode(&mut e)?;
+        for index in 0..num_htlcs {
+            let sig: Signature = self.htlc_signatures[index as usize];
+            len += sig.lightning_encode(&mut e)?;
+        }
+
+        let mut tlvs = self.unknown_tlvs.clone();
+        if let Some(partial_sig) = self.partial_signature_with_nonce {
+            tlvs.insert(2usize.into(), partial_sig.lightning_serialize()?);
+        }
+        len += tlvs.lightning_encode(&mut e)?;
+        Ok(len)
+    }
+}
+
+impl lightning_encoding::LightningDecode for CommitmentSigned {
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let channel_id = ChannelId::lightning_decode(&mut d)?;
+        let signature = Signature::lightning_decode(&mut d)?;
+        let num_htlcs = u16::lightning_decode(&mut d)?;
+
+        let mut htlc_signatures = Vec::with_capacity(num_htlcs as usize);
+        for _ in 0..num_htlcs {
+            let sig = Signature::lightning_decode(&mut d)?;
+            htlc_signatures.push(sig);
+        }
+
+        let mut partial_signature_with_nonce = None;
+        let mut unknown_tlvs = tlv::Stream::new();
+        for (ty, value) in tlv::Stream::lightning_decode(&mut d)? {
+            if ty.into_inner() == 2 {
+                partial_signature_with_nonce =
+                    Some(PartialSigWithNonce::lightning_deserialize(value)?);
+            } else if ty.is_even() {
+                return Err(TlvError::UnknownEvenType(ty.into_inner()).into());
+            } else {
+                unknown_tlvs.insert(ty, value);
+            }
+        }
+
+        Ok(CommitmentSigned {
+            channel_id,
+            signature,
+            htlc_signatures,
+            partial_signature_with_nonce,
+            unknown_tlvs,
+        })
+    }
+}
+
+impl lightning_encoding::LightningEncode for TlvFlag {
+    #[inline]
+    fn lightning_encode<E: io::Write>(&self, _: E) -> Result<usize, Error> {
+        Ok(0)
+    }
+}
+
+impl lightning_encoding::LightningDecode for TlvFlag {
+    #[inline]
+    fn lightning_decode<D: io::Read>(_: D) -> Result<Self, Error> {
+        Ok(TlvFlag)
+    }
+}
+
+#[cfg(feature = "strict_encoding")]
+impl strict_encoding::StrictEncode for TlvFlag {
+    #[inline]
+    fn strict_encode<E: io::Write>(
+        &self,
+        _: E,
+    ) -> Result<usize, strict_encoding::Error> {
+        Ok(0)
+    }
+}
+
+#[cfg(feature = "strict_encoding")]
+impl strict_encoding::StrictDecode for TlvFlag {
+    #[inline]
+    fn strict_decode<D: io::Read>(
+        _: D,
+    ) -> Result<Self, strict_encoding::Error> {
+        Ok(TlvFlag)
+    }
+}
+
+impl lightning_encoding::LightningEncode for FundingContribution {
+    #[inline]
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        e.write_all(&self.0.to_be_bytes())?;
+        Ok(8)
+    }
+}
+
+impl lightning_encoding::LightningDecode for FundingContribution {
+    #[inline]
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let mut buf = [0u8; 8];
+        d.read_exact(&mut buf)?;
+        Ok(FundingContribution(i64::from_be_bytes(buf)))
+    }
+}
+
+#[cfg(feature = "strict_encoding")]
+impl strict_encoding::StrictEncode for FundingContribution {
+    #[inline]
+    fn strict_encode<E: io::Write>(
+        &self,
+        e: E,
+    ) -> Result<usize, strict_encoding::Error> {
+        self.0.strict_encode(e)
+    }
+}
+
+#[cfg(feature = "strict_encoding")]
+impl strict_encoding::StrictDecode for FundingContribution {
+    #[inline]
+    fn strict_decode<D: io::Read>(
+        d: D,
+    ) -> Result<Self, strict_encoding::Error> {
+        i64::strict_decode(d).map(FundingContribution)
+    }
+}
+
+/// Encodes byte string with u16 length prefix, as used in interactive
+/// transaction construction messages
+fn lightning_encode_u16_bytes<E: io::Write>(
+    data: &[u8],
+    mut e: E,
+) -> Result<usize, Error> {
+    if data.len() > u16::MAX as usize {
+        return Err(Error::TooLargeData(data.len()));
+    }
+    let len = lightning_encoding::LightningEncode::lightning_encode(
+        &(data.len() as u16),
+        &mut e,
+    )?;
+    e.write_all(data)?;
+    Ok(len + data.len())
+}
+
+/// Decodes byte string with u16 length prefix, as used in interactive
+/// transaction construction messages
+fn lightning_decode_u16_bytes<D: io::Read>(mut d: D) -> Result<Vec<u8>, Error> {
+    let len: u16 =
+        lightning_encoding::LightningDecode::lightning_decode(&mut d)?;
+    let mut data = vec![0u8; len as usize];
+    d.read_exact(&mut data)?;
+    Ok(data)
+}
+
+fn consensus_deserialize<T: consensus::Decodable>(
+    data: &[u8],
+) -> Result<T, Error> {
+    consensus::deserialize(data)
+        .map_err(|err| Error::DataIntegrityError(err.to_string()))
+}
+
+// TODO: Replace this custom implementation with derivation in v0.10
+impl lightning_encoding::LightningEncode for TxAddInput {
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.channel_id.lightning_encode(&mut e)?;
+        len += self.serial_id.lightning_encode(&mut e)?;
+        len += lightning_encode_u16_bytes(
+            &consensus::serialize(&self.prevtx),
+            &mut e,
+        )?;
+        len += self.prevtx_vout.lightning_encode(&mut e)?;
+        len += self.sequence.lightning_encode(&mut e)?;
+        Ok(len)
+    }
+}
+
+impl lightning_encoding::LightningDecode for TxAddInput {
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        Ok(TxAddInput {
+            channel_id: ChannelId::lightning_decode(&mut d)?,
+            serial_id: u64::lightning_decode(&mut d)?,
+            prevtx: consensus_deserialize(&lightning_decode_u16_bytes(
+                &mut d,
+            )?)?,
+            prevtx_vout: u32::lightning_decode(&mut d)?,
+            sequence: u32::lightning_decode(&mut d)?,
+        })
+    }
+}
+
+impl lightning_encoding::LightningEncode for TxAddOutput {
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.channel_id.lightning_encode(&mut e)?;
+        len += self.serial_id.lightning_encode(&mut e)?;
+        len += self.sats.lightning_encode(&mut e)?;
+        len += lightning_encode_u16_bytes(self.script.as_bytes(), &mut e)?;
+        Ok(len)
+    }
+}
+
+impl lightning_encoding::LightningDecode for TxAddOutput {
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        Ok(TxAddOutput {
+            channel_id: ChannelId::lightning_decode(&mut d)?,
+            serial_id: u64::lightning_decode(&mut d)?,
+            sats: u64::lightning_decode(&mut d)?,
+            script: Script::from(lightning_decode_u16_bytes(&mut d)?).into(),
+        })
+    }
+}
+
+impl lightning_encoding::LightningEncode for TxSignatures {
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let mut len = 0;
+        len += self.channel_id.lightning_encode(&mut e)?;
+        len += self.txid.lightning_encode(&mut e)?;
+
+        if self.witnesses.len() > u16::MAX as usize {
+            return Err(Error::TooLargeData(self.witnesses.len()));
+        }
+        len += (self.witnesses.len() as u16).lightning_encode(&mut e)?;
+        for witness in &self.witnesses {
+            len += lightning_encode_u16_bytes(
+                &consensus::serialize(witness),
+                &mut e,
+            )?;
+        }
+        Ok(len)
+    }
+}
+
+impl lightning_encoding::LightningDecode for TxSignatures {
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        let channel_id = ChannelId::lightning_decode(&mut d)?;
+        let txid = Txid::lightning_decode(&mut d)?;
+        let num_witnesses = u16::lightning_decode(&mut d)?;
+
+        let mut witnesses = Vec::with_capacity(num_witnesses as usize);
+        for _ in 0..num_witnesses {
+            witnesses.push(consensus_deserialize(
+                &lightning_decode_u16_bytes(&mut d)?,
+            )?);
+        }
+
+        Ok(TxSignatures {
+            channel_id,
+            txid,
+            witnesses,
+        })
+    }
+}
+
+impl lightning_encoding::LightningEncode for TxAbort {
+    fn lightning_encode<E: io::Write>(&self, mut e: E) -> Result<usize, Error> {
+        let len = self.channel_id.lightning_encode(&mut e)?;
+        Ok(len + lightning_encode_u16_bytes(&self.data, &mut e)?)
+    }
+}
+
+impl lightning_encoding::LightningDecode for TxAbort {
+    fn lightning_decode<D: io::Read>(mut d: D) -> Result<Self, Error> {
+        Ok(TxAbort {
+            channel_id: ChannelId::lightning_decode(&mut d)?,
+            data: lightning_decode_u16_bytes(&mut d)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use lightning_encoding::{LightningDecode, LightningEncode};
+    use lnpbp::chain::AssetId;
+
+    use super::*;
+    use crate::bolt::Messages;
+
+    #[test]
+    fn real_clightning_open_channel() {
+        // Real open_channel message sent by clightning
+        let msg_recv = [
+            0u8, 32, 6, 34, 110, 70, 17, 26, 11, 89, 202, 175, 18, 96, 67, 235,
+            91, 191, 40, 195, 79, 58, 94, 51, 42, 31, 199, 178, 183, 60, 241,
+            136, 145, 15, 55, 163, 222, 247, 199, 217, 62, 176, 50, 239, 35, 1,
+            82, 129, 198, 46, 117, 47, 78, 64, 130, 130, 167, 89, 107, 148,
+            190, 121, 88, 127, 175, 82, 0, 0, 0, 0, 0, 1, 134, 160, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 34, 255, 255, 255, 255, 255, 255,
+            255, 255, 0, 0, 0, 0, 0, 0, 3, 232, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 253, 0, 6, 1, 227, 3, 33, 98, 70, 252, 48, 195, 103, 238, 233,
+            231, 193, 79, 109, 137, 240, 0, 34, 234, 4, 191, 125, 249, 102, 44,
+            137, 141, 152, 246, 118, 166, 205, 60, 3, 96, 241, 203, 115, 211,
+            19, 224, 138, 23, 92, 68, 226, 196, 234, 61, 226, 143, 211, 90, 92,
+            44, 147, 5, 89, 185, 117, 71, 57, 241, 139, 196, 28, 3, 252, 250,
+            227, 188, 85, 7, 237, 113, 4, 18, 45, 7, 192, 165, 147, 18, 113,
+            191, 216, 125, 175, 201, 118, 225, 63, 243, 29, 155, 194, 235, 167,
+            20, 3, 12, 61, 69, 17, 92, 121, 215, 107, 192, 35, 192, 160, 214,
+            235, 86, 202, 92, 206, 239, 201, 48, 28, 215, 9, 43, 255, 250, 80,
+            32, 129, 98, 29, 3, 57, 9, 153, 179, 206, 248, 130, 112, 219, 32,
+            69, 209, 220, 105, 18, 211, 2, 165, 247, 245, 245, 1, 170, 100,
+            208, 34, 98, 123, 207, 130, 10, 66, 2, 21, 90, 74, 135, 143, 98,
+            75, 173, 210, 81, 201, 99, 45, 76, 125, 176, 84, 187, 222, 90, 218,
+            87, 5, 11, 119, 191, 75, 185, 108, 124, 8, 32, 1, 0, 0, 1, 2, 16,
+            0,
+        ];
+        Messages::lightning_deserialize(&msg_recv).unwrap();
+    }
+
+    #[test]
+    fn real_clightning_accept_message() {
+        // Real accept_channel message sent by clightning
+        let msg_recv = [
+            0u8, 33, 117, 72, 156, 134, 70, 5, 93, 232, 6, 166, 206, 185, 243,
+            33, 125, 57, 230, 233, 235, 59, 255, 0, 23, 127, 91, 135, 129, 43,
+            74, 208, 254, 247, 0, 0, 0, 0, 0, 0, 2, 34, 255, 255, 255, 255,
+            255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 3, 232, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 1, 0, 6, 1, 227, 3, 147, 217, 39, 113, 17, 182, 164,
+            198, 126, 180, 51, 123, 215, 81, 65, 205, 222, 78, 101, 98, 199, 9,
+            5, 82, 67, 253, 162, 180, 223, 72, 98, 66, 2, 128, 65, 61, 107,
+            193, 243, 6, 121, 64, 101, 217, 132, 255, 102, 24, 104, 82, 231,
+            85, 38, 41, 202, 139, 32, 111, 38, 234, 127, 68, 163, 60, 140, 2,
+            39, 52, 86, 138, 94, 124, 142, 9, 235, 164, 16, 181, 217, 161, 26,
+            12, 8, 130, 181, 137, 220, 99, 201, 127, 201, 112, 190, 163, 193,
+            106, 156, 37, 2, 190, 147, 103, 247, 7, 229, 100, 68, 242, 62, 188,
+            34, 207, 164, 62, 66, 28, 7, 175, 210, 8, 124, 194, 36, 83, 236,
+            44, 127, 223, 168, 157, 68, 3, 14, 128, 103, 81, 154, 149, 202,
+            159, 71, 124, 151, 73, 105, 239, 176, 47, 156, 129, 14, 188, 71,
+            184, 153, 30, 177, 53, 89, 69, 99, 111, 56, 131, 3, 199, 31, 18,
+            222, 84, 187, 107, 58, 128, 108, 91, 102, 62, 231, 232, 67, 121,
+            29, 89, 1, 3, 82, 96, 15, 23, 248, 232, 249, 141, 149, 229, 70, 1,
+            0,
+        ];
+
+        Messages::lightning_deserialize(&msg_recv).unwrap();
+    }
+
+    #[test]
+    fn real_clightning_close_message() {
+        // Real close_channel message sent by clightning
+        let msg_recv = [
+            0u8, 38, 240, 6, 9, 251, 176, 118, 10, 79, 144, 36, 249, 193, 225,
+            103, 87, 223, 185, 26, 36, 177, 75, 202, 215, 227, 75, 79, 49, 101,
+            79, 167, 93, 206, 0, 22, 0, 20, 42, 238, 172, 27, 222, 161, 61,
+            181, 251, 208, 97, 79, 71, 255, 98, 8, 213, 205, 114, 94,
+        ];
+        Messages::lightning_deserialize(&msg_recv).unwrap();
+    }
 
     #[test]
     fn decode_static_remotekey_channel_type() {
@@ -884,4 +1808,217 @@ mod test {
 
         assert_eq!(msg_recv.to_vec(), vec);
     }
+
+    #[test]
+    fn interactive_tx_messages_roundtrip() {
+        let prevtx = Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: 100_000_000,
+                script_pubkey: Script::new_v0_p2wpkh(
+                    &bitcoin::WPubkeyHash::all_zeros(),
+                ),
+            }],
+        };
+        let channel_id = ChannelId::with(Txid::all_zeros(), 1);
+
+        let messages = [
+            Messages::TxAddInput(TxAddInput {
+                channel_id,
+                serial_id: 2,
+                prevtx,
+                prevtx_vout: 0,
+                sequence: 0xfffffffd,
+            }),
+            Messages::TxAddOutput(TxAddOutput {
+                channel_id,
+                serial_id: 4,
+                sats: 49_000_000,
+                script:
+                    Script::new_v0_p2wsh(&bitcoin::WScriptHash::all_zeros())
+                        .into(),
+            }),
+            Messages::TxComplete(TxComplete { channel_id }),
+            Messages::TxSignatures(TxSignatures {
+                channel_id,
+                txid: Txid::all_zeros(),
+                witnesses: vec![Witness::from_vec(vec![vec![0x30; 71], vec![
+                    0x02;
+                    33
+                ]])],
+            }),
+            Messages::TxInitRbf(TxInitRbf {
+                channel_id,
+                locktime: 120,
+                feerate: 253,
+                funding_output_contribution: Some((-10_000).into()),
+                require_confirmed_inputs: Some(TlvFlag),
+                unknown_tlvs: none!(),
+            }),
+            Messages::TxAbort(TxAbort {
+                channel_id,
+                data: b"negotiation failed".to_vec(),
+            }),
+        ];
+
+        for msg in messages {
+            let data = msg.lightning_serialize().unwrap();
+            let decoded = Messages::lightning_deserialize(&data).unwrap();
+            assert_eq!(decoded.lightning_serialize().unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn tx_add_output_encoding() {
+        let msg = TxAddOutput {
+            channel_id: ChannelId::with(Txid::all_zeros(), 0),
+            serial_id: 1,
+            sats: 0x1234,
+            script: Script::from(vec![0x00, 0x14]).into(),
+        };
+        let data = msg.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 8 + 8 + 2 + 2);
+        assert_eq!(&data[48..], &[0x00, 0x02, 0x00, 0x14]);
+    }
+
+    #[test]
+    fn splice_messages_roundtrip() {
+        let channel_id = ChannelId::with(Txid::all_zeros(), 1);
+        let messages = [
+            Messages::Stfu(Stfu {
+                channel_id,
+                initiator: 1,
+            }),
+            Messages::SpliceInit(SpliceInit {
+                channel_id,
+                funding_contribution_satoshis: (-100_000).into(),
+                funding_feerate_perkw: 253,
+                locktime: 0,
+                funding_pubkey: dumb_pubkey!(),
+                require_confirmed_inputs: None,
+                unknown_tlvs: none!(),
+            }),
+            Messages::SpliceAck(SpliceAck {
+                channel_id,
+                funding_contribution_satoshis: 50_000.into(),
+                funding_pubkey: dumb_pubkey!(),
+                require_confirmed_inputs: Some(TlvFlag),
+                unknown_tlvs: none!(),
+            }),
+            Messages::SpliceLocked(SpliceLocked {
+                channel_id,
+                splice_txid: Txid::all_zeros(),
+            }),
+        ];
+
+        for msg in messages {
+            let data = msg.lightning_serialize().unwrap();
+            let decoded = Messages::lightning_deserialize(&data).unwrap();
+            assert_eq!(decoded.lightning_serialize().unwrap(), data);
+        }
+
+        let data = Messages::Stfu(Stfu {
+            channel_id,
+            initiator: 1,
+        })
+        .lightning_serialize()
+        .unwrap();
+        assert_eq!(&data[..2], &[0x00, 0x02]);
+        assert_eq!(data.len(), 2 + 32 + 1);
+    }
+
+    #[test]
+    fn funding_locked_alias() {
+        let mut funding_locked = FundingLocked {
+            channel_id: ChannelId::with(Txid::all_zeros(), 1),
+            next_per_commitment_point: dumb_pubkey!(),
+            short_channel_id: None,
+            next_local_nonce: None,
+            unknown_tlvs: none!(),
+        };
+        let data = funding_locked.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 33);
+
+        let alias = ShortChannelId::random_alias();
+        assert!(alias.is_alias());
+        funding_locked.short_channel_id = Some(alias);
+        let data = funding_locked.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 33 + 1 + 1 + 8);
+        assert_eq!(&data[65..67], &[0x01, 0x08]);
+        assert_eq!(
+            FundingLocked::lightning_deserialize(&data).unwrap(),
+            funding_locked
+        );
+    }
+
+    #[test]
+    fn taproot_nonces_and_partial_sigs() {
+        let data = ChannelType::SimpleTaproot.lightning_serialize().unwrap();
+        assert_eq!(
+            ChannelType::lightning_deserialize(&data).unwrap(),
+            ChannelType::SimpleTaproot
+        );
+        assert_eq!(
+            ChannelType::from_str("simple_taproot"),
+            Ok(ChannelType::SimpleTaproot)
+        );
+
+        let signature = Signature::from_compact(&[1u8; 64]).unwrap();
+        let partial_sig = PartialSigWithNonce {
+            partial_sig: PartialSignature::from_inner([0x11; 32]),
+            nonce: PublicNonce::from_inner([0x22; 66]),
+        };
+        let mut commitment_signed = CommitmentSigned {
+            channel_id: ChannelId::with(Txid::all_zeros(), 1),
+            signature,
+            htlc_signatures: vec![signature],
+            partial_signature_with_nonce: None,
+            unknown_tlvs: none!(),
+        };
+        let data = commitment_signed.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 64 + 2 + 64);
+
+        commitment_signed.partial_signature_with_nonce = Some(partial_sig);
+        let data = commitment_signed.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 64 + 2 + 64 + 1 + 1 + 98);
+        assert_eq!(
+            CommitmentSigned::lightning_deserialize(&data).unwrap(),
+            commitment_signed
+        );
+
+        let revoke_and_ack = RevokeAndAck {
+            channel_id: ChannelId::with(Txid::all_zeros(), 1),
+            per_commitment_secret: SecretKey::from_slice(&[1u8; 32]).unwrap(),
+            next_per_commitment_point: dumb_pubkey!(),
+            next_local_nonce: Some(partial_sig.nonce),
+            unknown_tlvs: none!(),
+        };
+        let data = revoke_and_ack.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 32 + 33 + 1 + 1 + 66);
+        assert_eq!(
+            RevokeAndAck::lightning_deserialize(&data).unwrap(),
+            revoke_and_ack
+        );
+    }
+
+    #[test]
+    fn rgb_asset_tlvs() {
+        let asset = AssetAmount {
+            asset_id: AssetId::from_inner([1u8; 32]),
+            amount: 100,
+        };
+
+        let mut open_channel = OpenChannel::dumb_default();
+        let len = open_channel.lightning_serialize().unwrap().len();
+        open_channel.funding_assets = Some(vec![asset]);
+        let data = open_channel.lightning_serialize().unwrap();
+        // Odd TLV type 65537 encoded as BigSize
+        assert_eq!(&data[len..len + 5], &[0xfe, 0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(
+            OpenChannel::lightning_deserialize(&data).unwrap(),
+            open_channel
+        );
+    }
 }
@@ -15,8 +15,12 @@
 
 //! Bolt 7 Gossip messages
 
-use amplify::Slice32;
+use std::io;
+
+use amplify::{Slice32, Wrapper};
 use internet2::addr::NodeId;
+use internet2::tlv;
+use lightning_encoding::{BigSize, LightningDecode, LightningEncode};
 use secp256k1::ecdsa::Signature;
 
 use super::{
@@ -89,6 +93,9 @@ pub struct ChannelAnnouncement {
 
     /// Bitcoin key 2
     pub bitcoin_key_2: NodeId,
+
+    /// Extension data following the known fields
+    pub extra_data: ExtraData,
 }
 
 /// This gossip message allows a node to indicate extra data associated with it,
@@ -119,6 +126,9 @@ pub struct NodeAnnouncements {
 
     /// Node address
     pub addresses: AddressList,
+
+    /// Extension data following the known fields
+    pub extra_data: ExtraData,
 }
 
 /// After a channel has been initially announced, each side independently
@@ -128,7 +138,7 @@ pub struct NodeAnnouncements {
 /// which end of the channel it's on (origin or final). A node can do this
 /// multiple times, in order to change fees.
 // TODO: Do custom encoding due to `message_flags` and `option_channel_htlc_max`
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Display)]
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
 #[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
 #[display("channel_id({chain_hash}, {short_channel_id}, {timestamp}, ...)")]
@@ -167,19 +177,526 @@ pub struct ChannelUpdate {
 
     /// Used only if `option_channel_htlc_max` in `message_flags` is set
     pub htlc_maximum_msat: u64,
+
+    /// Extension data following the known fields
+    pub extra_data: ExtraData,
+}
+
+/// Data following the known fields of a gossip message, which may be added by
+/// the future protocol versions. They are covered by the message signature,
+/// so they must be kept for the signature to be verifiable.
+#[derive(Wrapper, Clone, PartialEq, Eq, Hash, Debug, Default, From)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+pub struct ExtraData(Vec<u8>);
+
+impl LightningEncode for ExtraData {
+    fn lightning_encode<E: io::Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, lightning_encoding::Error> {
+        e.write_all(&self.0)?;
+        Ok(self.0.len())
+    }
+}
+
+impl LightningDecode for ExtraData {
+    fn lightning_decode<D: io::Read>(
+        mut d: D,
+    ) -> Result<Self, lightning_encoding::Error> {
+        let mut data = vec![];
+        d.read_to_end(&mut data)?;
+        Ok(ExtraData(data))
+    }
+}
+
+/// Maximum size of a decompressed gossip query array, in bytes. Zlib may
+/// compress repeated data more than a thousand times, so arrays inflating
+/// beyond this size are rejected before they exhaust memory.
+pub const MAX_INFLATED_LEN: usize = 1 << 20;
+
+/// Encoding type of the arrays used in gossip queries
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[repr(u8)]
+pub enum EncodingType {
+    /// Array elements follow each other without compression
+    #[display("uncompressed")]
+    Uncompressed = 0,
+
+    /// Array is compressed with zlib deflate
+    #[display("zlib")]
+    Zlib = 1,
+}
+
+impl Default for EncodingType {
+    fn default() -> Self {
+        EncodingType::Uncompressed
+    }
+}
+
+impl EncodingType {
+    /// Prepends encoding type to the array data, compressing them if needed
+    fn encode(self, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![self as u8];
+        match self {
+            EncodingType::Uncompressed => bytes.extend_from_slice(data),
+            EncodingType::Zlib => {
+                bytes.extend(deflate::deflate_bytes_zlib(data))
+            }
+        }
+        bytes
+    }
+
+    /// Detects encoding type of the array and decompresses it, checking that
+    /// the array consists of `item_len`-sized elements. Empty data are
+    /// treated as an empty uncompressed array.
+    fn decode(
+        bytes: &[u8],
+        item_len: usize,
+    ) -> Result<(EncodingType, Vec<u8>), lightning_encoding::Error> {
+        let (encoding, data) = match bytes.split_first() {
+            None => return Ok((EncodingType::Uncompressed, vec![])),
+            Some((0, data)) => (EncodingType::Uncompressed, data.to_vec()),
+            Some((1, data)) => (EncodingType::Zlib, inflate_bounded(data)?),
+            Some((encoding, _)) => {
+                return Err(lightning_encoding::Error::DataIntegrityError(
+                    format!("unknown gossip query encoding type {}", encoding),
+                ))
+            }
+        };
+        if data.len() % item_len != 0 {
+            return Err(lightning_encoding::Error::DataIntegrityError(
+                format!(
+                    "gossip query array length {} is not proportional to {} \
+                     bytes",
+                    data.len(),
+                    item_len
+                ),
+            ));
+        }
+        Ok((encoding, data))
+    }
+}
+
+/// Decompresses zlib data, failing if the output exceeds [`MAX_INFLATED_LEN`]
+fn inflate_bounded(data: &[u8]) -> Result<Vec<u8>, lightning_encoding::Error> {
+    let mut stream = inflate::InflateStream::from_zlib();
+    let mut inflated = vec![];
+    let mut pos = 0;
+    loop {
+        let (read, bytes) = stream
+            .update(&data[pos..])
+            .map_err(lightning_encoding::Error::DataIntegrityError)?;
+        if bytes.is_empty() {
+            break;
+        }
+        if inflated.len() + bytes.len() > MAX_INFLATED_LEN {
+            return Err(lightning_encoding::Error::DataIntegrityError(
+                format!(
+                    "decompressed gossip query array exceeds {} bytes",
+                    MAX_INFLATED_LEN
+                ),
+            ));
+        }
+        inflated.extend_from_slice(bytes);
+        pos += read;
+    }
+    Ok(inflated)
+}
+
+/// Implements strict encoding for the gossip query data types by wrapping
+/// their lightning encoding into a byte vector
+macro_rules! strict_encoding_via_lightning {
+    ($ty:ty) => {
+        #[cfg(feature = "strict_encoding")]
+        impl strict_encoding::StrictEncode for $ty {
+            fn strict_encode<E: io::Write>(
+                &self,
+                e: E,
+            ) -> Result<usize, strict_encoding::Error> {
+                self.lightning_serialize()
+                    .map_err(|err| {
+                        strict_encoding::Error::DataIntegrityError(
+                            err.to_string(),
+                        )
+                    })?
+                    .strict_encode(e)
+            }
+        }
+
+        #[cfg(feature = "strict_encoding")]
+        impl strict_encoding::StrictDecode for $ty {
+            fn strict_decode<D: io::Read>(
+                d: D,
+            ) -> Result<Self, strict_encoding::Error> {
+                let data = Vec::<u8>::strict_decode(d)?;
+                <$ty>::lightning_deserialize(&data).map_err(|err| {
+                    strict_encoding::Error::DataIntegrityError(err.to_string())
+                })
+            }
+        }
+    };
+}
+
+/// List of short channel ids used in `query_short_channel_ids` and
+/// `reply_channel_range` messages
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct EncodedShortIds {
+    /// Encoding used for the list
+    pub encoding: EncodingType,
+
+    /// Short channel ids, in ascending order
+    pub short_ids: Vec<ShortChannelId>,
+}
+
+impl EncodedShortIds {
+    /// Constructs uncompressed list of short channel ids
+    pub fn uncompressed(short_ids: Vec<ShortChannelId>) -> EncodedShortIds {
+        EncodedShortIds {
+            encoding: EncodingType::Uncompressed,
+            short_ids,
+        }
+    }
+}
+
+impl LightningEncode for EncodedShortIds {
+    fn lightning_encode<E: io::Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, lightning_encoding::Error> {
+        let mut data = Vec::with_capacity(self.short_ids.len() * 8);
+        for short_id in &self.short_ids {
+            short_id.lightning_encode(&mut data)?;
+        }
+        // Unlike other byte strings, encoded short ids are prefixed with u16
+        // length
+        let bytes = self.encoding.encode(&data);
+        let len = u16::try_from(bytes.len()).map_err(|_| {
+            lightning_encoding::Error::TooLargeData(bytes.len())
+        })?;
+        len.lightning_encode(&mut e)?;
+        e.write_all(&bytes)?;
+        Ok(bytes.len() + 2)
+    }
+}
+
+impl LightningDecode for EncodedShortIds {
+    fn lightning_decode<D: io::Read>(
+        mut d: D,
+    ) -> Result<Self, lightning_encoding::Error> {
+        let len = u16::lightning_decode(&mut d)?;
+        let mut bytes = vec![0u8; len as usize];
+        d.read_exact(&mut bytes)?;
+        let (encoding, data) = EncodingType::decode(&bytes, 8)?;
+        let short_ids = data
+            .chunks(8)
+            .map(ShortChannelId::lightning_deserialize)
+            .collect::<Result<_, _>>()?;
+        Ok(EncodedShortIds {
+            encoding,
+            short_ids,
+        })
+    }
+}
+
+strict_encoding_via_lightning!(EncodedShortIds);
+
+/// Timestamps of the latest `channel_update` messages for both directions of
+/// a channel; zero if there is no update for the direction
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("{timestamp_node_id_1}/{timestamp_node_id_2}")]
+pub struct UpdateTimestamps {
+    /// Timestamp of the update sent by the first channel node
+    pub timestamp_node_id_1: u32,
+
+    /// Timestamp of the update sent by the second channel node
+    pub timestamp_node_id_2: u32,
+}
+
+/// Timestamps of channel updates for each of the short channel ids in
+/// `reply_channel_range` message
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct EncodedTimestamps {
+    /// Encoding used for the timestamps
+    pub encoding: EncodingType,
+
+    /// Timestamps, in the order of the short channel ids in the reply
+    pub timestamps: Vec<UpdateTimestamps>,
+}
+
+impl LightningEncode for EncodedTimestamps {
+    fn lightning_encode<E: io::Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, lightning_encoding::Error> {
+        let mut data = Vec::with_capacity(self.timestamps.len() * 8);
+        for timestamps in &self.timestamps {
+            timestamps.lightning_encode(&mut data)?;
+        }
+        let bytes = self.encoding.encode(&data);
+        e.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl LightningDecode for EncodedTimestamps {
+    fn lightning_decode<D: io::Read>(
+        mut d: D,
+    ) -> Result<Self, lightning_encoding::Error> {
+        let mut bytes = vec![];
+        d.read_to_end(&mut bytes)?;
+        let (encoding, data) = EncodingType::decode(&bytes, 8)?;
+        let timestamps = data
+            .chunks(8)
+            .map(UpdateTimestamps::lightning_deserialize)
+            .collect::<Result<_, _>>()?;
+        Ok(EncodedTimestamps {
+            encoding,
+            timestamps,
+        })
+    }
+}
+
+strict_encoding_via_lightning!(EncodedTimestamps);
+
+/// CRC32C checksums of the latest `channel_update` messages for both
+/// directions of a channel; zero if there is no update for the direction.
+///
+/// See [`ChannelUpdate::checksum`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("{checksum_node_id_1:#010x}/{checksum_node_id_2:#010x}")]
+pub struct UpdateChecksums {
+    /// Checksum of the update sent by the first channel node
+    pub checksum_node_id_1: u32,
+
+    /// Checksum of the update sent by the second channel node
+    pub checksum_node_id_2: u32,
+}
+
+/// Checksums of channel updates for each of the short channel ids in
+/// `reply_channel_range` message
+#[derive(Wrapper, Clone, PartialEq, Eq, Hash, Debug, Default, From)]
+pub struct ChecksumList(Vec<UpdateChecksums>);
+
+impl LightningEncode for ChecksumList {
+    fn lightning_encode<E: io::Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, lightning_encoding::Error> {
+        self.0.iter().try_fold(0usize, |len, checksums| {
+            Ok(len + checksums.lightning_encode(&mut e)?)
+        })
+    }
+}
+
+impl LightningDecode for ChecksumList {
+    fn lightning_decode<D: io::Read>(
+        mut d: D,
+    ) -> Result<Self, lightning_encoding::Error> {
+        let mut bytes = vec![];
+        d.read_to_end(&mut bytes)?;
+        // Unlike other arrays, checksums are never compressed
+        if bytes.len() % 8 != 0 {
+            return Err(lightning_encoding::Error::DataIntegrityError(
+                format!(
+                    "checksum list length {} is not proportional to 8 bytes",
+                    bytes.len()
+                ),
+            ));
+        }
+        bytes
+            .chunks(8)
+            .map(UpdateChecksums::lightning_deserialize)
+            .collect::<Result<_, _>>()
+            .map(ChecksumList)
+    }
+}
+
+strict_encoding_via_lightning!(ChecksumList);
+
+/// Flags of `query_short_channel_ids` specifying which gossip messages are
+/// requested for a channel
+#[derive(
+    Wrapper, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default,
+    From
+)]
+pub struct QueryFlags(u64);
+
+impl QueryFlags {
+    /// Requests `channel_announcement`
+    pub const CHANNEL_ANNOUNCEMENT: QueryFlags = QueryFlags(1 << 0);
+    /// Requests `channel_update` of the first channel node
+    pub const CHANNEL_UPDATE_1: QueryFlags = QueryFlags(1 << 1);
+    /// Requests `channel_update` of the second channel node
+    pub const CHANNEL_UPDATE_2: QueryFlags = QueryFlags(1 << 2);
+    /// Requests `node_announcement` of the first channel node
+    pub const NODE_ANNOUNCEMENT_1: QueryFlags = QueryFlags(1 << 3);
+    /// Requests `node_announcement` of the second channel node
+    pub const NODE_ANNOUNCEMENT_2: QueryFlags = QueryFlags(1 << 4);
+    /// Requests all gossip messages for the channel
+    pub const ALL: QueryFlags = QueryFlags(0b11111);
+
+    /// Detects whether all the `flags` are set
+    #[inline]
+    pub fn contains(self, flags: QueryFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl std::ops::BitOr for QueryFlags {
+    type Output = QueryFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        QueryFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for QueryFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0
+    }
+}
+
+/// Query flags for each of the short channel ids in
+/// `query_short_channel_ids` message
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct EncodedQueryFlags {
+    /// Encoding used for the flags
+    pub encoding: EncodingType,
+
+    /// Flags, in the order of the short channel ids in the query
+    pub flags: Vec<QueryFlags>,
+}
+
+impl LightningEncode for EncodedQueryFlags {
+    fn lightning_encode<E: io::Write>(
+        &self,
+        mut e: E,
+    ) -> Result<usize, lightning_encoding::Error> {
+        let mut data = vec![];
+        for flags in &self.flags {
+            BigSize::from(flags.0).lightning_encode(&mut data)?;
+        }
+        let bytes = self.encoding.encode(&data);
+        e.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+}
+
+impl LightningDecode for EncodedQueryFlags {
+    fn lightning_decode<D: io::Read>(
+        mut d: D,
+    ) -> Result<Self, lightning_encoding::Error> {
+        let mut bytes = vec![];
+        d.read_to_end(&mut bytes)?;
+        let (encoding, data) = EncodingType::decode(&bytes, 1)?;
+        let mut cursor = io::Cursor::new(&data);
+        let mut flags = vec![];
+        while (cursor.position() as usize) < data.len() {
+            let value = BigSize::lightning_decode(&mut cursor)?;
+            flags.push(QueryFlags(value.into_inner()));
+        }
+        Ok(EncodedQueryFlags { encoding, flags })
+    }
+}
+
+strict_encoding_via_lightning!(EncodedQueryFlags);
+
+/// Options of `query_channel_range` requesting additional information about
+/// the channels
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct QueryOption {
+    /// Sender wants timestamps of the channel updates
+    pub want_timestamps: bool,
+
+    /// Sender wants checksums of the channel updates
+    pub want_checksums: bool,
+}
+
+impl LightningEncode for QueryOption {
+    fn lightning_encode<E: io::Write>(
+        &self,
+        e: E,
+    ) -> Result<usize, lightning_encoding::Error> {
+        let value =
+            self.want_timestamps as u64 | (self.want_checksums as u64) << 1;
+        BigSize::from(value).lightning_encode(e)
+    }
+}
+
+impl LightningDecode for QueryOption {
+    fn lightning_decode<D: io::Read>(
+        d: D,
+    ) -> Result<Self, lightning_encoding::Error> {
+        let value = BigSize::lightning_decode(d)?.into_inner();
+        Ok(QueryOption {
+            want_timestamps: value & 0b01 != 0,
+            want_checksums: value & 0b10 != 0,
+        })
+    }
+}
+
+strict_encoding_via_lightning!(QueryOption);
+
+impl ChannelUpdate {
+    /// Computes CRC32C checksum of the message with `signature` and
+    /// `timestamp` fields removed, used by `gossip_queries_ex` to detect
+    /// whether channel updates differ
+    pub fn checksum(&self) -> u32 {
+        let data = self
+            .lightning_serialize()
+            .expect("in-memory encoding of channel update can't fail");
+        // Signature is followed by 32-byte chain hash and 8-byte short
+        // channel id, which are followed by the timestamp
+        crc32c(&[&data[64..104], &data[108..]].concat())
+    }
+}
+
+/// Computes CRC32C (Castagnoli) checksum
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
 }
 
 /// Extended Gossip messages
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
 #[display("query_short_channel_ids({chain_hash}, {short_ids:#?}, ...tlvs)")]
 pub struct QueryShortChannelIds {
     /// chain hash
     pub chain_hash: Slice32,
 
     /// short ids to query
-    pub short_ids: Vec<ShortChannelId>,
+    pub short_ids: EncodedShortIds,
+
+    /// Flags specifying which gossip messages are requested for each of the
+    /// short ids
+    #[lightning_encoding(tlv = 1)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
+    pub query_flags: Option<EncodedQueryFlags>,
+
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
@@ -194,9 +711,14 @@ pub struct ReplyShortChannelIdsEnd {
     pub full_information: u8,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
 #[display(
     "query_channel_range({chain_hash}, {first_blocknum}, {number_of_blocks}, \
      ...tlvs)"
@@ -210,16 +732,25 @@ pub struct QueryChannelRange {
 
     /// number of blocks
     pub number_of_blocks: u32,
-    /* TODO: define custom type
+
+    /// Additional information requested for the channels
     #[lightning_encoding(tlv = 1)]
-    #[cfg_attr(feature = "strict_encoding", strict_encoding(tlv = 1))]
-    pub query_short_channel_ids_tlvs: (),
-     */
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
+    pub query_option: Option<QueryOption>,
+
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
 #[derive(LightningEncode, LightningDecode)]
-#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
 #[display(
     "reply_channel_range({chain_hash}, {first_blocknum}, {number_of_blocks}, \
      ...)"
@@ -238,10 +769,23 @@ pub struct ReplyChannelRange {
     pub full_information: u8,
 
     /// encoded short ids
-    pub encoded_short_ids: Vec<ShortChannelId>,
-    /* reply channel range tlvs
-    TODO: Implement channel range data types
-    *pub reply_channel_range_tlvs: BTreeMap<u8, Vec<u8>>, */
+    pub encoded_short_ids: EncodedShortIds,
+
+    /// Timestamps of the channel updates, if requested with
+    /// [`QueryOption::want_timestamps`]
+    #[lightning_encoding(tlv = 1)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 1))]
+    pub timestamps: Option<EncodedTimestamps>,
+
+    /// Checksums of the channel updates, if requested with
+    /// [`QueryOption::want_checksums`]
+    #[lightning_encoding(tlv = 3)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(tlv = 3))]
+    pub checksums: Option<ChecksumList>,
+
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
@@ -261,3 +805,208 @@ pub struct GossipTimestampFilter {
     /// timestamp range
     pub timestamp_range: u32,
 }
+
+#[cfg(test)]
+mod test {
+    use internet2::TypedEnum;
+
+    use super::*;
+    use crate::bolt::Messages;
+
+    fn scid(block_height: u32, tx_index: u32) -> ShortChannelId {
+        ShortChannelId::with(block_height, tx_index, 0).unwrap()
+    }
+
+    #[test]
+    fn query_channel_range_tlv() {
+        let query = QueryChannelRange {
+            chain_hash: Slice32::from([0xAA; 32]),
+            first_blocknum: 700_000,
+            number_of_blocks: 1000,
+            query_option: Some(QueryOption {
+                want_timestamps: true,
+                want_checksums: true,
+            }),
+            unknown_tlvs: none!(),
+        };
+        let data = Messages::QueryChannelRange(query.clone()).serialize();
+        assert_eq!(&data[..2], &[0x01, 0x07]);
+        assert_eq!(&data[34..], &[
+            // first blocknum
+            0x00, 0x0A, 0xAE, 0x60, //
+            // number of blocks
+            0x00, 0x00, 0x03, 0xE8, //
+            // query option TLV
+            0x01, 0x01, 0x03
+        ]);
+        assert_eq!(
+            QueryChannelRange::lightning_deserialize(&data[2..]).unwrap(),
+            query
+        );
+    }
+
+    #[test]
+    fn encoded_short_ids() {
+        let short_ids = (1..=100).map(|n| scid(700_000, n)).collect::<Vec<_>>();
+        let uncompressed = EncodedShortIds::uncompressed(short_ids.clone());
+        let data = uncompressed.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 2 + 1 + 800);
+        assert_eq!(data[2], 0);
+
+        let zlib = EncodedShortIds {
+            encoding: EncodingType::Zlib,
+            short_ids,
+        };
+        let compressed = zlib.lightning_serialize().unwrap();
+        assert_eq!(compressed[2], 1);
+        assert!(compressed.len() < data.len());
+        assert_eq!(
+            EncodedShortIds::lightning_deserialize(&compressed).unwrap(),
+            zlib
+        );
+
+        // Array length must be proportional to the short channel id size
+        assert!(
+            EncodedShortIds::lightning_deserialize([0, 4, 0, 1, 2, 3]).is_err()
+        );
+        // Unknown encoding type
+        assert!(EncodedShortIds::lightning_deserialize([0, 1, 2]).is_err());
+
+        // Decompression bombs are rejected
+        let bomb = EncodingType::Zlib.encode(&[0u8; MAX_INFLATED_LEN + 8]);
+        let mut data = (bomb.len() as u16).to_be_bytes().to_vec();
+        data.extend(bomb);
+        assert!(EncodedShortIds::lightning_deserialize(&data).is_err());
+        let fit = EncodingType::Zlib.encode(&[0u8; MAX_INFLATED_LEN]);
+        let mut data = (fit.len() as u16).to_be_bytes().to_vec();
+        data.extend(fit);
+        assert_eq!(
+            EncodedShortIds::lightning_deserialize(&data)
+                .unwrap()
+                .short_ids
+                .len(),
+            MAX_INFLATED_LEN / 8
+        );
+    }
+
+    #[test]
+    fn reply_channel_range_tlvs() {
+        let reply = ReplyChannelRange {
+            chain_hash: Slice32::from([0xAA; 32]),
+            first_blocknum: 700_000,
+            number_of_blocks: 1000,
+            full_information: 1,
+            encoded_short_ids: EncodedShortIds {
+                encoding: EncodingType::Zlib,
+                short_ids: vec![scid(700_000, 1), scid(700_001, 2)],
+            },
+            timestamps: Some(EncodedTimestamps {
+                encoding: EncodingType::Zlib,
+                timestamps: vec![
+                    UpdateTimestamps {
+                        timestamp_node_id_1: 10,
+                        timestamp_node_id_2: 0,
+                    },
+                    UpdateTimestamps {
+                        timestamp_node_id_1: 11,
+                        timestamp_node_id_2: 12,
+                    },
+                ],
+            }),
+            checksums: Some(ChecksumList::from(vec![
+                UpdateChecksums {
+                    checksum_node_id_1: 0xDEADBEEF,
+                    checksum_node_id_2: 0,
+                },
+                UpdateChecksums {
+                    checksum_node_id_1: 1,
+                    checksum_node_id_2: 2,
+                },
+            ])),
+            unknown_tlvs: none!(),
+        };
+        let data = Messages::ReplyChannelRange(reply.clone()).serialize();
+        assert_eq!(
+            ReplyChannelRange::lightning_deserialize(&data[2..]).unwrap(),
+            reply
+        );
+
+        let query = QueryShortChannelIds {
+            chain_hash: Slice32::from([0xAA; 32]),
+            short_ids: EncodedShortIds::uncompressed(vec![
+                scid(700_000, 1),
+                scid(700_001, 2),
+            ]),
+            query_flags: Some(EncodedQueryFlags {
+                encoding: EncodingType::Uncompressed,
+                flags: vec![
+                    QueryFlags::ALL,
+                    QueryFlags::CHANNEL_UPDATE_1 | QueryFlags::CHANNEL_UPDATE_2,
+                ],
+            }),
+            unknown_tlvs: none!(),
+        };
+        let data = Messages::QueryShortChannelIds(query.clone()).serialize();
+        // Query flags TLV: type, length, encoding and two single-byte flags
+        assert_eq!(&data[data.len() - 5..], &[0x01, 0x03, 0x00, 0x1F, 0x06]);
+        assert_eq!(
+            QueryShortChannelIds::lightning_deserialize(&data[2..]).unwrap(),
+            query
+        );
+    }
+
+    #[test]
+    fn update_checksum() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+
+        let mut update = ChannelUpdate {
+            signature: Signature::from_compact(&[1u8; 64]).unwrap(),
+            chain_hash: Slice32::from([0xAA; 32]),
+            short_channel_id: scid(700_000, 1),
+            timestamp: 10,
+            message_flags: 1,
+            channel_flags: 0,
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: 1000,
+            fee_base_msat: 1000,
+            fee_proportional_millionths: 100,
+            htlc_maximum_msat: 100_000_000,
+            extra_data: none!(),
+        };
+        let checksum = update.checksum();
+        update.signature = Signature::from_compact(&[2u8; 64]).unwrap();
+        update.timestamp = 11;
+        assert_eq!(update.checksum(), checksum);
+        update.fee_base_msat = 1001;
+        assert_ne!(update.checksum(), checksum);
+        let checksum = update.checksum();
+        update.extra_data = ExtraData::from(vec![0xDE, 0xAD]);
+        assert_ne!(update.checksum(), checksum);
+    }
+
+    #[test]
+    fn gossip_extra_data() {
+        let update = ChannelUpdate {
+            signature: Signature::from_compact(&[1u8; 64]).unwrap(),
+            chain_hash: Slice32::from([0xAA; 32]),
+            short_channel_id: scid(700_000, 1),
+            timestamp: 10,
+            message_flags: 1,
+            channel_flags: 0,
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: 1000,
+            fee_base_msat: 1000,
+            fee_proportional_millionths: 100,
+            htlc_maximum_msat: 100_000_000,
+            extra_data: ExtraData::from(vec![0xDE, 0xAD, 0xBE, 0xEF]),
+        };
+        let data = Messages::ChannelUpdate(update.clone())
+            .lightning_serialize()
+            .unwrap();
+        assert_eq!(&data[data.len() - 4..], &[0xDE, 0xAD, 0xBE, 0xEF]);
+        match Messages::lightning_deserialize(&data).unwrap() {
+            Messages::ChannelUpdate(decoded) => assert_eq!(decoded, update),
+            _ => panic!("channel_update must be decoded"),
+        }
+    }
+}
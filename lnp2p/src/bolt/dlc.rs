@@ -0,0 +1,178 @@
+// LNP P2P library, plmeneting both bolt (BOLT) and Bifrost P2P messaging
+// system for Lightning network protocol (LNP)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Experimental discreet log contract (DLC) channel messages.
+//!
+//! These messages are not a part of BOLT specifications; they use custom odd
+//! message types, so peers not supporting DLCs will ignore them.
+
+use secp256k1::{PublicKey, SecretKey};
+
+use super::{AdaptorSignature, ChannelId};
+
+/// Announcement of a future event by an oracle, committing to the nonce which
+/// will be used to sign the event outcome
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[display("{event_id}@{oracle_pubkey}")]
+pub struct OracleAnnouncement {
+    /// Public key of the oracle; only its x coordinate is used
+    pub oracle_pubkey: PublicKey,
+
+    /// Nonce point which will be used in the attestation signature; only its
+    /// x coordinate is used
+    pub nonce_point: PublicKey,
+
+    /// Oracle-specific event identifier
+    pub event_id: String,
+
+    /// List of all possible event outcomes
+    pub outcomes: Vec<String>,
+}
+
+/// Distribution of the contract funds for a single outcome
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[display("{offerer_sat}:{accepter_sat}")]
+pub struct DlcPayout {
+    /// Amount paid to the node which has offered the contract
+    pub offerer_sat: u64,
+
+    /// Amount paid to the node which has accepted the contract
+    pub accepter_sat: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("dlc_offer({channel_id}, {announcement}, ...)")]
+pub struct DlcOffer {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Oracle announcement for the event the contract is based on
+    pub announcement: OracleAnnouncement,
+
+    /// Amount of the offering node funds locked in the contract
+    pub offerer_collateral_sat: u64,
+
+    /// Amount of the accepting node funds locked in the contract
+    pub accepter_collateral_sat: u64,
+
+    /// Payouts, one per announced outcome, in the order of outcomes
+    pub payouts: Vec<DlcPayout>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("dlc_accept({channel_id}, ...signatures)")]
+pub struct DlcAccept {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Adaptor signatures of the accepting node for contract execution
+    /// transactions, one per outcome, each encrypted with the oracle
+    /// attestation point of the outcome
+    pub cet_adaptor_signatures: Vec<AdaptorSignature>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("dlc_sign({channel_id}, ...signatures)")]
+pub struct DlcSign {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Adaptor signatures of the offering node for contract execution
+    /// transactions, one per outcome
+    pub cet_adaptor_signatures: Vec<AdaptorSignature>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("dlc_settle({channel_id}, {outcome_index}, ...)")]
+pub struct DlcSettle {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Index of the outcome attested by the oracle
+    pub outcome_index: u16,
+
+    /// Scalar part of the oracle attestation signature, which is the discrete
+    /// logarithm of the outcome attestation point
+    pub attestation: SecretKey,
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+    use lightning_encoding::{LightningDecode, LightningEncode};
+
+    use super::*;
+    use crate::bolt::Messages;
+
+    #[test]
+    fn dlc_messages() {
+        let key = PublicKey::from_secret_key(
+            secp256k1::SECP256K1,
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer = DlcOffer {
+            channel_id: ChannelId::with(Txid::all_zeros(), 1),
+            announcement: OracleAnnouncement {
+                oracle_pubkey: key,
+                nonce_point: key,
+                event_id: s!("btcusd"),
+                outcomes: vec![s!("up"), s!("down")],
+            },
+            offerer_collateral_sat: 1000,
+            accepter_collateral_sat: 1000,
+            payouts: vec![
+                DlcPayout {
+                    offerer_sat: 2000,
+                    accepter_sat: 0,
+                },
+                DlcPayout {
+                    offerer_sat: 0,
+                    accepter_sat: 2000,
+                },
+            ],
+        };
+        let data = Messages::DlcOffer(offer.clone())
+            .lightning_serialize()
+            .unwrap();
+        assert_eq!(&data[..2], &33009u16.to_be_bytes());
+        assert!(matches!(
+            Messages::lightning_deserialize(&data).unwrap(),
+            Messages::DlcOffer(msg) if msg == offer
+        ));
+    }
+}
@@ -19,6 +19,8 @@ mod bolt2;
 mod bolt4;
 mod bolt7;
 mod bolt9;
+mod dlc;
+mod ptlc;
 mod types;
 
 use std::io;
@@ -32,9 +34,11 @@ pub use bolt7::*;
 pub use bolt9::{
     ChannelFeatures, Feature, FeatureContext, InitFeatures, UnknownFeatureError,
 };
+pub use dlc::*;
 use internet2::{CreateUnmarshaller, Payload, Unmarshall, Unmarshaller};
 use lightning_encoding::{self, LightningDecode, LightningEncode};
 use once_cell::sync::Lazy;
+pub use ptlc::*;
 pub use types::*;
 
 /// Default bolt Lightning port number
@@ -125,6 +129,59 @@ pub enum Messages {
     #[api(type = 39)]
     ClosingSigned(ClosingSigned),
 
+    /// This message initiates v2 channel establishment, where the funding
+    /// transaction is constructed interactively by both peers.
+    #[api(type = 64)]
+    OpenChannel2(OpenChannel2),
+
+    /// This message indicates acceptance of the channel opened with v2 channel
+    /// establishment protocol.
+    #[api(type = 65)]
+    AcceptChannel2(AcceptChannel2),
+
+    // 1.1. Interactive transaction construction
+    // -----------------------------------------
+    #[api(type = 66)]
+    TxAddInput(TxAddInput),
+
+    #[api(type = 67)]
+    TxAddOutput(TxAddOutput),
+
+    #[api(type = 68)]
+    TxRemoveInput(TxRemoveInput),
+
+    #[api(type = 69)]
+    TxRemoveOutput(TxRemoveOutput),
+
+    #[api(type = 70)]
+    TxComplete(TxComplete),
+
+    #[api(type = 71)]
+    TxSignatures(TxSignatures),
+
+    #[api(type = 72)]
+    TxInitRbf(TxInitRbf),
+
+    #[api(type = 73)]
+    TxAckRbf(TxAckRbf),
+
+    #[api(type = 74)]
+    TxAbort(TxAbort),
+
+    // 1.2. Quiescence and splicing
+    // ----------------------------
+    #[api(type = 2)]
+    Stfu(Stfu),
+
+    #[api(type = 80)]
+    SpliceInit(SpliceInit),
+
+    #[api(type = 81)]
+    SpliceAck(SpliceAck),
+
+    #[api(type = 77)]
+    SpliceLocked(SpliceLocked),
+
     // 2. Channel operations
     // ---------------------
     #[api(type = 128)]
@@ -151,6 +208,34 @@ pub enum Messages {
     #[api(type = 136)]
     ChannelReestablish(ChannelReestablish),
 
+    // 3. Experimental PTLC channel operations
+    // ---------------------------------------
+    #[api(type = 33001)]
+    UpdateAddPtlc(UpdateAddPtlc),
+
+    #[api(type = 33003)]
+    UpdateFulfillPtlc(UpdateFulfillPtlc),
+
+    #[api(type = 33005)]
+    UpdateFailPtlc(UpdateFailPtlc),
+
+    #[api(type = 33007)]
+    PtlcSignatures(PtlcSignatures),
+
+    // 4. Experimental DLC channel operations
+    // --------------------------------------
+    #[api(type = 33009)]
+    DlcOffer(DlcOffer),
+
+    #[api(type = 33011)]
+    DlcAccept(DlcAccept),
+
+    #[api(type = 33013)]
+    DlcSign(DlcSign),
+
+    #[api(type = 33015)]
+    DlcSettle(DlcSettle),
+
     // Part III. Gossip protocol (BOLT-7)
     // ==================================
     /// This is a direct message between the two endpoints of a channel and
@@ -0,0 +1,184 @@
+// LNP P2P library, plmeneting both bolt (BOLT) and Bifrost P2P messaging
+// system for Lightning network protocol (LNP)
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Experimental point-time-locked contract (PTLC) channel update messages.
+//!
+//! These messages are not a part of BOLT specifications; they use custom odd
+//! message types, so peers not supporting PTLCs will ignore them.
+
+use internet2::presentation::sphinx::Onion;
+use internet2::tlv;
+use secp256k1::{PublicKey, SecretKey};
+
+use super::{ChannelId, PaymentOnion, PAYMENT_SPHINX_LEN};
+
+/// Schnorr adaptor signature, consisting of the compressed adapted nonce point
+/// `R' = R + T` and the pre-signature scalar `s'`.
+#[derive(
+    Wrapper,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
+    From,
+    LightningEncode,
+    LightningDecode
+)]
+#[cfg_attr(feature = "strict_encoding", derive(StrictEncode, StrictDecode))]
+pub struct AdaptorSignature([u8; 65]);
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(
+    feature = "strict_encoding",
+    derive(NetworkEncode, NetworkDecode),
+    network_encoding(use_tlv)
+)]
+#[lightning_encoding(use_tlv)]
+#[display(
+    "update_add_ptlc({channel_id}, {ptlc_id}, {amount_msat}, {payment_point}, \
+     ...)"
+)]
+pub struct UpdateAddPtlc {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// The PTLC ID
+    pub ptlc_id: u64,
+
+    /// The PTLC value in milli-satoshi
+    pub amount_msat: u64,
+
+    /// The payment point, the discrete logarithm of which controls PTLC
+    /// redemption
+    pub payment_point: PublicKey,
+
+    /// The expiry height of the PTLC
+    pub cltv_expiry: u32,
+
+    /// An obfuscated list of hops and instructions for each hop along the
+    /// path. It commits to the PTLC by setting the serialized payment point as
+    /// associated data.
+    pub onion_routing_packet: Onion<PaymentOnion, PAYMENT_SPHINX_LEN>,
+
+    /// The rest of TLVs with unknown odd type ids
+    #[lightning_encoding(unknown_tlvs)]
+    #[cfg_attr(feature = "strict_encoding", network_encoding(unknown_tlvs))]
+    pub unknown_tlvs: tlv::Stream,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("update_fulfill_ptlc({channel_id}, {ptlc_id}, ...scalar)")]
+pub struct UpdateFulfillPtlc {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// The PTLC ID
+    pub ptlc_id: u64,
+
+    /// The discrete logarithm of the payment point, allowing PTLC redemption
+    pub payment_scalar: SecretKey,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("update_fail_ptlc({channel_id}, {ptlc_id}, ...reason)")]
+pub struct UpdateFailPtlc {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// The PTLC ID
+    pub ptlc_id: u64,
+
+    /// Opaque encrypted failure reason for the benefit of the original PTLC
+    /// initiator, the same as used by `update_fail_htlc`
+    pub reason: Vec<u8>,
+}
+
+/// Adaptor signatures for the second-level PTLC transactions of the receiving
+/// node commitment, which must accompany `commitment_signed` message when the
+/// commitment has PTLC outputs.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+#[derive(LightningEncode, LightningDecode)]
+#[cfg_attr(feature = "strict_encoding", derive(NetworkEncode, NetworkDecode))]
+#[display("ptlc_signatures({channel_id}, ...signatures)")]
+pub struct PtlcSignatures {
+    /// The channel ID
+    pub channel_id: ChannelId,
+
+    /// Adaptor signatures, one per PTLC output of the commitment transaction,
+    /// in the order of PTLC ids
+    pub adaptor_signatures: Vec<AdaptorSignature>,
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Wrapper;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Txid;
+    use lightning_encoding::{LightningDecode, LightningEncode};
+
+    use super::*;
+    use crate::bolt::Messages;
+
+    #[test]
+    fn ptlc_messages() {
+        let channel_id = ChannelId::with(Txid::all_zeros(), 1);
+
+        let fulfill = UpdateFulfillPtlc {
+            channel_id,
+            ptlc_id: 7,
+            payment_scalar: SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        };
+        let data = Messages::UpdateFulfillPtlc(fulfill.clone())
+            .lightning_serialize()
+            .unwrap();
+        assert_eq!(&data[..2], &33003u16.to_be_bytes());
+        assert!(matches!(
+            Messages::lightning_deserialize(&data).unwrap(),
+            Messages::UpdateFulfillPtlc(msg) if msg == fulfill
+        ));
+
+        let fail = UpdateFailPtlc {
+            channel_id,
+            ptlc_id: 7,
+            reason: vec![0xde, 0xad],
+        };
+        let data = Messages::UpdateFailPtlc(fail.clone())
+            .lightning_serialize()
+            .unwrap();
+        assert_eq!(&data[..2], &33005u16.to_be_bytes());
+        assert!(matches!(
+            Messages::lightning_deserialize(&data).unwrap(),
+            Messages::UpdateFailPtlc(msg) if msg == fail
+        ));
+
+        let signatures = PtlcSignatures {
+            channel_id,
+            adaptor_signatures: vec![AdaptorSignature::from_inner([2u8; 65])],
+        };
+        let data = signatures.lightning_serialize().unwrap();
+        assert_eq!(data.len(), 32 + 1 + 65);
+        assert_eq!(
+            PtlcSignatures::lightning_deserialize(&data).unwrap(),
+            signatures
+        );
+    }
+}
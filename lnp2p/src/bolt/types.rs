@@ -20,7 +20,7 @@ use amplify::hex::{self, FromHex};
 use amplify::num::error::OverflowError;
 use amplify::num::u24;
 use amplify::{Display, DumbDefault, Slice32, Wrapper};
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::Txid;
 use chrono::{DateTime, Local, TimeZone, Utc};
 use lightning_encoding::{self, LightningDecode, LightningEncode};
@@ -153,6 +153,30 @@ impl FromHex for ChannelId {
 }
 
 impl ChannelId {
+    /// Constructs channel id for the channels established with v2 protocol
+    /// (interactive transaction construction): `SHA256(lesser-revocation-
+    /// basepoint || greater-revocation-basepoint)`, where basepoints are
+    /// ordered lexicographically by their compressed serialization.
+    pub fn with_revocation_basepoints(
+        local: secp256k1::PublicKey,
+        remote: secp256k1::PublicKey,
+    ) -> Self {
+        let (lesser, greater) = {
+            let local = local.serialize();
+            let remote = remote.serialize();
+            if local <= remote {
+                (local, remote)
+            } else {
+                (remote, local)
+            }
+        };
+        let mut engine = sha256::Hash::engine();
+        engine.input(&lesser);
+        engine.input(&greater);
+        let hash = sha256::Hash::from_engine(engine);
+        ChannelId::from_inner(Slice32::from_inner(hash.into_inner()))
+    }
+
     pub fn with(funding_txid: Txid, funding_vout: u16) -> Self {
         let mut slice = funding_txid.into_inner();
         let vout = funding_vout.to_be_bytes();
@@ -319,7 +343,17 @@ pub struct Alias(
     Slice32,
 );
 
+/// Minimal block height used in short channel id aliases. Blocks of this
+/// height will not be mined for the next three centuries, so aliases allocated
+/// from this range never collide with the real short channel ids.
+pub const SCID_ALIAS_MIN_HEIGHT: u32 = 16_000_000;
+
 /// Lightning network short channel Id as per BOLT7
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
 #[derive(
     Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Default,
     From
@@ -345,6 +379,32 @@ impl ShortChannelId {
             output_index,
         })
     }
+
+    /// Allocates random short channel id alias, which is used instead of the
+    /// real short channel id for zero-conf and private channels.
+    pub fn random_alias() -> Self {
+        let entropy = Slice32::random();
+        let entropy = entropy.as_inner();
+        let height =
+            u32::from_be_bytes([0, entropy[0], entropy[1], entropy[2]])
+                % (u24::MAX.as_u32() - SCID_ALIAS_MIN_HEIGHT + 1);
+        let tx_index =
+            u32::from_be_bytes([0, entropy[3], entropy[4], entropy[5]]);
+        let output_index = u16::from_be_bytes([entropy[6], entropy[7]]);
+        ShortChannelId::with(
+            SCID_ALIAS_MIN_HEIGHT + height,
+            tx_index,
+            output_index,
+        )
+        .expect("alias components are always within u24 range")
+    }
+
+    /// Detects whether short channel id is an alias allocated from
+    /// [`SCID_ALIAS_MIN_HEIGHT`] range
+    #[inline]
+    pub fn is_alias(self) -> bool {
+        self.block_height.as_u32() >= SCID_ALIAS_MIN_HEIGHT
+    }
 }
 
 #[derive(
@@ -0,0 +1,463 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use bitcoin_scripts::PubkeyScript;
+use internet2::addr::NodeId;
+use lnp2p::bolt::{ChannelType, InitFeatures, OpenChannel, OpenChannel2};
+
+use super::PeerParams;
+
+/// Channel proposed by a remote peer, which is passed to a
+/// [`ChannelAcceptor`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChannelProposal<'msg> {
+    /// Channel proposed with `open_channel` message
+    V1(&'msg OpenChannel),
+
+    /// Dual-funded channel proposed with `open_channel2` message
+    V2(&'msg OpenChannel2),
+}
+
+impl<'msg> ChannelProposal<'msg> {
+    /// Detects whether the channel is proposed with dual-funding protocol
+    #[inline]
+    pub fn is_dual_funded(self) -> bool {
+        matches!(self, ChannelProposal::V2(_))
+    }
+
+    /// Funds provided to the channel by the remote peer, in satoshis
+    #[inline]
+    pub fn funding_satoshis(self) -> u64 {
+        match self {
+            ChannelProposal::V1(open_channel) => open_channel.funding_satoshis,
+            ChannelProposal::V2(open_channel) => open_channel.funding_satoshis,
+        }
+    }
+
+    /// Channel type proposed by the remote peer, if any
+    #[inline]
+    pub fn channel_type(self) -> Option<ChannelType> {
+        match self {
+            ChannelProposal::V1(open_channel) => open_channel.channel_type,
+            ChannelProposal::V2(open_channel) => open_channel.channel_type,
+        }
+    }
+
+    /// Channel flags proposed by the remote peer
+    #[inline]
+    pub fn channel_flags(self) -> u8 {
+        match self {
+            ChannelProposal::V1(open_channel) => open_channel.channel_flags,
+            ChannelProposal::V2(open_channel) => open_channel.channel_flags,
+        }
+    }
+
+    /// Upfront shutdown script of the remote peer, if any
+    #[inline]
+    pub fn shutdown_scriptpubkey(self) -> Option<&'msg PubkeyScript> {
+        match self {
+            ChannelProposal::V1(open_channel) => {
+                open_channel.shutdown_scriptpubkey.as_ref()
+            }
+            ChannelProposal::V2(open_channel) => {
+                open_channel.shutdown_scriptpubkey.as_ref()
+            }
+        }
+    }
+
+    /// Parameters which the remote peer requires from the local node
+    #[inline]
+    pub fn remote_params(self) -> PeerParams {
+        match self {
+            ChannelProposal::V1(open_channel) => PeerParams::from(open_channel),
+            ChannelProposal::V2(open_channel) => PeerParams::from(open_channel),
+        }
+    }
+}
+
+/// Decision of a [`ChannelAcceptor`] on the channel proposed by a remote peer
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AcceptorDecision {
+    /// Accept the channel with the parameters provided by the local node
+    /// configuration
+    Accept,
+
+    /// Accept the channel, overriding some of the local channel parameters
+    Override(ParamsOverride),
+
+    /// Reject the channel, providing human-readable reason for the rejection
+    Reject(String),
+}
+
+/// Local channel parameters which may be overridden by a [`ChannelAcceptor`]
+/// for a specific inbound channel. Parameters set to `None` are kept intact.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ParamsOverride {
+    /// Parameters required from the remote node, like channel reserve, dust
+    /// limit or `to_self_delay`
+    pub local_params: Option<PeerParams>,
+
+    /// Minimum depth of the funding transaction required from the remote
+    /// node
+    pub minimum_depth: Option<u32>,
+
+    /// Upfront shutdown script of the local node. Must be in one of the forms
+    /// allowed by BOLT-2 for the features negotiated with the remote node; an
+    /// empty script means the local node does not commit to a specific
+    /// script.
+    pub shutdown_scriptpubkey: Option<PubkeyScript>,
+}
+
+/// Pluggable hook applying custom business rules to inbound channels, on top
+/// of the numeric checks performed by the [`super::Policy`].
+///
+/// The hook is called by [`super::BoltChannel`] when it receives
+/// `open_channel` or `open_channel2` message, after the message parameters
+/// were successfully validated against the channel policy. Local parameters
+/// overridden by the hook are validated against the policy as well.
+pub trait ChannelAcceptor {
+    /// Decides whether to accept the channel proposed by the `remote_node`.
+    /// Features negotiated with the remote node are those present both in
+    /// `local_features` announced by the local node and in `remote_features`
+    /// announced by the remote node in their `init` messages.
+    fn accept(
+        &self,
+        proposal: ChannelProposal,
+        remote_node: NodeId,
+        local_features: &InitFeatures,
+        remote_features: &InitFeatures,
+    ) -> AcceptorDecision;
+}
+
+impl<F> ChannelAcceptor for F
+where
+    F: Fn(
+        ChannelProposal,
+        NodeId,
+        &InitFeatures,
+        &InitFeatures,
+    ) -> AcceptorDecision,
+{
+    #[inline]
+    fn accept(
+        &self,
+        proposal: ChannelProposal,
+        remote_node: NodeId,
+        local_features: &InitFeatures,
+        remote_features: &InitFeatures,
+    ) -> AcceptorDecision {
+        self(proposal, remote_node, local_features, remote_features)
+    }
+}
+
+/// Channel acceptor installed for a channel with a specific remote node.
+///
+/// The acceptor is a runtime configuration and is never persisted as a part
+/// of the channel data.
+#[derive(Clone)]
+pub struct AcceptorHook {
+    /// Remote node with which the channel is established
+    pub remote_node: NodeId,
+
+    /// Acceptor, which may be shared between multiple channels
+    pub acceptor: Arc<dyn ChannelAcceptor>,
+}
+
+impl PartialEq for AcceptorHook {
+    fn eq(&self, other: &Self) -> bool {
+        self.remote_node == other.remote_node
+            && Arc::ptr_eq(&self.acceptor, &other.acceptor)
+    }
+}
+
+impl Eq for AcceptorHook {}
+
+impl Debug for AcceptorHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcceptorHook")
+            .field("remote_node", &self.remote_node)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AcceptorHook {
+    /// Calls the acceptor for the channel proposed by the remote node
+    #[inline]
+    pub fn accept(
+        &self,
+        proposal: ChannelProposal,
+        local_features: &InitFeatures,
+        remote_features: &InitFeatures,
+    ) -> AcceptorDecision {
+        self.acceptor.accept(
+            proposal,
+            self.remote_node,
+            local_features,
+            remote_features,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::DumbDefault;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Script;
+    use lnp2p::bolt::TempChannelId;
+
+    use super::*;
+
+    fn open_channel() -> OpenChannel {
+        let mut open_channel = OpenChannel::dumb_default();
+        open_channel.funding_satoshis = 1_000_000;
+        open_channel.dust_limit_satoshis = 354;
+        open_channel.channel_reserve_satoshis = 20_000;
+        open_channel.channel_flags = 1;
+        open_channel
+    }
+
+    fn open_channel2() -> OpenChannel2 {
+        let open_channel = open_channel();
+        OpenChannel2 {
+            chain_hash: open_channel.chain_hash,
+            temporary_channel_id: TempChannelId::dumb_default(),
+            funding_feerate_perkw: 253,
+            commitment_feerate_perkw: 253,
+            funding_satoshis: open_channel.funding_satoshis,
+            dust_limit_satoshis: open_channel.dust_limit_satoshis,
+            max_htlc_value_in_flight_msat: 0,
+            htlc_minimum_msat: 0,
+            to_self_delay: 0,
+            max_accepted_htlcs: 0,
+            locktime: 0,
+            funding_pubkey: dumb_pubkey!(),
+            revocation_basepoint: dumb_pubkey!(),
+            payment_basepoint: dumb_pubkey!(),
+            delayed_payment_basepoint: dumb_pubkey!(),
+            htlc_basepoint: dumb_pubkey!(),
+            first_per_commitment_point: dumb_pubkey!(),
+            second_per_commitment_point: dumb_pubkey!(),
+            channel_flags: 0,
+            shutdown_scriptpubkey: None,
+            channel_type: Some(ChannelType::default()),
+            require_confirmed_inputs: None,
+            unknown_tlvs: none!(),
+        }
+    }
+
+    /// Acceptor rejecting small channels and requiring more confirmations for
+    /// the channels which are not funded by the remote node alone
+    struct MinFunding(u64);
+
+    impl ChannelAcceptor for MinFunding {
+        fn accept(
+            &self,
+            proposal: ChannelProposal,
+            _remote_node: NodeId,
+            _local_features: &InitFeatures,
+            _remote_features: &InitFeatures,
+        ) -> AcceptorDecision {
+            if proposal.funding_satoshis() < self.0 {
+                return AcceptorDecision::Reject(s!("channel is too small"));
+            }
+            if proposal.is_dual_funded() {
+                return AcceptorDecision::Override(ParamsOverride {
+                    minimum_depth: Some(6),
+                    ..default!()
+                });
+            }
+            AcceptorDecision::Accept
+        }
+    }
+
+    #[test]
+    fn proposal() {
+        let script = PubkeyScript::from(Script::new_v0_p2wpkh(
+            &bitcoin::WPubkeyHash::hash(b"alice"),
+        ));
+        let mut open_channel = open_channel();
+        open_channel.shutdown_scriptpubkey = Some(script.clone());
+        let proposal = ChannelProposal::V1(&open_channel);
+        assert!(!proposal.is_dual_funded());
+        assert_eq!(proposal.funding_satoshis(), 1_000_000);
+        assert_eq!(proposal.channel_type(), None);
+        assert_eq!(proposal.channel_flags(), 1);
+        assert_eq!(proposal.shutdown_scriptpubkey(), Some(&script));
+        assert_eq!(proposal.remote_params().channel_reserve_satoshis, 20_000);
+
+        let open_channel = open_channel2();
+        let proposal = ChannelProposal::V2(&open_channel);
+        assert!(proposal.is_dual_funded());
+        assert_eq!(proposal.funding_satoshis(), 1_000_000);
+        assert_eq!(proposal.channel_type(), Some(ChannelType::default()));
+        assert_eq!(proposal.channel_flags(), 0);
+        assert_eq!(proposal.shutdown_scriptpubkey(), None);
+        // Reserve is not negotiated in dual-funded channels
+        assert_eq!(proposal.remote_params().channel_reserve_satoshis, 10_000);
+    }
+
+    #[test]
+    fn acceptor() {
+        let remote_node = NodeId::from(dumb_pubkey!());
+        let features = InitFeatures::default();
+        let open_channel = open_channel();
+        let open_channel2 = open_channel2();
+
+        assert_eq!(
+            MinFunding(1_000_000).accept(
+                ChannelProposal::V1(&open_channel),
+                remote_node,
+                &features,
+                &features,
+            ),
+            AcceptorDecision::Accept
+        );
+        assert_eq!(
+            MinFunding(1_000_000).accept(
+                ChannelProposal::V2(&open_channel2),
+                remote_node,
+                &features,
+                &features,
+            ),
+            AcceptorDecision::Override(ParamsOverride {
+                local_params: None,
+                minimum_depth: Some(6),
+                shutdown_scriptpubkey: None,
+            })
+        );
+        assert_eq!(
+            MinFunding(1_000_001).accept(
+                ChannelProposal::V1(&open_channel),
+                remote_node,
+                &features,
+                &features,
+            ),
+            AcceptorDecision::Reject(s!("channel is too small"))
+        );
+    }
+
+    #[test]
+    fn fn_acceptor() {
+        // Acceptor requiring the upfront shutdown script to be negotiated
+        let acceptor = |_: ChannelProposal,
+                        _: NodeId,
+                        local_features: &InitFeatures,
+                        remote_features: &InitFeatures| {
+            if local_features.option_upfront_shutdown_script.is_some()
+                && remote_features.option_upfront_shutdown_script.is_some()
+            {
+                AcceptorDecision::Accept
+            } else {
+                AcceptorDecision::Reject(s!("no upfront shutdown script"))
+            }
+        };
+        let remote_node = NodeId::from(dumb_pubkey!());
+        let open_channel = open_channel();
+        let proposal = ChannelProposal::V1(&open_channel);
+        let features = InitFeatures {
+            option_upfront_shutdown_script: Some(false),
+            ..default!()
+        };
+        let no_features = InitFeatures::default();
+
+        for (local_features, remote_features, accepted) in [
+            (&features, &features, true),
+            (&features, &no_features, false),
+            (&no_features, &features, false),
+        ] {
+            let decision = ChannelAcceptor::accept(
+                &acceptor,
+                proposal,
+                remote_node,
+                local_features,
+                remote_features,
+            );
+            assert_eq!(decision == AcceptorDecision::Accept, accepted);
+        }
+    }
+
+    #[test]
+    fn hook() {
+        let trusted = NodeId::from(dumb_pubkey!());
+        let acceptor: Arc<dyn ChannelAcceptor> = Arc::new(
+            move |_: ChannelProposal,
+                  remote_node: NodeId,
+                  _: &InitFeatures,
+                  _: &InitFeatures| {
+                if remote_node == trusted {
+                    AcceptorDecision::Accept
+                } else {
+                    AcceptorDecision::Reject(s!("unknown peer"))
+                }
+            },
+        );
+        let untrusted = NodeId::from(secp256k1::PublicKey::from_secret_key(
+            secp256k1::SECP256K1,
+            &secp256k1::SecretKey::from_slice(&[0x01; 32]).unwrap(),
+        ));
+        let hook = AcceptorHook {
+            remote_node: trusted,
+            acceptor: acceptor.clone(),
+        };
+        let open_channel = open_channel();
+        let proposal = ChannelProposal::V1(&open_channel);
+        let features = InitFeatures::default();
+
+        // Hook passes its remote node to the acceptor
+        assert_eq!(
+            hook.accept(proposal, &features, &features),
+            AcceptorDecision::Accept
+        );
+        let untrusted_hook = AcceptorHook {
+            remote_node: untrusted,
+            acceptor: acceptor.clone(),
+        };
+        assert_eq!(
+            untrusted_hook.accept(proposal, &features, &features),
+            AcceptorDecision::Reject(s!("unknown peer"))
+        );
+
+        // Hooks are equal only if they share the same acceptor instance
+        assert_eq!(hook, hook.clone());
+        assert_ne!(hook, untrusted_hook);
+        let other = AcceptorHook {
+            remote_node: trusted,
+            acceptor: Arc::new(MinFunding(0)),
+        };
+        assert_ne!(hook, other);
+        assert_eq!(
+            format!("{:?}", hook),
+            format!("AcceptorHook {{ remote_node: {:?}, .. }}", trusted)
+        );
+    }
+
+    #[test]
+    fn params_override() {
+        assert_eq!(ParamsOverride::default(), ParamsOverride {
+            local_params: None,
+            minimum_depth: None,
+            shutdown_scriptpubkey: None,
+        });
+        let overrides = ParamsOverride {
+            local_params: Some(PeerParams::default()),
+            ..default!()
+        };
+        assert_eq!(
+            AcceptorDecision::Override(overrides.clone()),
+            AcceptorDecision::Override(overrides)
+        );
+    }
+}
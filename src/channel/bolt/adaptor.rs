@@ -0,0 +1,278 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! BIP-340 compatible Schnorr adaptor signatures used by point-time-locked
+//! contracts (PTLCs).
+//!
+//! Adaptor signature `(R', s')` is produced for an adaptor point `T` and can
+//! be completed into a valid BIP-340 signature only by the party knowing the
+//! discrete logarithm `t` of `T`. Publishing the completed signature reveals
+//! `t` to the signer of the adaptor signature.
+
+use amplify::Wrapper;
+use p2p::bolt::AdaptorSignature;
+use secp256k1::{
+    schnorr, Parity, PublicKey, Scalar, SecretKey, XOnlyPublicKey, SECP256K1,
+};
+
+use super::musig2::{has_even_y, tagged_hash};
+
+/// Errors of Schnorr adaptor signature operations
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum AdaptorError {
+    /// adaptor signature contains invalid nonce point or scalar
+    InvalidEncoding,
+
+    /// adaptor signature does not match the public key, message or adaptor
+    /// point
+    InvalidSignature,
+
+    /// adaptor signature computation has produced zero scalar or point at
+    /// infinity
+    InvalidScalar,
+}
+
+/// Operations with BIP-340 compatible Schnorr adaptor signatures
+pub trait SchnorrAdaptor: Sized {
+    /// Creates adaptor signature for the message `msg` with the adaptor point
+    /// `adaptor_point` using the provided auxiliary randomness `entropy`.
+    fn sign(
+        secret_key: SecretKey,
+        msg: [u8; 32],
+        adaptor_point: PublicKey,
+        entropy: [u8; 32],
+    ) -> Result<Self, AdaptorError>;
+
+    /// Verifies that the adaptor signature, once completed with the discrete
+    /// logarithm of `adaptor_point`, will be a valid signature for the message
+    /// `msg` under the public key `pubkey`.
+    fn verify(
+        &self,
+        pubkey: XOnlyPublicKey,
+        msg: [u8; 32],
+        adaptor_point: PublicKey,
+    ) -> Result<(), AdaptorError>;
+
+    /// Completes adaptor signature into a valid BIP-340 signature using the
+    /// discrete logarithm of the adaptor point.
+    fn complete(
+        &self,
+        adaptor_secret: SecretKey,
+    ) -> Result<schnorr::Signature, AdaptorError>;
+
+    /// Extracts discrete logarithm of the adaptor point from the completed
+    /// signature.
+    fn extract(
+        &self,
+        signature: &schnorr::Signature,
+        adaptor_point: PublicKey,
+    ) -> Result<SecretKey, AdaptorError>;
+}
+
+impl SchnorrAdaptor for AdaptorSignature {
+    fn sign(
+        secret_key: SecretKey,
+        msg: [u8; 32],
+        adaptor_point: PublicKey,
+        entropy: [u8; 32],
+    ) -> Result<Self, AdaptorError> {
+        let (pubkey, parity) = secret_key.x_only_public_key(SECP256K1);
+        let d = match parity {
+            Parity::Even => secret_key,
+            Parity::Odd => secret_key.negate(),
+        };
+
+        let k = scalar(tagged_hash("PTLC/adaptor nonce", &[
+            &entropy,
+            &d.secret_bytes(),
+            &adaptor_point.serialize(),
+            &msg,
+        ]))?;
+        let nonce = PublicKey::from_secret_key(SECP256K1, &k)
+            .combine(&adaptor_point)
+            .map_err(|_| AdaptorError::InvalidScalar)?;
+        let k = if has_even_y(nonce) { k } else { k.negate() };
+
+        let e = challenge(nonce, pubkey, msg)?;
+        let s = add(k, mul(e, d))?;
+
+        let mut data = [0u8; 65];
+        data[..33].copy_from_slice(&nonce.serialize());
+        data[33..].copy_from_slice(&s.secret_bytes());
+        Ok(AdaptorSignature::from_inner(data))
+    }
+
+    fn verify(
+        &self,
+        pubkey: XOnlyPublicKey,
+        msg: [u8; 32],
+        adaptor_point: PublicKey,
+    ) -> Result<(), AdaptorError> {
+        let (nonce, s) = parse(self)?;
+        let e = challenge(nonce, pubkey, msg)?;
+
+        // R = R' - T, negated if R' has odd Y coordinate
+        let mut r = nonce
+            .combine(&adaptor_point.negate(SECP256K1))
+            .map_err(|_| AdaptorError::InvalidSignature)?;
+        if !has_even_y(nonce) {
+            r = r.negate(SECP256K1);
+        }
+        let ep = PublicKey::from_x_only_public_key(pubkey, Parity::Even)
+            .mul_tweak(SECP256K1, &Scalar::from(e))
+            .map_err(|_| AdaptorError::InvalidScalar)?;
+        let expected =
+            r.combine(&ep).map_err(|_| AdaptorError::InvalidSignature)?;
+
+        if PublicKey::from_secret_key(SECP256K1, &s) != expected {
+            return Err(AdaptorError::InvalidSignature);
+        }
+        Ok(())
+    }
+
+    fn complete(
+        &self,
+        adaptor_secret: SecretKey,
+    ) -> Result<schnorr::Signature, AdaptorError> {
+        let (nonce, s) = parse(self)?;
+        let t = if has_even_y(nonce) {
+            adaptor_secret
+        } else {
+            adaptor_secret.negate()
+        };
+        let s = add(s, t)?;
+
+        let mut data = [0u8; 64];
+        data[..32].copy_from_slice(&nonce.x_only_public_key().0.serialize());
+        data[32..].copy_from_slice(&s.secret_bytes());
+        schnorr::Signature::from_slice(&data)
+            .map_err(|_| AdaptorError::InvalidEncoding)
+    }
+
+    fn extract(
+        &self,
+        signature: &schnorr::Signature,
+        adaptor_point: PublicKey,
+    ) -> Result<SecretKey, AdaptorError> {
+        let (nonce, s) = parse(self)?;
+        let sig = signature.as_ref();
+        if sig[..32] != nonce.x_only_public_key().0.serialize() {
+            return Err(AdaptorError::InvalidSignature);
+        }
+        let completed = SecretKey::from_slice(&sig[32..])
+            .map_err(|_| AdaptorError::InvalidEncoding)?;
+
+        let t = add(completed, s.negate())?;
+        let t = if has_even_y(nonce) { t } else { t.negate() };
+        if PublicKey::from_secret_key(SECP256K1, &t) != adaptor_point {
+            return Err(AdaptorError::InvalidSignature);
+        }
+        Ok(t)
+    }
+}
+
+fn parse(
+    sig: &AdaptorSignature,
+) -> Result<(PublicKey, SecretKey), AdaptorError> {
+    let data = sig.as_inner();
+    let nonce = PublicKey::from_slice(&data[..33])
+        .map_err(|_| AdaptorError::InvalidEncoding)?;
+    let s = SecretKey::from_slice(&data[33..])
+        .map_err(|_| AdaptorError::InvalidEncoding)?;
+    Ok((nonce, s))
+}
+
+/// BIP-340 challenge for the adapted nonce point
+fn challenge(
+    nonce: PublicKey,
+    pubkey: XOnlyPublicKey,
+    msg: [u8; 32],
+) -> Result<SecretKey, AdaptorError> {
+    scalar(tagged_hash("BIP0340/challenge", &[
+        &nonce.x_only_public_key().0.serialize(),
+        &pubkey.serialize(),
+        &msg,
+    ]))
+}
+
+fn scalar(hash: [u8; 32]) -> Result<SecretKey, AdaptorError> {
+    SecretKey::from_slice(&hash).map_err(|_| AdaptorError::InvalidScalar)
+}
+
+fn add(a: SecretKey, b: SecretKey) -> Result<SecretKey, AdaptorError> {
+    a.add_tweak(&Scalar::from(b))
+        .map_err(|_| AdaptorError::InvalidScalar)
+}
+
+fn mul(a: SecretKey, b: SecretKey) -> SecretKey {
+    a.mul_tweak(&Scalar::from(b))
+        .expect("product of non-zero scalars modulo prime order is non-zero")
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::{sha256, Hash};
+    use secp256k1::Message;
+
+    use super::*;
+
+    #[test]
+    fn adaptor_roundtrip() {
+        let msg = sha256::Hash::hash(b"ptlc-success").into_inner();
+        let adaptor_secret = SecretKey::from_slice(&[0x77; 32]).unwrap();
+        let adaptor_point =
+            PublicKey::from_secret_key(SECP256K1, &adaptor_secret);
+        let wrong_point = PublicKey::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(&[0x78; 32]).unwrap(),
+        );
+
+        // Iterate over different keys and nonces to cover both parities
+        for seed in 1u8..=8 {
+            let secret_key = SecretKey::from_slice(&[seed; 32]).unwrap();
+            let pubkey = secret_key.x_only_public_key(SECP256K1).0;
+
+            let adaptor_sig = AdaptorSignature::sign(
+                secret_key,
+                msg,
+                adaptor_point,
+                [seed; 32],
+            )
+            .unwrap();
+            adaptor_sig.verify(pubkey, msg, adaptor_point).unwrap();
+            assert_eq!(
+                adaptor_sig.verify(pubkey, msg, wrong_point),
+                Err(AdaptorError::InvalidSignature)
+            );
+
+            let signature = adaptor_sig.complete(adaptor_secret).unwrap();
+            SECP256K1
+                .verify_schnorr(
+                    &signature,
+                    &Message::from_slice(&msg).unwrap(),
+                    &pubkey,
+                )
+                .unwrap();
+
+            assert_eq!(
+                adaptor_sig.extract(&signature, adaptor_point).unwrap(),
+                adaptor_secret
+            );
+            assert_eq!(
+                adaptor_sig.extract(&signature, wrong_point),
+                Err(AdaptorError::InvalidSignature)
+            );
+        }
+    }
+}
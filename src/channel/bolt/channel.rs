@@ -11,35 +11,59 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
 use amplify::{DumbDefault, Slice32, Wrapper};
 use bitcoin::blockdata::opcodes::all::*;
 use bitcoin::blockdata::script;
 use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::secp256k1::PublicKey;
-use bitcoin::{Network, TxOut};
-use bitcoin_scripts::hlc::HashLock;
+use bitcoin::util::sighash::{Prevouts, SighashCache};
+use bitcoin::{Network, SchnorrSighashType, TxOut, Txid};
+use bitcoin_scripts::hlc::{HashLock, HashPreimage};
 use bitcoin_scripts::{LockScript, PubkeyScript, WitnessScript};
 use internet2::addr::NodeId;
 use internet2::presentation::sphinx::{self, Hop, Onion, OnionPacket};
 use lnp2p::bolt::{
-    AcceptChannel, ActiveChannelId, ChannelId, Messages, OpenChannel,
-    TempChannelId,
+    AcceptChannel, AcceptChannel2, ActiveChannelId, ChannelId, InitFeatures,
+    Messages, OpenChannel, OpenChannel2, TempChannelId,
 };
 use lnpbp::chain::Chain;
 use p2p::bolt::{
-    ChannelReestablish, FundingLocked, PaymentOnion, UpdateAddHtlc,
+    AssetAmount, ChannelReestablish, ChannelType, DlcAccept, DlcOffer,
+    DlcPayout, DlcSettle, DlcSign, FundingLocked, OracleAnnouncement,
+    PartialSigWithNonce, PaymentOnion, PublicNonce, ShortChannelId, Shutdown,
+    SpliceAck, SpliceInit, SpliceLocked, Stfu, UpdateAddHtlc, UpdateAddPtlc,
+    UpdateFailHtlc, UpdateFailPtlc, UpdateFee, UpdateFulfillHtlc,
+    UpdateFulfillPtlc,
 };
 use secp256k1::ecdsa::Signature;
-use secp256k1::Secp256k1;
+use secp256k1::{schnorr, Secp256k1, SecretKey};
 use strict_encoding::StrictDecode;
 use wallet::lex_order::LexOrder;
 use wallet::psbt::{
     Psbt, {self},
 };
 
-use super::keyset::{LocalKeyset, LocalPubkey, RemoteKeyset};
+use super::acceptor::{
+    AcceptorDecision, AcceptorHook, ChannelAcceptor, ChannelProposal,
+};
+use super::keyset::{
+    derive_privkey, derive_pubkey, derive_revocationpubkey, LocalKeyset,
+    LocalPubkey, RemoteKeyset,
+};
+use super::musig2::{KeyAggContext, MuSig2Error, NonceSlot, SecretNonce};
 use super::policy::{CommonParams, PeerParams, Policy};
-use super::{AnchorOutputs, BoltExt, ChannelState, Lifecycle};
+use super::policy_book::PolicyBook;
+use super::splice::is_update_message;
+use super::taproot::{self, TaprootScriptGenerators};
+use super::{
+    is_valid_shutdown_script, AnchorOutputs, BoltExt, ChannelState,
+    CommitmentPolicyError, DlcError, InteractiveTx, InteractiveTxError,
+    Lifecycle, Quiescence, RgbError, ShutdownScriptError, SpliceContributions,
+    SpliceError, SpliceParams, INTERACTIVE_TX_MAX_SEQUENCE,
+};
 use crate::channel::bolt::util::UpdateReq;
 use crate::channel::bolt::PolicyError;
 use crate::channel::funding::{self, Funding, PsbtLnpFunding};
@@ -71,11 +95,61 @@ pub enum Error {
     #[display(inner)]
     Htlc(String),
 
+    /// PTLC extension error
+    #[display(inner)]
+    Ptlc(String),
+
+    /// Discreet log contract extension error
+    #[from]
+    #[display(inner)]
+    Dlc(DlcError),
+
+    /// RGB asset allocations error
+    #[from]
+    #[display(inner)]
+    Rgb(RgbError),
+
+    /// Shutdown script error
+    #[from]
+    #[display(inner)]
+    ShutdownScript(ShutdownScriptError),
+
     /// Policy errors happening during channel negotiation
     #[from]
     #[display(inner)]
     Policy(PolicyError),
 
+    /// Commitment transaction violates channel parameters
+    #[from]
+    #[display(inner)]
+    CommitmentPolicy(CommitmentPolicyError),
+
+    /// Error during interactive construction of the funding transaction
+    #[from]
+    #[display(inner)]
+    InteractiveTx(InteractiveTxError),
+
+    /// Error during channel quiescence or splicing
+    #[from]
+    #[display(inner)]
+    Splice(SpliceError),
+
+    /// MuSig2 error in simple taproot channel
+    #[from]
+    #[display(inner)]
+    MuSig2(MuSig2Error),
+
+    /// the operation requires the channel to be a simple taproot channel
+    NotTaproot,
+
+    /// remote peer has not provided MuSig2 nonce required for signing the
+    /// commitment transaction in simple taproot channel
+    NoRemoteNonce,
+
+    /// remote peer has not provided MuSig2 partial signature for the local
+    /// commitment transaction in simple taproot channel
+    NoRemotePartialSig,
+
     /// channel is in a state {current} incompatible with the requested
     /// operation
     #[display(doc_comments)]
@@ -84,9 +158,24 @@ pub enum Error {
         required: &'static [Lifecycle],
     },
 
+    /// remote peer has accepted the channel with type {accepted}, while the
+    /// proposed channel type was {proposed}
+    ChannelTypeMismatch {
+        proposed: ChannelType,
+        accepted: ChannelType,
+    },
+
     /// the channel does not have permanent channel_id assigned
     NoChannelId,
 
+    /// channel proposed by the remote peer was rejected by the channel
+    /// acceptor: {0}
+    ChannelRejected(String),
+
+    /// `update_fee` can be sent only by the channel funder responsible for
+    /// paying the commitment transaction fee
+    FeeUpdateByNonFunder,
+
     /// the channel must have a temporary channel id and not be active for the
     /// operation
     NoTemporaryId,
@@ -167,24 +256,62 @@ impl Channel<BoltExt> {
             channel.add_extender(AnchorOutputs::new());
         }
 
-        let core = channel.constructor_mut();
-        core.set_temp_channel_id(temp_channel_id);
-        core.set_chain_hash(chain_hash);
-        core.set_policy(policy);
-        core.set_common_params(common_params);
-        core.set_local_params(local_params);
-        core.set_local_keys(local_keys);
-
+        channel.update_constructor(|core| {
+            core.set_temp_channel_id(temp_channel_id);
+            core.set_chain_hash(chain_hash);
+            core.set_policy(policy);
+            core.set_common_params(common_params);
+            core.set_local_params(local_params);
+            core.set_local_keys(local_keys);
+        });
         channel
     }
 
+    /// Constructs the new channel with the remote node, using the policy
+    /// resolved for this node from the provided policy book.
+    ///
+    /// See [`Channel::with`] for the details on the rest of arguments.
+    pub fn with_policy_book(
+        temp_channel_id: TempChannelId,
+        chain_hash: Slice32,
+        policy_book: &PolicyBook,
+        remote_node: NodeId,
+        common_params: CommonParams,
+        local_params: PeerParams,
+        local_keys: LocalKeyset,
+    ) -> Self {
+        Self::with(
+            temp_channel_id,
+            chain_hash,
+            policy_book.policy_for(remote_node),
+            common_params,
+            local_params,
+            local_keys,
+        )
+    }
+
     /// Sets channel policy.
     ///
     /// Can be used for changing the policy on the fly to enable accepting new
     /// `open_channel` - or follow-up `accept_channel` requests.
     #[inline]
     pub fn set_policy(&mut self, policy: Policy) {
-        self.constructor_mut().set_policy(policy)
+        self.update_constructor(|core| core.set_policy(policy))
+    }
+
+    /// Installs channel acceptor, which is called on `open_channel` message
+    /// from the `remote_node` to apply custom rules for accepting the channel
+    /// and adjust local channel parameters.
+    #[inline]
+    pub fn set_acceptor(
+        &mut self,
+        remote_node: NodeId,
+        acceptor: Arc<dyn ChannelAcceptor>,
+    ) {
+        self.constructor_mut().set_acceptor(AcceptorHook {
+            remote_node,
+            acceptor,
+        })
     }
 
     /// Sets common parameters for the channel.
@@ -194,7 +321,15 @@ impl Channel<BoltExt> {
     /// requests.
     #[inline]
     pub fn set_common_params(&mut self, params: CommonParams) {
-        self.constructor_mut().set_common_params(params)
+        self.update_constructor(|core| core.set_common_params(params))
+    }
+
+    /// Sets features announced by the local node in its `init` message,
+    /// propagating them to the channel extensions negotiating features with
+    /// the remote peer. Must be called before the remote `init` message is
+    /// processed.
+    pub fn set_local_features(&mut self, features: InitFeatures) {
+        self.update_constructor(|core| core.set_local_features(features))
     }
 
     /// Sets local parameters for the channel.
@@ -204,7 +339,7 @@ impl Channel<BoltExt> {
     /// requests.
     #[inline]
     pub fn set_local_params(&mut self, params: PeerParams) {
-        self.constructor_mut().set_local_params(params)
+        self.update_constructor(|core| core.set_local_params(params))
     }
 
     /// Returns active channel id, covering both temporary and final channel ids
@@ -251,14 +386,24 @@ impl Channel<BoltExt> {
         local_keys: LocalKeyset,
     ) -> Result<OpenChannel, Error> {
         self.set_funding_amount(funding_sat);
-        self.constructor_mut().compose_open_channel(
-            funding_sat,
-            push_msat,
-            policy,
-            common_params,
-            local_params,
-            local_keys,
-        )
+        let open_channel = self.update_constructor(|core| {
+            core.compose_open_channel(
+                funding_sat,
+                push_msat,
+                policy,
+                common_params,
+                local_params,
+                local_keys,
+            )
+        })?;
+
+        // Let extensions add their data to the message
+        let mut message = Messages::OpenChannel(open_channel);
+        self.state_change(&UpdateReq::OpenChannel, &mut message)?;
+        match message {
+            Messages::OpenChannel(open_channel) => Ok(open_channel),
+            _ => unreachable!("extensions must not change message type"),
+        }
     }
 
     /// Composes `accept_channel` message used for accepting channel opening
@@ -270,7 +415,215 @@ impl Channel<BoltExt> {
     /// Fails if the node is not in [`Lifecycle::Initial`] or
     /// [`Lifecycle::Reestablishing`] state.
     pub fn compose_accept_channel(&mut self) -> Result<AcceptChannel, Error> {
-        self.constructor_mut().compose_accept_channel()
+        let accept_channel = self.constructor_mut().compose_accept_channel()?;
+
+        // Let extensions add their data to the message
+        let mut message = Messages::AcceptChannel(accept_channel);
+        self.state_change(&UpdateReq::AcceptChannel, &mut message)?;
+        match message {
+            Messages::AcceptChannel(accept_channel) => Ok(accept_channel),
+            _ => unreachable!("extensions must not change message type"),
+        }
+    }
+
+    /// Composes `open_channel2` message used for proposing dual-funded channel
+    /// opening to a remote peer with v2 channel establishment protocol. Works
+    /// like [`Channel::compose_open_channel`], but instead of pushing funds
+    /// the remote peer may contribute to the channel funding, which is
+    /// constructed interactively (see [`Channel::interactive_tx`]).
+    ///
+    /// Fails if the node is not in [`Lifecycle::Initial`] or
+    /// [`Lifecycle::Reestablishing`] state.
+    #[allow(clippy::too_many_arguments)]
+    pub fn compose_open_channel2(
+        &mut self,
+        funding_sat: u64,
+        funding_feerate_perkw: u32,
+        locktime: u32,
+        policy: Policy,
+        common_params: CommonParams,
+        local_params: PeerParams,
+        local_keys: LocalKeyset,
+    ) -> Result<OpenChannel2, Error> {
+        self.set_funding_amount(funding_sat);
+        let open_channel = self.update_constructor(|core| {
+            core.compose_open_channel2(
+                funding_sat,
+                funding_feerate_perkw,
+                locktime,
+                policy,
+                common_params,
+                local_params,
+                local_keys,
+            )
+        })?;
+        Ok(open_channel)
+    }
+
+    /// Composes `accept_channel2` message used for accepting dual-funded
+    /// channel opening from a remote peer, contributing `funding_sat` to the
+    /// channel funding.
+    ///
+    /// Fails if the node is not in [`Lifecycle::Initial`],
+    /// [`Lifecycle::Proposed`] or [`Lifecycle::Reestablishing`] state.
+    pub fn compose_accept_channel2(
+        &mut self,
+        funding_sat: u64,
+    ) -> Result<AcceptChannel2, Error> {
+        let accept_channel = self.update_constructor(|core| {
+            core.compose_accept_channel2(funding_sat)
+        })?;
+        let total_sat =
+            (self.local_amount_msat() + self.remote_amount_msat()) / 1000;
+        self.set_funding_amount(total_sat);
+        Ok(accept_channel)
+    }
+
+    /// Starts interactive construction of the funding transaction for the
+    /// channel established with v2 protocol.
+    ///
+    /// Fails if the channel id was not yet derived, i.e. before
+    /// `accept_channel2` was sent or received.
+    pub fn interactive_tx(
+        &self,
+        locktime: u32,
+    ) -> Result<InteractiveTx, Error> {
+        Ok(InteractiveTx::new(
+            self.try_channel_id()?,
+            self.constructor().direction().is_outbound(),
+            locktime,
+            self.funding_script_pubkey(),
+        ))
+    }
+
+    /// Sets channel funding from a completed interactive transaction
+    /// construction.
+    pub fn set_interactive_funding(
+        &mut self,
+        interactive_tx: &InteractiveTx,
+    ) -> Result<(), Error> {
+        let psbt = interactive_tx.to_psbt()?;
+        self.set_funding(psbt)
+    }
+
+    /// Starts channel quiescence, required before splicing the channel.
+    #[inline]
+    pub fn compose_stfu(&mut self) -> Result<Stfu, Error> {
+        self.constructor_mut().compose_stfu()
+    }
+
+    /// Initiates splicing of the quiescent channel. Positive contribution
+    /// adds funds to the channel, negative removes them from the local
+    /// balance.
+    #[inline]
+    pub fn compose_splice_init(
+        &mut self,
+        contribution_sat: i64,
+        funding_feerate_perkw: u32,
+        locktime: u32,
+    ) -> Result<SpliceInit, Error> {
+        self.constructor_mut().compose_splice_init(
+            contribution_sat,
+            funding_feerate_perkw,
+            locktime,
+        )
+    }
+
+    /// Accepts splice initiated by the remote peer.
+    #[inline]
+    pub fn compose_splice_ack(
+        &mut self,
+        contribution_sat: i64,
+    ) -> Result<SpliceAck, Error> {
+        self.constructor_mut().compose_splice_ack(contribution_sat)
+    }
+
+    /// Starts interactive construction of the splice transaction once
+    /// `splice_init` and `splice_ack` messages were exchanged.
+    ///
+    /// Splice initiator adds the shared input spending the current channel
+    /// funding and the new funding output; the messages for them are returned
+    /// alongside the transaction constructor and must be sent to the remote
+    /// peer.
+    pub fn splice_tx(
+        &mut self,
+    ) -> Result<(InteractiveTx, Vec<Messages>), Error> {
+        let splice =
+            self.constructor().splice().ok_or(SpliceError::NoSplice)?;
+        let is_initiator = self.constructor().quiescence().initiator;
+        let mut interactive_tx = InteractiveTx::new(
+            self.try_channel_id()?,
+            is_initiator,
+            splice.locktime,
+            self.funding_script_pubkey(),
+        );
+        let mut messages = vec![];
+        if is_initiator {
+            let funding = self.funding();
+            let prevtx = funding.psbt().to_unsigned_tx();
+            let vout = funding.output() as u32;
+            let amount = funding.amount() as i64
+                + splice.local_contribution_sat
+                + splice.remote_contribution_sat;
+            messages.push(Messages::TxAddInput(interactive_tx.add_input(
+                prevtx,
+                vout,
+                INTERACTIVE_TX_MAX_SEQUENCE,
+            )?));
+            messages.push(Messages::TxAddOutput(interactive_tx.add_output(
+                amount.max(0) as u64,
+                self.funding_script_pubkey(),
+            )?));
+        }
+        Ok((interactive_tx, messages))
+    }
+
+    /// Registers transaction from a completed splice construction as a
+    /// pending funding candidate and ends channel quiescence. Returns txid
+    /// of the splice transaction.
+    ///
+    /// Until the splice is locked by both peers, commitments must be signed
+    /// for each of the funding candidates, see [`Channel::commitment_txs`].
+    pub fn add_splice_candidate(
+        &mut self,
+        interactive_tx: &InteractiveTx,
+    ) -> Result<Txid, Error> {
+        let psbt = interactive_tx.to_psbt()?;
+        let txid = self.add_funding_candidate(psbt)?;
+        self.constructor_mut().add_splice_candidate(txid)?;
+        Ok(txid)
+    }
+
+    /// Composes `splice_locked` message once the splice transaction has
+    /// reached enough confirmations. If the remote peer has already locked
+    /// the same splice, it becomes the new channel funding.
+    pub fn compose_splice_locked(
+        &mut self,
+        splice_txid: Txid,
+    ) -> Result<SpliceLocked, Error> {
+        if !self
+            .constructor()
+            .splice_candidates()
+            .contains_key(&splice_txid)
+        {
+            return Err(SpliceError::UnknownCandidate(splice_txid).into());
+        }
+        let channel_id = self.try_channel_id()?;
+        self.constructor_mut().local_splice_locked = Some(splice_txid);
+        if self.constructor().remote_splice_locked() == Some(splice_txid) {
+            self.promote_splice(splice_txid)?;
+        }
+        Ok(SpliceLocked {
+            channel_id,
+            splice_txid,
+        })
+    }
+
+    /// Replaces channel funding with the splice transaction locked by both
+    /// peers and updates channel balances
+    pub(super) fn promote_splice(&mut self, txid: Txid) -> Result<(), Error> {
+        self.promote_funding_candidate(txid)?;
+        self.update_constructor(|core| core.apply_splice(txid))
     }
 
     #[inline]
@@ -305,6 +658,296 @@ impl Channel<BoltExt> {
         Ok(message)
     }
 
+    /// Composes `update_add_htlc` message transferring RGB asset together
+    /// with the HTLC, which requires [`super::Rgb`] modifier to be added to
+    /// the channel.
+    pub fn compose_add_update_asset_htlc(
+        &mut self,
+        amount_msat: u64,
+        asset: AssetAmount,
+        payment_hash: HashLock,
+        cltv_expiry: u32,
+        route: Vec<Hop<PaymentOnion>>,
+    ) -> Result<Messages, Error> {
+        let mut message = self.constructor_mut().compose_add_update_htlc(
+            amount_msat,
+            payment_hash,
+            cltv_expiry,
+            route.clone(),
+        )?;
+        if let Messages::UpdateAddHtlc(ref mut update_add_htlc) = message {
+            update_add_htlc.asset = Some(asset);
+        }
+
+        self.state_change(&UpdateReq::PayBolt(route), &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `update_fulfill_htlc` message settling HTLC received from the
+    /// remote peer with the preimage of its payment hash.
+    pub fn compose_fulfill_update_htlc(
+        &mut self,
+        htlc_id: u64,
+        payment_preimage: HashPreimage,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::UpdateFulfillHtlc(UpdateFulfillHtlc {
+            channel_id: self.try_channel_id()?,
+            htlc_id,
+            payment_preimage,
+        });
+
+        self.state_change(&UpdateReq::FulfillHtlc, &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `update_fail_htlc` message failing HTLC received from the
+    /// remote peer.
+    pub fn compose_fail_update_htlc(
+        &mut self,
+        htlc_id: u64,
+        reason: Vec<u8>,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::UpdateFailHtlc(UpdateFailHtlc {
+            channel_id: self.try_channel_id()?,
+            htlc_id,
+            reason,
+        });
+
+        self.state_change(&UpdateReq::FailHtlc, &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `update_add_ptlc` message offering a new PTLC, which requires
+    /// [`super::Ptlc`] extender to be added to the channel.
+    pub fn compose_add_update_ptlc(
+        &mut self,
+        amount_msat: u64,
+        payment_point: PublicKey,
+        cltv_expiry: u32,
+        route: Vec<Hop<PaymentOnion>>,
+    ) -> Result<Messages, Error> {
+        let mut message = self.constructor_mut().compose_add_update_ptlc(
+            amount_msat,
+            payment_point,
+            cltv_expiry,
+            route.clone(),
+        )?;
+
+        self.state_change(&UpdateReq::PayPtlc(route), &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `update_fulfill_ptlc` message settling PTLC received from the
+    /// remote peer with the discrete logarithm of its payment point.
+    pub fn compose_fulfill_update_ptlc(
+        &mut self,
+        ptlc_id: u64,
+        payment_scalar: SecretKey,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::UpdateFulfillPtlc(UpdateFulfillPtlc {
+            channel_id: self.try_channel_id()?,
+            ptlc_id,
+            payment_scalar,
+        });
+
+        self.state_change(&UpdateReq::FulfillPtlc, &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `update_fail_ptlc` message failing PTLC received from the
+    /// remote peer.
+    pub fn compose_fail_update_ptlc(
+        &mut self,
+        ptlc_id: u64,
+        reason: Vec<u8>,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::UpdateFailPtlc(UpdateFailPtlc {
+            channel_id: self.try_channel_id()?,
+            ptlc_id,
+            reason,
+        });
+
+        self.state_change(&UpdateReq::FailPtlc, &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `dlc_offer` message proposing a new discreet log contract
+    /// based on the oracle announcement, which requires [`super::Dlc`]
+    /// extender to be added to the channel.
+    pub fn compose_dlc_offer(
+        &mut self,
+        announcement: OracleAnnouncement,
+        offerer_collateral_sat: u64,
+        accepter_collateral_sat: u64,
+        payouts: Vec<DlcPayout>,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::DlcOffer(DlcOffer {
+            channel_id: self.try_channel_id()?,
+            announcement,
+            offerer_collateral_sat,
+            accepter_collateral_sat,
+            payouts,
+        });
+
+        self.state_change(&UpdateReq::OfferDlc, &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `dlc_accept` message accepting discreet log contract offered
+    /// by the remote peer and providing it with CET adaptor signatures.
+    pub fn compose_dlc_accept(
+        &mut self,
+        funding_secret: SecretKey,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::DlcAccept(DlcAccept {
+            channel_id: self.try_channel_id()?,
+            cet_adaptor_signatures: vec![],
+        });
+
+        self.state_change(&UpdateReq::SignDlc(funding_secret), &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `dlc_sign` message providing the remote peer, which has
+    /// accepted our discreet log contract, with CET adaptor signatures.
+    pub fn compose_dlc_sign(
+        &mut self,
+        funding_secret: SecretKey,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::DlcSign(DlcSign {
+            channel_id: self.try_channel_id()?,
+            cet_adaptor_signatures: vec![],
+        });
+
+        self.state_change(&UpdateReq::SignDlc(funding_secret), &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `dlc_settle` message settling discreet log contract with the
+    /// oracle attestation of the outcome.
+    pub fn compose_dlc_settle(
+        &mut self,
+        outcome_index: u16,
+        attestation: SecretKey,
+    ) -> Result<Messages, Error> {
+        let mut message = Messages::DlcSettle(DlcSettle {
+            channel_id: self.try_channel_id()?,
+            outcome_index,
+            attestation,
+        });
+
+        self.state_change(&UpdateReq::SettleDlc, &mut message)?;
+        Ok(message)
+    }
+
+    /// Composes `update_fee` message changing the fee rate of the commitment
+    /// transactions. Can be used only by the channel funder. The new fee rate
+    /// is checked by the [`super::Htlc`] extension not to increase dust HTLC
+    /// exposure of the commitment transactions above the local policy limit.
+    pub fn compose_update_fee(
+        &mut self,
+        feerate_per_kw: u32,
+    ) -> Result<Messages, Error> {
+        if self.constructor().direction().is_inbound() {
+            return Err(Error::FeeUpdateByNonFunder);
+        }
+        let mut message = Messages::UpdateFee(UpdateFee {
+            channel_id: self.try_channel_id()?,
+            feerate_per_kw,
+        });
+
+        self.state_change(&UpdateReq::UpdateFee, &mut message)?;
+        self.update_constructor(|core| {
+            core.common_params.feerate_per_kw = feerate_per_kw
+        });
+        Ok(message)
+    }
+
+    /// Composes `shutdown` message initiating mutual channel close. If no
+    /// `scriptpubkey` is given, uses upfront shutdown script from the local
+    /// keyset. The script is validated by [`super::ShutdownScript`]
+    /// extension, which also makes sure that it matches the upfront shutdown
+    /// script if `option_upfront_shutdown_script` was negotiated.
+    pub fn compose_shutdown(
+        &mut self,
+        scriptpubkey: Option<PubkeyScript>,
+    ) -> Result<Messages, Error> {
+        let scriptpubkey = scriptpubkey
+            .or_else(|| {
+                self.constructor()
+                    .local_keys()
+                    .shutdown_scriptpubkey
+                    .clone()
+            })
+            .filter(|script| !script.as_inner().is_empty())
+            .ok_or(ShutdownScriptError::NoScript)?;
+        let mut message = Messages::Shutdown(Shutdown {
+            channel_id: self.try_channel_id()?,
+            scriptpubkey,
+        });
+
+        self.state_change(&UpdateReq::Shutdown, &mut message)?;
+        Ok(message)
+    }
+
+    /// Constructs mutual closing transaction paying channel balances to the
+    /// shutdown scripts exchanged by the peers in `shutdown` messages. The
+    /// closing fee is paid by the channel funder; outputs below the dust limit
+    /// of the local node, which constructs the transaction, are omitted and
+    /// the rest are ordered according to BIP-69. The channel must have no
+    /// pending HTLCs or PTLCs.
+    pub fn closing_tx(&self, fee_sat: u64) -> Result<Psbt, Error> {
+        let state = self.state();
+        let pending = state.offered_htlcs.len()
+            + state.received_htlcs.len()
+            + state.offered_ptlcs.len()
+            + state.received_ptlcs.len();
+        if pending > 0 {
+            return Err(ShutdownScriptError::PendingHtlcs(pending).into());
+        }
+        let (local_script, remote_script) = match (
+            state.local_shutdown_scriptpubkey,
+            state.remote_shutdown_scriptpubkey,
+        ) {
+            (Some(local), Some(remote)) => (local, remote),
+            _ => return Err(ShutdownScriptError::NoShutdown.into()),
+        };
+
+        let mut local_amount = state.local_amount_msat / 1000;
+        let mut remote_amount = state.remote_amount_msat / 1000;
+        let funder_amount = if state.direction.is_outbound() {
+            &mut local_amount
+        } else {
+            &mut remote_amount
+        };
+        *funder_amount = funder_amount.checked_sub(fee_sat).ok_or(
+            ShutdownScriptError::InsufficientFunds {
+                fee: fee_sat,
+                balance: *funder_amount,
+            },
+        )?;
+
+        let mut tx_graph = TxGraph::from_funding(self.funding());
+        tx_graph.cmt_version = 2;
+        tx_graph.cmt_locktime = 0;
+        tx_graph.cmt_sequence = u32::MAX;
+        let dust_limit = state.local_params.dust_limit_satoshis;
+        tx_graph.cmt_outs =
+            [(local_amount, local_script), (remote_amount, remote_script)]
+                .into_iter()
+                .filter(|(amount, _)| *amount >= dust_limit)
+                .map(|(value, script)| {
+                    let txout = TxOut {
+                        value,
+                        script_pubkey: script.into(),
+                    };
+                    psbt::Output::with(0, default!(), txout)
+                })
+                .collect();
+        tx_graph.cmt_outs.lex_order();
+        Ok(tx_graph.render_cmt())
+    }
+
     #[inline]
     pub fn chain_hash(&self) -> Slice32 {
         self.constructor().chain_hash()
@@ -323,6 +966,30 @@ impl Channel<BoltExt> {
         None
     }
 
+    /// Returns short channel id used for routing payments through the
+    /// channel: the real one once the funding transaction is mined, or the
+    /// alias allocated by the remote peer before that.
+    #[inline]
+    pub fn short_channel_id(&self) -> Option<ShortChannelId> {
+        let core = self.constructor();
+        core.short_channel_id().or_else(|| core.remote_alias())
+    }
+
+    /// Sets real short channel id once the funding transaction is mined.
+    #[inline]
+    pub fn set_short_channel_id(&mut self, short_channel_id: ShortChannelId) {
+        self.constructor_mut().short_channel_id = Some(short_channel_id);
+    }
+
+    /// Sets short channel id alias, which will be sent to the remote peer
+    /// in `funding_locked`. Can be used by nodes which allocate aliases
+    /// themselves; otherwise zero-conf and private channels get a random
+    /// alias.
+    #[inline]
+    pub fn set_local_alias(&mut self, alias: ShortChannelId) {
+        self.constructor_mut().local_alias = Some(alias);
+    }
+
     pub fn channel_info(&self, remote_node: NodeId) -> LocalChannelInfo {
         // TODO: Fill with the real data
         LocalChannelInfo {
@@ -330,7 +997,7 @@ impl Channel<BoltExt> {
             channel_id: self
                 .channel_id()
                 .expect("channel id must be known at this stage"),
-            short_channel_id: Default::default(),
+            short_channel_id: self.short_channel_id().unwrap_or_default(),
             chain_hash: self.chain_hash(),
             inbound_capacity_msat: self.remote_amount_msat(),
             outbound_capacity_msat: self.local_amount_msat(),
@@ -352,6 +1019,13 @@ impl Channel<BoltExt> {
     pub fn funding_script_pubkey(&self) -> PubkeyScript {
         let funding = self.funding();
         let core = self.constructor();
+        if core.is_taproot() {
+            return PubkeyScript::ln_taproot_funding(
+                funding.amount(),
+                &core.local_keys().funding_pubkey,
+                core.remote_keys().funding_pubkey,
+            );
+        }
         PubkeyScript::ln_funding(
             funding.amount(),
             &core.local_keys().funding_pubkey,
@@ -359,6 +1033,50 @@ impl Channel<BoltExt> {
         )
     }
 
+    /// Produces MuSig2 partial signature for the current remote commitment
+    /// transaction of simple taproot channel, which has to be sent to the
+    /// remote peer in `funding_created`, `funding_signed` or
+    /// `commitment_signed` messages
+    pub fn sign_remote_commitment_musig2(
+        &mut self,
+        funding_secret: SecretKey,
+    ) -> Result<PartialSigWithNonce, Error> {
+        let sighash = self.commitment_sighash(true)?;
+        self.constructor_mut()
+            .sign_remote_commitment(funding_secret, sighash)
+    }
+
+    /// Constructs final Schnorr signature for the current local commitment
+    /// transaction of simple taproot channel from the MuSig2 partial signature
+    /// received from the remote peer
+    pub fn sign_local_commitment_musig2(
+        &mut self,
+        funding_secret: SecretKey,
+    ) -> Result<schnorr::Signature, Error> {
+        let sighash = self.commitment_sighash(false)?;
+        self.constructor_mut()
+            .sign_local_commitment(funding_secret, sighash)
+    }
+
+    /// Computes BIP-341 signature hash for the key-path spending of the
+    /// funding output by the commitment transaction
+    fn commitment_sighash(&mut self, remote: bool) -> Result<[u8; 32], Error> {
+        let psbt = self.commitment_tx(remote)?;
+        let prevout = psbt.inputs[0]
+            .witness_utxo
+            .clone()
+            .expect("commitment transaction always spends funding output");
+        let tx = psbt.into_unsigned_tx();
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&[prevout]),
+                SchnorrSighashType::Default,
+            )
+            .expect("commitment transaction has a single input");
+        Ok(sighash.into_inner())
+    }
+
     #[inline]
     pub fn feerate_per_kw(&self) -> u32 {
         let core = self.constructor();
@@ -427,6 +1145,20 @@ pub struct BoltChannel {
     #[getter(as_ref)]
     policy: Policy,
 
+    /// Hook applying custom rules for accepting inbound channels. Not
+    /// persisted.
+    #[getter(skip)]
+    #[strict_encoding(skip)]
+    acceptor: Option<AcceptorHook>,
+
+    /// Features announced by the local node in its `init` message
+    #[getter(as_ref)]
+    local_features: InitFeatures,
+
+    /// Features announced by the remote node in its `init` message
+    #[getter(as_ref)]
+    remote_features: InitFeatures,
+
     /// Common parameters applying for both nodes
     #[getter(as_copy)]
     common_params: CommonParams,
@@ -453,6 +1185,61 @@ pub struct BoltChannel {
     /// Keeps information about node directionality
     #[getter(as_copy)]
     direction: Direction,
+
+    /// Progress of the channel quiescence negotiation
+    #[getter(as_copy)]
+    quiescence: Quiescence,
+
+    /// Splice which is being negotiated with the remote peer
+    #[getter(as_copy)]
+    splice: Option<SpliceParams>,
+
+    /// Channel balance changes introduced by each of the pending splice
+    /// transactions
+    #[getter(as_ref)]
+    splice_candidates: BTreeMap<Txid, SpliceContributions>,
+
+    /// Splice transaction which has reached enough confirmations from the
+    /// local node point of view
+    #[getter(as_copy)]
+    local_splice_locked: Option<Txid>,
+
+    /// Splice transaction which has reached enough confirmations from the
+    /// remote node point of view
+    #[getter(as_copy)]
+    remote_splice_locked: Option<Txid>,
+
+    /// Short channel id, known once the funding transaction is mined
+    #[getter(as_copy)]
+    short_channel_id: Option<ShortChannelId>,
+
+    /// Short channel id alias allocated by the local node, which it recognizes
+    /// for the HTLCs routed through this channel
+    #[getter(as_copy)]
+    local_alias: Option<ShortChannelId>,
+
+    /// Short channel id alias allocated by the remote node, which must be used
+    /// for routing through this channel until the real short channel id is
+    /// known
+    #[getter(as_copy)]
+    remote_alias: Option<ShortChannelId>,
+
+    /// MuSig2 secret nonce of the local node, whose public counterpart was
+    /// sent to the remote peer for signing the next local commitment
+    /// transaction in simple taproot channel. Nonces are never persisted.
+    #[getter(skip)]
+    #[strict_encoding(skip)]
+    local_nonce: NonceSlot,
+
+    /// MuSig2 public nonce of the remote node, which it will use for signing
+    /// its next commitment transaction in simple taproot channel
+    #[getter(as_copy)]
+    remote_nonce: Option<PublicNonce>,
+
+    /// MuSig2 partial signature of the remote node on the current local
+    /// commitment transaction in simple taproot channel
+    #[getter(as_copy)]
+    remote_partial_sig: Option<PartialSigWithNonce>,
 }
 
 impl Default for BoltChannel {
@@ -468,6 +1255,9 @@ impl Default for BoltChannel {
             commitment_number: 0,
             commitment_sigs: vec![],
             policy: default!(),
+            acceptor: None,
+            local_features: default!(),
+            remote_features: default!(),
             common_params: default!(),
             local_params: default!(),
             remote_params: default!(),
@@ -476,6 +1266,17 @@ impl Default for BoltChannel {
             remote_per_commitment_point: dumb_pubkey!(),
             local_per_commitment_point: dumb_pubkey!(),
             direction,
+            quiescence: default!(),
+            splice: None,
+            splice_candidates: empty!(),
+            local_splice_locked: None,
+            remote_splice_locked: None,
+            short_channel_id: None,
+            local_alias: None,
+            remote_alias: None,
+            local_nonce: default!(),
+            remote_nonce: None,
+            remote_partial_sig: None,
         }
     }
 }
@@ -531,12 +1332,94 @@ impl BoltChannel {
         self.policy = policy
     }
 
-    /// Sets common parameters for the channel
+    /// Sets features announced by the local node in its `init` message
     #[inline]
-    pub fn set_common_params(&mut self, params: CommonParams) {
+    pub fn set_local_features(&mut self, features: InitFeatures) {
+        self.local_features = features
+    }
+
+    /// Sets channel acceptor hook
+    #[inline]
+    pub fn set_acceptor(&mut self, acceptor: AcceptorHook) {
+        self.acceptor = Some(acceptor)
+    }
+
+    /// Applies decision of the channel acceptor, if any, to the channel
+    /// proposed by the remote peer. Local parameters overridden by the
+    /// acceptor are validated against the channel policy.
+    fn run_acceptor(&mut self, proposal: ChannelProposal) -> Result<(), Error> {
+        let acceptor = match &self.acceptor {
+            Some(acceptor) => acceptor,
+            None => return Ok(()),
+        };
+        match acceptor.accept(
+            proposal,
+            &self.local_features,
+            &self.remote_features,
+        ) {
+            AcceptorDecision::Accept => {}
+            AcceptorDecision::Override(overrides) => {
+                // Empty script means no commitment to a specific script
+                if let Some(script) = &overrides.shutdown_scriptpubkey {
+                    let anysegwit =
+                        self.local_features.option_shutdown_anysegwit.is_some()
+                            && self
+                                .remote_features
+                                .option_shutdown_anysegwit
+                                .is_some();
+                    if !script.as_inner().is_empty()
+                        && !is_valid_shutdown_script(script, anysegwit)
+                    {
+                        return Err(ShutdownScriptError::InvalidForm(
+                            script.clone(),
+                        )
+                        .into());
+                    }
+                }
+                if let Some(mut params) = overrides.local_params {
+                    if let ChannelProposal::V2(open_channel) = proposal {
+                        // Reserve is not negotiated in dual-funded channels
+                        params.set_dual_funded_reserve(
+                            open_channel.funding_satoshis,
+                        );
+                    }
+                    self.policy
+                        .validate_local_params(params, self.remote_params)?;
+                    self.local_params = params;
+                }
+                if let Some(depth) = overrides.minimum_depth {
+                    self.common_params.minimum_depth = depth;
+                }
+                if let Some(script) = overrides.shutdown_scriptpubkey {
+                    self.local_keys.shutdown_scriptpubkey = Some(script);
+                }
+            }
+            AcceptorDecision::Reject(reason) => {
+                return Err(Error::ChannelRejected(reason))
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets common parameters for the channel.
+    ///
+    /// Channel type is fixed once the channel negotiation has started, so
+    /// after the channel has left [`Lifecycle::Initial`] stage the channel
+    /// type from the provided parameters is ignored.
+    #[inline]
+    pub fn set_common_params(&mut self, mut params: CommonParams) {
+        if self.stage != Lifecycle::Initial {
+            params.channel_type = self.common_params.channel_type;
+        }
         self.common_params = params
     }
 
+    /// Returns channel type negotiated during the channel opening
+    #[inline]
+    pub fn channel_type(&self) -> ChannelType {
+        self.common_params.channel_type
+    }
+
     /// Sets local parameters for the channel
     #[inline]
     pub fn set_local_params(&mut self, params: PeerParams) {
@@ -567,81 +1450,236 @@ impl Extension<BoltExt> for BoltChannel {
         Ok(())
     }
 
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        _message: &mut Messages,
+    ) -> Result<(), Error> {
+        match request {
+            UpdateReq::UpdateFee
+            | UpdateReq::PayBolt(_)
+            | UpdateReq::FulfillHtlc
+            | UpdateReq::FailHtlc
+            | UpdateReq::PayPtlc(_)
+            | UpdateReq::FulfillPtlc
+            | UpdateReq::FailPtlc
+                if self.quiescence.sent =>
+            {
+                Err(SpliceError::UpdateWhileQuiescent.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_peer_message(&self, message: &Messages) -> Result<(), Error> {
+        if self.quiescence.received && is_update_message(message) {
+            return Err(SpliceError::UpdateWhileQuiescent.into());
+        }
+        if let Messages::UpdateFee(update_fee) = message {
+            if self.direction.is_outbound() {
+                return Err(Error::FeeUpdateByNonFunder);
+            }
+            let feerate_range = &self.policy.feerate_per_kw_range;
+            if !feerate_range.contains(&update_fee.feerate_per_kw) {
+                return Err(PolicyError::FeeRateUnreasonable {
+                    proposed: update_fee.feerate_per_kw,
+                    lowest_accepted: feerate_range.start,
+                    highest_accepted: feerate_range.end,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
     fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
         // TODO: Check lifecycle
         let result = match message {
+            Messages::Init(init) => {
+                self.remote_features = init.local_features.clone();
+            }
             Messages::OpenChannel(open_channel) => {
                 self.stage = Lifecycle::Proposed;
 
                 self.direction = Direction::Inbound;
                 self.active_channel_id =
                     ActiveChannelId::from(open_channel.temporary_channel_id);
-                self.remote_amount_msat = open_channel.funding_satoshis * 1000
-                    - open_channel.push_msat;
-                self.local_amount_msat = open_channel.push_msat;
-
-                // TODO: Add channel checks and fail on:
-                // 1) the `chain_hash` value is set to a hash of a chain that is
-                //    unknown to the receiver;
-                // 2) `push_msat` is greater than `funding_satoshis` * 1000;
-                // 3) the funder's amount for the initial commitment transaction
-                //    is not sufficient for full fee payment;
-                // 4) both `to_local` and `to_remote` amounts for the initial
-                //    commitment transaction are less than or equal to
-                //    `channel_reserve_satoshis`;
-                // 5) funding_satoshis is greater than or equal to 2^24 and the
-                //    receiver does not support `option_support_large_channel`.
+                let funding_msat = funding_msat(open_channel.funding_satoshis)?;
+                self.remote_amount_msat = funding_msat
+                    .checked_sub(open_channel.push_msat)
+                    .ok_or(PolicyError::PushExceedsFunding {
+                        push_msat: open_channel.push_msat,
+                        funding_msat,
+                    })?;
+                self.local_amount_msat = open_channel.push_msat;
+
+                // TODO: Add channel checks and fail on:
+                // 1) the `chain_hash` value is set to a hash of a chain that is
+                //    unknown to the receiver;
+                // 3) the funder's amount for the initial commitment transaction
+                //    is not sufficient for full fee payment;
+                // 4) both `to_local` and `to_remote` amounts for the initial
+                //    commitment transaction are less than or equal to
+                //    `channel_reserve_satoshis`;
+                // 5) funding_satoshis is greater than or equal to 2^24 and the
+                //    receiver does not support `option_support_large_channel`.
+
+                // Keys
+                self.remote_keys.funding_pubkey = open_channel.funding_pubkey;
+                self.remote_keys.payment_basepoint = open_channel.payment_point;
+                self.remote_keys.revocation_basepoint =
+                    open_channel.revocation_basepoint;
+                self.remote_keys.delayed_payment_basepoint =
+                    open_channel.delayed_payment_basepoint;
+                self.remote_keys.htlc_basepoint = open_channel.htlc_basepoint;
+                self.remote_keys.first_per_commitment_point =
+                    open_channel.first_per_commitment_point;
+                self.remote_per_commitment_point =
+                    open_channel.first_per_commitment_point;
+
+                // Policies
+                self.common_params = CommonParams::with(
+                    open_channel,
+                    self.policy.inbound_minimum_depth(),
+                );
+                let static_remotekey =
+                    self.common_params.channel_type.has_static_remotekey();
+                self.local_keys.static_remotekey = static_remotekey;
+                self.remote_keys.static_remotekey = static_remotekey;
+                self.remote_nonce = open_channel.next_local_nonce;
+                let inbound_params = self.policy.validate_inbound(open_channel);
+                self.remote_params = inbound_params?;
+
+                // Custom rules of the local node
+                self.run_acceptor(ChannelProposal::V1(open_channel))?;
+            }
+            Messages::AcceptChannel(accept_channel) => {
+                self.stage = Lifecycle::Accepted;
+
+                // TODO: Add other checks
+                // 1) the `temporary_channel_id` MUST be the same as the
+                //    `temporary_channel_id` in the `open_channel` message;
+
+                // Channel type is fixed by the `open_channel` message; if the
+                // remote peer provides `channel_type` it must match it
+                let proposed = self.common_params.channel_type;
+                if let Some(accepted) = accept_channel.channel_type {
+                    if accepted != proposed {
+                        return Err(Error::ChannelTypeMismatch {
+                            proposed,
+                            accepted,
+                        });
+                    }
+                }
+                let static_remotekey = proposed.has_static_remotekey();
+                self.local_keys.static_remotekey = static_remotekey;
+                self.remote_keys.static_remotekey = static_remotekey;
+                self.common_params.minimum_depth = accept_channel.minimum_depth;
+
+                // Keys
+                self.remote_keys.funding_pubkey = accept_channel.funding_pubkey;
+                self.remote_keys.payment_basepoint =
+                    accept_channel.payment_point;
+                self.remote_keys.revocation_basepoint =
+                    accept_channel.revocation_basepoint;
+                self.remote_keys.delayed_payment_basepoint =
+                    accept_channel.delayed_payment_basepoint;
+                self.remote_keys.htlc_basepoint = accept_channel.htlc_basepoint;
+                self.remote_keys.first_per_commitment_point =
+                    accept_channel.first_per_commitment_point;
+                self.remote_per_commitment_point =
+                    accept_channel.first_per_commitment_point;
+                self.remote_nonce = accept_channel.next_local_nonce;
+
+                // Policies
+                let outbound_params = self
+                    .policy
+                    .confirm_outbound(self.local_params, accept_channel);
+                self.remote_params = outbound_params?;
+            }
+            Messages::OpenChannel2(open_channel) => {
+                self.stage = Lifecycle::Proposed;
+
+                self.direction = Direction::Inbound;
+                self.active_channel_id =
+                    ActiveChannelId::from(open_channel.temporary_channel_id);
+                self.remote_amount_msat =
+                    funding_msat(open_channel.funding_satoshis)?;
+                self.local_amount_msat = 0;
 
                 // Keys
                 self.remote_keys.funding_pubkey = open_channel.funding_pubkey;
-                self.remote_keys.payment_basepoint = open_channel.payment_point;
+                self.remote_keys.payment_basepoint =
+                    open_channel.payment_basepoint;
                 self.remote_keys.revocation_basepoint =
                     open_channel.revocation_basepoint;
                 self.remote_keys.delayed_payment_basepoint =
                     open_channel.delayed_payment_basepoint;
+                self.remote_keys.htlc_basepoint = open_channel.htlc_basepoint;
                 self.remote_keys.first_per_commitment_point =
                     open_channel.first_per_commitment_point;
                 self.remote_per_commitment_point =
                     open_channel.first_per_commitment_point;
 
                 // Policies
-                self.common_params = CommonParams::with(
+                self.common_params = CommonParams::with_v2(
                     open_channel,
-                    open_channel.to_self_delay.into(),
+                    self.policy.inbound_minimum_depth(),
                 );
-                let inbound_params = self.policy.validate_inbound(open_channel);
+                let static_remotekey =
+                    self.common_params.channel_type.has_static_remotekey();
+                self.local_keys.static_remotekey = static_remotekey;
+                self.remote_keys.static_remotekey = static_remotekey;
+                let inbound_params =
+                    self.policy.validate_inbound_v2(open_channel);
                 self.remote_params = inbound_params?;
+
+                // Custom rules of the local node
+                self.run_acceptor(ChannelProposal::V2(open_channel))?;
             }
-            Messages::AcceptChannel(accept_channel) => {
+            Messages::AcceptChannel2(accept_channel) => {
                 self.stage = Lifecycle::Accepted;
 
-                // TODO: Add channel type and other checks
-                // 1) the `temporary_channel_id` MUST be the same as the
-                //    `temporary_channel_id` in the `open_channel`
-                //    message;
-                // 2) if `channel_type` is set, and `channel_type` was set in
-                //    `open_channel`, and they are equal types;
-                // 3) if the `channel_type` is not set it must had not been set
-                //    in the `open_channel`.
+                let proposed = self.common_params.channel_type;
+                if let Some(accepted) = accept_channel.channel_type {
+                    if accepted != proposed {
+                        return Err(Error::ChannelTypeMismatch {
+                            proposed,
+                            accepted,
+                        });
+                    }
+                }
+                let static_remotekey = proposed.has_static_remotekey();
+                self.local_keys.static_remotekey = static_remotekey;
+                self.remote_keys.static_remotekey = static_remotekey;
+                self.common_params.minimum_depth = accept_channel.minimum_depth;
+                self.remote_amount_msat =
+                    funding_msat(accept_channel.funding_satoshis)?;
 
                 // Keys
                 self.remote_keys.funding_pubkey = accept_channel.funding_pubkey;
                 self.remote_keys.payment_basepoint =
-                    accept_channel.payment_point;
+                    accept_channel.payment_basepoint;
                 self.remote_keys.revocation_basepoint =
                     accept_channel.revocation_basepoint;
                 self.remote_keys.delayed_payment_basepoint =
                     accept_channel.delayed_payment_basepoint;
+                self.remote_keys.htlc_basepoint = accept_channel.htlc_basepoint;
                 self.remote_keys.first_per_commitment_point =
                     accept_channel.first_per_commitment_point;
                 self.remote_per_commitment_point =
                     accept_channel.first_per_commitment_point;
 
                 // Policies
-                let outbound_params = self
-                    .policy
-                    .confirm_outbound(self.local_params, accept_channel);
+                let outbound_params = self.policy.confirm_outbound_v2(
+                    self.local_amount_msat / 1000,
+                    accept_channel,
+                );
                 self.remote_params = outbound_params?;
+                self.local_params
+                    .set_dual_funded_reserve(self.total_funding_sat());
+
+                self.active_channel_id = self.dual_funded_channel_id().into();
             }
             Messages::FundingCreated(funding_created) => {
                 self.stage = Lifecycle::Funding;
@@ -650,6 +1688,8 @@ impl Extension<BoltExt> for BoltChannel {
                     funding_created.funding_txid,
                     funding_created.funding_output_index,
                 );
+                self.remote_partial_sig =
+                    funding_created.partial_signature_with_nonce;
             }
             Messages::FundingSigned(funding_signed) => {
                 self.stage = Lifecycle::Funded;
@@ -657,12 +1697,108 @@ impl Extension<BoltExt> for BoltChannel {
                 self.active_channel_id =
                     ActiveChannelId::from(funding_signed.channel_id);
                 self.commitment_sigs.push(funding_signed.signature);
+                self.remote_partial_sig =
+                    funding_signed.partial_signature_with_nonce;
                 // TODO: Verify signature against transaction
             }
             Messages::FundingLocked(funding_locked) => {
-                self.stage = Lifecycle::Locked; // TODO: or Active
+                // Zero-conf channels become operational without waiting for
+                // the funding transaction to be mined
+                self.stage = if self.is_zero_conf() {
+                    Lifecycle::Active
+                } else {
+                    Lifecycle::Locked // TODO: or Active
+                };
                 self.remote_per_commitment_point =
                     funding_locked.next_per_commitment_point;
+                if let Some(alias) = funding_locked.short_channel_id {
+                    self.remote_alias = Some(alias);
+                }
+                if let Some(nonce) = funding_locked.next_local_nonce {
+                    self.remote_nonce = Some(nonce);
+                }
+            }
+            Messages::CommitmentSigned(commitment_signed) => {
+                self.remote_partial_sig =
+                    commitment_signed.partial_signature_with_nonce;
+            }
+            Messages::RevokeAndAck(revoke_and_ack) => {
+                self.remote_per_commitment_point =
+                    revoke_and_ack.next_per_commitment_point;
+                if let Some(nonce) = revoke_and_ack.next_local_nonce {
+                    self.remote_nonce = Some(nonce);
+                }
+            }
+            Messages::Stfu(stfu) => {
+                self.check_quiescence_allowed()?;
+                self.quiescence.received = true;
+                if !self.quiescence.sent {
+                    self.quiescence.initiator = false;
+                } else if stfu.is_initiator() && self.quiescence.initiator {
+                    // Both peers have initiated quiescence simultaneously: the
+                    // channel funder wins
+                    self.quiescence.initiator = self.direction.is_outbound();
+                }
+                if self.quiescence.is_quiescent() {
+                    self.stage = Lifecycle::Quiescent;
+                }
+            }
+            Messages::SpliceInit(splice_init) => {
+                if self.stage != Lifecycle::Quiescent {
+                    return Err(SpliceError::NotQuiescent.into());
+                }
+                if self.quiescence.initiator {
+                    return Err(SpliceError::QuiescenceInitiator.into());
+                }
+                // TODO: Support funding key rotation during splicing
+                if splice_init.funding_pubkey != self.remote_keys.funding_pubkey
+                {
+                    return Err(SpliceError::FundingKeyChange.into());
+                }
+                let remote_contribution_sat =
+                    splice_init.funding_contribution_satoshis.into_inner();
+                check_contribution(
+                    self.remote_amount_msat,
+                    remote_contribution_sat,
+                )?;
+                self.splice = Some(SpliceParams {
+                    local_contribution_sat: 0,
+                    remote_contribution_sat,
+                    funding_feerate_perkw: splice_init.funding_feerate_perkw,
+                    locktime: splice_init.locktime,
+                });
+            }
+            Messages::SpliceAck(splice_ack) => {
+                if !self.quiescence.initiator {
+                    return Err(SpliceError::NotQuiescenceInitiator.into());
+                }
+                let splice =
+                    self.splice.as_mut().ok_or(SpliceError::NoSplice)?;
+                if splice_ack.funding_pubkey != self.remote_keys.funding_pubkey
+                {
+                    return Err(SpliceError::FundingKeyChange.into());
+                }
+                let remote_contribution_sat =
+                    splice_ack.funding_contribution_satoshis.into_inner();
+                check_contribution(
+                    self.remote_amount_msat,
+                    remote_contribution_sat,
+                )?;
+                splice.remote_contribution_sat = remote_contribution_sat;
+            }
+            Messages::SpliceLocked(splice_locked) => {
+                // If the splice was already locked by both peers, the
+                // candidate is already promoted to the channel funding
+                if self
+                    .splice_candidates
+                    .contains_key(&splice_locked.splice_txid)
+                {
+                    self.remote_splice_locked = Some(splice_locked.splice_txid);
+                }
+            }
+            Messages::UpdateFee(update_fee) => {
+                // The fee rate is validated by `validate_peer_message`
+                self.common_params.feerate_per_kw = update_fee.feerate_per_kw;
             }
             Messages::Shutdown(_)
             | Messages::ClosingSigned(_)
@@ -680,8 +1816,6 @@ impl Extension<BoltExt> for BoltChannel {
             Messages::UpdateFulfillHtlc(_)
             | Messages::UpdateFailHtlc(_)
             | Messages::UpdateFailMalformedHtlc(_)
-            | Messages::CommitmentSigned(_)
-            | Messages::RevokeAndAck(_)
             | Messages::ChannelReestablish(_)
             | _ => (),
         };
@@ -697,6 +1831,7 @@ impl Extension<BoltExt> for BoltChannel {
         self.commitment_number = state.commitment_number;
         self.commitment_sigs = state.commitment_sigs.clone();
         self.policy = state.policy.clone();
+        self.local_features = state.local_features.clone();
         self.common_params = state.common_params;
         self.local_params = state.local_params;
         self.remote_params = state.remote_params;
@@ -705,6 +1840,12 @@ impl Extension<BoltExt> for BoltChannel {
         self.remote_per_commitment_point = state.remote_per_commitment_point;
         self.local_per_commitment_point = state.local_per_commitment_point;
         self.direction = state.direction;
+        self.splice_candidates = state.splice_candidates.clone();
+        self.local_splice_locked = state.local_splice_locked;
+        self.remote_splice_locked = state.remote_splice_locked;
+        self.short_channel_id = state.short_channel_id;
+        self.local_alias = state.local_alias;
+        self.remote_alias = state.remote_alias;
     }
 
     fn store_state(&self, state: &mut ChannelState) {
@@ -716,6 +1857,7 @@ impl Extension<BoltExt> for BoltChannel {
         state.commitment_number = self.commitment_number;
         state.commitment_sigs = self.commitment_sigs.clone();
         state.policy = self.policy.clone();
+        state.local_features = self.local_features.clone();
         state.common_params = self.common_params;
         state.local_params = self.local_params;
         state.remote_params = self.remote_params;
@@ -724,6 +1866,12 @@ impl Extension<BoltExt> for BoltChannel {
         state.remote_per_commitment_point = self.remote_per_commitment_point;
         state.local_per_commitment_point = self.local_per_commitment_point;
         state.direction = self.direction;
+        state.splice_candidates = self.splice_candidates.clone();
+        state.local_splice_locked = self.local_splice_locked;
+        state.remote_splice_locked = self.remote_splice_locked;
+        state.short_channel_id = self.short_channel_id;
+        state.local_alias = self.local_alias;
+        state.remote_alias = self.remote_alias;
     }
 }
 
@@ -810,6 +1958,9 @@ impl BoltChannel {
             shutdown_scriptpubkey: local_keyset.shutdown_scriptpubkey,
             channel_flags: u8::from(common_params.announce_channel),
             channel_type: common_params.channel_type.into_option(),
+            next_local_nonce: self.next_local_nonce(),
+            // Filled in by the channel extensions, if needed
+            funding_assets: None,
             unknown_tlvs: none!(),
         })
     }
@@ -841,7 +1992,7 @@ impl BoltChannel {
                 .local_params
                 .channel_reserve_satoshis,
             htlc_minimum_msat: self.local_params.htlc_minimum_msat,
-            minimum_depth: self.policy.minimum_depth,
+            minimum_depth: self.common_params.minimum_depth,
             to_self_delay: self.local_params.to_self_delay,
             max_accepted_htlcs: self.local_params.max_accepted_htlcs,
             funding_pubkey: self.local_keys.funding_pubkey.key,
@@ -861,10 +2012,264 @@ impl BoltChannel {
                 .shutdown_scriptpubkey
                 .clone(),
             channel_type: self.common_params.channel_type.into_option(),
+            next_local_nonce: self.next_local_nonce(),
+            unknown_tlvs: none!(),
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn compose_open_channel2(
+        &mut self,
+        funding_sat: u64,
+        funding_feerate_perkw: u32,
+        locktime: u32,
+        policy: Policy,
+        common_params: CommonParams,
+        local_params: PeerParams,
+        local_keyset: LocalKeyset,
+    ) -> Result<OpenChannel2, Error> {
+        if self.stage != Lifecycle::Initial
+            && self.stage != Lifecycle::Reestablishing
+        {
+            return Err(Error::LifecycleMismatch {
+                current: self.stage,
+                required: &[Lifecycle::Initial, Lifecycle::Reestablishing],
+            });
+        }
+
+        self.direction = Direction::Outbount;
+        self.policy = policy;
+        self.common_params = common_params;
+        self.local_params = local_params;
+        self.local_keys = local_keyset.clone();
+        self.local_amount_msat = funding_sat * 1000;
+        self.remote_amount_msat = 0;
+        self.local_per_commitment_point =
+            local_keyset.first_per_commitment_point.key;
+
+        Ok(OpenChannel2 {
+            chain_hash: self.chain_hash(),
+            temporary_channel_id: self.temp_channel_id().expect(
+                "initial channel state must always have a temporary channel id",
+            ),
+            funding_feerate_perkw,
+            commitment_feerate_perkw: common_params.feerate_per_kw,
+            funding_satoshis: funding_sat,
+            dust_limit_satoshis: local_params.dust_limit_satoshis,
+            max_htlc_value_in_flight_msat: local_params
+                .max_htlc_value_in_flight_msat,
+            htlc_minimum_msat: local_params.htlc_minimum_msat,
+            to_self_delay: local_params.to_self_delay,
+            max_accepted_htlcs: local_params.max_accepted_htlcs,
+            locktime,
+            funding_pubkey: local_keyset.funding_pubkey.key,
+            revocation_basepoint: local_keyset.revocation_basepoint.key,
+            payment_basepoint: local_keyset.payment_basepoint.key,
+            delayed_payment_basepoint: local_keyset
+                .delayed_payment_basepoint
+                .key,
+            htlc_basepoint: local_keyset.htlc_basepoint.key,
+            first_per_commitment_point: local_keyset
+                .first_per_commitment_point
+                .key,
+            second_per_commitment_point: self.next_per_commitment_point(),
+            channel_flags: u8::from(common_params.announce_channel),
+            shutdown_scriptpubkey: local_keyset.shutdown_scriptpubkey,
+            channel_type: common_params.channel_type.into_option(),
+            require_confirmed_inputs: None,
+            unknown_tlvs: none!(),
+        })
+    }
+
+    fn compose_accept_channel2(
+        &mut self,
+        funding_sat: u64,
+    ) -> Result<AcceptChannel2, Error> {
+        if self.stage != Lifecycle::Initial
+            && self.stage != Lifecycle::Proposed
+            && self.stage != Lifecycle::Reestablishing
+        {
+            return Err(Error::LifecycleMismatch {
+                current: self.stage,
+                required: &[
+                    Lifecycle::Initial,
+                    Lifecycle::Proposed,
+                    Lifecycle::Reestablishing,
+                ],
+            });
+        }
+
+        let temporary_channel_id =
+            self.temp_channel_id().ok_or(Error::NoTemporaryId)?;
+
+        self.local_amount_msat = funding_sat * 1000;
+        let total_funding_sat = self.total_funding_sat();
+        self.local_params.set_dual_funded_reserve(total_funding_sat);
+        self.remote_params
+            .set_dual_funded_reserve(total_funding_sat);
+        self.active_channel_id = self.dual_funded_channel_id().into();
+
+        Ok(AcceptChannel2 {
+            temporary_channel_id,
+            funding_satoshis: funding_sat,
+            dust_limit_satoshis: self.local_params.dust_limit_satoshis,
+            max_htlc_value_in_flight_msat: self
+                .local_params
+                .max_htlc_value_in_flight_msat,
+            htlc_minimum_msat: self.local_params.htlc_minimum_msat,
+            minimum_depth: self.common_params.minimum_depth,
+            to_self_delay: self.local_params.to_self_delay,
+            max_accepted_htlcs: self.local_params.max_accepted_htlcs,
+            funding_pubkey: self.local_keys.funding_pubkey.key,
+            revocation_basepoint: self.local_keys.revocation_basepoint.key,
+            payment_basepoint: self.local_keys.payment_basepoint.key,
+            delayed_payment_basepoint: self
+                .local_keys
+                .delayed_payment_basepoint
+                .key,
+            htlc_basepoint: self.local_keys.htlc_basepoint.key,
+            first_per_commitment_point: self
+                .local_keys
+                .first_per_commitment_point
+                .key,
+            second_per_commitment_point: self.next_per_commitment_point(),
+            shutdown_scriptpubkey: self
+                .local_keys
+                .shutdown_scriptpubkey
+                .clone(),
+            channel_type: self.common_params.channel_type.into_option(),
+            require_confirmed_inputs: None,
+            unknown_tlvs: none!(),
+        })
+    }
+
+    /// Total channel funding contributed by both peers, in satoshis
+    #[inline]
+    fn total_funding_sat(&self) -> u64 {
+        (self.local_amount_msat + self.remote_amount_msat) / 1000
+    }
+
+    /// Channel id for the channels established with v2 protocol, derived from
+    /// the revocation basepoints of both peers
+    #[inline]
+    fn dual_funded_channel_id(&self) -> ChannelId {
+        ChannelId::with_revocation_basepoints(
+            self.local_keys.revocation_basepoint.key,
+            self.remote_keys.revocation_basepoint,
+        )
+    }
+
+    /// Detects whether the channel does not require its funding transaction to
+    /// be mined before becoming operational
+    #[inline]
+    pub fn is_zero_conf(&self) -> bool {
+        self.common_params.minimum_depth == 0
+    }
+
+    fn check_quiescence_allowed(&self) -> Result<(), Error> {
+        if self.stage != Lifecycle::Locked && self.stage != Lifecycle::Active {
+            return Err(Error::LifecycleMismatch {
+                current: self.stage,
+                required: &[Lifecycle::Locked, Lifecycle::Active],
+            });
+        }
+        Ok(())
+    }
+
+    fn compose_stfu(&mut self) -> Result<Stfu, Error> {
+        self.check_quiescence_allowed()?;
+        let channel_id = self.try_channel_id()?;
+        self.quiescence.sent = true;
+        self.quiescence.initiator = !self.quiescence.received;
+        if self.quiescence.is_quiescent() {
+            self.stage = Lifecycle::Quiescent;
+        }
+        Ok(Stfu {
+            channel_id,
+            initiator: u8::from(self.quiescence.initiator),
+        })
+    }
+
+    fn compose_splice_init(
+        &mut self,
+        contribution_sat: i64,
+        funding_feerate_perkw: u32,
+        locktime: u32,
+    ) -> Result<SpliceInit, Error> {
+        if self.stage != Lifecycle::Quiescent {
+            return Err(SpliceError::NotQuiescent.into());
+        }
+        if !self.quiescence.initiator {
+            return Err(SpliceError::NotQuiescenceInitiator.into());
+        }
+        check_contribution(self.local_amount_msat, contribution_sat)?;
+        self.splice = Some(SpliceParams {
+            local_contribution_sat: contribution_sat,
+            remote_contribution_sat: 0,
+            funding_feerate_perkw,
+            locktime,
+        });
+        Ok(SpliceInit {
+            channel_id: self.try_channel_id()?,
+            funding_contribution_satoshis: contribution_sat.into(),
+            funding_feerate_perkw,
+            locktime,
+            funding_pubkey: self.local_keys.funding_pubkey.key,
+            require_confirmed_inputs: None,
+            unknown_tlvs: none!(),
+        })
+    }
+
+    fn compose_splice_ack(
+        &mut self,
+        contribution_sat: i64,
+    ) -> Result<SpliceAck, Error> {
+        if self.quiescence.initiator {
+            return Err(SpliceError::QuiescenceInitiator.into());
+        }
+        check_contribution(self.local_amount_msat, contribution_sat)?;
+        let channel_id = self.try_channel_id()?;
+        let splice = self.splice.as_mut().ok_or(SpliceError::NoSplice)?;
+        splice.local_contribution_sat = contribution_sat;
+        Ok(SpliceAck {
+            channel_id,
+            funding_contribution_satoshis: contribution_sat.into(),
+            funding_pubkey: self.local_keys.funding_pubkey.key,
+            require_confirmed_inputs: None,
             unknown_tlvs: none!(),
         })
     }
 
+    /// Registers negotiated splice transaction as a pending funding candidate
+    /// and ends channel quiescence
+    fn add_splice_candidate(&mut self, txid: Txid) -> Result<(), Error> {
+        let splice = self.splice.take().ok_or(SpliceError::NoSplice)?;
+        self.splice_candidates.insert(txid, SpliceContributions {
+            local_sat: splice.local_contribution_sat,
+            remote_sat: splice.remote_contribution_sat,
+        });
+        self.quiescence = default!();
+        self.stage = Lifecycle::Active;
+        Ok(())
+    }
+
+    /// Applies balance changes from the splice locked by both peers
+    fn apply_splice(&mut self, txid: Txid) -> Result<(), Error> {
+        let contributions = self
+            .splice_candidates
+            .get(&txid)
+            .copied()
+            .ok_or(SpliceError::UnknownCandidate(txid))?;
+        let (local_amount_msat, remote_amount_msat) = contributions
+            .apply(self.local_amount_msat, self.remote_amount_msat);
+        self.local_amount_msat = local_amount_msat;
+        self.remote_amount_msat = remote_amount_msat;
+        self.splice_candidates = empty!();
+        self.local_splice_locked = None;
+        self.remote_splice_locked = None;
+        Ok(())
+    }
+
     fn compose_reestablish_channel(
         &mut self,
         remote_channel_reestablish: &ChannelReestablish,
@@ -897,13 +2302,127 @@ impl BoltChannel {
     }
 
     fn compose_funding_locked(&mut self) -> FundingLocked {
+        // Zero-conf and private channels can't be referenced by the real short
+        // channel id (which is either unknown yet or must not be disclosed),
+        // so we allocate an alias for them
+        if self.local_alias.is_none()
+            && (self.is_zero_conf() || !self.common_params.announce_channel)
+        {
+            self.local_alias = Some(ShortChannelId::random_alias());
+        }
         FundingLocked {
             channel_id: self
                 .active_channel_id
                 .channel_id()
                 .expect("channel id must be known at FUNDING_LOCKED stage"),
             next_per_commitment_point: self.next_per_commitment_point(),
+            short_channel_id: self.local_alias,
+            next_local_nonce: self.next_local_nonce(),
+            unknown_tlvs: none!(),
+        }
+    }
+
+    /// Detects whether the channel is a simple taproot channel
+    #[inline]
+    pub fn is_taproot(&self) -> bool {
+        self.common_params.channel_type.has_simple_taproot()
+    }
+
+    /// Generates new MuSig2 nonce for signing the next local commitment
+    /// transaction in simple taproot channel, returning its public part for
+    /// sending to the remote peer. For other channel types returns `None`.
+    fn next_local_nonce(&mut self) -> Option<PublicNonce> {
+        if !self.is_taproot() {
+            return None;
+        }
+        let nonce = SecretNonce::generate(
+            Slice32::random().into_inner(),
+            self.local_keys.funding_pubkey.key,
+            None,
+        )
+        .expect("nonce generation fails with negligible probability");
+        let public_nonce = nonce.public_nonce();
+        self.local_nonce.put(nonce);
+        Some(public_nonce)
+    }
+
+    /// Returns MuSig2 key aggregation context for the funding output of
+    /// simple taproot channel
+    pub fn funding_key_agg(&self) -> Result<KeyAggContext, Error> {
+        if !self.is_taproot() {
+            return Err(Error::NotTaproot);
         }
+        taproot::funding_key_agg(
+            self.local_keys.funding_pubkey.key,
+            self.remote_keys.funding_pubkey,
+        )
+        .map_err(Error::from)
+    }
+
+    /// Produces MuSig2 partial signature for the remote commitment transaction
+    /// with the given `sighash`, using a just-in-time nonce and the nonce
+    /// previously provided by the remote peer.
+    fn sign_remote_commitment(
+        &mut self,
+        funding_secret: SecretKey,
+        sighash: [u8; 32],
+    ) -> Result<PartialSigWithNonce, Error> {
+        let key_agg = self.funding_key_agg()?;
+        let remote_nonce = self.remote_nonce.ok_or(Error::NoRemoteNonce)?;
+        let nonce = SecretNonce::generate(
+            Slice32::random().into_inner(),
+            self.local_keys.funding_pubkey.key,
+            Some(key_agg.x_only_key()),
+        )?;
+        let public_nonce = nonce.public_nonce();
+        let partial_sig = key_agg.partial_sign(
+            nonce,
+            funding_secret,
+            &[public_nonce, remote_nonce],
+            sighash,
+        )?;
+        Ok(PartialSigWithNonce {
+            partial_sig,
+            nonce: public_nonce,
+        })
+    }
+
+    /// Completes signature of the local commitment transaction with the given
+    /// `sighash` by verifying remote partial signature and aggregating it with
+    /// the local one. Consumes local nonce, so a new one must be sent to the
+    /// remote peer afterwards.
+    fn sign_local_commitment(
+        &mut self,
+        funding_secret: SecretKey,
+        sighash: [u8; 32],
+    ) -> Result<schnorr::Signature, Error> {
+        let key_agg = self.funding_key_agg()?;
+        let remote_partial_sig =
+            self.remote_partial_sig.ok_or(Error::NoRemotePartialSig)?;
+        let local_nonce =
+            self.local_nonce.take().ok_or(MuSig2Error::InvalidNonce)?;
+        let nonces = [local_nonce.public_nonce(), remote_partial_sig.nonce];
+
+        key_agg.partial_verify(
+            remote_partial_sig.partial_sig,
+            remote_partial_sig.nonce,
+            self.remote_keys.funding_pubkey,
+            &nonces,
+            sighash,
+        )?;
+        let partial_sig = key_agg.partial_sign(
+            local_nonce,
+            funding_secret,
+            &nonces,
+            sighash,
+        )?;
+        key_agg
+            .aggregate(
+                &[partial_sig, remote_partial_sig.partial_sig],
+                &nonces,
+                sighash,
+            )
+            .map_err(Error::from)
     }
 
     pub fn compose_add_update_htlc(
@@ -924,6 +2443,30 @@ impl BoltChannel {
             payment_hash,
             cltv_expiry,
             onion_routing_packet: Onion::Onion(onion_packet),
+            asset: None,
+            unknown_tlvs: none!(),
+        });
+        Ok(message)
+    }
+
+    pub fn compose_add_update_ptlc(
+        &mut self,
+        amount_msat: u64,
+        payment_point: PublicKey,
+        cltv_expiry: u32,
+        route: Vec<Hop<PaymentOnion>>,
+    ) -> Result<Messages, Error> {
+        // TODO: Optimize and keep Secp256k1 on a permanent basis
+        let secp = Secp256k1::new();
+        let onion_packet =
+            OnionPacket::with(&secp, &route, &payment_point.serialize())?;
+        let message = Messages::UpdateAddPtlc(UpdateAddPtlc {
+            channel_id: self.try_channel_id()?,
+            ptlc_id: 0,
+            amount_msat,
+            payment_point,
+            cltv_expiry,
+            onion_routing_packet: Onion::Onion(onion_packet),
             unknown_tlvs: none!(),
         });
         Ok(message)
@@ -935,8 +2478,6 @@ impl BoltChannel {
     }
 
     fn remote_paymentpubkey(&self, as_remote_node: bool) -> PublicKey {
-        // TODO: Optimize and keep Secp256k1 on a permanent basis
-        let secp = Secp256k1::verification_only();
         let per_commitment_point = if as_remote_node {
             self.remote_per_commitment_point
         } else {
@@ -948,23 +2489,49 @@ impl BoltChannel {
             self.remote_keys.payment_basepoint
         };
 
-        let mut engine = sha256::Hash::engine();
-        engine.input(&per_commitment_point.serialize());
-        engine.input(&payment_basepoint.serialize());
-        let tweak = sha256::Hash::from_engine(engine);
-        let tweak = secp256k1::Scalar::from_be_bytes(tweak.into_inner())
-            .expect("negligible probability");
+        derive_pubkey(payment_basepoint, per_commitment_point)
+    }
+
+    /// Returns public key used in the `to_remote` output of the local (if
+    /// `as_remote_node` is `false`) or remote (if `as_remote_node` is `true`)
+    /// commitment transaction.
+    ///
+    /// For channels with `option_static_remotekey` (including all anchored
+    /// channel types) this is the payment basepoint of the party receiving the
+    /// output; otherwise the basepoint is tweaked with the per-commitment point
+    /// of the commitment owner.
+    pub fn to_remote_pubkey(&self, as_remote_node: bool) -> PublicKey {
+        if !self.common_params.channel_type.has_static_remotekey() {
+            return self.remote_paymentpubkey(as_remote_node);
+        }
+        if as_remote_node {
+            self.local_keys.payment_basepoint.key
+        } else {
+            self.remote_keys.payment_basepoint
+        }
+    }
 
-        let payment_pubkey = payment_basepoint;
-        payment_pubkey
-            .add_exp_tweak(&secp, &tweak)
-            .expect("negligible probability")
+    /// Derives private key for sweeping funds from the `to_remote` output
+    /// paying the local node in the remote commitment transaction with the
+    /// given `per_commitment_point`.
+    ///
+    /// For channels with `option_static_remotekey` (including all anchored
+    /// channel types) the key is the payment basepoint secret itself and the
+    /// `per_commitment_point` is ignored; for legacy channels it is tweaked
+    /// according to BOLT-3.
+    pub fn to_remote_sweep_key(
+        &self,
+        payment_basepoint_secret: SecretKey,
+        per_commitment_point: PublicKey,
+    ) -> SecretKey {
+        if self.common_params.channel_type.has_static_remotekey() {
+            payment_basepoint_secret
+        } else {
+            derive_privkey(payment_basepoint_secret, per_commitment_point)
+        }
     }
 
     fn local_delayedpubkey(&self, as_remote_node: bool) -> PublicKey {
-        // TODO: Optimize and keep Secp256k1 on a permanent basis
-        let secp = Secp256k1::verification_only();
-
         let delayed_payment_basepoint = if as_remote_node {
             self.remote_keys.delayed_payment_basepoint
         } else {
@@ -976,23 +2543,10 @@ impl BoltChannel {
             self.local_per_commitment_point
         };
 
-        let mut engine = sha256::Hash::engine();
-        engine.input(&per_commitment_point.serialize());
-        engine.input(&delayed_payment_basepoint.serialize());
-        let tweak = sha256::Hash::from_engine(engine);
-        let tweak = secp256k1::Scalar::from_be_bytes(tweak.into_inner())
-            .expect("negligible probability");
-
-        let delayed_pubkey = delayed_payment_basepoint;
-        delayed_pubkey
-            .add_exp_tweak(&secp, &tweak)
-            .expect("negligible probability")
+        derive_pubkey(delayed_payment_basepoint, per_commitment_point)
     }
 
     fn remote_revocationpubkey(&self, as_remote_node: bool) -> PublicKey {
-        // TODO: Optimize and keep Secp256k1 on a permanent basis
-        let secp = Secp256k1::verification_only();
-
         let revocation_basepoint = if as_remote_node {
             self.local_keys.revocation_basepoint.key
         } else {
@@ -1004,34 +2558,30 @@ impl BoltChannel {
             self.local_per_commitment_point
         };
 
-        let mut tweaked_revocation_basepoint = revocation_basepoint;
-        let mut engine = sha256::Hash::engine();
-        engine.input(&revocation_basepoint.serialize());
-        engine.input(&per_commitment_point.serialize());
-        let revocation_tweak = sha256::Hash::from_engine(engine);
-        let revocation_tweak =
-            secp256k1::Scalar::from_be_bytes(revocation_tweak.into_inner())
-                .expect("negligible probability");
-        tweaked_revocation_basepoint = tweaked_revocation_basepoint
-            .mul_tweak(&secp, &revocation_tweak)
-            .expect("negligible probability");
-
-        let mut tweaked_per_commitment_point = per_commitment_point;
-        let mut engine = sha256::Hash::engine();
-        engine.input(&per_commitment_point.serialize());
-        engine.input(&revocation_basepoint.serialize());
-        let per_commitment_tweak = sha256::Hash::from_engine(engine);
-        let per_commitment_tweak =
-            secp256k1::Scalar::from_be_bytes(per_commitment_tweak.into_inner())
-                .expect("negligible probability");
-        tweaked_per_commitment_point = tweaked_per_commitment_point
-            .mul_tweak(&secp, &per_commitment_tweak)
-            .expect("negligible probability");
-
-        tweaked_revocation_basepoint
-            .combine(&tweaked_per_commitment_point)
-            .expect("negligible probability")
+        derive_revocationpubkey(revocation_basepoint, per_commitment_point)
+    }
+}
+
+/// Converts channel funding proposed by a remote peer into millisatoshis
+fn funding_msat(funding_sat: u64) -> Result<u64, PolicyError> {
+    funding_sat
+        .checked_mul(1000)
+        .ok_or(PolicyError::FundingOverflow(funding_sat))
+}
+
+/// Checks that negative splice contribution does not exceed channel balance
+fn check_contribution(
+    balance_msat: u64,
+    contribution_sat: i64,
+) -> Result<(), SpliceError> {
+    let balance = balance_msat / 1000;
+    if contribution_sat < 0 && contribution_sat.unsigned_abs() > balance {
+        return Err(SpliceError::ContributionExceedsBalance {
+            balance,
+            contribution: contribution_sat,
+        });
     }
+    Ok(())
 }
 
 impl ChannelExtension<BoltExt> for BoltChannel {
@@ -1050,13 +2600,12 @@ impl ChannelExtension<BoltExt> for BoltChannel {
             (0x20u32 << 24) | (obscured_commitment as u32 & 0x00_FF_FF_FF);
         let sequence = (0x80u32 << 24) | (obscured_commitment >> 24) as u32;
 
+        // The fee is paid by the channel funder, whose balance goes to the
+        // `to_local` output only of its own commitment transaction
         let fee = self.commitment_fee();
+        let funder_is_owner = self.direction.is_outbound() != as_remote_node;
         let (to_remote_fee, to_local_fee) =
-            if self.direction == Direction::Outbount {
-                (fee, 0)
-            } else {
-                (0, fee)
-            };
+            if funder_is_owner { (0, fee) } else { (fee, 0) };
 
         tx_graph.cmt_version = 2;
         tx_graph.cmt_locktime = lock_time;
@@ -1064,34 +2613,78 @@ impl ChannelExtension<BoltExt> for BoltChannel {
         // We are doing counterparty's transaction!
         tx_graph.cmt_outs = Vec::with_capacity(2);
 
+        // Commitments spending pending splice transactions must reflect
+        // balance changes introduced by them
+        let (local_amount_msat, remote_amount_msat) = self
+            .splice_candidates
+            .get(&tx_graph.funding().txid())
+            .map(|contributions| {
+                contributions
+                    .apply(self.local_amount_msat, self.remote_amount_msat)
+            })
+            .unwrap_or((self.local_amount_msat, self.remote_amount_msat));
         let to_local_amount = if as_remote_node {
-            self.remote_amount_msat
+            remote_amount_msat
         } else {
-            self.local_amount_msat
+            local_amount_msat
         };
         let to_remote_amount = if as_remote_node {
-            self.local_amount_msat
+            local_amount_msat
         } else {
-            self.remote_amount_msat
+            remote_amount_msat
         };
         let to_self_delay = if as_remote_node {
             self.local_params.to_self_delay
         } else {
             self.remote_params.to_self_delay
         };
-        if to_local_amount > 0 {
-            tx_graph.cmt_outs.push(ScriptGenerators::ln_to_local(
-                to_local_amount / 1000 - to_local_fee,
-                self.remote_revocationpubkey(as_remote_node),
-                self.local_delayedpubkey(as_remote_node),
-                to_self_delay,
-            ));
+        // Balance outputs below the dust limit of the commitment owner are
+        // trimmed according to BOLT-3
+        let dust_limit = if as_remote_node {
+            self.remote_params.dust_limit_satoshis
+        } else {
+            self.local_params.dust_limit_satoshis
+        };
+        let to_local_sat =
+            (to_local_amount / 1000).saturating_sub(to_local_fee);
+        let to_remote_sat =
+            (to_remote_amount / 1000).saturating_sub(to_remote_fee);
+        if to_local_sat > 0 && to_local_sat >= dust_limit {
+            let amount = to_local_sat;
+            let revocationpubkey = self.remote_revocationpubkey(as_remote_node);
+            let delayedpubkey = self.local_delayedpubkey(as_remote_node);
+            let to_local = if self.is_taproot() {
+                TaprootScriptGenerators::ln_taproot_to_local(
+                    amount,
+                    revocationpubkey,
+                    delayedpubkey,
+                    to_self_delay,
+                )
+            } else {
+                ScriptGenerators::ln_to_local(
+                    amount,
+                    revocationpubkey,
+                    delayedpubkey,
+                    to_self_delay,
+                )
+            };
+            tx_graph.cmt_outs.push(to_local);
         }
-        if to_remote_amount > 0 {
-            tx_graph.cmt_outs.push(ScriptGenerators::ln_to_remote_v1(
-                to_remote_amount / 1000 - to_remote_fee,
-                self.remote_paymentpubkey(as_remote_node),
-            ));
+        if to_remote_sat > 0 && to_remote_sat >= dust_limit {
+            let amount = to_remote_sat;
+            let remote_pubkey = self.to_remote_pubkey(as_remote_node);
+            // Anchored channels lock `to_remote` with 1-block CSV delay
+            let to_remote = if self.is_taproot() {
+                TaprootScriptGenerators::ln_taproot_to_remote(
+                    amount,
+                    remote_pubkey,
+                )
+            } else if self.common_params.channel_type.has_anchors() {
+                ScriptGenerators::ln_to_remote_v2(amount, remote_pubkey)
+            } else {
+                ScriptGenerators::ln_to_remote_v1(amount, remote_pubkey)
+            };
+            tx_graph.cmt_outs.push(to_remote);
         }
         Ok(())
     }
@@ -1106,11 +2699,22 @@ impl ChannelConstructor<BoltExt> for BoltChannel {
         let vout = psbt
             .channel_funding_output()
             .ok_or(funding::Error::NoFundingOutput)?;
-        psbt.outputs[vout].witness_script = Some(WitnessScript::ln_funding(
-            funding.amount(),
-            &self.local_keys.funding_pubkey,
-            self.remote_keys.funding_pubkey,
-        ));
+        if self.is_taproot() {
+            // Taproot funding output is spent only through the key path with
+            // MuSig2 aggregated key, so it does not have a script tree
+            let key_agg = KeyAggContext::new([
+                self.local_keys.funding_pubkey.key,
+                self.remote_keys.funding_pubkey,
+            ])?;
+            psbt.outputs[vout].tap_internal_key = Some(key_agg.x_only_key());
+        } else {
+            psbt.outputs[vout].witness_script =
+                Some(WitnessScript::ln_funding(
+                    funding.amount(),
+                    &self.local_keys.funding_pubkey,
+                    self.remote_keys.funding_pubkey,
+                ));
+        }
         psbt.outputs[vout].bip32_derivation =
             self.local_keys.funding_pubkey.to_bip32_derivation_map();
         Ok(())
@@ -1373,9 +2977,12 @@ mod test {
     use amplify::hex::ToHex;
     use bitcoin::hashes::hex::FromHex;
     use bitcoin::{OutPoint, Script, Transaction, TxIn, Txid};
+    use p2p::bolt::{CommitmentSigned, HopRealm};
+    use secp256k1::SECP256K1;
     use wallet::psbt::PsbtVersion;
 
     use super::*;
+    use crate::channel::bolt::{DustExposure, HtlcSecret, ParamsOverride};
     use crate::channel::shared_ext::Bip96;
 
     macro_rules! pk {
@@ -1601,4 +3208,1083 @@ mod test {
             pk!("02916e326636d19c33f13e8c0c3a03dd157f332f3e99c317c141dd865eb01f8ff0")
         );
     }
+
+    #[test]
+    fn bolt3_localprivkey_derivation() {
+        let base_secret = SecretKey::from_str(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        )
+        .unwrap();
+        let per_commitment_point = pk!("025f7117a78150fe2ef97db7cfc83bd57b2e2c0d0dd25eaf467a4a1c2a45ce1486");
+        let core = core_for_tests();
+        assert_eq!(
+            core.to_remote_sweep_key(base_secret, per_commitment_point),
+            SecretKey::from_str(
+                "cbced912d3b21bf196a766651e436aff192362621ce317704ea2f75d87e7be0f"
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn to_remote_by_channel_type() {
+        let base_point = pk!("036d6caac248af96f6afa7f904f550253a0f3ef3f5aa2fe6838a95b216691468e2");
+        let per_commitment_point = pk!("025f7117a78150fe2ef97db7cfc83bd57b2e2c0d0dd25eaf467a4a1c2a45ce1486");
+        let funding = Funding::new();
+        let mut core = core_for_tests();
+        core.remote_amount_msat = 3000000000;
+        core.remote_keys.payment_basepoint = base_point;
+        core.local_per_commitment_point = per_commitment_point;
+
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        core.build_graph(&mut tx_graph, false).unwrap();
+        assert_eq!(
+            tx_graph.cmt_outs[0].script,
+            PubkeyScript::ln_to_remote_v1(0, pk!("0235f2dbfaa89b57ec7b055afe29849ef7ddfeb1cefdb9ebdc43f5494984db29e5"))
+        );
+
+        core.common_params.channel_type = ChannelType::StaticRemotekey;
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        core.build_graph(&mut tx_graph, false).unwrap();
+        assert_eq!(
+            tx_graph.cmt_outs[0].script,
+            PubkeyScript::ln_to_remote_v1(0, base_point)
+        );
+        let secret = SecretKey::from_str(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        )
+        .unwrap();
+        assert_eq!(
+            core.to_remote_sweep_key(secret, per_commitment_point),
+            secret
+        );
+
+        core.common_params.channel_type =
+            ChannelType::AnchorsZeroFeeHtlcTxStaticRemotekey;
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        core.build_graph(&mut tx_graph, false).unwrap();
+        assert_eq!(
+            tx_graph.cmt_outs[0].script,
+            PubkeyScript::ln_to_remote_v2(0, base_point)
+        );
+    }
+
+    #[test]
+    fn channel_type_fixed_at_open() {
+        let mut remote = core_for_tests();
+        remote.common_params.channel_type = ChannelType::StaticRemotekey;
+        let accept_channel = remote.compose_accept_channel().unwrap();
+
+        let mut core = core_for_tests();
+        assert_eq!(
+            core.update_from_peer(&Messages::AcceptChannel(accept_channel)),
+            Err(Error::ChannelTypeMismatch {
+                proposed: ChannelType::Basic,
+                accepted: ChannelType::StaticRemotekey
+            })
+        );
+
+        core.stage = Lifecycle::Proposed;
+        core.set_common_params(CommonParams {
+            channel_type: ChannelType::StaticRemotekey,
+            ..CommonParams::default()
+        });
+        assert_eq!(core.channel_type(), ChannelType::Basic);
+    }
+
+    #[test]
+    fn funding_amount_checks() {
+        let overflow = Err(Error::Policy(PolicyError::FundingOverflow(
+            u64::MAX / 1000 + 1,
+        )));
+
+        let mut open_channel = OpenChannel::dumb_default();
+        open_channel.funding_satoshis = u64::MAX / 1000 + 1;
+        let mut core = BoltChannel::default();
+        assert_eq!(
+            core.update_from_peer(&Messages::OpenChannel(open_channel.clone())),
+            overflow
+        );
+        open_channel.funding_satoshis = 1_000;
+        open_channel.push_msat = 1_000_001;
+        assert_eq!(
+            core.update_from_peer(&Messages::OpenChannel(open_channel)),
+            Err(Error::Policy(PolicyError::PushExceedsFunding {
+                push_msat: 1_000_001,
+                funding_msat: 1_000_000,
+            }))
+        );
+
+        let mut alice = Channel::<BoltExt>::default();
+        let open_channel2 = alice
+            .compose_open_channel2(
+                1_000_000,
+                253,
+                100,
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        let mut bob = Channel::<BoltExt>::default();
+        bob.update_from_peer(&Messages::OpenChannel2(open_channel2.clone()))
+            .unwrap();
+        let mut accept_channel2 = bob.compose_accept_channel2(0).unwrap();
+        accept_channel2.funding_satoshis = u64::MAX / 1000 + 1;
+        assert_eq!(
+            alice.update_from_peer(&Messages::AcceptChannel2(accept_channel2)),
+            overflow
+        );
+
+        let mut open_channel2 = open_channel2;
+        open_channel2.funding_satoshis = u64::MAX / 1000 + 1;
+        let mut core = BoltChannel::default();
+        assert_eq!(
+            core.update_from_peer(&Messages::OpenChannel2(open_channel2)),
+            overflow
+        );
+    }
+
+    fn keyset_for_tests(seed: u8) -> LocalKeyset {
+        let key = |n: u8| {
+            let sk = SecretKey::from_slice(&[seed + n; 32]).unwrap();
+            lk!(PublicKey::from_secret_key(secp256k1::SECP256K1, &sk))
+        };
+        LocalKeyset {
+            funding_pubkey: key(0),
+            revocation_basepoint: key(1),
+            payment_basepoint: key(2),
+            delayed_payment_basepoint: key(3),
+            htlc_basepoint: key(4),
+            first_per_commitment_point: key(5),
+            ..LocalKeyset::dumb_default()
+        }
+    }
+
+    fn prevtx_for_tests(value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(value as u32),
+            input: vec![],
+            output: vec![TxOut {
+                value,
+                script_pubkey: Script::new_v0_p2wpkh(
+                    &bitcoin::WPubkeyHash::all_zeros(),
+                ),
+            }],
+        }
+    }
+
+    fn dual_funded_channels(
+        bob_policy: Policy,
+    ) -> (Channel<BoltExt>, Channel<BoltExt>) {
+        let mut alice = Channel::<BoltExt>::with(
+            TempChannelId::random(),
+            default!(),
+            Policy::default(),
+            CommonParams::default(),
+            PeerParams::default(),
+            keyset_for_tests(1),
+        );
+        let mut bob = Channel::<BoltExt>::with(
+            TempChannelId::random(),
+            default!(),
+            bob_policy,
+            CommonParams::default(),
+            PeerParams::default(),
+            keyset_for_tests(11),
+        );
+
+        let open_channel = alice
+            .compose_open_channel2(
+                1_000_000,
+                253,
+                100,
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        bob.update_from_peer(&Messages::OpenChannel2(open_channel))
+            .unwrap();
+        let accept_channel = bob.compose_accept_channel2(500_000).unwrap();
+        alice
+            .update_from_peer(&Messages::AcceptChannel2(accept_channel))
+            .unwrap();
+
+        let mut alice_tx = alice.interactive_tx(100).unwrap();
+        let mut bob_tx = bob.interactive_tx(100).unwrap();
+        let msg = alice_tx
+            .add_input(prevtx_for_tests(1_100_000), 0, 0)
+            .unwrap();
+        bob_tx.update_from_peer(&Messages::TxAddInput(msg)).unwrap();
+        let msg = alice_tx
+            .add_output(1_500_000, alice.funding_script_pubkey())
+            .unwrap();
+        bob_tx
+            .update_from_peer(&Messages::TxAddOutput(msg))
+            .unwrap();
+        let msg = bob_tx.add_input(prevtx_for_tests(600_000), 0, 0).unwrap();
+        alice_tx
+            .update_from_peer(&Messages::TxAddInput(msg))
+            .unwrap();
+        let msg = bob_tx.complete().unwrap();
+        alice_tx
+            .update_from_peer(&Messages::TxComplete(msg))
+            .unwrap();
+        let msg = alice_tx.complete().unwrap();
+        bob_tx.update_from_peer(&Messages::TxComplete(msg)).unwrap();
+
+        alice.set_interactive_funding(&alice_tx).unwrap();
+        bob.set_interactive_funding(&bob_tx).unwrap();
+        (alice, bob)
+    }
+
+    #[test]
+    fn dual_funded_open() {
+        let (alice, bob) = dual_funded_channels(Policy::default());
+
+        assert!(alice.channel_id().is_some());
+        assert_eq!(alice.channel_id(), bob.channel_id());
+        assert_eq!(alice.local_amount_msat(), bob.remote_amount_msat());
+        assert_eq!(alice.remote_amount_msat(), bob.local_amount_msat());
+        assert_eq!(
+            alice.constructor().remote_params().channel_reserve_satoshis,
+            15_000
+        );
+        assert_eq!(alice.funding_script_pubkey(), bob.funding_script_pubkey());
+        assert_eq!(alice.funding().amount(), 1_500_000);
+        assert_eq!(alice.funding().outpoint(), bob.funding().outpoint());
+    }
+
+    #[test]
+    fn splice_in() {
+        let (mut alice, mut bob) = dual_funded_channels(Policy::default());
+        let funding_outpoint = alice.funding().outpoint();
+
+        // Splicing requires channel to be locked
+        assert!(matches!(
+            alice.compose_stfu(),
+            Err(Error::LifecycleMismatch { .. })
+        ));
+        let msg = alice.compose_funding_locked();
+        bob.update_from_peer(&Messages::FundingLocked(msg)).unwrap();
+        let msg = bob.compose_funding_locked();
+        alice
+            .update_from_peer(&Messages::FundingLocked(msg))
+            .unwrap();
+
+        let msg = alice.compose_stfu().unwrap();
+        assert!(msg.is_initiator());
+        bob.update_from_peer(&Messages::Stfu(msg)).unwrap();
+        let msg = bob.compose_stfu().unwrap();
+        assert!(!msg.is_initiator());
+        alice.update_from_peer(&Messages::Stfu(msg)).unwrap();
+        assert_eq!(alice.constructor().stage(), Lifecycle::Quiescent);
+        assert_eq!(bob.constructor().stage(), Lifecycle::Quiescent);
+
+        assert_eq!(
+            bob.compose_splice_init(100_000, 253, 200),
+            Err(SpliceError::NotQuiescenceInitiator.into())
+        );
+        let msg = alice.compose_splice_init(200_000, 253, 200).unwrap();
+        bob.update_from_peer(&Messages::SpliceInit(msg)).unwrap();
+        let msg = bob.compose_splice_ack(0).unwrap();
+        alice.update_from_peer(&Messages::SpliceAck(msg)).unwrap();
+
+        let (mut alice_tx, messages) = alice.splice_tx().unwrap();
+        let (mut bob_tx, none) = bob.splice_tx().unwrap();
+        assert!(none.is_empty());
+        for msg in messages {
+            bob_tx.update_from_peer(&msg).unwrap();
+        }
+        let msg = alice_tx.add_input(prevtx_for_tests(210_000), 0, 0).unwrap();
+        bob_tx.update_from_peer(&Messages::TxAddInput(msg)).unwrap();
+        let msg = bob_tx.complete().unwrap();
+        alice_tx
+            .update_from_peer(&Messages::TxComplete(msg))
+            .unwrap();
+        let msg = alice_tx.complete().unwrap();
+        bob_tx.update_from_peer(&Messages::TxComplete(msg)).unwrap();
+
+        let splice_txid = alice.add_splice_candidate(&alice_tx).unwrap();
+        assert_eq!(bob.add_splice_candidate(&bob_tx).unwrap(), splice_txid);
+        assert_eq!(alice.constructor().stage(), Lifecycle::Active);
+        assert_eq!(alice.funding().outpoint(), funding_outpoint);
+        assert_eq!(alice.funding().outpoints().len(), 2);
+
+        // Commitments are signed for both current and splice fundings
+        let commitments = alice.commitment_txs(false).unwrap();
+        assert_eq!(commitments.len(), 2);
+        assert_eq!(
+            commitments[0].inputs[0].previous_outpoint,
+            funding_outpoint
+        );
+        assert_eq!(
+            commitments[1].inputs[0].previous_outpoint.txid,
+            splice_txid
+        );
+        let to_local = |psbt: &Psbt| {
+            psbt.outputs
+                .iter()
+                .map(|output| output.amount)
+                .max()
+                .unwrap()
+        };
+        assert_eq!(
+            to_local(&commitments[1]) - to_local(&commitments[0]),
+            200_000
+        );
+
+        let local_amount_msat = alice.local_amount_msat();
+        let msg = alice.compose_splice_locked(splice_txid).unwrap();
+        bob.update_from_peer(&Messages::SpliceLocked(msg)).unwrap();
+        assert!(bob.funding().has_candidates());
+        let msg = bob.compose_splice_locked(splice_txid).unwrap();
+        assert!(!bob.funding().has_candidates());
+        alice
+            .update_from_peer(&Messages::SpliceLocked(msg))
+            .unwrap();
+
+        assert!(!alice.funding().has_candidates());
+        assert_eq!(alice.funding().txid(), splice_txid);
+        assert_eq!(alice.funding().amount(), 1_700_000);
+        assert_eq!(alice.local_amount_msat(), local_amount_msat + 200_000_000);
+        assert_eq!(alice.local_amount_msat(), bob.remote_amount_msat());
+        assert_eq!(alice.commitment_txs(false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn splice_out() {
+        let (mut alice, mut bob) = dual_funded_channels(Policy::default());
+        let msg = alice.compose_funding_locked();
+        bob.update_from_peer(&Messages::FundingLocked(msg)).unwrap();
+        let msg = bob.compose_funding_locked();
+        alice
+            .update_from_peer(&Messages::FundingLocked(msg))
+            .unwrap();
+
+        let msg = alice.compose_stfu().unwrap();
+        bob.update_from_peer(&Messages::Stfu(msg)).unwrap();
+
+        // No channel updates are allowed once `stfu` was sent or received
+        let channel_id = alice.try_channel_id().unwrap();
+        let feerate_per_kw = bob.constructor().common_params().feerate_per_kw;
+        assert_eq!(
+            alice.compose_update_fee(300).unwrap_err(),
+            SpliceError::UpdateWhileQuiescent.into()
+        );
+        let update_fee = Messages::UpdateFee(UpdateFee {
+            channel_id,
+            feerate_per_kw: 300,
+        });
+        assert_eq!(
+            bob.update_from_peer(&update_fee).unwrap_err(),
+            SpliceError::UpdateWhileQuiescent.into()
+        );
+        assert_eq!(
+            bob.constructor().common_params().feerate_per_kw,
+            feerate_per_kw
+        );
+
+        let msg = bob.compose_stfu().unwrap();
+        alice.update_from_peer(&Messages::Stfu(msg)).unwrap();
+        assert_eq!(alice.constructor().stage(), Lifecycle::Quiescent);
+
+        let msg = alice.compose_splice_init(-100_000, 253, 200).unwrap();
+        bob.update_from_peer(&Messages::SpliceInit(msg)).unwrap();
+        let msg = bob.compose_splice_ack(0).unwrap();
+        alice.update_from_peer(&Messages::SpliceAck(msg)).unwrap();
+
+        let (mut alice_tx, messages) = alice.splice_tx().unwrap();
+        let (mut bob_tx, _) = bob.splice_tx().unwrap();
+        for msg in messages {
+            bob_tx.update_from_peer(&msg).unwrap();
+        }
+        let msg = bob_tx.complete().unwrap();
+        alice_tx
+            .update_from_peer(&Messages::TxComplete(msg))
+            .unwrap();
+        let msg = alice_tx.complete().unwrap();
+        bob_tx.update_from_peer(&Messages::TxComplete(msg)).unwrap();
+
+        let splice_txid = alice.add_splice_candidate(&alice_tx).unwrap();
+        assert_eq!(bob.add_splice_candidate(&bob_tx).unwrap(), splice_txid);
+
+        // Pending candidates survive restart, while the funding encoding
+        // stays compatible with the one not knowing about splices
+        let mut state = ChannelState::dumb_default();
+        alice.store_state(&mut state);
+        let mut restored = Channel::<BoltExt>::default();
+        restored.load_state(&state);
+        assert_eq!(restored.funding().outpoints(), alice.funding().outpoints());
+        let unspliced = Funding::with(alice.funding().psbt().clone()).unwrap();
+        assert_eq!(
+            strict_encoding::strict_serialize(alice.funding()).unwrap(),
+            strict_encoding::strict_serialize(&unspliced).unwrap()
+        );
+
+        let local_amount_msat = alice.local_amount_msat();
+        let remote_amount_msat = alice.remote_amount_msat();
+        let msg = alice.compose_splice_locked(splice_txid).unwrap();
+        bob.update_from_peer(&Messages::SpliceLocked(msg)).unwrap();
+        let msg = bob.compose_splice_locked(splice_txid).unwrap();
+        alice
+            .update_from_peer(&Messages::SpliceLocked(msg))
+            .unwrap();
+
+        assert_eq!(alice.funding().txid(), splice_txid);
+        assert_eq!(alice.funding().amount(), 1_400_000);
+        assert_eq!(alice.local_amount_msat(), local_amount_msat - 100_000_000);
+        assert_eq!(alice.remote_amount_msat(), remote_amount_msat);
+        assert_eq!(alice.local_amount_msat(), bob.remote_amount_msat());
+    }
+
+    #[test]
+    fn channel_acceptor() {
+        let trusted = NodeId::from(keyset_for_tests(1).funding_pubkey.key);
+        let script = PubkeyScript::from(Script::new_v0_p2wpkh(
+            &bitcoin::WPubkeyHash::hash(b"bob"),
+        ));
+        let shutdown_scriptpubkey = Some(script.clone());
+        let acceptor: Arc<dyn ChannelAcceptor> = Arc::new(
+            move |proposal: ChannelProposal,
+                  remote_node: NodeId,
+                  local_features: &InitFeatures,
+                  remote_features: &InitFeatures| {
+                if remote_node != trusted {
+                    return AcceptorDecision::Reject(s!("unknown peer"));
+                }
+                if local_features.option_upfront_shutdown_script.is_none()
+                    || remote_features.option_upfront_shutdown_script.is_none()
+                {
+                    return AcceptorDecision::Reject(s!("no upfront script"));
+                }
+                if proposal.funding_satoshis() >= 10_000_000 {
+                    return AcceptorDecision::Accept;
+                }
+                AcceptorDecision::Override(ParamsOverride {
+                    local_params: Some(PeerParams {
+                        channel_reserve_satoshis: 100_000,
+                        ..PeerParams::default()
+                    }),
+                    minimum_depth: Some(6),
+                    shutdown_scriptpubkey: shutdown_scriptpubkey.clone(),
+                })
+            },
+        );
+
+        let open_channel = Channel::<BoltExt>::default()
+            .compose_open_channel(
+                1_000_000,
+                0,
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        let features = InitFeatures {
+            option_upfront_shutdown_script: Some(false),
+            ..default!()
+        };
+        let init = Messages::Init(p2p::bolt::Init {
+            global_features: none!(),
+            local_features: features.clone(),
+            assets: none!(),
+            unknown_tlvs: none!(),
+        });
+        let bob_with = |remote_node: NodeId| {
+            let mut bob = Channel::<BoltExt>::with(
+                TempChannelId::random(),
+                default!(),
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(11),
+            );
+            bob.set_local_features(features.clone());
+            bob.set_acceptor(remote_node, acceptor.clone());
+            bob
+        };
+
+        let mut untrusted = bob_with(NodeId::from(dumb_pubkey!()));
+        untrusted.update_from_peer(&init).unwrap();
+        assert_eq!(
+            untrusted
+                .update_from_peer(&Messages::OpenChannel(open_channel.clone())),
+            Err(Error::ChannelRejected(s!("unknown peer")))
+        );
+
+        let mut bob = bob_with(trusted);
+        assert_eq!(
+            bob.update_from_peer(&Messages::OpenChannel(open_channel.clone())),
+            Err(Error::ChannelRejected(s!("no upfront script")))
+        );
+        bob.update_from_peer(&init).unwrap();
+        bob.update_from_peer(&Messages::OpenChannel(open_channel))
+            .unwrap();
+        let accept_channel = bob.compose_accept_channel().unwrap();
+        assert_eq!(accept_channel.channel_reserve_satoshis, 100_000);
+        assert_eq!(accept_channel.minimum_depth, 6);
+        assert_eq!(accept_channel.shutdown_scriptpubkey, Some(script.clone()));
+
+        // Dual-funded channels are checked by the acceptor as well
+        let open_channel2 = Channel::<BoltExt>::default()
+            .compose_open_channel2(
+                1_000_000,
+                253,
+                100,
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        let mut untrusted = bob_with(NodeId::from(dumb_pubkey!()));
+        assert_eq!(
+            untrusted.update_from_peer(&Messages::OpenChannel2(
+                open_channel2.clone()
+            )),
+            Err(Error::ChannelRejected(s!("unknown peer")))
+        );
+        let mut bob2 = bob_with(trusted);
+        bob2.update_from_peer(&init).unwrap();
+        bob2.update_from_peer(&Messages::OpenChannel2(open_channel2))
+            .unwrap();
+        let accept_channel = bob2.compose_accept_channel2(0).unwrap();
+        assert_eq!(accept_channel.minimum_depth, 6);
+        assert_eq!(accept_channel.shutdown_scriptpubkey, Some(script));
+
+        // Overridden local parameters must comply with the policy
+        let mut bob = Channel::<BoltExt>::default();
+        bob.set_acceptor(
+            trusted,
+            Arc::new(
+                |_: ChannelProposal,
+                 _: NodeId,
+                 _: &InitFeatures,
+                 _: &InitFeatures| {
+                    AcceptorDecision::Override(ParamsOverride {
+                        local_params: Some(PeerParams {
+                            channel_reserve_satoshis: 100,
+                            ..PeerParams::default()
+                        }),
+                        ..default!()
+                    })
+                },
+            ),
+        );
+        let open_channel = Channel::<BoltExt>::default()
+            .compose_open_channel(
+                1_000_000,
+                0,
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        assert_eq!(
+            bob.update_from_peer(&Messages::OpenChannel(open_channel)),
+            Err(Error::Policy(PolicyError::ChannelReserveLessDust {
+                reserve: 100,
+                dust_limit: PeerParams::default().dust_limit_satoshis,
+            }))
+        );
+    }
+
+    #[test]
+    fn channel_acceptor_overrides() {
+        let override_with = |overrides: ParamsOverride| {
+            let acceptor: Arc<dyn ChannelAcceptor> = Arc::new(
+                move |_: ChannelProposal,
+                      _: NodeId,
+                      _: &InitFeatures,
+                      _: &InitFeatures| {
+                    AcceptorDecision::Override(overrides.clone())
+                },
+            );
+            acceptor
+        };
+        let features = InitFeatures {
+            option_shutdown_anysegwit: Some(false),
+            ..default!()
+        };
+        let init = |local_features: InitFeatures| {
+            Messages::Init(p2p::bolt::Init {
+                global_features: none!(),
+                local_features,
+                assets: none!(),
+                unknown_tlvs: none!(),
+            })
+        };
+        let open_channel = Channel::<BoltExt>::default()
+            .compose_open_channel(
+                1_000_000,
+                0,
+                Policy::default(),
+                CommonParams::default(),
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        let remote_node = NodeId::from(keyset_for_tests(1).funding_pubkey.key);
+
+        // Overridden shutdown script must have one of BOLT-2 forms, which
+        // depend on the negotiated features
+        let p2pk = PubkeyScript::from(Script::new_p2pk(
+            &bitcoin::PublicKey::new(keyset_for_tests(11).funding_pubkey.key),
+        ));
+        let taproot = PubkeyScript::from(
+            script::Builder::new()
+                .push_int(1)
+                .push_slice(&[0x42; 32])
+                .into_script(),
+        );
+        for (script, local_features, remote_features, valid) in [
+            (p2pk, features.clone(), features.clone(), false),
+            (taproot.clone(), features.clone(), default!(), false),
+            (taproot.clone(), default!(), features.clone(), false),
+            (taproot, features.clone(), features.clone(), true),
+            (empty!(), default!(), default!(), true),
+        ] {
+            let mut bob = Channel::<BoltExt>::default();
+            bob.set_local_features(local_features);
+            bob.set_acceptor(
+                remote_node,
+                override_with(ParamsOverride {
+                    shutdown_scriptpubkey: Some(script.clone()),
+                    ..default!()
+                }),
+            );
+            bob.update_from_peer(&init(remote_features)).unwrap();
+            let result = bob
+                .update_from_peer(&Messages::OpenChannel(open_channel.clone()));
+            if valid {
+                result.unwrap();
+                assert_eq!(
+                    bob.constructor().local_keys().shutdown_scriptpubkey,
+                    Some(script)
+                );
+            } else {
+                assert_eq!(
+                    result,
+                    Err(ShutdownScriptError::InvalidForm(script).into())
+                );
+            }
+        }
+
+        // Overridden parameters are propagated to the channel extensions: the
+        // HTLC below is not a dust with the default dust limit, but is a dust
+        // with the overridden one, exceeding the dust exposure limit
+        let policy = Policy {
+            max_dust_htlc_exposure: Some(DustExposure::FixedLimitMsat(500_000)),
+            ..Policy::default()
+        };
+        let htlc_with = |bob: &Channel<BoltExt>| {
+            let hashlock =
+                HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+            let route = vec![Hop::with(remote_node, PaymentOnion {
+                realm: HopRealm::Legacy(ShortChannelId::default()),
+                amt_to_forward: 600_000,
+                outgoing_cltv_value: 500,
+            })];
+            let onion_packet =
+                OnionPacket::with(SECP256K1, &route, hashlock.as_ref())
+                    .unwrap();
+            Messages::UpdateAddHtlc(UpdateAddHtlc {
+                channel_id: ChannelId::from_inner(
+                    bob.active_channel_id().as_slice32(),
+                ),
+                htlc_id: 0,
+                amount_msat: 600_000,
+                payment_hash: hashlock,
+                cltv_expiry: 500,
+                onion_routing_packet: Onion::Onion(onion_packet),
+                asset: None,
+                unknown_tlvs: none!(),
+            })
+        };
+        for (dust_limit_satoshis, accepted) in [(354, true), (1000, false)] {
+            let mut bob = Channel::<BoltExt>::default();
+            bob.set_policy(policy.clone());
+            bob.set_acceptor(
+                remote_node,
+                override_with(ParamsOverride {
+                    local_params: Some(PeerParams {
+                        dust_limit_satoshis,
+                        ..PeerParams::default()
+                    }),
+                    ..default!()
+                }),
+            );
+            bob.update_from_peer(&Messages::OpenChannel(open_channel.clone()))
+                .unwrap();
+            let htlc = htlc_with(&bob);
+            assert_eq!(bob.update_from_peer(&htlc).is_ok(), accepted);
+        }
+    }
+
+    #[test]
+    fn mutual_close() {
+        let (mut alice, mut bob) = dual_funded_channels(Policy::default());
+        let alice_script = PubkeyScript::from(Script::new_v0_p2wpkh(
+            &bitcoin::WPubkeyHash::hash(b"alice"),
+        ));
+        let bob_script = PubkeyScript::from(Script::new_v0_p2wsh(
+            &bitcoin::WScriptHash::hash(b"bob"),
+        ));
+
+        // No upfront shutdown script was set in the keyset
+        assert_eq!(
+            alice.compose_shutdown(None).unwrap_err(),
+            ShutdownScriptError::NoScript.into()
+        );
+        let msg = alice.compose_shutdown(Some(alice_script.clone())).unwrap();
+        bob.update_from_peer(&msg).unwrap();
+        assert_eq!(
+            alice.closing_tx(1000),
+            Err(ShutdownScriptError::NoShutdown.into())
+        );
+        let msg = bob.compose_shutdown(Some(bob_script.clone())).unwrap();
+        alice.update_from_peer(&msg).unwrap();
+
+        // Shutdown script can't be changed once sent
+        assert!(matches!(
+            alice.compose_shutdown(Some(bob_script.clone())),
+            Err(Error::ShutdownScript(
+                ShutdownScriptError::ScriptChanged { .. }
+            ))
+        ));
+
+        let tx = alice.closing_tx(1000).unwrap().into_unsigned_tx();
+        assert_eq!(tx, bob.closing_tx(1000).unwrap().into_unsigned_tx());
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output, alice.funding().outpoint());
+        assert_eq!(tx.input[0].sequence, bitcoin::Sequence::MAX);
+        // Alice has opened the channel, so she pays the fee
+        assert!(tx.output.contains(&TxOut {
+            value: 999_000,
+            script_pubkey: alice_script.into(),
+        }));
+        assert!(tx.output.contains(&TxOut {
+            value: 500_000,
+            script_pubkey: bob_script.into(),
+        }));
+    }
+
+    #[test]
+    fn closing_tx_checks() {
+        let (mut alice, mut bob) = dual_funded_channels(Policy::default());
+        let alice_script = PubkeyScript::from(Script::new_v0_p2wpkh(
+            &bitcoin::WPubkeyHash::hash(b"alice"),
+        ));
+        let bob_script = PubkeyScript::from(Script::new_v0_p2wsh(
+            &bitcoin::WScriptHash::hash(b"bob"),
+        ));
+        let msg = alice.compose_shutdown(Some(alice_script.clone())).unwrap();
+        bob.update_from_peer(&msg).unwrap();
+        let msg = bob.compose_shutdown(Some(bob_script)).unwrap();
+        alice.update_from_peer(&msg).unwrap();
+
+        // Closing is not possible until all HTLCs are resolved
+        let mut state = ChannelState::dumb_default();
+        alice.store_state(&mut state);
+        state.received_htlcs.insert(0, HtlcSecret {
+            amount: 100_000,
+            hashlock: HashLock::from_inner([1u8; 32].into()),
+            id: 0,
+            cltv_expiry: 500_000,
+        });
+        alice.load_state(&state);
+        assert_eq!(
+            alice.closing_tx(1000),
+            Err(ShutdownScriptError::PendingHtlcs(1).into())
+        );
+
+        // Both outputs are checked against the dust limit of the node
+        // constructing the transaction
+        state.received_htlcs.clear();
+        state.local_params.dust_limit_satoshis = 500_001;
+        state.remote_params.dust_limit_satoshis = 999_001;
+        alice.load_state(&state);
+        let tx = alice.closing_tx(1000).unwrap().into_unsigned_tx();
+        assert_eq!(tx.output, vec![TxOut {
+            value: 999_000,
+            script_pubkey: alice_script.into(),
+        }]);
+    }
+
+    #[test]
+    fn local_init_features() {
+        let features = InitFeatures {
+            option_upfront_shutdown_script: Some(false),
+            ..default!()
+        };
+        let init = Messages::Init(p2p::bolt::Init {
+            global_features: none!(),
+            local_features: features.clone(),
+            assets: none!(),
+            unknown_tlvs: none!(),
+        });
+        let open_channel = |channel: &mut Channel<BoltExt>| {
+            channel
+                .compose_open_channel(
+                    1_000_000,
+                    0,
+                    Policy::default(),
+                    CommonParams::default(),
+                    PeerParams::default(),
+                    keyset_for_tests(1),
+                )
+                .unwrap()
+        };
+
+        // Without local features the upfront shutdown script can't be
+        // negotiated
+        let mut alice = Channel::<BoltExt>::default();
+        alice.update_from_peer(&init).unwrap();
+        assert_eq!(open_channel(&mut alice).shutdown_scriptpubkey, None);
+
+        let mut alice = Channel::<BoltExt>::default();
+        alice.set_local_features(features.clone());
+        alice.update_from_peer(&init).unwrap();
+        assert_eq!(alice.constructor().local_features(), &features);
+        assert_eq!(
+            open_channel(&mut alice).shutdown_scriptpubkey,
+            Some(empty!())
+        );
+    }
+
+    #[test]
+    fn zero_conf_aliases() {
+        let (mut alice, mut bob) = dual_funded_channels(Policy::default());
+        assert!(!alice.constructor().is_zero_conf());
+        let msg = alice.compose_funding_locked();
+        // Announced channels which are not zero-conf do not need an alias
+        assert_eq!(msg.short_channel_id, None);
+        bob.update_from_peer(&Messages::FundingLocked(msg)).unwrap();
+        assert_eq!(bob.constructor().stage(), Lifecycle::Locked);
+
+        let (mut alice, mut bob) = dual_funded_channels(Policy {
+            accept_zero_conf: true,
+            ..Policy::default()
+        });
+        assert!(alice.constructor().is_zero_conf());
+        assert!(bob.constructor().is_zero_conf());
+        assert_eq!(alice.short_channel_id(), None);
+
+        let msg = alice.compose_funding_locked();
+        let alice_alias = msg.short_channel_id.unwrap();
+        assert!(alice_alias.is_alias());
+        assert_eq!(alice.constructor().local_alias(), Some(alice_alias));
+        bob.update_from_peer(&Messages::FundingLocked(msg)).unwrap();
+        let msg = bob.compose_funding_locked();
+        let bob_alias = msg.short_channel_id.unwrap();
+        alice
+            .update_from_peer(&Messages::FundingLocked(msg))
+            .unwrap();
+
+        assert_eq!(alice.constructor().stage(), Lifecycle::Active);
+        assert_eq!(bob.constructor().stage(), Lifecycle::Active);
+        assert_eq!(alice.short_channel_id(), Some(bob_alias));
+        assert_eq!(bob.short_channel_id(), Some(alice_alias));
+        let remote_node = NodeId::from(dumb_pubkey!());
+        assert_eq!(alice.channel_info(remote_node).short_channel_id, bob_alias);
+
+        let short_channel_id = ShortChannelId::with(700_000, 1, 0).unwrap();
+        alice.set_short_channel_id(short_channel_id);
+        assert_eq!(alice.short_channel_id(), Some(short_channel_id));
+        assert_eq!(
+            alice.channel_info(remote_node).short_channel_id,
+            short_channel_id
+        );
+    }
+
+    #[test]
+    fn simple_taproot_commitment() {
+        let common_params = CommonParams {
+            channel_type: ChannelType::SimpleTaproot,
+            ..CommonParams::default()
+        };
+        let mut alice = Channel::<BoltExt>::with(
+            TempChannelId::random(),
+            default!(),
+            Policy::default(),
+            common_params,
+            PeerParams::default(),
+            keyset_for_tests(1),
+        );
+        let mut bob = Channel::<BoltExt>::with(
+            TempChannelId::random(),
+            default!(),
+            Policy::default(),
+            CommonParams::default(),
+            PeerParams::default(),
+            keyset_for_tests(11),
+        );
+
+        let open_channel = alice
+            .compose_open_channel(
+                1_000_000,
+                100_000_000,
+                Policy::default(),
+                common_params,
+                PeerParams::default(),
+                keyset_for_tests(1),
+            )
+            .unwrap();
+        assert!(open_channel.next_local_nonce.is_some());
+        bob.update_from_peer(&Messages::OpenChannel(open_channel))
+            .unwrap();
+        assert!(bob.constructor().is_taproot());
+        let accept_channel = bob.compose_accept_channel().unwrap();
+        assert!(accept_channel.next_local_nonce.is_some());
+        alice
+            .update_from_peer(&Messages::AcceptChannel(accept_channel))
+            .unwrap();
+
+        // Funding output is a key-path only P2TR output with MuSig2 key
+        let funding_script = alice.funding_script_pubkey();
+        assert_eq!(funding_script, bob.funding_script_pubkey());
+        assert!(funding_script.is_v1_p2tr());
+        let key_agg = alice.constructor().funding_key_agg().unwrap();
+        // Signing context contains already tweaked key
+        assert_eq!(
+            &funding_script.as_inner()[2..],
+            &key_agg.x_only_key().serialize()[..]
+        );
+
+        let funding_tx = Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: OutPoint::new(
+                    prevtx_for_tests(1_100_000).txid(),
+                    0,
+                ),
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                value: 1_000_000,
+                script_pubkey: funding_script.into(),
+            }],
+        };
+        let mut funding_psbt = Psbt::with(funding_tx, PsbtVersion::V0).unwrap();
+        funding_psbt.set_channel_funding_output(0).unwrap();
+        alice.set_funding(funding_psbt.clone()).unwrap();
+        bob.set_funding(funding_psbt).unwrap();
+
+        // All commitment outputs are P2TR
+        let commitment = bob.commitment_tx(false).unwrap();
+        assert_eq!(
+            commitment.clone().into_unsigned_tx(),
+            alice.commitment_tx(true).unwrap().into_unsigned_tx()
+        );
+        assert_eq!(commitment.outputs.len(), 2);
+        assert!(commitment
+            .outputs
+            .iter()
+            .all(|output| output.script.is_v1_p2tr()));
+        assert!(commitment.inputs[0].tap_internal_key.is_some());
+
+        let alice_secret = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let bob_secret = SecretKey::from_slice(&[11u8; 32]).unwrap();
+        assert_eq!(
+            bob.sign_local_commitment_musig2(bob_secret),
+            Err(Error::NoRemotePartialSig)
+        );
+        let partial_sig =
+            alice.sign_remote_commitment_musig2(alice_secret).unwrap();
+        bob.update_from_peer(&Messages::CommitmentSigned(CommitmentSigned {
+            channel_id: ChannelId::default(),
+            signature: Signature::from_compact(&[1u8; 64]).unwrap(),
+            htlc_signatures: vec![],
+            partial_signature_with_nonce: Some(partial_sig),
+            unknown_tlvs: none!(),
+        }))
+        .unwrap();
+        let signature = bob.sign_local_commitment_musig2(bob_secret).unwrap();
+
+        let prevout = commitment.inputs[0].witness_utxo.clone().unwrap();
+        let tx = commitment.into_unsigned_tx();
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&[prevout]),
+                SchnorrSighashType::Default,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_slice(&sighash[..]).unwrap();
+        secp256k1::SECP256K1
+            .verify_schnorr(&signature, &msg, &key_agg.x_only_key())
+            .unwrap();
+    }
+
+    #[test]
+    fn update_fee_rejected_before_applying() {
+        let mut state = ChannelState::dumb_default();
+        state.common_params.feerate_per_kw = 253;
+        state.local_params.dust_limit_satoshis = 354;
+        state.remote_params.dust_limit_satoshis = 354;
+        state.policy.max_dust_htlc_exposure =
+            Some(DustExposure::FixedLimitMsat(500_000));
+        // Not trimmed at 253 sat/kw, but trimmed at 499 sat/kw
+        state.received_htlcs.insert(0, HtlcSecret {
+            amount: 600_000,
+            hashlock: HashLock::from_inner([1u8; 32].into()),
+            id: 0,
+            cltv_expiry: 500_000,
+        });
+        let mut channel = Channel::<BoltExt>::default();
+        channel.load_state(&state);
+
+        let update_fee = Messages::UpdateFee(UpdateFee {
+            channel_id: ChannelId::default(),
+            feerate_per_kw: 499,
+        });
+        assert!(channel.update_from_peer(&update_fee).is_err());
+        assert_eq!(channel.constructor().common_params().feerate_per_kw, 253);
+
+        let update_fee = Messages::UpdateFee(UpdateFee {
+            channel_id: ChannelId::default(),
+            feerate_per_kw: 300,
+        });
+        channel.update_from_peer(&update_fee).unwrap();
+        assert_eq!(channel.constructor().common_params().feerate_per_kw, 300);
+    }
+
+    #[test]
+    fn constructor_updates_propagated() {
+        let mut state = ChannelState::dumb_default();
+        state.common_params.feerate_per_kw = 253;
+        state.local_params.dust_limit_satoshis = 354;
+        state.remote_params.dust_limit_satoshis = 354;
+        state.policy.max_dust_htlc_exposure =
+            Some(DustExposure::FixedLimitMsat(500_000));
+        state.received_htlcs.insert(0, HtlcSecret {
+            amount: 600_000,
+            hashlock: HashLock::from_inner([1u8; 32].into()),
+            id: 0,
+            cltv_expiry: 500_000,
+        });
+        let mut channel = Channel::<BoltExt>::default();
+        channel.load_state(&state);
+
+        // Policy relaxed after the channel was restored must be seen by the
+        // HTLC extension checking the dust exposure
+        let mut policy = state.policy.clone();
+        policy.max_dust_htlc_exposure =
+            Some(DustExposure::FixedLimitMsat(1_000_000));
+        channel.set_policy(policy);
+        let update_fee = Messages::UpdateFee(UpdateFee {
+            channel_id: ChannelId::default(),
+            feerate_per_kw: 499,
+        });
+        channel.update_from_peer(&update_fee).unwrap();
+        assert_eq!(channel.constructor().common_params().feerate_per_kw, 499);
+    }
 }
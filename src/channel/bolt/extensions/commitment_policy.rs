@@ -0,0 +1,530 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::collections::BTreeMap;
+
+use p2p::bolt::Messages;
+
+use crate::channel::bolt::util::UpdateReq;
+use crate::channel::bolt::{
+    BoltExt, ChannelState, Direction, Error, PeerParams, TxType,
+};
+use crate::channel::tx_graph::TxGraph;
+use crate::{ChannelExtension, Extension};
+
+/// Channel peer offering HTLCs or owning a commitment transaction
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display)]
+pub enum Offerer {
+    /// HTLCs are offered by the local node
+    #[display("local")]
+    Local,
+
+    /// HTLCs are offered by the remote node
+    #[display("remote")]
+    Remote,
+}
+
+/// Violations of the channel parameters by a commitment transaction
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum CommitmentPolicyError {
+    /// commitment transaction contains {count} HTLCs offered by the {offerer}
+    /// node, while only {max_accepted} HTLCs can be accepted by its peer
+    MaxAcceptedHtlcsExceeded {
+        offerer: Offerer,
+        count: usize,
+        max_accepted: u16,
+    },
+
+    /// total value of HTLCs offered by the {offerer} node is {value_msat}
+    /// msat, exceeding the limit of {max_value_msat} msat in flight set by its
+    /// peer
+    MaxHtlcValueInFlightExceeded {
+        offerer: Offerer,
+        value_msat: u64,
+        max_value_msat: u64,
+    },
+
+    /// balance of the {offerer} node offering HTLCs is {balance_sat} sat,
+    /// which is below the channel reserve of {reserve_sat} sat required by its
+    /// peer
+    ChannelReserveViolated {
+        offerer: Offerer,
+        balance_sat: u64,
+        reserve_sat: u64,
+    },
+
+    /// dust HTLCs in the commitment transaction of the {owner} node amount to
+    /// {exposure_msat} msat, exceeding the dust exposure limit of
+    /// {limit_msat} msat set by the local policy
+    DustExposureExceeded {
+        owner: Offerer,
+        exposure_msat: u64,
+        limit_msat: u64,
+    },
+}
+
+/// Policy extension enforcing aggregate constraints of the channel parameters
+/// negotiated by the peers (number and value of HTLCs in flight and channel
+/// reserves) on the commitment transactions.
+///
+/// HTLCs and PTLCs in flight are tracked from the same state and messages as
+/// used by the payment extensions, so the HTLCs trimmed from the commitment
+/// transaction are accounted for. The commitment fee is taken from the
+/// rendered transaction graph, so the extension must always be applied after
+/// all other extenders, which is guaranteed by the [`BoltExt::Policy`]
+/// ordering.
+#[derive(Getters, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CommitmentPolicy {
+    /// Parameters required by the local node from the remote one
+    local_params: PeerParams,
+
+    /// Parameters required by the remote node from the local one
+    remote_params: PeerParams,
+
+    /// Channel direction, defining which of the nodes pays commitment fees
+    direction: Direction,
+
+    // Channel balances, not including HTLCs in flight
+    local_amount_msat: u64,
+    remote_amount_msat: u64,
+
+    // Amounts of HTLCs and PTLCs in flight, in millisatoshis, by their ids
+    offered_htlcs: BTreeMap<u64, u64>,
+    received_htlcs: BTreeMap<u64, u64>,
+    offered_ptlcs: BTreeMap<u64, u64>,
+    received_ptlcs: BTreeMap<u64, u64>,
+}
+
+impl Default for CommitmentPolicy {
+    fn default() -> Self {
+        CommitmentPolicy {
+            local_params: default!(),
+            remote_params: default!(),
+            direction: Direction::Outbount,
+            local_amount_msat: 0,
+            remote_amount_msat: 0,
+            offered_htlcs: empty!(),
+            received_htlcs: empty!(),
+            offered_ptlcs: empty!(),
+            received_ptlcs: empty!(),
+        }
+    }
+}
+
+impl CommitmentPolicy {
+    /// Checks HTLCs and PTLCs with amounts `offered` (in millisatoshis) by the
+    /// `offerer` against the limits set by its peer.
+    fn check_offered(
+        &self,
+        offerer: Offerer,
+        offered: &[u64],
+        balance_sat: u64,
+    ) -> Result<(), CommitmentPolicyError> {
+        // Local node offers HTLCs to the remote, which sets the limits, and
+        // vice versa
+        let peer_params = match offerer {
+            Offerer::Local => self.remote_params,
+            Offerer::Remote => self.local_params,
+        };
+
+        if offered.len() > peer_params.max_accepted_htlcs as usize {
+            return Err(CommitmentPolicyError::MaxAcceptedHtlcsExceeded {
+                offerer,
+                count: offered.len(),
+                max_accepted: peer_params.max_accepted_htlcs,
+            });
+        }
+
+        let value_msat = offered.iter().sum::<u64>();
+        if value_msat > peer_params.max_htlc_value_in_flight_msat {
+            return Err(CommitmentPolicyError::MaxHtlcValueInFlightExceeded {
+                offerer,
+                value_msat,
+                max_value_msat: peer_params.max_htlc_value_in_flight_msat,
+            });
+        }
+
+        // The reserve requirement applies only to the node which is spending
+        // its funds: a node may not have enough funds to meet the reserve
+        // right after the channel opening
+        if !offered.is_empty()
+            && balance_sat < peer_params.channel_reserve_satoshis
+        {
+            return Err(CommitmentPolicyError::ChannelReserveViolated {
+                offerer,
+                balance_sat,
+                reserve_sat: peer_params.channel_reserve_satoshis,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Extension<BoltExt> for CommitmentPolicy {
+    #[inline]
+    fn identity(&self) -> BoltExt {
+        BoltExt::Policy
+    }
+
+    fn update_from_local(&mut self, _message: &()) -> Result<(), Error> {
+        // Nothing to do here so far
+        Ok(())
+    }
+
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        message: &mut Messages,
+    ) -> Result<(), Error> {
+        // Ids of the offered HTLCs and PTLCs are assigned by the payment
+        // extensions, which precede the policy
+        match (request, message) {
+            (UpdateReq::PayBolt(_), Messages::UpdateAddHtlc(message)) => {
+                self.offered_htlcs
+                    .insert(message.htlc_id, message.amount_msat);
+            }
+            (UpdateReq::FulfillHtlc, Messages::UpdateFulfillHtlc(message)) => {
+                self.received_htlcs.remove(&message.htlc_id);
+            }
+            (UpdateReq::FailHtlc, Messages::UpdateFailHtlc(message)) => {
+                self.received_htlcs.remove(&message.htlc_id);
+            }
+            (UpdateReq::PayPtlc(_), Messages::UpdateAddPtlc(message)) => {
+                self.offered_ptlcs
+                    .insert(message.ptlc_id, message.amount_msat);
+            }
+            (UpdateReq::FulfillPtlc, Messages::UpdateFulfillPtlc(message)) => {
+                self.received_ptlcs.remove(&message.ptlc_id);
+            }
+            (UpdateReq::FailPtlc, Messages::UpdateFailPtlc(message)) => {
+                self.received_ptlcs.remove(&message.ptlc_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
+        // Parameters of the channels established with v2 protocol are
+        // propagated with the channel state once the funding is constructed
+        match message {
+            Messages::OpenChannel(open_channel) => {
+                self.direction = Direction::Inbound;
+                self.remote_params = PeerParams::from(open_channel);
+                self.local_amount_msat = open_channel.push_msat;
+                self.remote_amount_msat = (open_channel.funding_satoshis
+                    * 1000)
+                    .saturating_sub(open_channel.push_msat);
+            }
+            Messages::AcceptChannel(accept_channel) => {
+                self.remote_params = PeerParams::from(accept_channel);
+            }
+            Messages::UpdateAddHtlc(message) => {
+                self.received_htlcs
+                    .insert(message.htlc_id, message.amount_msat);
+            }
+            Messages::UpdateFulfillHtlc(message) => {
+                self.offered_htlcs.remove(&message.htlc_id);
+            }
+            Messages::UpdateFailHtlc(message) => {
+                self.offered_htlcs.remove(&message.htlc_id);
+            }
+            Messages::UpdateAddPtlc(message) => {
+                self.received_ptlcs
+                    .insert(message.ptlc_id, message.amount_msat);
+            }
+            Messages::UpdateFulfillPtlc(message) => {
+                self.offered_ptlcs.remove(&message.ptlc_id);
+            }
+            Messages::UpdateFailPtlc(message) => {
+                self.offered_ptlcs.remove(&message.ptlc_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, state: &ChannelState) {
+        self.local_params = state.local_params;
+        self.remote_params = state.remote_params;
+        self.direction = state.direction;
+        self.local_amount_msat = state.local_amount_msat;
+        self.remote_amount_msat = state.remote_amount_msat;
+        self.offered_htlcs = state
+            .offered_htlcs
+            .iter()
+            .map(|(id, htlc)| (*id, htlc.amount))
+            .collect();
+        self.received_htlcs = state
+            .received_htlcs
+            .iter()
+            .map(|(id, htlc)| (*id, htlc.amount))
+            .collect();
+        self.offered_ptlcs = state
+            .offered_ptlcs
+            .iter()
+            .map(|(id, ptlc)| (*id, ptlc.amount))
+            .collect();
+        self.received_ptlcs = state
+            .received_ptlcs
+            .iter()
+            .map(|(id, ptlc)| (*id, ptlc.amount))
+            .collect();
+    }
+
+    fn store_state(&self, _state: &mut ChannelState) {
+        // Nothing to do here: the extension does not have its own state
+    }
+}
+
+impl ChannelExtension<BoltExt> for CommitmentPolicy {
+    #[inline]
+    fn new() -> Box<dyn ChannelExtension<BoltExt>>
+    where
+        Self: Sized,
+    {
+        Box::default() as Box<CommitmentPolicy>
+    }
+
+    fn build_graph(
+        &self,
+        tx_graph: &mut TxGraph,
+        _as_remote_node: bool,
+    ) -> Result<(), Error> {
+        // HTLCs offered by each of the nodes are the same for both commitment
+        // transactions
+        let local_offered = self
+            .offered_htlcs
+            .values()
+            .chain(self.offered_ptlcs.values())
+            .copied()
+            .collect::<Vec<_>>();
+        let remote_offered = self
+            .received_htlcs
+            .values()
+            .chain(self.received_ptlcs.values())
+            .copied()
+            .collect::<Vec<_>>();
+
+        // Amounts of HTLCs trimmed from the commitment transaction go to the
+        // miners, so they are excluded from the commitment fee. Untrimmed
+        // HTLCs have their second-stage transactions in the graph.
+        let untrimmed_sat = tx_graph
+            .vec_mut()
+            .into_iter()
+            .filter(|(role, _, _)| {
+                matches!(
+                    TxType::from(*role),
+                    TxType::HtlcTimeout
+                        | TxType::HtlcSuccess
+                        | TxType::PtlcTimeout
+                        | TxType::PtlcSuccess
+                )
+            })
+            .flat_map(|(_, _, tx)| tx.outputs.iter().map(|out| out.amount))
+            .sum::<u64>();
+        let in_flight_sat = local_offered
+            .iter()
+            .chain(&remote_offered)
+            .map(|amount_msat| amount_msat / 1000)
+            .sum::<u64>();
+        let trimmed_sat = in_flight_sat.saturating_sub(untrimmed_sat);
+
+        // Commitment fee is paid by the channel funder
+        let outputs_sat = tx_graph
+            .cmt_outs
+            .iter()
+            .map(|output| output.amount)
+            .sum::<u64>();
+        let fee = tx_graph
+            .funding()
+            .amount()
+            .saturating_sub(outputs_sat)
+            .saturating_sub(trimmed_sat);
+        let (local_fee, remote_fee) = if self.direction.is_outbound() {
+            (fee, 0)
+        } else {
+            (0, fee)
+        };
+
+        let local_balance_sat = (self.local_amount_msat / 1000)
+            .saturating_sub(local_offered.iter().sum::<u64>() / 1000)
+            .saturating_sub(local_fee);
+        let remote_balance_sat = (self.remote_amount_msat / 1000)
+            .saturating_sub(remote_offered.iter().sum::<u64>() / 1000)
+            .saturating_sub(remote_fee);
+        self.check_offered(Offerer::Local, &local_offered, local_balance_sat)?;
+        self.check_offered(
+            Offerer::Remote,
+            &remote_offered,
+            remote_balance_sat,
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{PackedLockTime, Transaction, TxOut};
+    use wallet::psbt::{self, Psbt, PsbtVersion};
+
+    use super::*;
+    use crate::channel::Funding;
+
+    fn output(amount: u64) -> psbt::Output {
+        let txout = TxOut {
+            value: amount,
+            script_pubkey: empty!(),
+        };
+        psbt::Output::with(0, default!(), txout)
+    }
+
+    fn htlc_tx(amount: u64) -> Psbt {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: vec![TxOut {
+                value: amount,
+                script_pubkey: empty!(),
+            }],
+        };
+        Psbt::with(tx, PsbtVersion::V0).unwrap()
+    }
+
+    /// Builds commitment transaction of the local node (or of the remote
+    /// node if `as_remote_node` is set) with HTLCs offered and received by
+    /// its owner, trimming HTLCs below 354 sat
+    fn check(
+        policy: &CommitmentPolicy,
+        as_remote_node: bool,
+        balances: [u64; 2],
+        owner_offered: &[u64],
+        owner_received: &[u64],
+    ) -> Result<(), Error> {
+        let mut policy = policy.clone();
+        let msat = |amounts: &[u64]| {
+            amounts
+                .iter()
+                .enumerate()
+                .map(|(id, amount)| (id as u64, amount * 1000))
+                .collect::<BTreeMap<_, _>>()
+        };
+        if as_remote_node {
+            policy.received_htlcs = msat(owner_offered);
+            policy.offered_ptlcs = msat(owner_received);
+        } else {
+            policy.offered_htlcs = msat(owner_offered);
+            policy.received_ptlcs = msat(owner_received);
+        }
+
+        let funding = Funding::preliminary(1_000_000);
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        tx_graph.cmt_outs = balances.iter().copied().map(output).collect();
+        for (ty, htlcs) in [
+            (TxType::HtlcTimeout, owner_offered),
+            (TxType::PtlcSuccess, owner_received),
+        ] {
+            for (id, amount) in htlcs.iter().enumerate() {
+                if *amount < 354 {
+                    continue;
+                }
+                tx_graph.cmt_outs.push(output(*amount));
+                tx_graph.insert_tx(ty, id as u64, htlc_tx(*amount));
+            }
+        }
+        policy.build_graph(&mut tx_graph, as_remote_node)
+    }
+
+    #[test]
+    fn commitment_constraints() {
+        let policy = CommitmentPolicy {
+            local_params: PeerParams {
+                max_accepted_htlcs: 2,
+                max_htlc_value_in_flight_msat: 200_000_000,
+                channel_reserve_satoshis: 250_000,
+                ..default!()
+            },
+            remote_params: default!(),
+            direction: Direction::Outbount,
+            local_amount_msat: 600_000_000,
+            remote_amount_msat: 400_000_000,
+            ..default!()
+        };
+
+        // Local node pays 1000 sat fee from its balance
+        check(&policy, false, [499_000, 400_000], &[100_000], &[]).unwrap();
+        check(&policy, true, [400_000, 499_000], &[], &[100_000]).unwrap();
+
+        let err = Error::CommitmentPolicy(
+            CommitmentPolicyError::MaxAcceptedHtlcsExceeded {
+                offerer: Offerer::Remote,
+                count: 3,
+                max_accepted: 2,
+            },
+        );
+        let htlcs = [10_000, 10_000, 10_000];
+        assert_eq!(
+            check(&policy, false, [599_000, 370_000], &[], &htlcs),
+            Err(err.clone())
+        );
+        assert_eq!(
+            check(&policy, true, [370_000, 599_000], &htlcs, &[]),
+            Err(err)
+        );
+
+        assert_eq!(
+            check(&policy, false, [599_000, 150_000], &[], &[250_000]),
+            Err(Error::CommitmentPolicy(
+                CommitmentPolicyError::MaxHtlcValueInFlightExceeded {
+                    offerer: Offerer::Remote,
+                    value_msat: 250_000_000,
+                    max_value_msat: 200_000_000,
+                }
+            ))
+        );
+
+        assert_eq!(
+            check(&policy, false, [599_000, 205_000], &[], &[195_000]),
+            Err(Error::CommitmentPolicy(
+                CommitmentPolicyError::ChannelReserveViolated {
+                    offerer: Offerer::Remote,
+                    balance_sat: 205_000,
+                    reserve_sat: 250_000,
+                }
+            ))
+        );
+        // Remote node is below reserve, but it does not spend its funds
+        check(&policy, false, [599_000, 205_000], &[], &[]).unwrap();
+
+        // Trimmed HTLCs are counted against the limits, but not charged as a
+        // part of the commitment fee
+        check(&policy, false, [498_700, 400_000], &[100_000, 300], &[])
+            .unwrap();
+        assert_eq!(
+            check(&policy, false, [599_000, 379_700], &[], &[
+                10_000, 10_000, 300
+            ]),
+            Err(Error::CommitmentPolicy(
+                CommitmentPolicyError::MaxAcceptedHtlcsExceeded {
+                    offerer: Offerer::Remote,
+                    count: 3,
+                    max_accepted: 2,
+                }
+            ))
+        );
+    }
+}
@@ -0,0 +1,1165 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Experimental discreet log contracts (DLCs) inside the channel.
+//!
+//! The contract funds are locked in a separate commitment output, spendable
+//! by both parties signing together. For each of the outcomes announced by an
+//! oracle there is a contract execution transaction (CET) distributing the
+//! funds according to the outcome payout. The parties exchange adaptor
+//! signatures for CETs, encrypted with oracle attestation points, so a CET can
+//! be completed only once the oracle attests the corresponding outcome.
+
+use amplify::{Slice32, Wrapper};
+use bitcoin::blockdata::opcodes::all::*;
+use bitcoin::blockdata::script;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{
+    schnorr, Parity, PublicKey, Scalar, SecretKey, SECP256K1,
+};
+use bitcoin::util::sighash::{Prevouts, SighashCache};
+use bitcoin::util::taproot::{LeafVersion, TapLeafHash, TaprootBuilder};
+use bitcoin::{
+    OutPoint, PackedLockTime, SchnorrSighashType, Script, Sequence,
+    Transaction, TxIn, TxOut, Witness,
+};
+use lnp2p::bolt::{
+    AcceptChannel, AcceptChannel2, AdaptorSignature, ChannelId, DlcOffer,
+    DlcPayout, Messages, OpenChannel, OpenChannel2, OracleAnnouncement,
+};
+use p2p::bolt::ChannelType;
+use wallet::psbt::{self, Psbt, PsbtVersion};
+
+use super::htlc::CommitmentKeys;
+use crate::channel::bolt::musig2::tagged_hash;
+use crate::channel::bolt::taproot::{nums_point, taproot_output};
+use crate::channel::bolt::util::UpdateReq;
+use crate::channel::bolt::{
+    derive_pubkey, derive_revocationpubkey, AdaptorError, BoltExt,
+    ChannelState, Error, SchnorrAdaptor, TxType,
+};
+use crate::channel::tx_graph::TxGraph;
+use crate::{ChannelExtension, Extension};
+
+/// Errors happening during DLC negotiation and settlement
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DlcError {
+    /// the channel has no discreet log contract
+    NoContract,
+
+    /// the channel already has active discreet log contract
+    ContractExists,
+
+    /// oracle announcement contains invalid public key, nonce or no outcomes
+    InvalidAnnouncement,
+
+    /// contract has {payouts} payouts while oracle announces {outcomes}
+    /// outcomes
+    PayoutMismatch { outcomes: usize, payouts: usize },
+
+    /// payout for the outcome {0} does not match the total contract collateral
+    CollateralMismatch(u16),
+
+    /// contract collateral exceeds the channel balance
+    InsufficientBalance,
+
+    /// outcome {0} is not announced by the oracle
+    UnknownOutcome(u16),
+
+    /// attestation does not match the oracle announcement for outcome {0}
+    InvalidAttestation(u16),
+
+    /// expected {expected} CET adaptor signatures, got {actual}
+    SignatureCount { expected: usize, actual: usize },
+
+    /// the contract has no remote CET adaptor signatures yet
+    NoRemoteSignatures,
+
+    /// the contract is not settled with an oracle attestation yet
+    NotSettled,
+
+    /// invalid CET adaptor signature: {0}
+    #[from]
+    Adaptor(AdaptorError),
+}
+
+/// Discreet log contract between the channel parties
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct DlcContract {
+    pub announcement: OracleAnnouncement,
+    pub offerer_collateral_sat: u64,
+    pub accepter_collateral_sat: u64,
+    pub payouts: Vec<DlcPayout>,
+}
+
+impl DlcContract {
+    /// Constructs and validates contract from the `dlc_offer` message data
+    pub fn with(offer: &DlcOffer) -> Result<DlcContract, DlcError> {
+        let contract = DlcContract {
+            announcement: offer.announcement.clone(),
+            offerer_collateral_sat: offer.offerer_collateral_sat,
+            accepter_collateral_sat: offer.accepter_collateral_sat,
+            payouts: offer.payouts.clone(),
+        };
+
+        let outcomes = contract.announcement.outcomes.len();
+        if outcomes == 0 || outcomes > u16::MAX as usize {
+            return Err(DlcError::InvalidAnnouncement);
+        }
+        if outcomes != contract.payouts.len() {
+            return Err(DlcError::PayoutMismatch {
+                outcomes,
+                payouts: contract.payouts.len(),
+            });
+        }
+        let total = contract.total_collateral();
+        for (index, payout) in contract.payouts.iter().enumerate() {
+            if payout.offerer_sat.checked_add(payout.accepter_sat)
+                != Some(total)
+            {
+                return Err(DlcError::CollateralMismatch(index as u16));
+            }
+        }
+        for index in 0..outcomes {
+            contract.attestation_point(index as u16)?;
+        }
+        Ok(contract)
+    }
+
+    /// Total amount of funds locked in the contract
+    #[inline]
+    pub fn total_collateral(&self) -> u64 {
+        self.offerer_collateral_sat
+            .saturating_add(self.accepter_collateral_sat)
+    }
+
+    /// Computes the point which discrete logarithm will be revealed by the
+    /// oracle when it attests the outcome with the given index.
+    ///
+    /// The oracle attests the outcome by producing BIP-340 signature `(R, s)`
+    /// for SHA256 hash of the outcome string, so the attestation point is
+    /// `s·G = R + e·P`.
+    pub fn attestation_point(
+        &self,
+        outcome_index: u16,
+    ) -> Result<PublicKey, DlcError> {
+        let outcome = self
+            .announcement
+            .outcomes
+            .get(outcome_index as usize)
+            .ok_or(DlcError::UnknownOutcome(outcome_index))?;
+        let oracle_key = self.announcement.oracle_pubkey.x_only_public_key().0;
+        let nonce = self.announcement.nonce_point.x_only_public_key().0;
+        let msg = sha256::Hash::hash(outcome.as_bytes());
+
+        let e = SecretKey::from_slice(&tagged_hash("BIP0340/challenge", &[
+            &nonce.serialize(),
+            &oracle_key.serialize(),
+            &msg[..],
+        ]))
+        .map_err(|_| DlcError::InvalidAnnouncement)?;
+        let ep = PublicKey::from_x_only_public_key(oracle_key, Parity::Even)
+            .mul_tweak(SECP256K1, &Scalar::from(e))
+            .map_err(|_| DlcError::InvalidAnnouncement)?;
+        PublicKey::from_x_only_public_key(nonce, Parity::Even)
+            .combine(&ep)
+            .map_err(|_| DlcError::InvalidAnnouncement)
+    }
+
+    /// Checks that the attestation scalar corresponds to the given outcome
+    pub fn verify_attestation(
+        &self,
+        outcome_index: u16,
+        attestation: SecretKey,
+    ) -> Result<(), DlcError> {
+        let point = self.attestation_point(outcome_index)?;
+        if PublicKey::from_secret_key(SECP256K1, &attestation) != point {
+            return Err(DlcError::InvalidAttestation(outcome_index));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Getters, Clone, PartialEq, Eq, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct Dlc {
+    /// Channel type negotiated during channel establishment, which affects
+    /// the form of the balance outputs
+    channel_type: ChannelType,
+
+    /// Active contract, if any
+    contract: Option<DlcContract>,
+
+    /// Whether the local node has offered the contract
+    offerer: bool,
+
+    /// Oracle attestation settling the contract: outcome index and the
+    /// attestation scalar
+    attestation: Option<(u16, SecretKey)>,
+
+    /// CET adaptor signatures received from the remote node
+    remote_cet_sigs: Vec<AdaptorSignature>,
+
+    // Keys controlling contract and payout outputs
+    local_funding_pubkey: PublicKey,
+    remote_funding_pubkey: PublicKey,
+    local_payment_basepoint: PublicKey,
+    remote_payment_basepoint: PublicKey,
+
+    // Commitment round specific information used to locate balance outputs
+    local_to_self_delay: u16,
+    remote_to_self_delay: u16,
+    local_revocation_basepoint: PublicKey,
+    remote_revocation_basepoint: PublicKey,
+    local_delayed_basepoint: PublicKey,
+    remote_delayed_basepoint: PublicKey,
+    local_per_commitment_point: PublicKey,
+    remote_per_commitment_point: PublicKey,
+
+    // Channel specific information
+    channel_id: ChannelId,
+    local_amount_msat: u64,
+    remote_amount_msat: u64,
+}
+
+impl Default for Dlc {
+    fn default() -> Self {
+        Dlc {
+            channel_type: ChannelType::Basic,
+            contract: None,
+            offerer: false,
+            attestation: None,
+            remote_cet_sigs: empty!(),
+            local_funding_pubkey: dumb_pubkey!(),
+            remote_funding_pubkey: dumb_pubkey!(),
+            local_payment_basepoint: dumb_pubkey!(),
+            remote_payment_basepoint: dumb_pubkey!(),
+            local_to_self_delay: 0,
+            remote_to_self_delay: 0,
+            local_revocation_basepoint: dumb_pubkey!(),
+            remote_revocation_basepoint: dumb_pubkey!(),
+            local_delayed_basepoint: dumb_pubkey!(),
+            remote_delayed_basepoint: dumb_pubkey!(),
+            local_per_commitment_point: dumb_pubkey!(),
+            remote_per_commitment_point: dumb_pubkey!(),
+            channel_id: Default::default(),
+            local_amount_msat: 0,
+            remote_amount_msat: 0,
+        }
+    }
+}
+
+impl Dlc {
+    /// Returns active contract or error if there is none
+    pub fn try_contract(&self) -> Result<&DlcContract, DlcError> {
+        self.contract.as_ref().ok_or(DlcError::NoContract)
+    }
+
+    /// Registers new contract offered by the local node (if `offerer` is
+    /// `true`) or by the remote node.
+    pub fn open_contract(
+        &mut self,
+        offer: &DlcOffer,
+        offerer: bool,
+    ) -> Result<&DlcContract, DlcError> {
+        if self.contract.is_some() {
+            return Err(DlcError::ContractExists);
+        }
+        let contract = DlcContract::with(offer)?;
+        let (local_collateral, remote_collateral) = if offerer {
+            (
+                contract.offerer_collateral_sat,
+                contract.accepter_collateral_sat,
+            )
+        } else {
+            (
+                contract.accepter_collateral_sat,
+                contract.offerer_collateral_sat,
+            )
+        };
+        if local_collateral > self.local_amount_msat / 1000
+            || remote_collateral > self.remote_amount_msat / 1000
+        {
+            return Err(DlcError::InsufficientBalance);
+        }
+        self.offerer = offerer;
+        self.attestation = None;
+        self.remote_cet_sigs = empty!();
+        Ok(self.contract.insert(contract))
+    }
+
+    /// Funding public keys of the contract offerer and accepter
+    fn contract_keys(&self) -> (PublicKey, PublicKey) {
+        if self.offerer {
+            (self.local_funding_pubkey, self.remote_funding_pubkey)
+        } else {
+            (self.remote_funding_pubkey, self.local_funding_pubkey)
+        }
+    }
+
+    /// Tapscript leaf of the contract output, requiring signatures of both
+    /// parties
+    pub fn contract_leaf(&self) -> Script {
+        let (offerer_key, accepter_key) = self.contract_keys();
+        script::Builder::new()
+            .push_x_only_key(&offerer_key.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_x_only_key(&accepter_key.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// Constructs contract output for the commitment transaction
+    pub fn contract_output(&self) -> Result<psbt::Output, DlcError> {
+        let contract = self.try_contract()?;
+        let builder = TaprootBuilder::new()
+            .add_leaf(0, self.contract_leaf())
+            .expect("single leaf always forms a complete tree");
+        Ok(taproot_output(
+            contract.total_collateral(),
+            nums_point(),
+            Some(builder),
+        ))
+    }
+
+    /// Constructs contract execution transaction for the given outcome.
+    ///
+    /// NB: CETs do not pay fees yet and spend a default outpoint instead of
+    /// the contract output of a specific commitment transaction.
+    pub fn cet(&self, outcome_index: u16) -> Result<Transaction, DlcError> {
+        let contract = self.try_contract()?;
+        let payout = contract
+            .payouts
+            .get(outcome_index as usize)
+            .ok_or(DlcError::UnknownOutcome(outcome_index))?;
+        let (offerer_basepoint, accepter_basepoint) = if self.offerer {
+            (self.local_payment_basepoint, self.remote_payment_basepoint)
+        } else {
+            (self.remote_payment_basepoint, self.local_payment_basepoint)
+        };
+
+        let output = [
+            (payout.offerer_sat, offerer_basepoint),
+            (payout.accepter_sat, accepter_basepoint),
+        ]
+        .into_iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(value, pubkey)| TxOut {
+            value,
+            script_pubkey: Script::new_v0_p2wpkh(
+                &bitcoin::PublicKey::new(pubkey)
+                    .wpubkey_hash()
+                    .expect("compressed key"),
+            ),
+        })
+        .collect();
+
+        Ok(Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                // TODO: do a two-staged graph generation process
+                previous_output: OutPoint::default(),
+                script_sig: none!(),
+                sequence: Sequence::MAX,
+                witness: empty!(),
+            }],
+            output,
+        })
+    }
+
+    /// Computes BIP-341 signature hash for the script-path spending of the
+    /// contract output by the CET for the given outcome
+    pub fn cet_sighash(
+        &self,
+        outcome_index: u16,
+    ) -> Result<[u8; 32], DlcError> {
+        let tx = self.cet(outcome_index)?;
+        let output = self.contract_output()?;
+        let prevout = TxOut {
+            value: output.amount,
+            script_pubkey: output.script.into_inner(),
+        };
+        let sighash = SighashCache::new(&tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&[prevout]),
+                TapLeafHash::from_script(
+                    &self.contract_leaf(),
+                    LeafVersion::TapScript,
+                ),
+                SchnorrSighashType::Default,
+            )
+            .expect("CET always has a single input");
+        Ok(sighash.into_inner())
+    }
+
+    /// Produces adaptor signatures for all CETs, encrypted with the
+    /// attestation points of the corresponding outcomes
+    pub fn sign_cets(
+        &self,
+        funding_secret: SecretKey,
+    ) -> Result<Vec<AdaptorSignature>, DlcError> {
+        let contract = self.try_contract()?;
+        (0..contract.payouts.len() as u16)
+            .map(|index| {
+                AdaptorSignature::sign(
+                    funding_secret,
+                    self.cet_sighash(index)?,
+                    contract.attestation_point(index)?,
+                    Slice32::random().into_inner(),
+                )
+                .map_err(DlcError::from)
+            })
+            .collect()
+    }
+
+    /// Verifies and stores CET adaptor signatures received from the remote
+    /// node
+    pub fn set_remote_cet_sigs(
+        &mut self,
+        signatures: &[AdaptorSignature],
+    ) -> Result<(), DlcError> {
+        let contract = self.try_contract()?;
+        if signatures.len() != contract.payouts.len() {
+            return Err(DlcError::SignatureCount {
+                expected: contract.payouts.len(),
+                actual: signatures.len(),
+            });
+        }
+        let remote_key = self.remote_funding_pubkey.x_only_public_key().0;
+        for (index, signature) in signatures.iter().enumerate() {
+            let index = index as u16;
+            signature.verify(
+                remote_key,
+                self.cet_sighash(index)?,
+                contract.attestation_point(index)?,
+            )?;
+        }
+        self.remote_cet_sigs = signatures.to_vec();
+        Ok(())
+    }
+
+    /// Settles the contract with the oracle attestation of the outcome. After
+    /// the settlement the contract output is removed from the commitment
+    /// transactions and the payouts are added to the channel balances.
+    pub fn settle(
+        &mut self,
+        outcome_index: u16,
+        attestation: SecretKey,
+    ) -> Result<DlcPayout, DlcError> {
+        let contract = self.try_contract()?;
+        contract.verify_attestation(outcome_index, attestation)?;
+        let payout = contract.payouts[outcome_index as usize];
+        self.attestation = Some((outcome_index, attestation));
+        Ok(payout)
+    }
+
+    /// Constructs fully signed CET for the attested outcome, which can be
+    /// published to enforce the contract on-chain
+    pub fn signed_cet(
+        &self,
+        funding_secret: SecretKey,
+    ) -> Result<Transaction, DlcError> {
+        let (outcome_index, attestation) =
+            self.attestation.ok_or(DlcError::NotSettled)?;
+        let remote_adaptor_sig = self
+            .remote_cet_sigs
+            .get(outcome_index as usize)
+            .ok_or(DlcError::NoRemoteSignatures)?;
+
+        let sighash = self.cet_sighash(outcome_index)?;
+        let remote_sig = remote_adaptor_sig.complete(attestation)?;
+        let msg = secp256k1::Message::from_slice(&sighash)
+            .expect("sighash is always 32 bytes");
+        let local_sig =
+            SECP256K1.sign_schnorr(&msg, &funding_secret.keypair(SECP256K1));
+        let (offerer_sig, accepter_sig) = if self.offerer {
+            (local_sig, remote_sig)
+        } else {
+            (remote_sig, local_sig)
+        };
+
+        let leaf = self.contract_leaf();
+        let control_block = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .expect("single leaf always forms a complete tree")
+            .finalize(SECP256K1, nums_point())
+            .expect("complete tree can always be finalized")
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .expect("leaf is a part of the tree");
+
+        let mut tx = self.cet(outcome_index)?;
+        tx.input[0].witness = Witness::from_vec(vec![
+            accepter_sig.as_ref().to_vec(),
+            offerer_sig.as_ref().to_vec(),
+            leaf.into_bytes(),
+            control_block.serialize(),
+        ]);
+        Ok(tx)
+    }
+
+    /// Returns public key used in the `to_remote` output of the local (if
+    /// `as_remote_node` is `false`) or remote (if `as_remote_node` is `true`)
+    /// commitment transaction.
+    fn remotepubkey(&self, as_remote_node: bool) -> PublicKey {
+        if as_remote_node {
+            CommitmentKeys::remotepubkey(
+                self.channel_type,
+                self.local_payment_basepoint,
+                self.remote_per_commitment_point,
+            )
+        } else {
+            CommitmentKeys::remotepubkey(
+                self.channel_type,
+                self.remote_payment_basepoint,
+                self.local_per_commitment_point,
+            )
+        }
+    }
+
+    /// Derives keys for the local (if `as_remote_node` is `false`) or remote
+    /// (if `as_remote_node` is `true`) commitment transaction.
+    fn commitment_keys(&self, as_remote_node: bool) -> CommitmentKeys {
+        let (
+            per_commitment_point,
+            revocation_basepoint,
+            delayed_basepoint,
+            to_self_delay,
+        ) = if as_remote_node {
+            (
+                self.remote_per_commitment_point,
+                self.local_revocation_basepoint,
+                self.remote_delayed_basepoint,
+                self.remote_to_self_delay,
+            )
+        } else {
+            (
+                self.local_per_commitment_point,
+                self.remote_revocation_basepoint,
+                self.local_delayed_basepoint,
+                self.local_to_self_delay,
+            )
+        };
+        CommitmentKeys {
+            revocationpubkey: derive_revocationpubkey(
+                revocation_basepoint,
+                per_commitment_point,
+            ),
+            // HTLC keys are not used by DLC outputs
+            local_htlcpubkey: dumb_pubkey!(),
+            remote_htlcpubkey: dumb_pubkey!(),
+            local_delayedpubkey: derive_pubkey(
+                delayed_basepoint,
+                per_commitment_point,
+            ),
+            to_self_delay,
+        }
+    }
+}
+
+impl Extension<BoltExt> for Dlc {
+    fn identity(&self) -> BoltExt {
+        BoltExt::Dlc
+    }
+
+    fn update_from_local(&mut self, _message: &()) -> Result<(), Error> {
+        // Nothing to do here so far
+        Ok(())
+    }
+
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        message: &mut Messages,
+    ) -> Result<(), Error> {
+        match (request, message) {
+            (UpdateReq::OfferDlc, Messages::DlcOffer(dlc_offer)) => {
+                self.open_contract(dlc_offer, true)?;
+            }
+            (
+                UpdateReq::SignDlc(funding_secret),
+                Messages::DlcAccept(dlc_accept),
+            ) if !self.offerer => {
+                dlc_accept.cet_adaptor_signatures =
+                    self.sign_cets(*funding_secret)?;
+            }
+            (
+                UpdateReq::SignDlc(funding_secret),
+                Messages::DlcSign(dlc_sign),
+            ) if self.offerer => {
+                dlc_sign.cet_adaptor_signatures =
+                    self.sign_cets(*funding_secret)?;
+            }
+            (UpdateReq::SettleDlc, Messages::DlcSettle(dlc_settle)) => {
+                self.settle(dlc_settle.outcome_index, dlc_settle.attestation)?;
+            }
+            (UpdateReq::OfferDlc, _)
+            | (UpdateReq::SignDlc(_), _)
+            | (UpdateReq::SettleDlc, _) => unreachable!(
+                "state change request must match provided LN P2P message"
+            ),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
+        match message {
+            Messages::OpenChannel(OpenChannel {
+                payment_point,
+                channel_type,
+                funding_pubkey,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            })
+            | Messages::AcceptChannel(AcceptChannel {
+                payment_point,
+                channel_type,
+                funding_pubkey,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            })
+            | Messages::OpenChannel2(OpenChannel2 {
+                payment_basepoint: payment_point,
+                channel_type,
+                funding_pubkey,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            })
+            | Messages::AcceptChannel2(AcceptChannel2 {
+                payment_basepoint: payment_point,
+                channel_type,
+                funding_pubkey,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            }) => {
+                self.channel_type = channel_type.unwrap_or_default();
+                self.remote_funding_pubkey = *funding_pubkey;
+                self.remote_payment_basepoint = *payment_point;
+                self.remote_revocation_basepoint = *revocation_basepoint;
+                self.remote_delayed_basepoint = *delayed_payment_basepoint;
+                self.remote_per_commitment_point = *first_per_commitment_point;
+                self.local_to_self_delay = *to_self_delay;
+            }
+            Messages::DlcOffer(dlc_offer)
+                if dlc_offer.channel_id == self.channel_id =>
+            {
+                self.open_contract(dlc_offer, false)?;
+            }
+            Messages::DlcAccept(dlc_accept)
+                if dlc_accept.channel_id == self.channel_id =>
+            {
+                self.set_remote_cet_sigs(&dlc_accept.cet_adaptor_signatures)?;
+            }
+            Messages::DlcSign(dlc_sign)
+                if dlc_sign.channel_id == self.channel_id =>
+            {
+                self.set_remote_cet_sigs(&dlc_sign.cet_adaptor_signatures)?;
+            }
+            Messages::DlcSettle(dlc_settle)
+                if dlc_settle.channel_id == self.channel_id =>
+            {
+                self.settle(dlc_settle.outcome_index, dlc_settle.attestation)?;
+            }
+            Messages::DlcOffer(_)
+            | Messages::DlcAccept(_)
+            | Messages::DlcSign(_)
+            | Messages::DlcSettle(_) => {
+                return Err(DlcError::NoContract.into());
+            }
+            Messages::FundingLocked(funding_locked) => {
+                self.remote_per_commitment_point =
+                    funding_locked.next_per_commitment_point;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, state: &ChannelState) {
+        self.channel_type = state.common_params.channel_type;
+
+        self.contract = state.dlc_contract.clone();
+        self.offerer = state.dlc_offerer;
+        self.attestation = state.dlc_attestation;
+
+        self.local_funding_pubkey = state.local_keys.funding_pubkey.key;
+        self.remote_funding_pubkey = state.remote_keys.funding_pubkey;
+        self.local_payment_basepoint = state.local_keys.payment_basepoint.key;
+        self.remote_payment_basepoint = state.remote_keys.payment_basepoint;
+
+        self.local_to_self_delay = state.remote_params.to_self_delay;
+        self.remote_to_self_delay = state.local_params.to_self_delay;
+        self.local_revocation_basepoint =
+            state.local_keys.revocation_basepoint.key;
+        self.remote_revocation_basepoint =
+            state.remote_keys.revocation_basepoint;
+        self.local_delayed_basepoint =
+            state.local_keys.delayed_payment_basepoint.key;
+        self.remote_delayed_basepoint =
+            state.remote_keys.delayed_payment_basepoint;
+        self.local_per_commitment_point = state.local_per_commitment_point;
+        self.remote_per_commitment_point = state.remote_per_commitment_point;
+
+        self.channel_id = state.active_channel_id.as_slice32().into();
+        self.local_amount_msat = state.local_amount_msat;
+        self.remote_amount_msat = state.remote_amount_msat;
+    }
+
+    fn store_state(&self, state: &mut ChannelState) {
+        state.dlc_contract = self.contract.clone();
+        state.dlc_offerer = self.offerer;
+        state.dlc_attestation = self.attestation;
+    }
+}
+
+impl ChannelExtension<BoltExt> for Dlc {
+    #[inline]
+    fn new() -> Box<dyn ChannelExtension<BoltExt>>
+    where
+        Self: Sized,
+    {
+        Box::new(Dlc::default())
+    }
+
+    fn build_graph(
+        &self,
+        tx_graph: &mut TxGraph,
+        as_remote_node: bool,
+    ) -> Result<(), Error> {
+        let contract = match self.contract {
+            Some(ref contract) => contract,
+            None => return Ok(()),
+        };
+        let keys = self.commitment_keys(as_remote_node);
+
+        // Locate balance outputs of the commitment owner and its counterparty
+        let (to_local_index, to_remote_index) = keys.balance_outputs(
+            tx_graph,
+            self.channel_type,
+            self.remotepubkey(as_remote_node),
+        );
+
+        // Offerer is the commitment owner if it is the local node building its
+        // own commitment or the remote node building its commitment
+        let owner_is_offerer = self.offerer != as_remote_node;
+        let (owner_collateral, counterparty_collateral) = if owner_is_offerer {
+            (
+                contract.offerer_collateral_sat,
+                contract.accepter_collateral_sat,
+            )
+        } else {
+            (
+                contract.accepter_collateral_sat,
+                contract.offerer_collateral_sat,
+            )
+        };
+
+        // Settled contract is folded into the balances; otherwise the funds
+        // are locked in the contract output with CETs spending it
+        let (owner_payout, counterparty_payout) = match self.attestation {
+            Some((outcome_index, _)) => {
+                let payout = contract.payouts[outcome_index as usize];
+                if owner_is_offerer {
+                    (payout.offerer_sat, payout.accepter_sat)
+                } else {
+                    (payout.accepter_sat, payout.offerer_sat)
+                }
+            }
+            None => {
+                tx_graph
+                    .cmt_outs
+                    .push(self.contract_output().map_err(Error::from)?);
+                for index in 0..contract.payouts.len() as u16 {
+                    let cet = Psbt::with(self.cet(index)?, PsbtVersion::V0)
+                        .expect(
+                            "Tx has empty sigs so PSBT creation does not fail",
+                        );
+                    tx_graph.insert_tx(TxType::DlcCet, index as u64, cet);
+                }
+                (0, 0)
+            }
+        };
+
+        for (index, collateral, payout) in [
+            (to_local_index, owner_collateral, owner_payout),
+            (
+                to_remote_index,
+                counterparty_collateral,
+                counterparty_payout,
+            ),
+        ] {
+            if let Some(index) = index {
+                let output = &mut tx_graph.cmt_outs[index];
+                output.amount = output
+                    .amount
+                    .saturating_sub(collateral)
+                    .saturating_add(payout);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Extracts attestation scalar from the BIP-340 signature of the oracle over
+/// the outcome, checking that the signature nonce matches the announcement
+pub fn attestation_scalar(
+    announcement: &OracleAnnouncement,
+    signature: &schnorr::Signature,
+) -> Result<SecretKey, DlcError> {
+    let sig = signature.as_ref();
+    let nonce = announcement.nonce_point.x_only_public_key().0;
+    if sig[..32] != nonce.serialize() {
+        return Err(DlcError::InvalidAnnouncement);
+    }
+    SecretKey::from_slice(&sig[32..]).map_err(|_| DlcError::InvalidAnnouncement)
+}
+
+#[cfg(test)]
+mod test {
+    use lnp2p::bolt::{DlcAccept, DlcSettle, DlcSign};
+    use secp256k1::Message;
+
+    use super::*;
+    use crate::channel::bolt::ScriptGenerators as BoltScripts;
+    use crate::channel::Funding;
+
+    fn seckey(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn key(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(SECP256K1, &seckey(byte))
+    }
+
+    /// Oracle committing to a single nonce for a single event
+    struct MockOracle {
+        secret_key: SecretKey,
+        nonce: SecretKey,
+    }
+
+    impl MockOracle {
+        fn announce(&self, outcomes: &[&str]) -> OracleAnnouncement {
+            OracleAnnouncement {
+                oracle_pubkey: PublicKey::from_secret_key(
+                    SECP256K1,
+                    &self.secret_key,
+                ),
+                nonce_point: PublicKey::from_secret_key(SECP256K1, &self.nonce),
+                event_id: s!("btcusd-2022"),
+                outcomes: outcomes.iter().map(|s| s.to_string()).collect(),
+            }
+        }
+
+        fn attest(&self, outcome: &str) -> schnorr::Signature {
+            let (pubkey, parity) = self.secret_key.x_only_public_key(SECP256K1);
+            let d = match parity {
+                Parity::Even => self.secret_key,
+                Parity::Odd => self.secret_key.negate(),
+            };
+            let (nonce, parity) = self.nonce.x_only_public_key(SECP256K1);
+            let k = match parity {
+                Parity::Even => self.nonce,
+                Parity::Odd => self.nonce.negate(),
+            };
+            let msg = sha256::Hash::hash(outcome.as_bytes());
+            let e =
+                SecretKey::from_slice(&tagged_hash("BIP0340/challenge", &[
+                    &nonce.serialize(),
+                    &pubkey.serialize(),
+                    &msg[..],
+                ]))
+                .unwrap();
+            let s = k
+                .add_tweak(&Scalar::from(
+                    d.mul_tweak(&Scalar::from(e)).unwrap(),
+                ))
+                .unwrap();
+
+            let mut data = [0u8; 64];
+            data[..32].copy_from_slice(&nonce.serialize());
+            data[32..].copy_from_slice(&s.secret_bytes());
+            schnorr::Signature::from_slice(&data).unwrap()
+        }
+    }
+
+    fn peers() -> (Dlc, Dlc) {
+        let alice = Dlc {
+            local_funding_pubkey: key(1),
+            remote_funding_pubkey: key(2),
+            local_payment_basepoint: key(3),
+            remote_payment_basepoint: key(4),
+            local_to_self_delay: 144,
+            remote_to_self_delay: 720,
+            local_revocation_basepoint: key(5),
+            remote_revocation_basepoint: key(6),
+            local_delayed_basepoint: key(7),
+            remote_delayed_basepoint: key(8),
+            local_per_commitment_point: key(9),
+            remote_per_commitment_point: key(10),
+            local_amount_msat: 5_000_000,
+            remote_amount_msat: 5_000_000,
+            ..Dlc::default()
+        };
+        let bob = Dlc {
+            local_funding_pubkey: key(2),
+            remote_funding_pubkey: key(1),
+            local_payment_basepoint: key(4),
+            remote_payment_basepoint: key(3),
+            local_to_self_delay: 720,
+            remote_to_self_delay: 144,
+            local_revocation_basepoint: key(6),
+            remote_revocation_basepoint: key(5),
+            local_delayed_basepoint: key(8),
+            remote_delayed_basepoint: key(7),
+            local_per_commitment_point: key(10),
+            remote_per_commitment_point: key(9),
+            local_amount_msat: 5_000_000,
+            remote_amount_msat: 5_000_000,
+            ..Dlc::default()
+        };
+        (alice, bob)
+    }
+
+    fn dlc_offer(announcement: OracleAnnouncement) -> DlcOffer {
+        DlcOffer {
+            channel_id: ChannelId::default(),
+            announcement,
+            offerer_collateral_sat: 1000,
+            accepter_collateral_sat: 1000,
+            payouts: vec![
+                DlcPayout {
+                    offerer_sat: 2000,
+                    accepter_sat: 0,
+                },
+                DlcPayout {
+                    offerer_sat: 0,
+                    accepter_sat: 2000,
+                },
+                DlcPayout {
+                    offerer_sat: 1000,
+                    accepter_sat: 1000,
+                },
+            ],
+        }
+    }
+
+    /// Builds commitment transaction of Alice, either by Alice or by Bob
+    fn commitment_outs(
+        peer: &Dlc,
+        as_remote_node: bool,
+        funding: &Funding,
+    ) -> Vec<psbt::Output> {
+        let keys = peer.commitment_keys(as_remote_node);
+        let mut tx_graph = TxGraph::from_funding(funding);
+        // Output not belonging to the channel balances must not be affected
+        tx_graph
+            .cmt_outs
+            .push(BoltScripts::ln_to_remote_v1(330, key(0x41)));
+        tx_graph.cmt_outs.push(BoltScripts::ln_to_local(
+            5000,
+            keys.revocationpubkey,
+            keys.local_delayedpubkey,
+            keys.to_self_delay,
+        ));
+        tx_graph.cmt_outs.push(BoltScripts::ln_to_remote_v1(
+            5000,
+            peer.remotepubkey(as_remote_node),
+        ));
+        peer.build_graph(&mut tx_graph, as_remote_node).unwrap();
+        for index in 0..3 {
+            assert_eq!(
+                tx_graph.tx(TxType::DlcCet, index).is_some(),
+                peer.attestation.is_none()
+            );
+        }
+        tx_graph.cmt_outs
+    }
+
+    #[test]
+    fn dlc_offer_sign_settle() {
+        let oracle = MockOracle {
+            secret_key: seckey(0x31),
+            nonce: seckey(0x32),
+        };
+        let announcement = oracle.announce(&["up", "down", "flat"]);
+        let (mut alice, mut bob) = peers();
+
+        // Invalid offers are rejected
+        let mut offer = dlc_offer(announcement.clone());
+        offer.payouts.pop();
+        assert_eq!(
+            DlcContract::with(&offer),
+            Err(DlcError::PayoutMismatch {
+                outcomes: 3,
+                payouts: 2
+            })
+        );
+        let mut offer = dlc_offer(announcement.clone());
+        offer.payouts[2].accepter_sat = 1001;
+        assert_eq!(
+            DlcContract::with(&offer),
+            Err(DlcError::CollateralMismatch(2))
+        );
+        let mut offer = dlc_offer(announcement.clone());
+        offer.offerer_collateral_sat = 5001;
+        offer.payouts = vec![
+            DlcPayout {
+                offerer_sat: 6001,
+                accepter_sat: 0,
+            };
+            3
+        ];
+        assert_eq!(
+            alice.open_contract(&offer, true),
+            Err(DlcError::InsufficientBalance)
+        );
+
+        // Alice offers the contract, Bob accepts it and both exchange CET
+        // adaptor signatures
+        let mut message = Messages::DlcOffer(dlc_offer(announcement));
+        alice
+            .state_change(&UpdateReq::OfferDlc, &mut message)
+            .unwrap();
+        bob.update_from_peer(&message).unwrap();
+        assert_eq!(alice.contract, bob.contract);
+        assert!(alice.offerer && !bob.offerer);
+        assert_eq!(alice.contract_leaf(), bob.contract_leaf());
+
+        let mut accept = Messages::DlcAccept(DlcAccept {
+            channel_id: ChannelId::default(),
+            cet_adaptor_signatures: vec![],
+        });
+        bob.state_change(&UpdateReq::SignDlc(seckey(2)), &mut accept)
+            .unwrap();
+        alice.update_from_peer(&accept).unwrap();
+        assert_eq!(alice.remote_cet_sigs.len(), 3);
+
+        // Signatures with a wrong key are not accepted
+        let mut sign = Messages::DlcSign(DlcSign {
+            channel_id: ChannelId::default(),
+            cet_adaptor_signatures: vec![],
+        });
+        alice
+            .state_change(&UpdateReq::SignDlc(seckey(3)), &mut sign)
+            .unwrap();
+        assert_eq!(
+            bob.update_from_peer(&sign),
+            Err(Error::Dlc(DlcError::Adaptor(
+                AdaptorError::InvalidSignature
+            )))
+        );
+        alice
+            .state_change(&UpdateReq::SignDlc(seckey(1)), &mut sign)
+            .unwrap();
+        bob.update_from_peer(&sign).unwrap();
+        assert_eq!(bob.remote_cet_sigs.len(), 3);
+
+        // Both peers build the same commitment with the contract output and
+        // CETs spending it
+        let funding = Funding::new();
+        let outs = commitment_outs(&alice, false, &funding);
+        assert_eq!(outs, commitment_outs(&bob, true, &funding));
+        assert_eq!(outs.len(), 4);
+        assert_eq!(outs[0].amount, 330);
+        assert_eq!(outs[1].amount, 4000);
+        assert_eq!(outs[2].amount, 4000);
+        assert_eq!(outs[3].amount, 2000);
+        assert!(outs[3].script.is_v1_p2tr());
+        assert_eq!(alice.cet(0).unwrap().output.len(), 1);
+        assert_eq!(alice.cet(2).unwrap(), bob.cet(2).unwrap());
+        assert_eq!(alice.cet(2).unwrap().output.len(), 2);
+
+        // Contract can't be enforced before the oracle attestation
+        assert_eq!(alice.signed_cet(seckey(1)), Err(DlcError::NotSettled));
+
+        // Oracle attests "down" outcome
+        let signature = oracle.attest("down");
+        let contract = alice.try_contract().unwrap().clone();
+        SECP256K1
+            .verify_schnorr(
+                &signature,
+                &Message::from_hashed_data::<sha256::Hash>(b"down"),
+                &contract.announcement.oracle_pubkey.x_only_public_key().0,
+            )
+            .unwrap();
+        let attestation =
+            attestation_scalar(&contract.announcement, &signature).unwrap();
+
+        let mut settle = Messages::DlcSettle(DlcSettle {
+            channel_id: ChannelId::default(),
+            outcome_index: 0,
+            attestation,
+        });
+        assert_eq!(
+            bob.state_change(&UpdateReq::SettleDlc, &mut settle),
+            Err(Error::Dlc(DlcError::InvalidAttestation(0)))
+        );
+        let mut settle = Messages::DlcSettle(DlcSettle {
+            channel_id: ChannelId::default(),
+            outcome_index: 1,
+            attestation,
+        });
+        bob.state_change(&UpdateReq::SettleDlc, &mut settle)
+            .unwrap();
+        alice.update_from_peer(&settle).unwrap();
+
+        // Each of the parties is able to complete CET with the attestation
+        for (peer, secret_key) in [(&alice, seckey(1)), (&bob, seckey(2))] {
+            let cet = peer.signed_cet(secret_key).unwrap();
+            let msg =
+                Message::from_slice(&peer.cet_sighash(1).unwrap()).unwrap();
+            let witness = cet.input[0].witness.to_vec();
+            assert_eq!(witness.len(), 4);
+            for (sig, pubkey) in [(&witness[0], key(2)), (&witness[1], key(1))]
+            {
+                SECP256K1
+                    .verify_schnorr(
+                        &schnorr::Signature::from_slice(sig).unwrap(),
+                        &msg,
+                        &pubkey.x_only_public_key().0,
+                    )
+                    .unwrap();
+            }
+        }
+
+        // After the settlement the contract is folded into the balances
+        let outs = commitment_outs(&alice, false, &funding);
+        assert_eq!(outs, commitment_outs(&bob, true, &funding));
+        assert_eq!(outs.len(), 3);
+        assert_eq!(outs[0].amount, 330);
+        assert_eq!(outs[1].amount, 4000);
+        assert_eq!(outs[2].amount, 6000);
+    }
+}
@@ -21,13 +21,23 @@ use bitcoin_scripts::hlc::{HashLock, HashPreimage};
 use bitcoin_scripts::{LockScript, PubkeyScript, WitnessScript};
 use lnp2p::bolt::{ChannelId, Messages};
 use p2p::bolt::ChannelType;
-use wallet::psbt::{self, Output, Psbt, PsbtVersion};
+use wallet::psbt::{self, Psbt, PsbtVersion};
 
 use crate::channel::bolt::util::UpdateReq;
-use crate::channel::bolt::{BoltExt, ChannelState, Error, TxType};
+use crate::channel::bolt::{
+    derive_pubkey, derive_revocationpubkey, BoltExt, ChannelState,
+    CommitmentPolicyError, DustExposure, Error, Offerer, ScriptGenerators as _,
+    TaprootScriptGenerators, TxType,
+};
 use crate::channel::tx_graph::TxGraph;
 use crate::{ChannelExtension, Extension};
 
+/// Weight of HTLC-timeout transaction according to BOLT-3
+pub const BOLT3_HTLC_TIMEOUT_WEIGHT: u64 = 663;
+
+/// Weight of HTLC-success transaction according to BOLT-3
+pub const BOLT3_HTLC_SUCCESS_WEIGHT: u64 = 703;
+
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 #[derive(StrictEncode, StrictDecode)]
 #[cfg_attr(
@@ -59,10 +69,10 @@ pub struct HtlcSecret {
 #[derive(Getters, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 #[derive(StrictEncode, StrictDecode)]
 pub struct Htlc {
-    /// Set if the feature `option_anchors_zero_fee_htlc_tx` was negotiated via
-    /// `channel_type`. Indicates that HTLC transactions will use zero fees and
-    /// will be pushed through an anchor transaction.
-    anchors_zero_fee_htlc_tx: bool,
+    /// Channel type negotiated during channel establishment. Defines whether
+    /// HTLC transactions use zero fees, whether HTLC outputs are P2TR outputs
+    /// (for simple taproot channels) and the form of the balance outputs.
+    channel_type: ChannelType,
 
     // Sets of HTLC information
     offered_htlcs: BTreeMap<u64, HtlcSecret>,
@@ -70,12 +80,22 @@ pub struct Htlc {
     resolved_htlcs: BTreeMap<u64, HtlcKnown>,
 
     // Commitment round specific information
-    to_self_delay: u16,
+    /// Delay applied to the outputs of the local node in the local commitment
+    /// transaction (requested by the remote node)
+    local_to_self_delay: u16,
+    /// Delay applied to the outputs of the remote node in the remote
+    /// commitment transaction (requested by the local node)
+    remote_to_self_delay: u16,
     local_revocation_basepoint: PublicKey,
     remote_revocation_basepoint: PublicKey,
-    local_basepoint: PublicKey,
-    remote_basepoint: PublicKey,
+    local_payment_basepoint: PublicKey,
+    remote_payment_basepoint: PublicKey,
+    local_htlc_basepoint: PublicKey,
+    remote_htlc_basepoint: PublicKey,
     local_delayed_basepoint: PublicKey,
+    remote_delayed_basepoint: PublicKey,
+    local_per_commitment_point: PublicKey,
+    remote_per_commitment_point: PublicKey,
 
     // Channel specific information
     channel_id: ChannelId,
@@ -87,31 +107,137 @@ pub struct Htlc {
 
     next_received_htlc_id: u64,
     next_offered_htlc_id: u64,
+
+    // Dust HTLC exposure information
+    /// Fee rate of the commitment transactions, defining which HTLCs are
+    /// trimmed as dust
+    feerate_per_kw: u32,
+    /// Dust limit for the local commitment transaction
+    local_dust_limit_satoshis: u64,
+    /// Dust limit for the remote commitment transaction
+    remote_dust_limit_satoshis: u64,
+    /// Limit on the dust HTLC exposure set by the local policy
+    max_dust_htlc_exposure: Option<DustExposure>,
 }
 
 impl Default for Htlc {
     fn default() -> Self {
         Htlc {
-            anchors_zero_fee_htlc_tx: false,
+            channel_type: ChannelType::Basic,
             offered_htlcs: empty!(),
             received_htlcs: empty!(),
             resolved_htlcs: empty!(),
-            to_self_delay: 0,
+            local_to_self_delay: 0,
+            remote_to_self_delay: 0,
             local_revocation_basepoint: dumb_pubkey!(),
             remote_revocation_basepoint: dumb_pubkey!(),
-            local_basepoint: dumb_pubkey!(),
-            remote_basepoint: dumb_pubkey!(),
+            local_payment_basepoint: dumb_pubkey!(),
+            remote_payment_basepoint: dumb_pubkey!(),
+            local_htlc_basepoint: dumb_pubkey!(),
+            remote_htlc_basepoint: dumb_pubkey!(),
             local_delayed_basepoint: dumb_pubkey!(),
+            remote_delayed_basepoint: dumb_pubkey!(),
+            local_per_commitment_point: dumb_pubkey!(),
+            remote_per_commitment_point: dumb_pubkey!(),
             channel_id: Default::default(),
             htlc_minimum_msat: 0,
             max_htlc_value_in_flight_msat: 0,
             max_accepted_htlcs: 0,
             next_received_htlc_id: 0,
             next_offered_htlc_id: 0,
+            feerate_per_kw: 0,
+            local_dust_limit_satoshis: 0,
+            remote_dust_limit_satoshis: 0,
+            max_dust_htlc_exposure: None,
         }
     }
 }
 
+/// Set of keys and parameters specific to a single commitment transaction,
+/// derived according to BOLT-3 from the channel basepoints and the
+/// per-commitment point of the commitment owner.
+///
+/// Terms "local" and "remote" here are relative to the owner of the
+/// commitment transaction, which may be the remote node.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct CommitmentKeys {
+    pub revocationpubkey: PublicKey,
+    pub local_htlcpubkey: PublicKey,
+    pub remote_htlcpubkey: PublicKey,
+    pub local_delayedpubkey: PublicKey,
+    pub to_self_delay: u16,
+}
+
+impl CommitmentKeys {
+    /// Returns public key used in the `to_remote` output, paying the party
+    /// with the given `payment_basepoint`, of the commitment transaction
+    /// having the given `per_commitment_point`.
+    ///
+    /// For channels with `option_static_remotekey` (including all anchored
+    /// channel types) this is the payment basepoint itself; otherwise the
+    /// basepoint is tweaked with the per-commitment point.
+    pub fn remotepubkey(
+        channel_type: ChannelType,
+        payment_basepoint: PublicKey,
+        per_commitment_point: PublicKey,
+    ) -> PublicKey {
+        if channel_type.has_static_remotekey() {
+            payment_basepoint
+        } else {
+            derive_pubkey(payment_basepoint, per_commitment_point)
+        }
+    }
+
+    /// Locates `to_local` and `to_remote` balance outputs produced by the
+    /// channel constructor in the commitment transaction by their scripts.
+    ///
+    /// Returns indexes of the outputs in [`TxGraph::cmt_outs`]; an output is
+    /// absent if its amount was trimmed or if it is not present in the
+    /// transaction for other reasons.
+    pub fn balance_outputs(
+        &self,
+        tx_graph: &TxGraph,
+        channel_type: ChannelType,
+        remotepubkey: PublicKey,
+    ) -> (Option<usize>, Option<usize>) {
+        let (to_local_script, to_remote_script) =
+            if channel_type.has_simple_taproot() {
+                (
+                    PubkeyScript::ln_taproot_to_local(
+                        0,
+                        self.revocationpubkey,
+                        self.local_delayedpubkey,
+                        self.to_self_delay,
+                    ),
+                    PubkeyScript::ln_taproot_to_remote(0, remotepubkey),
+                )
+            } else {
+                // Anchored channels lock `to_remote` with 1-block CSV delay
+                let to_remote_script = if channel_type.has_anchors() {
+                    PubkeyScript::ln_to_remote_v2(0, remotepubkey)
+                } else {
+                    PubkeyScript::ln_to_remote_v1(0, remotepubkey)
+                };
+                (
+                    PubkeyScript::ln_to_local(
+                        0,
+                        self.revocationpubkey,
+                        self.local_delayedpubkey,
+                        self.to_self_delay,
+                    ),
+                    to_remote_script,
+                )
+            };
+        let position = |script: &PubkeyScript| {
+            tx_graph
+                .cmt_outs
+                .iter()
+                .position(|out| &out.script == script)
+        };
+        (position(&to_local_script), position(&to_remote_script))
+    }
+}
+
 impl Htlc {
     pub fn offer_htlc(
         &mut self,
@@ -129,6 +255,237 @@ impl Htlc {
         });
         htlc_id
     }
+
+    /// Constructs second-level HTLC transaction spending HTLC output of the
+    /// commitment transaction with the given `keys`
+    fn htlc_tx(
+        &self,
+        amount: u64,
+        cltv_expiry: u32,
+        keys: &CommitmentKeys,
+    ) -> Psbt {
+        // TODO: do a two-staged graph generation process
+        let outpoint = OutPoint::default();
+        if self.channel_type.has_simple_taproot() {
+            Psbt::ln_taproot_htlc(
+                amount,
+                outpoint,
+                cltv_expiry,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            )
+        } else {
+            Psbt::ln_htlc(
+                amount,
+                outpoint,
+                cltv_expiry,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            )
+        }
+    }
+
+    /// Fulfills HTLC received from the remote node with the preimage of its
+    /// payment hash
+    pub fn fulfill_htlc(
+        &mut self,
+        htlc_id: u64,
+        payment_preimage: HashPreimage,
+    ) -> Result<HtlcKnown, Error> {
+        let received = self.received_htlcs.get(&htlc_id).ok_or_else(|| {
+            Error::Htlc(format!("unknown received HTLC {}", htlc_id))
+        })?;
+        if received.hashlock != HashLock::from(payment_preimage) {
+            return Err(Error::Htlc(format!(
+                "preimage does not match payment hash of HTLC {}",
+                htlc_id
+            )));
+        }
+        let resolved = HtlcKnown {
+            amount: received.amount,
+            preimage: payment_preimage,
+            id: htlc_id,
+            cltv_expiry: received.cltv_expiry,
+        };
+        self.received_htlcs.remove(&htlc_id);
+        self.resolved_htlcs.insert(htlc_id, resolved);
+        Ok(resolved)
+    }
+
+    /// Fails HTLC received from the remote node
+    pub fn fail_htlc(&mut self, htlc_id: u64) -> Result<HtlcSecret, Error> {
+        self.received_htlcs.remove(&htlc_id).ok_or_else(|| {
+            Error::Htlc(format!("unknown received HTLC {}", htlc_id))
+        })
+    }
+
+    /// Detects whether HTLC is trimmed from a commitment transaction with the
+    /// given dust limit, i.e. its amount does not cover the dust limit
+    /// together with the fee of the second-stage HTLC transaction.
+    ///
+    /// This is the only trimming rule used both for constructing commitment
+    /// transactions and for computing the dust HTLC exposure.
+    ///
+    /// The `offered` argument tells whether the HTLC is offered by the owner
+    /// of the commitment transaction (and is spent by HTLC-timeout
+    /// transaction) or is received by it (and is spent by HTLC-success).
+    pub fn is_dust(
+        &self,
+        amount_msat: u64,
+        offered: bool,
+        dust_limit_satoshis: u64,
+        feerate_per_kw: u32,
+    ) -> bool {
+        let htlc_tx_fee = if self.channel_type.has_anchors_zero_fee_htlc_tx() {
+            0
+        } else if offered {
+            BOLT3_HTLC_TIMEOUT_WEIGHT * feerate_per_kw as u64 / 1000
+        } else {
+            BOLT3_HTLC_SUCCESS_WEIGHT * feerate_per_kw as u64 / 1000
+        };
+        amount_msat / 1000 < dust_limit_satoshis + htlc_tx_fee
+    }
+
+    /// Returns HTLCs offered and received by the owner of the local (if
+    /// `as_remote_node` is `false`) or remote (if `as_remote_node` is `true`)
+    /// commitment transaction, together with the dust limit of the owner.
+    fn commitment_htlcs(
+        &self,
+        as_remote_node: bool,
+    ) -> (&BTreeMap<u64, HtlcSecret>, &BTreeMap<u64, HtlcSecret>, u64) {
+        // HTLCs offered by us are received by the remote node, so for the
+        // remote commitment transaction we have to swap the sets
+        if as_remote_node {
+            (
+                &self.received_htlcs,
+                &self.offered_htlcs,
+                self.remote_dust_limit_satoshis,
+            )
+        } else {
+            (
+                &self.offered_htlcs,
+                &self.received_htlcs,
+                self.local_dust_limit_satoshis,
+            )
+        }
+    }
+
+    /// Computes total amount of dust HTLCs, in millisatoshis, in the local
+    /// (if `as_remote_node` is `false`) or remote (if `as_remote_node` is
+    /// `true`) commitment transaction at the given fee rate.
+    pub fn dust_exposure_msat(
+        &self,
+        as_remote_node: bool,
+        feerate_per_kw: u32,
+    ) -> u64 {
+        let (offered_htlcs, received_htlcs, dust_limit) =
+            self.commitment_htlcs(as_remote_node);
+        let offered = offered_htlcs.values().map(|htlc| (htlc, true));
+        let received = received_htlcs.values().map(|htlc| (htlc, false));
+        offered
+            .chain(received)
+            .filter(|(htlc, offered)| {
+                self.is_dust(htlc.amount, *offered, dust_limit, feerate_per_kw)
+            })
+            .map(|(htlc, _)| htlc.amount)
+            .sum()
+    }
+
+    /// Checks that dust HTLC exposure of both local and remote commitment
+    /// transactions at the given fee rate does not exceed the limit set by
+    /// the local policy.
+    pub fn check_dust_exposure(
+        &self,
+        feerate_per_kw: u32,
+    ) -> Result<(), CommitmentPolicyError> {
+        let limit_msat = match self.max_dust_htlc_exposure {
+            None => return Ok(()),
+            Some(limit) => limit.limit_msat(feerate_per_kw),
+        };
+        for (owner, as_remote_node) in
+            [(Offerer::Local, false), (Offerer::Remote, true)]
+        {
+            let exposure_msat =
+                self.dust_exposure_msat(as_remote_node, feerate_per_kw);
+            if exposure_msat > limit_msat {
+                return Err(CommitmentPolicyError::DustExposureExceeded {
+                    owner,
+                    exposure_msat,
+                    limit_msat,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns public key used in the `to_remote` output of the local (if
+    /// `as_remote_node` is `false`) or remote (if `as_remote_node` is `true`)
+    /// commitment transaction.
+    pub fn remotepubkey(&self, as_remote_node: bool) -> PublicKey {
+        if as_remote_node {
+            CommitmentKeys::remotepubkey(
+                self.channel_type,
+                self.local_payment_basepoint,
+                self.remote_per_commitment_point,
+            )
+        } else {
+            CommitmentKeys::remotepubkey(
+                self.channel_type,
+                self.remote_payment_basepoint,
+                self.local_per_commitment_point,
+            )
+        }
+    }
+
+    /// Derives keys for the local (if `as_remote_node` is `false`) or remote
+    /// (if `as_remote_node` is `true`) commitment transaction.
+    pub fn commitment_keys(&self, as_remote_node: bool) -> CommitmentKeys {
+        if as_remote_node {
+            let per_commitment_point = self.remote_per_commitment_point;
+            CommitmentKeys {
+                revocationpubkey: derive_revocationpubkey(
+                    self.local_revocation_basepoint,
+                    per_commitment_point,
+                ),
+                local_htlcpubkey: derive_pubkey(
+                    self.remote_htlc_basepoint,
+                    per_commitment_point,
+                ),
+                remote_htlcpubkey: derive_pubkey(
+                    self.local_htlc_basepoint,
+                    per_commitment_point,
+                ),
+                local_delayedpubkey: derive_pubkey(
+                    self.remote_delayed_basepoint,
+                    per_commitment_point,
+                ),
+                to_self_delay: self.remote_to_self_delay,
+            }
+        } else {
+            let per_commitment_point = self.local_per_commitment_point;
+            CommitmentKeys {
+                revocationpubkey: derive_revocationpubkey(
+                    self.remote_revocation_basepoint,
+                    per_commitment_point,
+                ),
+                local_htlcpubkey: derive_pubkey(
+                    self.local_htlc_basepoint,
+                    per_commitment_point,
+                ),
+                remote_htlcpubkey: derive_pubkey(
+                    self.remote_htlc_basepoint,
+                    per_commitment_point,
+                ),
+                local_delayedpubkey: derive_pubkey(
+                    self.local_delayed_basepoint,
+                    per_commitment_point,
+                ),
+                to_self_delay: self.local_to_self_delay,
+            }
+        }
+    }
 }
 
 impl Extension<BoltExt> for Htlc {
@@ -156,11 +513,49 @@ impl Extension<BoltExt> for Htlc {
                     update_add_htlc.payment_hash,
                     update_add_htlc.cltv_expiry,
                 );
+                if let Err(err) = self.check_dust_exposure(self.feerate_per_kw)
+                {
+                    self.offered_htlcs.remove(&htlc_id);
+                    self.next_offered_htlc_id -= 1;
+                    return Err(err.into());
+                }
                 update_add_htlc.htlc_id = htlc_id;
             }
-            (UpdateReq::PayBolt(_), _) => unreachable!(
+            (
+                UpdateReq::FulfillHtlc,
+                Messages::UpdateFulfillHtlc(update_fulfill_htlc),
+            ) => {
+                self.fulfill_htlc(
+                    update_fulfill_htlc.htlc_id,
+                    update_fulfill_htlc.payment_preimage,
+                )?;
+            }
+            (
+                UpdateReq::FailHtlc,
+                Messages::UpdateFailHtlc(update_fail_htlc),
+            ) => {
+                self.fail_htlc(update_fail_htlc.htlc_id)?;
+            }
+            (UpdateReq::PayBolt(_), _)
+            | (UpdateReq::FulfillHtlc, _)
+            | (UpdateReq::FailHtlc, _) => unreachable!(
                 "state change request must match provided LN P2P message"
             ),
+            (UpdateReq::UpdateFee, Messages::UpdateFee(update_fee)) => {
+                self.check_dust_exposure(update_fee.feerate_per_kw)?;
+                self.feerate_per_kw = update_fee.feerate_per_kw;
+            }
+            (UpdateReq::UpdateFee, _) => unreachable!(
+                "state change request must match provided LN P2P message"
+            ),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn validate_peer_message(&self, message: &Messages) -> Result<(), Error> {
+        if let Messages::UpdateFee(update_fee) = message {
+            self.check_dust_exposure(update_fee.feerate_per_kw)?;
         }
         Ok(())
     }
@@ -168,36 +563,83 @@ impl Extension<BoltExt> for Htlc {
     fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
         match message {
             Messages::OpenChannel(open_channel) => {
-                self.anchors_zero_fee_htlc_tx = open_channel
-                    .channel_type
-                    .map(ChannelType::has_anchors_zero_fee_htlc_tx)
-                    .unwrap_or_default();
+                self.channel_type =
+                    open_channel.channel_type.unwrap_or_default();
                 self.htlc_minimum_msat = open_channel.htlc_minimum_msat;
                 self.max_accepted_htlcs = open_channel.max_accepted_htlcs;
                 self.max_htlc_value_in_flight_msat =
                     open_channel.max_htlc_value_in_flight_msat;
-                self.remote_basepoint = open_channel.htlc_basepoint;
+                self.remote_htlc_basepoint = open_channel.htlc_basepoint;
+                self.remote_payment_basepoint = open_channel.payment_point;
                 self.remote_revocation_basepoint =
                     open_channel.revocation_basepoint;
-                self.local_delayed_basepoint =
+                self.remote_delayed_basepoint =
                     open_channel.delayed_payment_basepoint;
-                self.to_self_delay = open_channel.to_self_delay;
+                self.remote_per_commitment_point =
+                    open_channel.first_per_commitment_point;
+                self.local_to_self_delay = open_channel.to_self_delay;
+                self.feerate_per_kw = open_channel.feerate_per_kw;
+                self.remote_dust_limit_satoshis =
+                    open_channel.dust_limit_satoshis;
             }
             Messages::AcceptChannel(accept_channel) => {
-                self.anchors_zero_fee_htlc_tx = accept_channel
-                    .channel_type
-                    .map(ChannelType::has_anchors_zero_fee_htlc_tx)
-                    .unwrap_or_default();
+                self.channel_type =
+                    accept_channel.channel_type.unwrap_or_default();
                 self.htlc_minimum_msat = accept_channel.htlc_minimum_msat;
                 self.max_accepted_htlcs = accept_channel.max_accepted_htlcs;
                 self.max_htlc_value_in_flight_msat =
                     accept_channel.max_htlc_value_in_flight_msat;
-                self.remote_basepoint = accept_channel.htlc_basepoint;
+                self.remote_htlc_basepoint = accept_channel.htlc_basepoint;
+                self.remote_payment_basepoint = accept_channel.payment_point;
                 self.remote_revocation_basepoint =
                     accept_channel.revocation_basepoint;
-                self.local_delayed_basepoint =
+                self.remote_delayed_basepoint =
                     accept_channel.delayed_payment_basepoint;
-                self.to_self_delay = accept_channel.to_self_delay;
+                self.remote_per_commitment_point =
+                    accept_channel.first_per_commitment_point;
+                self.local_to_self_delay = accept_channel.to_self_delay;
+                self.remote_dust_limit_satoshis =
+                    accept_channel.dust_limit_satoshis;
+            }
+            Messages::OpenChannel2(open_channel) => {
+                self.channel_type =
+                    open_channel.channel_type.unwrap_or_default();
+                self.htlc_minimum_msat = open_channel.htlc_minimum_msat;
+                self.max_accepted_htlcs = open_channel.max_accepted_htlcs;
+                self.max_htlc_value_in_flight_msat =
+                    open_channel.max_htlc_value_in_flight_msat;
+                self.remote_htlc_basepoint = open_channel.htlc_basepoint;
+                self.remote_payment_basepoint = open_channel.payment_basepoint;
+                self.remote_revocation_basepoint =
+                    open_channel.revocation_basepoint;
+                self.remote_delayed_basepoint =
+                    open_channel.delayed_payment_basepoint;
+                self.remote_per_commitment_point =
+                    open_channel.first_per_commitment_point;
+                self.local_to_self_delay = open_channel.to_self_delay;
+                self.feerate_per_kw = open_channel.commitment_feerate_perkw;
+                self.remote_dust_limit_satoshis =
+                    open_channel.dust_limit_satoshis;
+            }
+            Messages::AcceptChannel2(accept_channel) => {
+                self.channel_type =
+                    accept_channel.channel_type.unwrap_or_default();
+                self.htlc_minimum_msat = accept_channel.htlc_minimum_msat;
+                self.max_accepted_htlcs = accept_channel.max_accepted_htlcs;
+                self.max_htlc_value_in_flight_msat =
+                    accept_channel.max_htlc_value_in_flight_msat;
+                self.remote_htlc_basepoint = accept_channel.htlc_basepoint;
+                self.remote_payment_basepoint =
+                    accept_channel.payment_basepoint;
+                self.remote_revocation_basepoint =
+                    accept_channel.revocation_basepoint;
+                self.remote_delayed_basepoint =
+                    accept_channel.delayed_payment_basepoint;
+                self.remote_per_commitment_point =
+                    accept_channel.first_per_commitment_point;
+                self.local_to_self_delay = accept_channel.to_self_delay;
+                self.remote_dust_limit_satoshis =
+                    accept_channel.dust_limit_satoshis;
             }
             Messages::UpdateAddHtlc(message) => {
                 // TODO: Filter messages by channel_id at channel level with
@@ -241,6 +683,12 @@ impl Extension<BoltExt> for Htlc {
                             cltv_expiry: message.cltv_expiry,
                         };
                         self.received_htlcs.insert(htlc.id, htlc);
+                        if let Err(err) =
+                            self.check_dust_exposure(self.feerate_per_kw)
+                        {
+                            self.received_htlcs.remove(&htlc.id);
+                            return Err(err.into());
+                        }
 
                         self.next_received_htlc_id += 1;
                     }
@@ -285,9 +733,21 @@ impl Extension<BoltExt> for Htlc {
                     // TODO the failure reason should be handled here
                 }
             }
+            Messages::UpdateFee(update_fee) => {
+                // The fee rate is validated by `validate_peer_message`
+                self.feerate_per_kw = update_fee.feerate_per_kw;
+            }
+            Messages::FundingLocked(funding_locked) => {
+                self.remote_per_commitment_point =
+                    funding_locked.next_per_commitment_point;
+            }
             Messages::UpdateFailMalformedHtlc(_) => {}
             Messages::CommitmentSigned(_) => {}
-            Messages::RevokeAndAck(_) => {}
+            Messages::RevokeAndAck(revoke_and_ack) => {
+                // Next commitment we sign for the peer uses the new point
+                self.remote_per_commitment_point =
+                    revoke_and_ack.next_per_commitment_point;
+            }
             Messages::ChannelReestablish(_) => {}
             _ => {}
         }
@@ -295,24 +755,28 @@ impl Extension<BoltExt> for Htlc {
     }
 
     fn load_state(&mut self, state: &ChannelState) {
-        self.anchors_zero_fee_htlc_tx = state
-            .common_params
-            .channel_type
-            .has_anchors_zero_fee_htlc_tx();
+        self.channel_type = state.common_params.channel_type;
 
         self.offered_htlcs = state.offered_htlcs.clone();
         self.received_htlcs = state.received_htlcs.clone();
         self.resolved_htlcs = state.resolved_htlcs.clone();
 
-        self.to_self_delay = state.remote_params.to_self_delay;
+        self.local_to_self_delay = state.remote_params.to_self_delay;
+        self.remote_to_self_delay = state.local_params.to_self_delay;
         self.local_revocation_basepoint =
             state.local_keys.revocation_basepoint.key;
         self.remote_revocation_basepoint =
             state.remote_keys.revocation_basepoint;
-        self.local_basepoint = state.local_keys.payment_basepoint.key;
-        self.remote_basepoint = state.remote_keys.payment_basepoint;
+        self.local_payment_basepoint = state.local_keys.payment_basepoint.key;
+        self.remote_payment_basepoint = state.remote_keys.payment_basepoint;
+        self.local_htlc_basepoint = state.local_keys.htlc_basepoint.key;
+        self.remote_htlc_basepoint = state.remote_keys.htlc_basepoint;
         self.local_delayed_basepoint =
             state.local_keys.delayed_payment_basepoint.key;
+        self.remote_delayed_basepoint =
+            state.remote_keys.delayed_payment_basepoint;
+        self.local_per_commitment_point = state.local_per_commitment_point;
+        self.remote_per_commitment_point = state.remote_per_commitment_point;
 
         self.channel_id = state.active_channel_id.as_slice32().into();
 
@@ -323,6 +787,12 @@ impl Extension<BoltExt> for Htlc {
 
         self.next_received_htlc_id = state.last_received_htlc_id;
         self.next_offered_htlc_id = state.last_offered_htlc_id;
+
+        self.feerate_per_kw = state.common_params.feerate_per_kw;
+        self.local_dust_limit_satoshis = state.local_params.dust_limit_satoshis;
+        self.remote_dust_limit_satoshis =
+            state.remote_params.dust_limit_satoshis;
+        self.max_dust_htlc_exposure = state.policy.max_dust_htlc_exposure;
     }
 
     fn store_state(&self, state: &mut ChannelState) {
@@ -346,101 +816,123 @@ impl ChannelExtension<BoltExt> for Htlc {
     fn build_graph(
         &self,
         tx_graph: &mut TxGraph,
-        _as_remote_node: bool,
+        as_remote_node: bool,
     ) -> Result<(), Error> {
+        let keys = self.commitment_keys(as_remote_node);
+
+        let (offered_htlcs, received_htlcs, dust_limit) =
+            self.commitment_htlcs(as_remote_node);
+
+        // Locate balance outputs produced by the channel constructor: the
+        // amounts of the offered HTLCs are taken from the `to_local` output
+        // of the commitment owner, and the amounts of the received HTLCs -
+        // from `to_remote`
+        let (to_local_index, to_remote_index) = keys.balance_outputs(
+            tx_graph,
+            self.channel_type,
+            self.remotepubkey(as_remote_node),
+        );
+
+        // HTLCs which do not cover the dust limit of the commitment owner are
+        // trimmed: they do not have outputs and their amounts go to the fee.
+        // The same rule defines the dust HTLC exposure.
+        //
         // Process offered HTLCs
-        let mut accumulate = 0;
-        for (index, offered) in self.offered_htlcs.iter() {
-            let htlc_output: Output = ScriptGenerators::ln_offered_htlc(
-                offered.amount / 1000,
-                self.remote_revocation_basepoint,
-                self.local_basepoint,
-                self.remote_basepoint,
-                offered.hashlock,
-            );
-            // Sum amounts to same OutPoints
-            match tx_graph.cmt_outs.iter().position(|out| {
-                out.script == htlc_output.script
-                    && out.witness_script == htlc_output.witness_script
-            }) {
-                Some(index) => {
-                    let mut htlc_output = tx_graph.cmt_outs.remove(index);
-                    htlc_output.amount += offered.amount;
-                    tx_graph.cmt_outs.insert(index, htlc_output);
-                }
-                _ => tx_graph.cmt_outs.push(htlc_output),
+        let mut offered_sat = 0u64;
+        for (id, offered) in offered_htlcs {
+            let amount = offered.amount / 1000;
+            offered_sat += amount;
+            if self.is_dust(
+                offered.amount,
+                true,
+                dust_limit,
+                self.feerate_per_kw,
+            ) {
+                continue;
+            }
+
+            let output = if self.channel_type.has_simple_taproot() {
+                TaprootScriptGenerators::ln_taproot_offered_htlc(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_htlcpubkey,
+                    keys.remote_htlcpubkey,
+                    offered.hashlock,
+                )
+            } else {
+                ScriptGenerators::ln_offered_htlc(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_htlcpubkey,
+                    keys.remote_htlcpubkey,
+                    offered.hashlock,
+                )
             };
+            tx_graph.cmt_outs.push(output);
 
-            let htlc_tx = Psbt::ln_htlc(
-                offered.amount,
-                // TODO: do a two-staged graph generation process
-                OutPoint::default(),
-                offered.cltv_expiry,
-                self.remote_revocation_basepoint,
-                self.local_delayed_basepoint,
-                self.to_self_delay,
-            );
-            // Last index of transaction in graph
-            let last_index = tx_graph.last_index(TxType::HtlcTimeout) + 1;
-            tx_graph.insert_tx(
-                TxType::HtlcTimeout,
-                last_index as u64 + index,
-                htlc_tx,
-            );
-
-            accumulate += offered.amount / 1000;
+            let htlc_tx = self.htlc_tx(amount, offered.cltv_expiry, &keys);
+            tx_graph.insert_tx(TxType::HtlcTimeout, *id, htlc_tx);
         }
 
         // Process received HTLCs
-        for (index, received) in self.received_htlcs.iter() {
-            let htlc_output: Output = ScriptGenerators::ln_received_htlc(
-                received.amount / 1000,
-                self.remote_revocation_basepoint,
-                self.local_basepoint,
-                self.remote_basepoint,
-                received.cltv_expiry,
-                received.hashlock,
-            );
-
-            // Sum amounts to same OutPoints
-            match tx_graph.cmt_outs.iter().position(|out| {
-                out.script == htlc_output.script
-                    && out.witness_script == htlc_output.witness_script
-            }) {
-                Some(index) => {
-                    let mut htlc_output = tx_graph.cmt_outs.remove(index);
-                    htlc_output.amount += received.amount;
-                    tx_graph.cmt_outs.insert(index, htlc_output);
-                }
-                _ => tx_graph.cmt_outs.push(htlc_output),
+        let mut received_sat = 0u64;
+        for (id, received) in received_htlcs {
+            let amount = received.amount / 1000;
+            received_sat += amount;
+            if self.is_dust(
+                received.amount,
+                false,
+                dust_limit,
+                self.feerate_per_kw,
+            ) {
+                continue;
+            }
+
+            let output = if self.channel_type.has_simple_taproot() {
+                TaprootScriptGenerators::ln_taproot_received_htlc(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_htlcpubkey,
+                    keys.remote_htlcpubkey,
+                    received.cltv_expiry,
+                    received.hashlock,
+                )
+            } else {
+                ScriptGenerators::ln_received_htlc(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_htlcpubkey,
+                    keys.remote_htlcpubkey,
+                    received.cltv_expiry,
+                    received.hashlock,
+                )
             };
+            tx_graph.cmt_outs.push(output);
 
-            let htlc_tx = Psbt::ln_htlc(
-                received.amount,
-                // TODO: do a two-staged graph generation process
-                OutPoint::default(),
-                received.cltv_expiry,
-                self.remote_revocation_basepoint,
-                self.local_delayed_basepoint,
-                self.to_self_delay,
-            );
-            // Figure out the last index of transaction in graph
-            let last_index = tx_graph.last_index(TxType::HtlcSuccess) + 1;
-            tx_graph.insert_tx(
-                TxType::HtlcSuccess,
-                last_index as u64 + index,
-                htlc_tx,
-            );
-
-            accumulate += received.amount / 1000;
+            // HTLC success transactions are not timelocked
+            let htlc_tx = self.htlc_tx(amount, 0, &keys);
+            tx_graph.insert_tx(TxType::HtlcSuccess, *id, htlc_tx);
         }
 
-        // Subtracts from the total amount of the commitment transaction
-        if accumulate > 0 {
-            let mut refund_tx = tx_graph.cmt_outs.remove(0);
-            refund_tx.amount -= accumulate;
+        // Subtract HTLC amounts from the balances of the offering parties
+        if let Some(index) = to_local_index {
+            let output = &mut tx_graph.cmt_outs[index];
+            output.amount = output.amount.saturating_sub(offered_sat);
+        }
+        if let Some(index) = to_remote_index {
+            let output = &mut tx_graph.cmt_outs[index];
+            output.amount = output.amount.saturating_sub(received_sat);
+        }
 
-            tx_graph.cmt_outs.insert(tx_graph.cmt_outs.len(), refund_tx);
+        // Balance outputs which fall below the dust limit are trimmed as well
+        let mut trimmed = [to_local_index, to_remote_index]
+            .into_iter()
+            .flatten()
+            .filter(|index| tx_graph.cmt_outs[*index].amount < dust_limit)
+            .collect::<Vec<_>>();
+        trimmed.sort_unstable();
+        for index in trimmed.into_iter().rev() {
+            tx_graph.cmt_outs.remove(index);
         }
 
         Ok(())
@@ -857,6 +1349,18 @@ pub trait TxGenerators {
         local_delayedpubkey: PublicKey,
         to_self_delay: u16,
     ) -> Self;
+
+    /// Second-level HTLC transaction of simple taproot channel, which has
+    /// P2TR output and, like in all channels with anchor outputs, signals
+    /// 1-block relative timelock in the input sequence.
+    fn ln_taproot_htlc(
+        amount: u64,
+        outpoint: OutPoint,
+        cltv_expiry: u32,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self;
 }
 
 impl TxGenerators for Transaction {
@@ -888,6 +1392,38 @@ impl TxGenerators for Transaction {
             output: vec![txout],
         }
     }
+
+    /// NB: For HTLC Success transaction always set `cltv_expiry` parameter
+    ///     to zero!
+    fn ln_taproot_htlc(
+        amount: u64,
+        outpoint: OutPoint,
+        cltv_expiry: u32,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        let script_pubkey = PubkeyScript::ln_taproot_htlc_output(
+            amount,
+            revocationpubkey,
+            local_delayedpubkey,
+            to_self_delay,
+        );
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(cltv_expiry),
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: none!(),
+                sequence: bitcoin::Sequence(1),
+                witness: empty!(),
+            }],
+            output: vec![TxOut {
+                value: amount,
+                script_pubkey: script_pubkey.into(),
+            }],
+        }
+    }
 }
 
 impl TxGenerators for Psbt {
@@ -921,4 +1457,450 @@ impl TxGenerators for Psbt {
         psbt.outputs[0] = output;
         psbt
     }
+
+    fn ln_taproot_htlc(
+        amount: u64,
+        outpoint: OutPoint,
+        cltv_expiry: u32,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        let output = psbt::Output::ln_taproot_htlc_output(
+            amount,
+            revocationpubkey,
+            local_delayedpubkey,
+            to_self_delay,
+        );
+
+        let mut psbt = Psbt::with(
+            Transaction::ln_taproot_htlc(
+                amount,
+                outpoint,
+                cltv_expiry,
+                revocationpubkey,
+                local_delayedpubkey,
+                to_self_delay,
+            ),
+            PsbtVersion::V0,
+        )
+        .expect("Tx has empty sigs so PSBT creation does not fail");
+        psbt.outputs[0] = output;
+        psbt
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::{Slice32, Wrapper};
+    use internet2::addr::NodeId;
+    use internet2::presentation::sphinx::{Hop, Onion, OnionPacket};
+    use lnp2p::bolt::{
+        HopRealm, PaymentOnion, RevokeAndAck, ShortChannelId, UpdateAddHtlc,
+        UpdateFee,
+    };
+    use secp256k1::{SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::channel::bolt::ScriptGenerators as BoltScripts;
+    use crate::channel::Funding;
+
+    macro_rules! pk {
+        ($hex:expr) => {
+            PublicKey::from_str($hex).unwrap()
+        };
+    }
+
+    fn key(byte: u8) -> PublicKey {
+        let seckey = SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::from_secret_key(secp256k1::SECP256K1, &seckey)
+    }
+
+    #[test]
+    fn bolt3_htlckey_derivation() {
+        let base_point = pk!("036d6caac248af96f6afa7f904f550253a0f3ef3f5aa2fe6838a95b216691468e2");
+        let per_commitment_point = pk!("025f7117a78150fe2ef97db7cfc83bd57b2e2c0d0dd25eaf467a4a1c2a45ce1486");
+        let derived = pk!("0235f2dbfaa89b57ec7b055afe29849ef7ddfeb1cefdb9ebdc43f5494984db29e5");
+        let revocation = pk!("02916e326636d19c33f13e8c0c3a03dd157f332f3e99c317c141dd865eb01f8ff0");
+
+        let mut htlc = Htlc {
+            local_htlc_basepoint: base_point,
+            remote_revocation_basepoint: base_point,
+            local_per_commitment_point: per_commitment_point,
+            ..Htlc::default()
+        };
+        let keys = htlc.commitment_keys(false);
+        assert_eq!(keys.local_htlcpubkey, derived);
+        assert_eq!(keys.revocationpubkey, revocation);
+
+        htlc.remote_htlc_basepoint = base_point;
+        htlc.local_revocation_basepoint = base_point;
+        htlc.remote_per_commitment_point = per_commitment_point;
+        let keys = htlc.commitment_keys(true);
+        assert_eq!(keys.local_htlcpubkey, derived);
+        assert_eq!(keys.remote_htlcpubkey, derived);
+        assert_eq!(keys.revocationpubkey, revocation);
+    }
+
+    #[test]
+    fn remote_view_mirrors_peer() {
+        let mut alice = Htlc {
+            local_to_self_delay: 144,
+            remote_to_self_delay: 720,
+            local_revocation_basepoint: key(1),
+            remote_revocation_basepoint: key(2),
+            local_htlc_basepoint: key(3),
+            remote_htlc_basepoint: key(4),
+            local_delayed_basepoint: key(5),
+            remote_delayed_basepoint: key(6),
+            local_per_commitment_point: key(7),
+            remote_per_commitment_point: key(8),
+            ..Htlc::default()
+        };
+        let mut bob = Htlc {
+            local_to_self_delay: 720,
+            remote_to_self_delay: 144,
+            local_revocation_basepoint: key(2),
+            remote_revocation_basepoint: key(1),
+            local_htlc_basepoint: key(4),
+            remote_htlc_basepoint: key(3),
+            local_delayed_basepoint: key(6),
+            remote_delayed_basepoint: key(5),
+            local_per_commitment_point: key(8),
+            remote_per_commitment_point: key(7),
+            ..Htlc::default()
+        };
+
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        let id = alice.offer_htlc(2_000_000, hashlock, 500);
+        bob.received_htlcs.insert(id, HtlcSecret {
+            amount: 2_000_000,
+            hashlock,
+            id,
+            cltv_expiry: 500,
+        });
+
+        let funding = Funding::new();
+        for as_remote_node in [false, true] {
+            let mut alice_graph = TxGraph::from_funding(&funding);
+            let mut bob_graph = TxGraph::from_funding(&funding);
+            alice.build_graph(&mut alice_graph, as_remote_node).unwrap();
+            bob.build_graph(&mut bob_graph, !as_remote_node).unwrap();
+            assert_eq!(alice_graph.cmt_outs.len(), 1);
+            assert_eq!(alice_graph.cmt_outs, bob_graph.cmt_outs);
+            assert_eq!(alice_graph.render(), bob_graph.render());
+        }
+
+        // Offered HTLC in Alice's own commitment must differ from the one she
+        // signs for Bob
+        let mut local_graph = TxGraph::from_funding(&funding);
+        let mut remote_graph = TxGraph::from_funding(&funding);
+        alice.build_graph(&mut local_graph, false).unwrap();
+        alice.build_graph(&mut remote_graph, true).unwrap();
+        assert_ne!(local_graph.cmt_outs, remote_graph.cmt_outs);
+        assert!(local_graph.tx(TxType::HtlcTimeout, id).is_some());
+        assert!(remote_graph.tx(TxType::HtlcSuccess, id).is_some());
+    }
+
+    #[test]
+    fn remote_point_rotation() {
+        let mut alice = Htlc {
+            local_revocation_basepoint: key(1),
+            remote_revocation_basepoint: key(2),
+            local_htlc_basepoint: key(3),
+            remote_htlc_basepoint: key(4),
+            local_delayed_basepoint: key(5),
+            remote_delayed_basepoint: key(6),
+            local_per_commitment_point: key(7),
+            remote_per_commitment_point: key(8),
+            ..Htlc::default()
+        };
+        let mut bob = Htlc {
+            local_revocation_basepoint: key(2),
+            remote_revocation_basepoint: key(1),
+            local_htlc_basepoint: key(4),
+            remote_htlc_basepoint: key(3),
+            local_delayed_basepoint: key(6),
+            remote_delayed_basepoint: key(5),
+            local_per_commitment_point: key(8),
+            remote_per_commitment_point: key(7),
+            ..Htlc::default()
+        };
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        let id = alice.offer_htlc(2_000_000, hashlock, 500);
+        bob.received_htlcs.insert(id, HtlcSecret {
+            amount: 2_000_000,
+            hashlock,
+            id,
+            cltv_expiry: 500,
+        });
+
+        let funding = Funding::new();
+        let mut initial = TxGraph::from_funding(&funding);
+        alice.build_graph(&mut initial, true).unwrap();
+
+        // Each revocation by Bob moves his commitment to the next point
+        for round in 0..2u8 {
+            let next_per_commitment_point = key(9 + round);
+            alice
+                .update_from_peer(&Messages::RevokeAndAck(RevokeAndAck {
+                    channel_id: ChannelId::default(),
+                    per_commitment_secret: SecretKey::from_slice(&[0x11; 32])
+                        .unwrap(),
+                    next_per_commitment_point,
+                    next_local_nonce: None,
+                    unknown_tlvs: none!(),
+                }))
+                .unwrap();
+            bob.local_per_commitment_point = next_per_commitment_point;
+            assert_eq!(alice.commitment_keys(true), bob.commitment_keys(false));
+
+            let mut alice_graph = TxGraph::from_funding(&funding);
+            let mut bob_graph = TxGraph::from_funding(&funding);
+            alice.build_graph(&mut alice_graph, true).unwrap();
+            bob.build_graph(&mut bob_graph, false).unwrap();
+            assert_eq!(alice_graph.cmt_outs, bob_graph.cmt_outs);
+            assert_ne!(alice_graph.cmt_outs, initial.cmt_outs);
+        }
+    }
+
+    #[test]
+    fn balance_outputs_by_script() {
+        let mut htlc = Htlc {
+            channel_type: ChannelType::AnchorsZeroFeeHtlcTxStaticRemotekey,
+            local_payment_basepoint: key(1),
+            remote_payment_basepoint: key(2),
+            local_per_commitment_point: key(3),
+            remote_per_commitment_point: key(4),
+            ..Htlc::default()
+        };
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        htlc.received_htlcs.insert(0, HtlcSecret {
+            amount: 2_000_000,
+            hashlock,
+            id: 0,
+            cltv_expiry: 500,
+        });
+
+        // Commitment without `to_local` output, where `to_remote` is preceded
+        // by an output not belonging to the channel balances
+        let funding = Funding::new();
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        let foreign: psbt::Output = BoltScripts::ln_to_remote_v1(330, key(5));
+        let to_remote: psbt::Output =
+            BoltScripts::ln_to_remote_v2(10_000, htlc.remotepubkey(false));
+        tx_graph.cmt_outs = vec![foreign.clone(), to_remote];
+        htlc.build_graph(&mut tx_graph, false).unwrap();
+
+        assert_eq!(tx_graph.cmt_outs[0], foreign);
+        assert_eq!(tx_graph.cmt_outs[1].amount, 8_000);
+        assert_eq!(tx_graph.cmt_outs.len(), 3);
+    }
+
+    #[test]
+    fn trimmed_htlcs() {
+        let mut htlc = Htlc {
+            feerate_per_kw: 1000,
+            local_dust_limit_satoshis: 354,
+            local_payment_basepoint: key(1),
+            remote_payment_basepoint: key(2),
+            ..Htlc::default()
+        };
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        for (id, amount) in [(0, 1_016_000), (1, 1_017_000)] {
+            htlc.offered_htlcs.insert(id, HtlcSecret {
+                amount,
+                hashlock,
+                id,
+                cltv_expiry: 500,
+            });
+        }
+        htlc.received_htlcs.insert(0, HtlcSecret {
+            amount: 1_056_000,
+            hashlock,
+            id: 0,
+            cltv_expiry: 500,
+        });
+
+        let keys = htlc.commitment_keys(false);
+        let funding = Funding::new();
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        tx_graph.cmt_outs = vec![
+            BoltScripts::ln_to_local(
+                2_300,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            ),
+            BoltScripts::ln_to_remote_v1(10_000, htlc.remotepubkey(false)),
+        ];
+        htlc.build_graph(&mut tx_graph, false).unwrap();
+
+        // Only the second offered HTLC is above the dust threshold; the
+        // `to_local` output falls below the dust limit after paying for
+        // the offered HTLCs and is trimmed as well
+        assert_eq!(tx_graph.cmt_outs.len(), 2);
+        assert_eq!(tx_graph.cmt_outs[0].amount, 8_944);
+        assert_eq!(tx_graph.cmt_outs[1].amount, 1_017);
+        assert!(tx_graph.tx(TxType::HtlcTimeout, 0u64).is_none());
+        assert!(tx_graph.tx(TxType::HtlcTimeout, 1u64).is_some());
+        assert!(tx_graph.tx(TxType::HtlcSuccess, 0u64).is_none());
+    }
+
+    #[test]
+    fn taproot_htlc_txs() {
+        let mut htlc = Htlc {
+            channel_type: ChannelType::SimpleTaproot,
+            local_to_self_delay: 144,
+            local_payment_basepoint: key(1),
+            remote_payment_basepoint: key(2),
+            ..Htlc::default()
+        };
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        htlc.offered_htlcs.insert(0, HtlcSecret {
+            amount: 2_000_000,
+            hashlock,
+            id: 0,
+            cltv_expiry: 500,
+        });
+        htlc.received_htlcs.insert(0, HtlcSecret {
+            amount: 3_000_000,
+            hashlock,
+            id: 0,
+            cltv_expiry: 500,
+        });
+
+        let keys = htlc.commitment_keys(false);
+        let funding = Funding::new();
+        let mut tx_graph = TxGraph::from_funding(&funding);
+        htlc.build_graph(&mut tx_graph, false).unwrap();
+
+        for (tx_type, amount, cltv_expiry) in [
+            (TxType::HtlcTimeout, 2_000, 500),
+            (TxType::HtlcSuccess, 3_000, 0),
+        ] {
+            let psbt = tx_graph.tx(tx_type, 0u64).unwrap();
+            let output: psbt::Output =
+                TaprootScriptGenerators::ln_taproot_htlc_output(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_delayedpubkey,
+                    keys.to_self_delay,
+                );
+            assert_eq!(psbt.outputs, vec![output]);
+            assert!(psbt.outputs[0].script.as_inner().is_v1_p2tr());
+            let tx = psbt.clone().into_unsigned_tx();
+            assert_eq!(tx.lock_time.0, cltv_expiry);
+            assert_eq!(tx.input[0].sequence, bitcoin::Sequence(1));
+        }
+    }
+
+    fn update_add_htlc(htlc_id: u64, amount_msat: u64) -> UpdateAddHtlc {
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        let route = vec![Hop::with(NodeId::from(key(9)), PaymentOnion {
+            realm: HopRealm::Legacy(ShortChannelId::default()),
+            amt_to_forward: amount_msat,
+            outgoing_cltv_value: 500,
+        })];
+        let onion_packet =
+            OnionPacket::with(SECP256K1, &route, hashlock.as_ref()).unwrap();
+        UpdateAddHtlc {
+            channel_id: ChannelId::default(),
+            htlc_id,
+            amount_msat,
+            payment_hash: hashlock,
+            cltv_expiry: 500,
+            onion_routing_packet: Onion::Onion(onion_packet),
+            asset: None,
+            unknown_tlvs: none!(),
+        }
+    }
+
+    fn update_fee(feerate_per_kw: u32) -> Messages {
+        Messages::UpdateFee(UpdateFee {
+            channel_id: ChannelId::default(),
+            feerate_per_kw,
+        })
+    }
+
+    #[test]
+    fn dust_exposure() {
+        let mut htlc = Htlc {
+            max_accepted_htlcs: 10,
+            feerate_per_kw: 1000,
+            local_dust_limit_satoshis: 354,
+            remote_dust_limit_satoshis: 546,
+            max_dust_htlc_exposure: Some(DustExposure::FixedLimitMsat(
+                2_000_000,
+            )),
+            ..Htlc::default()
+        };
+
+        // Dust thresholds include fees of the second-stage HTLC transactions
+        assert!(htlc.is_dust(1_016_000, true, 354, 1000));
+        assert!(!htlc.is_dust(1_017_000, true, 354, 1000));
+        assert!(htlc.is_dust(1_056_000, false, 354, 1000));
+        assert!(!htlc.is_dust(1_057_000, false, 354, 1000));
+        htlc.channel_type = ChannelType::AnchorsZeroFeeHtlcTxStaticRemotekey;
+        assert!(!htlc.is_dust(354_000, false, 354, 1000));
+        htlc.channel_type = ChannelType::Basic;
+
+        // HTLC is dust only in the remote commitment, having larger dust limit
+        let message = Messages::UpdateAddHtlc(update_add_htlc(0, 1_200_000));
+        htlc.update_from_peer(&message).unwrap();
+        assert_eq!(htlc.dust_exposure_msat(false, 1000), 0);
+        assert_eq!(htlc.dust_exposure_msat(true, 1000), 1_200_000);
+
+        let message = Messages::UpdateAddHtlc(update_add_htlc(1, 1_200_000));
+        assert_eq!(
+            htlc.update_from_peer(&message).unwrap_err(),
+            CommitmentPolicyError::DustExposureExceeded {
+                owner: Offerer::Remote,
+                exposure_msat: 2_400_000,
+                limit_msat: 2_000_000,
+            }
+            .into()
+        );
+        assert_eq!(htlc.received_htlcs.len(), 1);
+        assert_eq!(htlc.next_received_htlc_id, 1);
+
+        // Our own HTLCs are checked as well
+        let mut message = Messages::UpdateAddHtlc(update_add_htlc(0, 900_000));
+        assert!(htlc
+            .state_change(&UpdateReq::PayBolt(vec![]), &mut message)
+            .is_err());
+        assert!(htlc.offered_htlcs.is_empty());
+        assert_eq!(htlc.next_offered_htlc_id, 0);
+
+        // Decreasing the fee rate makes the HTLC non-dust
+        htlc.update_from_peer(&update_fee(1)).unwrap();
+        assert_eq!(htlc.feerate_per_kw, 1);
+        assert_eq!(htlc.dust_exposure_msat(true, 1), 0);
+
+        // The limit may depend on the fee rate
+        htlc.max_dust_htlc_exposure =
+            Some(DustExposure::FeeRateMultiplier(1000));
+        htlc.update_from_peer(&update_fee(2000)).unwrap();
+        assert_eq!(htlc.dust_exposure_msat(false, 2000), 1_200_000);
+        let mut message = update_fee(1000);
+        assert_eq!(
+            htlc.state_change(&UpdateReq::UpdateFee, &mut message)
+                .unwrap_err(),
+            CommitmentPolicyError::DustExposureExceeded {
+                owner: Offerer::Remote,
+                exposure_msat: 1_200_000,
+                limit_msat: 1_000_000,
+            }
+            .into()
+        );
+        assert_eq!(htlc.feerate_per_kw, 2000);
+    }
 }
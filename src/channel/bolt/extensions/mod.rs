@@ -13,9 +13,27 @@
 
 // LN feature-flag based extensions
 mod anchor_outputs;
+mod shutdown_script;
+
+// Policies
+mod commitment_policy;
 
 // Payment protocols
 mod htlc;
+mod ptlc;
+
+// Contracts
+mod dlc;
+
+// Assets
+mod rgb;
 
 pub use anchor_outputs::AnchorOutputs;
-pub use htlc::{Htlc, HtlcKnown, HtlcSecret};
+pub use commitment_policy::{CommitmentPolicy, CommitmentPolicyError, Offerer};
+pub use dlc::{attestation_scalar, Dlc, DlcContract, DlcError};
+pub use htlc::{CommitmentKeys, Htlc, HtlcKnown, HtlcSecret};
+pub use ptlc::{Ptlc, PtlcKnown, PtlcLeaves, PtlcScriptGenerators, PtlcSecret};
+pub use rgb::{asset_tweak, Rgb, RgbError};
+pub use shutdown_script::{
+    is_valid_shutdown_script, ShutdownScript, ShutdownScriptError,
+};
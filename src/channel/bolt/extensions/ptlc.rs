@@ -0,0 +1,908 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Experimental point-time-locked contracts (PTLCs).
+//!
+//! PTLC outputs are P2TR outputs with the revocation key as an internal key
+//! and script paths requiring signatures of both parties. Instead of a hash
+//! preimage, the payment is claimed with a signature completed from an
+//! adaptor signature of the counterparty, which reveals the payment scalar to
+//! it.
+
+use std::collections::BTreeMap;
+
+use bitcoin::blockdata::opcodes::all::*;
+use bitcoin::blockdata::script;
+use bitcoin::secp256k1::{PublicKey, SecretKey, SECP256K1};
+use bitcoin::{OutPoint, Script};
+use lnp2p::bolt::{
+    AcceptChannel, AcceptChannel2, AdaptorSignature, ChannelId, Messages,
+    OpenChannel, OpenChannel2,
+};
+use p2p::bolt::ChannelType;
+use wallet::psbt::{self, Psbt};
+
+use super::htlc::{CommitmentKeys, TxGenerators};
+use crate::channel::bolt::taproot::{taproot_output, two_leaves};
+use crate::channel::bolt::util::UpdateReq;
+use crate::channel::bolt::{
+    derive_pubkey, derive_revocationpubkey, BoltExt, ChannelState, Error,
+    TaprootLeaves as _, TxType,
+};
+use crate::channel::tx_graph::TxGraph;
+use crate::{ChannelExtension, Extension};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct PtlcKnown {
+    pub amount: u64,
+    pub payment_scalar: SecretKey,
+    pub id: u64,
+    pub cltv_expiry: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct PtlcSecret {
+    pub amount: u64,
+    pub payment_point: PublicKey,
+    pub id: u64,
+    pub cltv_expiry: u32,
+}
+
+#[derive(Getters, Clone, PartialEq, Eq, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct Ptlc {
+    /// Channel type negotiated during channel establishment, which affects
+    /// the form of the balance outputs
+    channel_type: ChannelType,
+
+    // Sets of PTLC information
+    offered_ptlcs: BTreeMap<u64, PtlcSecret>,
+    received_ptlcs: BTreeMap<u64, PtlcSecret>,
+    resolved_ptlcs: BTreeMap<u64, PtlcKnown>,
+
+    /// Adaptor signatures received from the remote node for the latest local
+    /// commitment transaction
+    remote_adaptor_sigs: Vec<AdaptorSignature>,
+
+    // Commitment round specific information
+    local_to_self_delay: u16,
+    remote_to_self_delay: u16,
+    local_revocation_basepoint: PublicKey,
+    remote_revocation_basepoint: PublicKey,
+    local_payment_basepoint: PublicKey,
+    remote_payment_basepoint: PublicKey,
+    local_htlc_basepoint: PublicKey,
+    remote_htlc_basepoint: PublicKey,
+    local_delayed_basepoint: PublicKey,
+    remote_delayed_basepoint: PublicKey,
+    local_per_commitment_point: PublicKey,
+    remote_per_commitment_point: PublicKey,
+
+    // Channel specific information
+    channel_id: ChannelId,
+
+    /// PTLCs share limits with HTLCs, negotiated during channel establishment
+    ptlc_minimum_msat: u64,
+    max_accepted_ptlcs: u16,
+
+    next_received_ptlc_id: u64,
+    next_offered_ptlc_id: u64,
+}
+
+impl Default for Ptlc {
+    fn default() -> Self {
+        Ptlc {
+            channel_type: ChannelType::Basic,
+            offered_ptlcs: empty!(),
+            received_ptlcs: empty!(),
+            resolved_ptlcs: empty!(),
+            remote_adaptor_sigs: empty!(),
+            local_to_self_delay: 0,
+            remote_to_self_delay: 0,
+            local_revocation_basepoint: dumb_pubkey!(),
+            remote_revocation_basepoint: dumb_pubkey!(),
+            local_payment_basepoint: dumb_pubkey!(),
+            remote_payment_basepoint: dumb_pubkey!(),
+            local_htlc_basepoint: dumb_pubkey!(),
+            remote_htlc_basepoint: dumb_pubkey!(),
+            local_delayed_basepoint: dumb_pubkey!(),
+            remote_delayed_basepoint: dumb_pubkey!(),
+            local_per_commitment_point: dumb_pubkey!(),
+            remote_per_commitment_point: dumb_pubkey!(),
+            channel_id: Default::default(),
+            ptlc_minimum_msat: 0,
+            max_accepted_ptlcs: 0,
+            next_received_ptlc_id: 0,
+            next_offered_ptlc_id: 0,
+        }
+    }
+}
+
+impl Ptlc {
+    pub fn offer_ptlc(
+        &mut self,
+        amount_msat: u64,
+        payment_point: PublicKey,
+        cltv_expiry: u32,
+    ) -> u64 {
+        let ptlc_id = self.next_offered_ptlc_id;
+        self.next_offered_ptlc_id += 1;
+        self.offered_ptlcs.insert(ptlc_id, PtlcSecret {
+            amount: amount_msat,
+            payment_point,
+            id: ptlc_id,
+            cltv_expiry,
+        });
+        ptlc_id
+    }
+
+    /// Settles PTLC received from the remote node, checking that the provided
+    /// scalar is the discrete logarithm of the PTLC payment point.
+    pub fn fulfill_ptlc(
+        &mut self,
+        ptlc_id: u64,
+        payment_scalar: SecretKey,
+    ) -> Result<PtlcKnown, Error> {
+        let received = self.received_ptlcs.get(&ptlc_id).ok_or_else(|| {
+            Error::Ptlc(format!("unknown received PTLC {}", ptlc_id))
+        })?;
+        let resolved = resolve(received, payment_scalar)?;
+        self.received_ptlcs.remove(&ptlc_id);
+        self.resolved_ptlcs.insert(ptlc_id, resolved);
+        Ok(resolved)
+    }
+
+    /// Fails PTLC received from the remote node
+    pub fn fail_ptlc(&mut self, ptlc_id: u64) -> Result<PtlcSecret, Error> {
+        self.received_ptlcs.remove(&ptlc_id).ok_or_else(|| {
+            Error::Ptlc(format!("unknown received PTLC {}", ptlc_id))
+        })
+    }
+
+    /// Returns public key used in the `to_remote` output of the local (if
+    /// `as_remote_node` is `false`) or remote (if `as_remote_node` is `true`)
+    /// commitment transaction.
+    pub fn remotepubkey(&self, as_remote_node: bool) -> PublicKey {
+        if as_remote_node {
+            CommitmentKeys::remotepubkey(
+                self.channel_type,
+                self.local_payment_basepoint,
+                self.remote_per_commitment_point,
+            )
+        } else {
+            CommitmentKeys::remotepubkey(
+                self.channel_type,
+                self.remote_payment_basepoint,
+                self.local_per_commitment_point,
+            )
+        }
+    }
+
+    /// Derives keys for the local (if `as_remote_node` is `false`) or remote
+    /// (if `as_remote_node` is `true`) commitment transaction.
+    pub fn commitment_keys(&self, as_remote_node: bool) -> CommitmentKeys {
+        let (
+            per_commitment_point,
+            revocation_basepoint,
+            local_htlc_basepoint,
+            remote_htlc_basepoint,
+            delayed_basepoint,
+            to_self_delay,
+        ) = if as_remote_node {
+            (
+                self.remote_per_commitment_point,
+                self.local_revocation_basepoint,
+                self.remote_htlc_basepoint,
+                self.local_htlc_basepoint,
+                self.remote_delayed_basepoint,
+                self.remote_to_self_delay,
+            )
+        } else {
+            (
+                self.local_per_commitment_point,
+                self.remote_revocation_basepoint,
+                self.local_htlc_basepoint,
+                self.remote_htlc_basepoint,
+                self.local_delayed_basepoint,
+                self.local_to_self_delay,
+            )
+        };
+        CommitmentKeys {
+            revocationpubkey: derive_revocationpubkey(
+                revocation_basepoint,
+                per_commitment_point,
+            ),
+            local_htlcpubkey: derive_pubkey(
+                local_htlc_basepoint,
+                per_commitment_point,
+            ),
+            remote_htlcpubkey: derive_pubkey(
+                remote_htlc_basepoint,
+                per_commitment_point,
+            ),
+            local_delayedpubkey: derive_pubkey(
+                delayed_basepoint,
+                per_commitment_point,
+            ),
+            to_self_delay,
+        }
+    }
+}
+
+fn resolve(
+    ptlc: &PtlcSecret,
+    payment_scalar: SecretKey,
+) -> Result<PtlcKnown, Error> {
+    if PublicKey::from_secret_key(SECP256K1, &payment_scalar)
+        != ptlc.payment_point
+    {
+        return Err(Error::Ptlc(format!(
+            "payment scalar does not match payment point of PTLC {}",
+            ptlc.id
+        )));
+    }
+    Ok(PtlcKnown {
+        amount: ptlc.amount,
+        payment_scalar,
+        id: ptlc.id,
+        cltv_expiry: ptlc.cltv_expiry,
+    })
+}
+
+impl Extension<BoltExt> for Ptlc {
+    fn identity(&self) -> BoltExt {
+        BoltExt::Ptlc
+    }
+
+    fn update_from_local(&mut self, _message: &()) -> Result<(), Error> {
+        // Nothing to do here so far
+        Ok(())
+    }
+
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        message: &mut Messages,
+    ) -> Result<(), Error> {
+        match (request, message) {
+            (
+                UpdateReq::PayPtlc(_),
+                Messages::UpdateAddPtlc(update_add_ptlc),
+            ) => {
+                let ptlc_id = self.offer_ptlc(
+                    update_add_ptlc.amount_msat,
+                    update_add_ptlc.payment_point,
+                    update_add_ptlc.cltv_expiry,
+                );
+                update_add_ptlc.ptlc_id = ptlc_id;
+            }
+            (
+                UpdateReq::FulfillPtlc,
+                Messages::UpdateFulfillPtlc(update_fulfill_ptlc),
+            ) => {
+                self.fulfill_ptlc(
+                    update_fulfill_ptlc.ptlc_id,
+                    update_fulfill_ptlc.payment_scalar,
+                )?;
+            }
+            (
+                UpdateReq::FailPtlc,
+                Messages::UpdateFailPtlc(update_fail_ptlc),
+            ) => {
+                self.fail_ptlc(update_fail_ptlc.ptlc_id)?;
+            }
+            (UpdateReq::PayPtlc(_), _)
+            | (UpdateReq::FulfillPtlc, _)
+            | (UpdateReq::FailPtlc, _) => unreachable!(
+                "state change request must match provided LN P2P message"
+            ),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
+        match message {
+            Messages::OpenChannel(OpenChannel {
+                channel_type,
+                payment_point,
+                htlc_minimum_msat,
+                max_accepted_htlcs,
+                htlc_basepoint,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            })
+            | Messages::AcceptChannel(AcceptChannel {
+                channel_type,
+                payment_point,
+                htlc_minimum_msat,
+                max_accepted_htlcs,
+                htlc_basepoint,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            })
+            | Messages::OpenChannel2(OpenChannel2 {
+                channel_type,
+                payment_basepoint: payment_point,
+                htlc_minimum_msat,
+                max_accepted_htlcs,
+                htlc_basepoint,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            })
+            | Messages::AcceptChannel2(AcceptChannel2 {
+                channel_type,
+                payment_basepoint: payment_point,
+                htlc_minimum_msat,
+                max_accepted_htlcs,
+                htlc_basepoint,
+                revocation_basepoint,
+                delayed_payment_basepoint,
+                first_per_commitment_point,
+                to_self_delay,
+                ..
+            }) => {
+                self.channel_type = channel_type.unwrap_or_default();
+                self.ptlc_minimum_msat = *htlc_minimum_msat;
+                self.max_accepted_ptlcs = *max_accepted_htlcs;
+                self.remote_payment_basepoint = *payment_point;
+                self.remote_htlc_basepoint = *htlc_basepoint;
+                self.remote_revocation_basepoint = *revocation_basepoint;
+                self.remote_delayed_basepoint = *delayed_payment_basepoint;
+                self.remote_per_commitment_point = *first_per_commitment_point;
+                self.local_to_self_delay = *to_self_delay;
+            }
+            Messages::UpdateAddPtlc(message) => {
+                if message.channel_id != self.channel_id {
+                    return Err(Error::Ptlc(
+                        "Mismatched channel_id, bad remote node".to_string(),
+                    ));
+                }
+                if message.amount_msat == 0
+                    || message.amount_msat < self.ptlc_minimum_msat
+                {
+                    return Err(Error::Ptlc(
+                        "amount_msat is below the channel minimum".to_string(),
+                    ));
+                } else if self.received_ptlcs.len()
+                    >= self.max_accepted_ptlcs as usize
+                {
+                    return Err(Error::Ptlc(
+                        "max no. of PTLC limit exceeded".to_string(),
+                    ));
+                } else if message.cltv_expiry > 500000000 {
+                    return Err(Error::Ptlc(
+                        "cltv_expiry limit exceeded".to_string(),
+                    ));
+                } else if message.ptlc_id < self.next_received_ptlc_id {
+                    return Err(Error::Ptlc(
+                        "PTLC id violation occurred".to_string(),
+                    ));
+                }
+                self.received_ptlcs.insert(message.ptlc_id, PtlcSecret {
+                    amount: message.amount_msat,
+                    payment_point: message.payment_point,
+                    id: message.ptlc_id,
+                    cltv_expiry: message.cltv_expiry,
+                });
+                self.next_received_ptlc_id = message.ptlc_id + 1;
+            }
+            Messages::UpdateFulfillPtlc(message) => {
+                if message.channel_id != self.channel_id {
+                    return Err(Error::Ptlc(
+                        "Mismatched channel_id, bad remote node".to_string(),
+                    ));
+                }
+                let offered =
+                    self.offered_ptlcs.get(&message.ptlc_id).ok_or_else(
+                        || Error::Ptlc("PTLC id didn't match".to_string()),
+                    )?;
+                let resolved = resolve(offered, message.payment_scalar)?;
+                self.offered_ptlcs.remove(&message.ptlc_id);
+                self.resolved_ptlcs.insert(message.ptlc_id, resolved);
+            }
+            Messages::UpdateFailPtlc(message) => {
+                if message.channel_id != self.channel_id {
+                    return Err(Error::Ptlc(
+                        "Mismatched channel_id, bad remote node".to_string(),
+                    ));
+                }
+                self.offered_ptlcs.remove(&message.ptlc_id);
+
+                // TODO the failure reason should be handled here
+            }
+            Messages::PtlcSignatures(message) => {
+                let count =
+                    self.offered_ptlcs.len() + self.received_ptlcs.len();
+                if message.adaptor_signatures.len() != count {
+                    return Err(Error::Ptlc(format!(
+                        "expected {} PTLC adaptor signatures, got {}",
+                        count,
+                        message.adaptor_signatures.len()
+                    )));
+                }
+                self.remote_adaptor_sigs = message.adaptor_signatures.clone();
+            }
+            Messages::FundingLocked(funding_locked) => {
+                self.remote_per_commitment_point =
+                    funding_locked.next_per_commitment_point;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, state: &ChannelState) {
+        self.channel_type = state.common_params.channel_type;
+
+        self.offered_ptlcs = state.offered_ptlcs.clone();
+        self.received_ptlcs = state.received_ptlcs.clone();
+        self.resolved_ptlcs = state.resolved_ptlcs.clone();
+
+        self.local_to_self_delay = state.remote_params.to_self_delay;
+        self.remote_to_self_delay = state.local_params.to_self_delay;
+        self.local_revocation_basepoint =
+            state.local_keys.revocation_basepoint.key;
+        self.remote_revocation_basepoint =
+            state.remote_keys.revocation_basepoint;
+        self.local_payment_basepoint = state.local_keys.payment_basepoint.key;
+        self.remote_payment_basepoint = state.remote_keys.payment_basepoint;
+        self.local_htlc_basepoint = state.local_keys.htlc_basepoint.key;
+        self.remote_htlc_basepoint = state.remote_keys.htlc_basepoint;
+        self.local_delayed_basepoint =
+            state.local_keys.delayed_payment_basepoint.key;
+        self.remote_delayed_basepoint =
+            state.remote_keys.delayed_payment_basepoint;
+        self.local_per_commitment_point = state.local_per_commitment_point;
+        self.remote_per_commitment_point = state.remote_per_commitment_point;
+
+        self.channel_id = state.active_channel_id.as_slice32().into();
+
+        self.ptlc_minimum_msat = state.remote_params.htlc_minimum_msat;
+        self.max_accepted_ptlcs = state.remote_params.max_accepted_htlcs;
+
+        self.next_received_ptlc_id = state.last_received_ptlc_id;
+        self.next_offered_ptlc_id = state.last_offered_ptlc_id;
+    }
+
+    fn store_state(&self, state: &mut ChannelState) {
+        state.offered_ptlcs = self.offered_ptlcs.clone();
+        state.received_ptlcs = self.received_ptlcs.clone();
+        state.resolved_ptlcs = self.resolved_ptlcs.clone();
+        state.last_received_ptlc_id = self.next_received_ptlc_id;
+        state.last_offered_ptlc_id = self.next_offered_ptlc_id;
+    }
+}
+
+impl ChannelExtension<BoltExt> for Ptlc {
+    #[inline]
+    fn new() -> Box<dyn ChannelExtension<BoltExt>>
+    where
+        Self: Sized,
+    {
+        Box::new(Ptlc::default())
+    }
+
+    fn build_graph(
+        &self,
+        tx_graph: &mut TxGraph,
+        as_remote_node: bool,
+    ) -> Result<(), Error> {
+        let keys = self.commitment_keys(as_remote_node);
+
+        // PTLCs offered by us are received by the remote node, so for the
+        // remote commitment transaction we have to swap the sets
+        let (offered_ptlcs, received_ptlcs) = if as_remote_node {
+            (&self.received_ptlcs, &self.offered_ptlcs)
+        } else {
+            (&self.offered_ptlcs, &self.received_ptlcs)
+        };
+
+        // Locate balance outputs of the commitment owner and its counterparty
+        let (to_local_index, to_remote_index) = keys.balance_outputs(
+            tx_graph,
+            self.channel_type,
+            self.remotepubkey(as_remote_node),
+        );
+
+        let mut offered_sat = 0u64;
+        for (id, offered) in offered_ptlcs {
+            let amount = offered.amount / 1000;
+            tx_graph
+                .cmt_outs
+                .push(PtlcScriptGenerators::ln_offered_ptlc(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_htlcpubkey,
+                    keys.remote_htlcpubkey,
+                ));
+
+            let ptlc_tx = Psbt::ln_htlc(
+                amount,
+                // TODO: do a two-staged graph generation process
+                OutPoint::default(),
+                offered.cltv_expiry,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            );
+            tx_graph.insert_tx(TxType::PtlcTimeout, *id, ptlc_tx);
+
+            offered_sat += amount;
+        }
+
+        let mut received_sat = 0u64;
+        for (id, received) in received_ptlcs {
+            let amount = received.amount / 1000;
+            tx_graph
+                .cmt_outs
+                .push(PtlcScriptGenerators::ln_received_ptlc(
+                    amount,
+                    keys.revocationpubkey,
+                    keys.local_htlcpubkey,
+                    keys.remote_htlcpubkey,
+                    received.cltv_expiry,
+                ));
+
+            let ptlc_tx = Psbt::ln_htlc(
+                amount,
+                // TODO: do a two-staged graph generation process
+                OutPoint::default(),
+                // PTLC success transactions are not timelocked
+                0,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            );
+            tx_graph.insert_tx(TxType::PtlcSuccess, *id, ptlc_tx);
+
+            received_sat += amount;
+        }
+
+        // Subtract PTLC amounts from the balances of the offering parties
+        if let Some(index) = to_local_index {
+            let output = &mut tx_graph.cmt_outs[index];
+            output.amount = output.amount.saturating_sub(offered_sat);
+        }
+        if let Some(index) = to_remote_index {
+            let output = &mut tx_graph.cmt_outs[index];
+            output.amount = output.amount.saturating_sub(received_sat);
+        }
+
+        Ok(())
+    }
+}
+
+/// Tapscript leaves used by PTLC outputs
+pub trait PtlcLeaves {
+    /// Leaf spent by a pre-signed second-level PTLC transaction, where the
+    /// signature of the party claiming the funds is completed from an adaptor
+    /// signature.
+    fn ln_ptlc_presigned_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self;
+
+    /// Leaf allowing the remote node to claim offered PTLC directly, using
+    /// adaptor signature of the local node completed with the payment scalar.
+    fn ln_ptlc_claim_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self;
+}
+
+impl PtlcLeaves for Script {
+    fn ln_ptlc_presigned_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&local_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_x_only_key(&remote_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    fn ln_ptlc_claim_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&local_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_x_only_key(&remote_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .into_script()
+    }
+}
+
+/// Generators of point-locked commitment outputs
+pub trait PtlcScriptGenerators {
+    fn ln_offered_ptlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self;
+
+    fn ln_received_ptlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+    ) -> Self;
+}
+
+impl PtlcScriptGenerators for psbt::Output {
+    fn ln_offered_ptlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self {
+        let builder = two_leaves(
+            Script::ln_ptlc_presigned_leaf(local_htlcpubkey, remote_htlcpubkey),
+            Script::ln_ptlc_claim_leaf(local_htlcpubkey, remote_htlcpubkey),
+        );
+        taproot_output(
+            amount,
+            revocationpubkey.x_only_public_key().0,
+            Some(builder),
+        )
+    }
+
+    fn ln_received_ptlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+    ) -> Self {
+        let builder = two_leaves(
+            Script::ln_ptlc_presigned_leaf(local_htlcpubkey, remote_htlcpubkey),
+            Script::ln_received_timeout_leaf(remote_htlcpubkey, cltv_expiry),
+        );
+        taproot_output(
+            amount,
+            revocationpubkey.x_only_public_key().0,
+            Some(builder),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::{sha256, Hash};
+    use internet2::addr::NodeId;
+    use internet2::presentation::sphinx::{Hop, Onion, OnionPacket};
+    use lnp2p::bolt::{
+        HopRealm, PaymentOnion, PtlcSignatures, ShortChannelId, UpdateAddPtlc,
+        UpdateFailPtlc, UpdateFulfillPtlc,
+    };
+    use secp256k1::schnorr;
+
+    use super::*;
+    use crate::channel::bolt::{
+        SchnorrAdaptor, ScriptGenerators as BoltScripts,
+    };
+    use crate::channel::Funding;
+
+    fn seckey(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn key(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(SECP256K1, &seckey(byte))
+    }
+
+    fn peers() -> (Ptlc, Ptlc) {
+        let alice = Ptlc {
+            local_to_self_delay: 144,
+            remote_to_self_delay: 720,
+            local_revocation_basepoint: key(1),
+            remote_revocation_basepoint: key(2),
+            local_payment_basepoint: key(10),
+            remote_payment_basepoint: key(11),
+            local_htlc_basepoint: key(3),
+            remote_htlc_basepoint: key(4),
+            local_delayed_basepoint: key(5),
+            remote_delayed_basepoint: key(6),
+            local_per_commitment_point: key(7),
+            remote_per_commitment_point: key(8),
+            max_accepted_ptlcs: 8,
+            ..Ptlc::default()
+        };
+        let bob = Ptlc {
+            local_to_self_delay: 720,
+            remote_to_self_delay: 144,
+            local_revocation_basepoint: key(2),
+            remote_revocation_basepoint: key(1),
+            local_payment_basepoint: key(11),
+            remote_payment_basepoint: key(10),
+            local_htlc_basepoint: key(4),
+            remote_htlc_basepoint: key(3),
+            local_delayed_basepoint: key(6),
+            remote_delayed_basepoint: key(5),
+            local_per_commitment_point: key(8),
+            remote_per_commitment_point: key(7),
+            max_accepted_ptlcs: 8,
+            ..Ptlc::default()
+        };
+        (alice, bob)
+    }
+
+    fn update_add_ptlc(payment_point: PublicKey) -> Messages {
+        let route = vec![Hop::with(NodeId::from(key(9)), PaymentOnion {
+            realm: HopRealm::Legacy(ShortChannelId::default()),
+            amt_to_forward: 2_000_000,
+            outgoing_cltv_value: 500,
+        })];
+        let onion_packet =
+            OnionPacket::with(SECP256K1, &route, &payment_point.serialize())
+                .unwrap();
+        Messages::UpdateAddPtlc(UpdateAddPtlc {
+            channel_id: ChannelId::default(),
+            ptlc_id: 0,
+            amount_msat: 2_000_000,
+            payment_point,
+            cltv_expiry: 500,
+            onion_routing_packet: Onion::Onion(onion_packet),
+            unknown_tlvs: none!(),
+        })
+    }
+
+    #[test]
+    fn ptlc_offer_fulfill_fail() {
+        let (mut alice, mut bob) = peers();
+        let payment_scalar = seckey(0x55);
+        let payment_point = key(0x55);
+
+        // Alice offers PTLC to Bob
+        let mut message = update_add_ptlc(payment_point);
+        alice
+            .state_change(&UpdateReq::PayPtlc(vec![]), &mut message)
+            .unwrap();
+        bob.update_from_peer(&message).unwrap();
+        let id = match message {
+            Messages::UpdateAddPtlc(ref update_add_ptlc) => {
+                update_add_ptlc.ptlc_id
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(alice.offered_ptlcs, bob.received_ptlcs);
+
+        // Both peers build the same point-locked outputs
+        let funding = Funding::new();
+        for as_remote_node in [false, true] {
+            let mut alice_graph = TxGraph::from_funding(&funding);
+            let mut bob_graph = TxGraph::from_funding(&funding);
+            alice.build_graph(&mut alice_graph, as_remote_node).unwrap();
+            bob.build_graph(&mut bob_graph, !as_remote_node).unwrap();
+            assert_eq!(alice_graph.cmt_outs.len(), 1);
+            assert!(alice_graph.cmt_outs[0].script.is_v1_p2tr());
+            assert_eq!(alice_graph.cmt_outs, bob_graph.cmt_outs);
+            assert_eq!(alice_graph.render(), bob_graph.render());
+        }
+        let mut local_graph = TxGraph::from_funding(&funding);
+        alice.build_graph(&mut local_graph, false).unwrap();
+        assert!(local_graph.tx(TxType::PtlcTimeout, id).is_some());
+        let mut remote_graph = TxGraph::from_funding(&funding);
+        alice.build_graph(&mut remote_graph, true).unwrap();
+        assert!(remote_graph.tx(TxType::PtlcSuccess, id).is_some());
+
+        // Received PTLC is funded from `to_remote` balance output even if it
+        // is not the first output of the commitment
+        let mut bob_graph = TxGraph::from_funding(&funding);
+        let foreign: psbt::Output = BoltScripts::ln_to_remote_v1(330, key(12));
+        let to_remote: psbt::Output =
+            BoltScripts::ln_to_remote_v1(10_000, bob.remotepubkey(false));
+        bob_graph.cmt_outs = vec![foreign.clone(), to_remote];
+        bob.build_graph(&mut bob_graph, false).unwrap();
+        assert_eq!(bob_graph.cmt_outs[0], foreign);
+        assert_eq!(bob_graph.cmt_outs[1].amount, 8_000);
+
+        // Alice provides Bob with the adaptor signature for his PTLC success
+        // transaction, which he can complete only knowing the payment scalar
+        let msg = sha256::Hash::hash(b"ptlc success").into_inner();
+        let adaptor_sig =
+            AdaptorSignature::sign(seckey(3), msg, payment_point, [0u8; 32])
+                .unwrap();
+        bob.update_from_peer(&Messages::PtlcSignatures(PtlcSignatures {
+            channel_id: ChannelId::default(),
+            adaptor_signatures: vec![adaptor_sig],
+        }))
+        .unwrap();
+        assert!(bob
+            .update_from_peer(&Messages::PtlcSignatures(PtlcSignatures {
+                channel_id: ChannelId::default(),
+                adaptor_signatures: vec![],
+            }))
+            .is_err());
+        let adaptor_sig = bob.remote_adaptor_sigs[0];
+        adaptor_sig
+            .verify(key(3).x_only_public_key().0, msg, payment_point)
+            .unwrap();
+        let signature: schnorr::Signature =
+            adaptor_sig.complete(payment_scalar).unwrap();
+        assert_eq!(
+            adaptor_sig.extract(&signature, payment_point).unwrap(),
+            payment_scalar
+        );
+
+        // Bob settles PTLC with the payment scalar
+        let mut fulfill = Messages::UpdateFulfillPtlc(UpdateFulfillPtlc {
+            channel_id: ChannelId::default(),
+            ptlc_id: id,
+            payment_scalar: seckey(0x56),
+        });
+        assert!(bob
+            .state_change(&UpdateReq::FulfillPtlc, &mut fulfill)
+            .is_err());
+        assert!(alice.update_from_peer(&fulfill).is_err());
+        let mut fulfill = Messages::UpdateFulfillPtlc(UpdateFulfillPtlc {
+            channel_id: ChannelId::default(),
+            ptlc_id: id,
+            payment_scalar,
+        });
+        bob.state_change(&UpdateReq::FulfillPtlc, &mut fulfill)
+            .unwrap();
+        alice.update_from_peer(&fulfill).unwrap();
+        assert!(alice.offered_ptlcs.is_empty());
+        assert!(bob.received_ptlcs.is_empty());
+        assert_eq!(alice.resolved_ptlcs, bob.resolved_ptlcs);
+        assert_eq!(alice.resolved_ptlcs[&id].payment_scalar, payment_scalar);
+
+        // Next PTLC is failed by Bob
+        let mut message = update_add_ptlc(payment_point);
+        alice
+            .state_change(&UpdateReq::PayPtlc(vec![]), &mut message)
+            .unwrap();
+        bob.update_from_peer(&message).unwrap();
+        let mut fail = Messages::UpdateFailPtlc(UpdateFailPtlc {
+            channel_id: ChannelId::default(),
+            ptlc_id: id + 1,
+            reason: vec![],
+        });
+        bob.state_change(&UpdateReq::FailPtlc, &mut fail).unwrap();
+        alice.update_from_peer(&fail).unwrap();
+        assert!(alice.offered_ptlcs.is_empty());
+        assert!(bob.received_ptlcs.is_empty());
+        assert_eq!(alice.resolved_ptlcs.len(), 1);
+    }
+}
@@ -0,0 +1,824 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! RGB asset allocations in BOLT channels.
+//!
+//! Channel peers negotiate the set of assets supported by the channel with
+//! asset lists of their `init` messages; the funder allocates assets to the
+//! channel in `open_channel` message and the assets are moved between the
+//! peers with HTLCs. The state of the asset allocations is committed to each
+//! of the commitment transactions by tweaking the delayed public key of the
+//! `to_local` output of the commitment owner.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use amplify::Wrapper;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{PublicKey, Scalar, SecretKey, SECP256K1};
+use bitcoin_scripts::PubkeyScript;
+use lnp2p::bolt::{AssetAmount, AssetList, Messages};
+use lnpbp::chain::AssetId;
+use p2p::bolt::ChannelType;
+use strict_encoding::strict_serialize;
+use wallet::psbt;
+
+use super::htlc::CommitmentKeys;
+use crate::channel::bolt::musig2::tagged_hash;
+use crate::channel::bolt::util::UpdateReq;
+use crate::channel::bolt::{
+    derive_pubkey, derive_revocationpubkey, AssetsBalance, BoltExt,
+    ChannelState, Error, ScriptGenerators, TaprootScriptGenerators,
+};
+use crate::channel::tx_graph::TxGraph;
+use crate::{ChannelExtension, Extension};
+
+/// Errors of RGB asset allocations in the channel
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum RgbError {
+    /// asset {0} is not supported by both of the channel peers
+    UnsupportedAsset(AssetId),
+
+    /// asset {0} is allocated more than once in the channel funding
+    RepeatedAllocation(AssetId),
+
+    /// insufficient balance of asset {asset_id}: {available} is available,
+    /// while {required} is required
+    InsufficientBalance {
+        asset_id: AssetId,
+        available: u64,
+        required: u64,
+    },
+}
+
+#[derive(Getters, Clone, PartialEq, Eq, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct Rgb {
+    /// Assets supported by the local node, as announced in its `init` message
+    local_asset_list: BTreeSet<AssetId>,
+
+    /// Assets supported by the remote node, which are known once its `init`
+    /// message is received
+    remote_asset_list: Option<BTreeSet<AssetId>>,
+
+    /// Assets allocated by the local node to the channel when it opens the
+    /// channel
+    funding_assets: AssetsBalance,
+
+    // Asset balances of the channel peers, not including assets in HTLCs
+    local_assets: AssetsBalance,
+    remote_assets: AssetsBalance,
+
+    // Assets transferred with pending HTLCs, indexed by HTLC ids
+    offered_asset_htlcs: BTreeMap<u64, AssetAmount>,
+    received_asset_htlcs: BTreeMap<u64, AssetAmount>,
+
+    /// Set if the channel is a simple taproot channel, which affects the form
+    /// of the balance outputs
+    simple_taproot: bool,
+
+    // Commitment round specific information used to locate `to_local` output
+    local_to_self_delay: u16,
+    remote_to_self_delay: u16,
+    local_revocation_basepoint: PublicKey,
+    remote_revocation_basepoint: PublicKey,
+    local_delayed_basepoint: PublicKey,
+    remote_delayed_basepoint: PublicKey,
+    local_per_commitment_point: PublicKey,
+    remote_per_commitment_point: PublicKey,
+}
+
+impl Default for Rgb {
+    fn default() -> Self {
+        Rgb {
+            local_asset_list: empty!(),
+            remote_asset_list: None,
+            funding_assets: empty!(),
+            local_assets: empty!(),
+            remote_assets: empty!(),
+            offered_asset_htlcs: empty!(),
+            received_asset_htlcs: empty!(),
+            simple_taproot: false,
+            local_to_self_delay: 0,
+            remote_to_self_delay: 0,
+            local_revocation_basepoint: dumb_pubkey!(),
+            remote_revocation_basepoint: dumb_pubkey!(),
+            local_delayed_basepoint: dumb_pubkey!(),
+            remote_delayed_basepoint: dumb_pubkey!(),
+            local_per_commitment_point: dumb_pubkey!(),
+            remote_per_commitment_point: dumb_pubkey!(),
+        }
+    }
+}
+
+impl Rgb {
+    /// Constructs RGB modifier for a node supporting assets from the
+    /// `asset_list` of its `init` message. If the local node opens the
+    /// channel, it allocates `funding_assets` to it.
+    pub fn with(asset_list: &AssetList, funding_assets: AssetsBalance) -> Rgb {
+        Rgb {
+            local_asset_list: asset_list.as_inner().iter().copied().collect(),
+            funding_assets,
+            ..Rgb::default()
+        }
+    }
+
+    /// Returns assets which can be used by the channel, i.e. the intersection
+    /// of the asset lists of both peers.
+    pub fn channel_asset_list(&self) -> BTreeSet<AssetId> {
+        match self.remote_asset_list {
+            Some(ref remote_asset_list) => self
+                .local_asset_list
+                .intersection(remote_asset_list)
+                .copied()
+                .collect(),
+            None => empty!(),
+        }
+    }
+
+    /// Checks that the asset can be used by the channel
+    fn ensure_supported(&self, asset_id: AssetId) -> Result<(), RgbError> {
+        if !self.channel_asset_list().contains(&asset_id) {
+            return Err(RgbError::UnsupportedAsset(asset_id));
+        }
+        Ok(())
+    }
+
+    /// Constructs asset balance from the allocations of `open_channel`
+    /// message, checking that all of the assets are supported by the channel
+    fn funding_balance(
+        &self,
+        allocations: &[AssetAmount],
+    ) -> Result<AssetsBalance, RgbError> {
+        let mut balance = AssetsBalance::new();
+        for allocation in allocations {
+            self.ensure_supported(allocation.asset_id)?;
+            if balance
+                .insert(allocation.asset_id, allocation.amount)
+                .is_some()
+            {
+                return Err(RgbError::RepeatedAllocation(allocation.asset_id));
+            }
+        }
+        Ok(balance)
+    }
+
+    /// Commits to the state of asset allocations of the local (if
+    /// `as_remote_node` is `false`) or remote (if `as_remote_node` is `true`)
+    /// commitment transaction. Returns `None` if the channel has no assets.
+    pub fn asset_commitment(
+        &self,
+        as_remote_node: bool,
+    ) -> Option<sha256::Hash> {
+        // HTLCs offered by us are received by the remote node, so for the
+        // remote commitment transaction we have to swap the sets
+        let (owner_assets, counterparty_assets, offered, received) =
+            if as_remote_node {
+                (
+                    &self.remote_assets,
+                    &self.local_assets,
+                    &self.received_asset_htlcs,
+                    &self.offered_asset_htlcs,
+                )
+            } else {
+                (
+                    &self.local_assets,
+                    &self.remote_assets,
+                    &self.offered_asset_htlcs,
+                    &self.received_asset_htlcs,
+                )
+            };
+        if owner_assets.is_empty()
+            && counterparty_assets.is_empty()
+            && offered.is_empty()
+            && received.is_empty()
+        {
+            return None;
+        }
+
+        let data = [
+            strict_serialize(owner_assets),
+            strict_serialize(counterparty_assets),
+            strict_serialize(offered),
+            strict_serialize(received),
+        ]
+        .map(|data| data.expect("in-memory strict encoding can't fail"));
+        Some(sha256::Hash::from_inner(tagged_hash(
+            "RGB/channel-assets",
+            &[&data[0], &data[1], &data[2], &data[3]],
+        )))
+    }
+
+    /// Derives keys for the local (if `as_remote_node` is `false`) or remote
+    /// (if `as_remote_node` is `true`) commitment transaction.
+    fn commitment_keys(&self, as_remote_node: bool) -> CommitmentKeys {
+        let (
+            per_commitment_point,
+            revocation_basepoint,
+            delayed_basepoint,
+            to_self_delay,
+        ) = if as_remote_node {
+            (
+                self.remote_per_commitment_point,
+                self.local_revocation_basepoint,
+                self.remote_delayed_basepoint,
+                self.remote_to_self_delay,
+            )
+        } else {
+            (
+                self.local_per_commitment_point,
+                self.remote_revocation_basepoint,
+                self.local_delayed_basepoint,
+                self.local_to_self_delay,
+            )
+        };
+        CommitmentKeys {
+            revocationpubkey: derive_revocationpubkey(
+                revocation_basepoint,
+                per_commitment_point,
+            ),
+            // HTLC keys are not used by the asset commitments
+            local_htlcpubkey: dumb_pubkey!(),
+            remote_htlcpubkey: dumb_pubkey!(),
+            local_delayedpubkey: derive_pubkey(
+                delayed_basepoint,
+                per_commitment_point,
+            ),
+            to_self_delay,
+        }
+    }
+
+    fn to_local_output(
+        &self,
+        amount: u64,
+        keys: &CommitmentKeys,
+        local_delayedpubkey: PublicKey,
+    ) -> psbt::Output {
+        if self.simple_taproot {
+            TaprootScriptGenerators::ln_taproot_to_local(
+                amount,
+                keys.revocationpubkey,
+                local_delayedpubkey,
+                keys.to_self_delay,
+            )
+        } else {
+            ScriptGenerators::ln_to_local(
+                amount,
+                keys.revocationpubkey,
+                local_delayedpubkey,
+                keys.to_self_delay,
+            )
+        }
+    }
+}
+
+/// Computes tweak committing the delayed public key of `to_local` output to
+/// the state of asset allocations. The commitment owner adds the tweak to
+/// its delayed private key to spend the output.
+pub fn asset_tweak(
+    local_delayedpubkey: PublicKey,
+    commitment: sha256::Hash,
+) -> Scalar {
+    let tweak = tagged_hash("RGB/channel-tweak", &[
+        &local_delayedpubkey.serialize(),
+        &commitment[..],
+    ]);
+    Scalar::from(
+        SecretKey::from_slice(&tweak)
+            .expect("negligible probability of hash exceeding curve order"),
+    )
+}
+
+fn debit(
+    balance: &mut AssetsBalance,
+    asset: AssetAmount,
+) -> Result<(), RgbError> {
+    let available = balance.get(&asset.asset_id).copied().unwrap_or_default();
+    if available < asset.amount {
+        return Err(RgbError::InsufficientBalance {
+            asset_id: asset.asset_id,
+            available,
+            required: asset.amount,
+        });
+    }
+    balance.insert(asset.asset_id, available - asset.amount);
+    Ok(())
+}
+
+fn credit(balance: &mut AssetsBalance, asset: AssetAmount) {
+    *balance.entry(asset.asset_id).or_default() += asset.amount;
+}
+
+impl Extension<BoltExt> for Rgb {
+    fn identity(&self) -> BoltExt {
+        BoltExt::Rgb
+    }
+
+    fn update_from_local(&mut self, _message: &()) -> Result<(), Error> {
+        // Nothing to do here so far
+        Ok(())
+    }
+
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        message: &mut Messages,
+    ) -> Result<(), Error> {
+        match (request, message) {
+            (UpdateReq::OpenChannel, Messages::OpenChannel(open_channel)) => {
+                if self.funding_assets.is_empty() {
+                    return Ok(());
+                }
+                let allocations = self
+                    .funding_assets
+                    .iter()
+                    .map(|(asset_id, amount)| AssetAmount {
+                        asset_id: *asset_id,
+                        amount: *amount,
+                    })
+                    .collect::<Vec<_>>();
+                self.local_assets = self.funding_balance(&allocations)?;
+                self.remote_assets = empty!();
+                open_channel.funding_assets = Some(allocations);
+            }
+            (
+                UpdateReq::PayBolt(_),
+                Messages::UpdateAddHtlc(update_add_htlc),
+            ) => {
+                if let Some(asset) = update_add_htlc.asset {
+                    self.ensure_supported(asset.asset_id)?;
+                    debit(&mut self.local_assets, asset)?;
+                    self.offered_asset_htlcs
+                        .insert(update_add_htlc.htlc_id, asset);
+                }
+            }
+            (
+                UpdateReq::FulfillHtlc,
+                Messages::UpdateFulfillHtlc(update_fulfill_htlc),
+            ) => {
+                if let Some(asset) = self
+                    .received_asset_htlcs
+                    .remove(&update_fulfill_htlc.htlc_id)
+                {
+                    credit(&mut self.local_assets, asset);
+                }
+            }
+            (
+                UpdateReq::FailHtlc,
+                Messages::UpdateFailHtlc(update_fail_htlc),
+            ) => {
+                if let Some(asset) =
+                    self.received_asset_htlcs.remove(&update_fail_htlc.htlc_id)
+                {
+                    credit(&mut self.remote_assets, asset);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
+        match message {
+            Messages::Init(init) => {
+                self.remote_asset_list =
+                    Some(init.assets.as_inner().iter().copied().collect());
+            }
+            Messages::OpenChannel(open_channel) => {
+                self.simple_taproot = open_channel
+                    .channel_type
+                    .map(ChannelType::has_simple_taproot)
+                    .unwrap_or_default();
+                self.remote_revocation_basepoint =
+                    open_channel.revocation_basepoint;
+                self.remote_delayed_basepoint =
+                    open_channel.delayed_payment_basepoint;
+                self.remote_per_commitment_point =
+                    open_channel.first_per_commitment_point;
+                self.local_to_self_delay = open_channel.to_self_delay;
+
+                self.remote_assets = self.funding_balance(
+                    open_channel.funding_assets.as_deref().unwrap_or_default(),
+                )?;
+                self.local_assets = empty!();
+            }
+            Messages::AcceptChannel(accept_channel) => {
+                self.simple_taproot = accept_channel
+                    .channel_type
+                    .map(ChannelType::has_simple_taproot)
+                    .unwrap_or_default();
+                self.remote_revocation_basepoint =
+                    accept_channel.revocation_basepoint;
+                self.remote_delayed_basepoint =
+                    accept_channel.delayed_payment_basepoint;
+                self.remote_per_commitment_point =
+                    accept_channel.first_per_commitment_point;
+                self.local_to_self_delay = accept_channel.to_self_delay;
+            }
+            Messages::UpdateAddHtlc(update_add_htlc) => {
+                if let Some(asset) = update_add_htlc.asset {
+                    self.ensure_supported(asset.asset_id)?;
+                    debit(&mut self.remote_assets, asset)?;
+                    self.received_asset_htlcs
+                        .insert(update_add_htlc.htlc_id, asset);
+                }
+            }
+            Messages::UpdateFulfillHtlc(update_fulfill_htlc) => {
+                if let Some(asset) = self
+                    .offered_asset_htlcs
+                    .remove(&update_fulfill_htlc.htlc_id)
+                {
+                    credit(&mut self.remote_assets, asset);
+                }
+            }
+            Messages::UpdateFailHtlc(update_fail_htlc) => {
+                if let Some(asset) =
+                    self.offered_asset_htlcs.remove(&update_fail_htlc.htlc_id)
+                {
+                    credit(&mut self.local_assets, asset);
+                }
+            }
+            Messages::UpdateFailMalformedHtlc(update_fail_malformed_htlc) => {
+                if let Some(asset) = self
+                    .offered_asset_htlcs
+                    .remove(&update_fail_malformed_htlc.htlc_id)
+                {
+                    credit(&mut self.local_assets, asset);
+                }
+            }
+            Messages::FundingLocked(funding_locked) => {
+                self.remote_per_commitment_point =
+                    funding_locked.next_per_commitment_point;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, state: &ChannelState) {
+        self.simple_taproot =
+            state.common_params.channel_type.has_simple_taproot();
+
+        self.local_asset_list = state.local_asset_list.clone();
+        self.remote_asset_list = state.remote_asset_list.clone();
+        self.funding_assets = state.funding_assets.clone();
+        self.local_assets = state.local_assets.clone();
+        self.remote_assets = state.remote_assets.clone();
+        self.offered_asset_htlcs = state.offered_asset_htlcs.clone();
+        self.received_asset_htlcs = state.received_asset_htlcs.clone();
+
+        self.local_to_self_delay = state.remote_params.to_self_delay;
+        self.remote_to_self_delay = state.local_params.to_self_delay;
+        self.local_revocation_basepoint =
+            state.local_keys.revocation_basepoint.key;
+        self.remote_revocation_basepoint =
+            state.remote_keys.revocation_basepoint;
+        self.local_delayed_basepoint =
+            state.local_keys.delayed_payment_basepoint.key;
+        self.remote_delayed_basepoint =
+            state.remote_keys.delayed_payment_basepoint;
+        self.local_per_commitment_point = state.local_per_commitment_point;
+        self.remote_per_commitment_point = state.remote_per_commitment_point;
+    }
+
+    fn store_state(&self, state: &mut ChannelState) {
+        state.local_asset_list = self.local_asset_list.clone();
+        state.remote_asset_list = self.remote_asset_list.clone();
+        state.funding_assets = self.funding_assets.clone();
+        state.local_assets = self.local_assets.clone();
+        state.remote_assets = self.remote_assets.clone();
+        state.offered_asset_htlcs = self.offered_asset_htlcs.clone();
+        state.received_asset_htlcs = self.received_asset_htlcs.clone();
+    }
+}
+
+impl ChannelExtension<BoltExt> for Rgb {
+    #[inline]
+    fn new() -> Box<dyn ChannelExtension<BoltExt>>
+    where
+        Self: Sized,
+    {
+        Box::new(Rgb::default())
+    }
+
+    fn build_graph(
+        &self,
+        tx_graph: &mut TxGraph,
+        as_remote_node: bool,
+    ) -> Result<(), Error> {
+        let commitment = match self.asset_commitment(as_remote_node) {
+            Some(commitment) => commitment,
+            None => return Ok(()),
+        };
+        let keys = self.commitment_keys(as_remote_node);
+
+        let to_local_script = if self.simple_taproot {
+            PubkeyScript::ln_taproot_to_local(
+                0,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            )
+        } else {
+            PubkeyScript::ln_to_local(
+                0,
+                keys.revocationpubkey,
+                keys.local_delayedpubkey,
+                keys.to_self_delay,
+            )
+        };
+        if let Some(output) = tx_graph
+            .cmt_outs
+            .iter_mut()
+            .find(|out| out.script == to_local_script)
+        {
+            let tweaked_key = keys
+                .local_delayedpubkey
+                .add_exp_tweak(
+                    SECP256K1,
+                    &asset_tweak(keys.local_delayedpubkey, commitment),
+                )
+                .expect("negligible probability of tweak being a negated key");
+            *output = self.to_local_output(output.amount, &keys, tweaked_key);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::{DumbDefault, Slice32};
+    use bitcoin_scripts::hlc::{HashLock, HashPreimage};
+    use internet2::addr::NodeId;
+    use internet2::presentation::sphinx::{Hop, Onion, OnionPacket};
+    use lnp2p::bolt::{
+        ChannelId, HopRealm, Init, OpenChannel, PaymentOnion, ShortChannelId,
+        UpdateAddHtlc, UpdateFailHtlc, UpdateFulfillHtlc,
+    };
+
+    use super::*;
+    use crate::channel::Funding;
+
+    fn key(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(&[byte; 32]).unwrap(),
+        )
+    }
+
+    fn asset_id(byte: u8) -> AssetId {
+        AssetId::from_inner([byte; 32])
+    }
+
+    fn asset_list(assets: &[u8]) -> AssetList {
+        AssetList::from_inner(assets.iter().copied().map(asset_id).collect())
+    }
+
+    fn init(assets: &[u8]) -> Messages {
+        Messages::Init(Init {
+            global_features: none!(),
+            local_features: none!(),
+            assets: asset_list(assets),
+            unknown_tlvs: none!(),
+        })
+    }
+
+    fn update_add_htlc(htlc_id: u64, asset: AssetAmount) -> Messages {
+        let hashlock =
+            HashLock::from(HashPreimage::from(Slice32::from([0xAB; 32])));
+        let route = vec![Hop::with(NodeId::from(key(9)), PaymentOnion {
+            realm: HopRealm::Legacy(ShortChannelId::default()),
+            amt_to_forward: 2_000_000,
+            outgoing_cltv_value: 500,
+        })];
+        let onion_packet =
+            OnionPacket::with(SECP256K1, &route, hashlock.as_ref()).unwrap();
+        Messages::UpdateAddHtlc(UpdateAddHtlc {
+            channel_id: ChannelId::default(),
+            htlc_id,
+            amount_msat: 2_000_000,
+            payment_hash: hashlock,
+            cltv_expiry: 500,
+            onion_routing_packet: Onion::Onion(onion_packet),
+            asset: Some(asset),
+            unknown_tlvs: none!(),
+        })
+    }
+
+    fn to_local(peer: &Rgb, as_remote_node: bool) -> psbt::Output {
+        let keys = peer.commitment_keys(as_remote_node);
+        peer.to_local_output(5000, &keys, keys.local_delayedpubkey)
+    }
+
+    #[test]
+    fn asset_allocations() {
+        let asset = AssetAmount {
+            asset_id: asset_id(1),
+            amount: 1000,
+        };
+        let funding = bmap! { asset_id(1) => 1000 };
+
+        // Alice can't open channel with assets before she knows which of them
+        // are supported by Bob
+        let mut alice = Rgb::with(&asset_list(&[1, 2]), funding.clone());
+        let mut bob = Rgb::with(&asset_list(&[1, 3]), empty!());
+        let mut open_channel =
+            Messages::OpenChannel(OpenChannel::dumb_default());
+        assert_eq!(
+            alice.state_change(&UpdateReq::OpenChannel, &mut open_channel),
+            Err(Error::Rgb(RgbError::UnsupportedAsset(asset_id(1))))
+        );
+
+        alice.update_from_peer(&init(&[1, 3])).unwrap();
+        bob.update_from_peer(&init(&[1, 2])).unwrap();
+        assert_eq!(alice.channel_asset_list(), bset! { asset_id(1) });
+        assert_eq!(alice.channel_asset_list(), bob.channel_asset_list());
+
+        // Assets outside of the intersection of asset lists can't be used
+        let mut carol = Rgb::with(&asset_list(&[1, 2]), bmap! {
+            asset_id(2) => 1
+        });
+        carol.update_from_peer(&init(&[1, 3])).unwrap();
+        assert_eq!(
+            carol.state_change(&UpdateReq::OpenChannel, &mut open_channel),
+            Err(Error::Rgb(RgbError::UnsupportedAsset(asset_id(2))))
+        );
+
+        alice
+            .state_change(&UpdateReq::OpenChannel, &mut open_channel)
+            .unwrap();
+        match open_channel {
+            Messages::OpenChannel(ref open_channel) => {
+                assert_eq!(open_channel.funding_assets, Some(vec![asset]))
+            }
+            _ => unreachable!(),
+        }
+        bob.update_from_peer(&open_channel).unwrap();
+        assert_eq!(alice.local_assets, funding);
+        assert_eq!(bob.remote_assets, funding);
+
+        // Set up mirrored channel keys
+        for (peer, delay, local, remote) in
+            [(&mut alice, (144, 720), 1, 2), (&mut bob, (720, 144), 2, 1)]
+        {
+            peer.local_to_self_delay = delay.0;
+            peer.remote_to_self_delay = delay.1;
+            peer.local_revocation_basepoint = key(local);
+            peer.remote_revocation_basepoint = key(remote);
+            peer.local_delayed_basepoint = key(local + 2);
+            peer.remote_delayed_basepoint = key(remote + 2);
+            peer.local_per_commitment_point = key(local + 4);
+            peer.remote_per_commitment_point = key(remote + 4);
+        }
+
+        // Alice sends part of the asset to Bob
+        let transfer = AssetAmount {
+            asset_id: asset_id(1),
+            amount: 300,
+        };
+        let mut message = update_add_htlc(0, transfer);
+        alice
+            .state_change(&UpdateReq::PayBolt(vec![]), &mut message)
+            .unwrap();
+        bob.update_from_peer(&message).unwrap();
+        assert_eq!(alice.local_assets, bmap! { asset_id(1) => 700 });
+        assert_eq!(alice.offered_asset_htlcs, bob.received_asset_htlcs);
+
+        // Bob can't send more assets than he has
+        let mut message = update_add_htlc(0, transfer);
+        assert_eq!(
+            bob.state_change(&UpdateReq::PayBolt(vec![]), &mut message),
+            Err(Error::Rgb(RgbError::InsufficientBalance {
+                asset_id: asset_id(1),
+                available: 0,
+                required: 300
+            }))
+        );
+
+        // Both peers commit to the same asset state in the same commitment
+        // transaction
+        let funding = Funding::new();
+        for as_remote_node in [false, true] {
+            let commitment = alice.asset_commitment(as_remote_node).unwrap();
+            assert_eq!(Some(commitment), bob.asset_commitment(!as_remote_node));
+
+            let mut alice_graph = TxGraph::from_funding(&funding);
+            let mut bob_graph = TxGraph::from_funding(&funding);
+            alice_graph.cmt_outs.push(to_local(&alice, as_remote_node));
+            bob_graph.cmt_outs.push(to_local(&bob, !as_remote_node));
+            alice.build_graph(&mut alice_graph, as_remote_node).unwrap();
+            bob.build_graph(&mut bob_graph, !as_remote_node).unwrap();
+            assert_eq!(alice_graph.cmt_outs, bob_graph.cmt_outs);
+
+            let keys = alice.commitment_keys(as_remote_node);
+            let tweaked_key = keys
+                .local_delayedpubkey
+                .add_exp_tweak(
+                    SECP256K1,
+                    &asset_tweak(keys.local_delayedpubkey, commitment),
+                )
+                .unwrap();
+            assert_ne!(
+                alice_graph.cmt_outs[0],
+                to_local(&alice, as_remote_node)
+            );
+            assert_eq!(
+                alice_graph.cmt_outs[0],
+                alice.to_local_output(5000, &keys, tweaked_key)
+            );
+        }
+        assert_ne!(alice.asset_commitment(false), alice.asset_commitment(true));
+
+        // Bob fulfills the HTLC, receiving the asset
+        let mut message = Messages::UpdateFulfillHtlc(UpdateFulfillHtlc {
+            channel_id: ChannelId::default(),
+            htlc_id: 0,
+            payment_preimage: HashPreimage::from(Slice32::from([0xAB; 32])),
+        });
+        bob.state_change(&UpdateReq::FulfillHtlc, &mut message)
+            .unwrap();
+        alice.update_from_peer(&message).unwrap();
+        assert!(alice.offered_asset_htlcs.is_empty());
+        assert!(bob.received_asset_htlcs.is_empty());
+        assert_eq!(alice.remote_assets, bmap! { asset_id(1) => 300 });
+        assert_eq!(bob.local_assets, alice.remote_assets);
+        assert_eq!(bob.remote_assets, alice.local_assets);
+
+        // Failed HTLC returns the asset back
+        let mut message = update_add_htlc(1, transfer);
+        alice
+            .state_change(&UpdateReq::PayBolt(vec![]), &mut message)
+            .unwrap();
+        bob.update_from_peer(&message).unwrap();
+        assert_eq!(alice.local_assets, bmap! { asset_id(1) => 400 });
+        let mut message = Messages::UpdateFailHtlc(UpdateFailHtlc {
+            channel_id: ChannelId::default(),
+            htlc_id: 1,
+            reason: vec![],
+        });
+        bob.state_change(&UpdateReq::FailHtlc, &mut message)
+            .unwrap();
+        alice.update_from_peer(&message).unwrap();
+        assert_eq!(alice.local_assets, bmap! { asset_id(1) => 700 });
+        assert_eq!(bob.remote_assets, alice.local_assets);
+        assert_eq!(bob.local_assets, alice.remote_assets);
+        assert!(bob.received_asset_htlcs.is_empty());
+    }
+
+    #[test]
+    fn restart_roundtrip() {
+        let mut alice =
+            Rgb::with(&asset_list(&[1, 2]), bmap! { asset_id(1) => 1000 });
+        alice.update_from_peer(&init(&[1, 3])).unwrap();
+        let mut open_channel =
+            Messages::OpenChannel(OpenChannel::dumb_default());
+        alice
+            .state_change(&UpdateReq::OpenChannel, &mut open_channel)
+            .unwrap();
+        let mut message = update_add_htlc(0, AssetAmount {
+            asset_id: asset_id(1),
+            amount: 300,
+        });
+        alice
+            .state_change(&UpdateReq::PayBolt(vec![]), &mut message)
+            .unwrap();
+
+        let mut state = ChannelState::dumb_default();
+        alice.store_state(&mut state);
+        let mut restored = Rgb::default();
+        restored.load_state(&state);
+
+        assert_eq!(restored.local_asset_list, alice.local_asset_list);
+        assert_eq!(restored.remote_asset_list, alice.remote_asset_list);
+        assert_eq!(restored.funding_assets, alice.funding_assets);
+        assert_eq!(restored.local_assets, alice.local_assets);
+        assert_eq!(restored.remote_assets, alice.remote_assets);
+        assert_eq!(restored.offered_asset_htlcs, alice.offered_asset_htlcs);
+        assert_eq!(restored.channel_asset_list(), bset! { asset_id(1) });
+        assert_eq!(
+            restored.asset_commitment(false),
+            alice.asset_commitment(false)
+        );
+
+        // Restored channel keeps checking supported assets
+        let mut message = update_add_htlc(1, AssetAmount {
+            asset_id: asset_id(2),
+            amount: 1,
+        });
+        assert_eq!(
+            restored.state_change(&UpdateReq::PayBolt(vec![]), &mut message),
+            Err(Error::Rgb(RgbError::UnsupportedAsset(asset_id(2))))
+        );
+    }
+}
@@ -0,0 +1,463 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Shutdown scripts used for the mutual channel close.
+//!
+//! Peers provide the scripts receiving their funds on mutual close with
+//! `shutdown` messages. If `option_upfront_shutdown_script` is negotiated, a
+//! peer may commit to its shutdown script already in `open_channel` or
+//! `accept_channel` message, in which case the script is locked for the whole
+//! lifetime of the channel.
+
+use amplify::Wrapper;
+use bitcoin::blockdata::opcodes::all::OP_PUSHBYTES_0;
+use bitcoin_scripts::PubkeyScript;
+use p2p::bolt::{InitFeatures, Messages};
+
+use crate::channel::bolt::util::UpdateReq;
+use crate::channel::bolt::{BoltExt, ChannelState, Error};
+use crate::channel::tx_graph::TxGraph;
+use crate::{ChannelExtension, Extension};
+
+/// Errors of shutdown script processing
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ShutdownScriptError {
+    /// shutdown scriptpubkey `{0}` is not in one of the forms allowed by
+    /// BOLT-2
+    InvalidForm(PubkeyScript),
+
+    /// shutdown scriptpubkey `{proposed}` does not match the upfront shutdown
+    /// script `{upfront}` the peer has committed to when opening the channel
+    UpfrontMismatch {
+        upfront: PubkeyScript,
+        proposed: PubkeyScript,
+    },
+
+    /// shutdown scriptpubkey `{proposed}` differs from `{sent}` which was
+    /// already sent with the previous `shutdown` message
+    ScriptChanged {
+        sent: PubkeyScript,
+        proposed: PubkeyScript,
+    },
+
+    /// no shutdown scriptpubkey is provided and the local node has no upfront
+    /// shutdown script
+    NoScript,
+
+    /// the closing transaction requires both peers to exchange `shutdown`
+    /// messages
+    NoShutdown,
+
+    /// closing transaction can't be constructed while {0} HTLCs or PTLCs
+    /// are still pending
+    PendingHtlcs(usize),
+
+    /// closing fee of {fee} sat exceeds channel funder balance of {balance}
+    /// sat
+    InsufficientFunds { fee: u64, balance: u64 },
+
+    /// shutdown state change is requested with a message other than
+    /// `shutdown`
+    NotShutdownMessage,
+}
+
+/// Checks that the script has one of the forms which BOLT-2 allows for
+/// `shutdown_scriptpubkey`: P2PKH, P2SH, P2WPKH, P2WSH or, if
+/// `option_shutdown_anysegwit` is negotiated, a witness program of any future
+/// segwit version.
+pub fn is_valid_shutdown_script(
+    script: &PubkeyScript,
+    anysegwit: bool,
+) -> bool {
+    let script = script.as_inner();
+    script.is_p2pkh()
+        || script.is_p2sh()
+        || script.is_v0_p2wpkh()
+        || script.is_v0_p2wsh()
+        || (anysegwit
+            && script.is_witness_program()
+            && script.as_bytes()[0] != OP_PUSHBYTES_0.to_u8())
+}
+
+#[derive(Getters, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ShutdownScript {
+    /// Features announced by the local node in its `init` message
+    local_features: InitFeatures,
+
+    /// Both peers have announced `option_upfront_shutdown_script`
+    upfront_shutdown_script: bool,
+
+    /// Both peers have announced `option_shutdown_anysegwit`
+    shutdown_anysegwit: bool,
+
+    // Scripts committed to in `open_channel` / `accept_channel` messages
+    local_upfront_script: Option<PubkeyScript>,
+    remote_upfront_script: Option<PubkeyScript>,
+
+    // Scripts provided in `shutdown` messages
+    local_shutdown_script: Option<PubkeyScript>,
+    remote_shutdown_script: Option<PubkeyScript>,
+}
+
+impl ShutdownScript {
+    /// Constructs shutdown script extension for a node announcing
+    /// `local_features` in its `init` message. The features are negotiated
+    /// once the remote `init` message is received or provided with
+    /// [`ShutdownScript::negotiate`].
+    pub fn with(local_features: InitFeatures) -> ShutdownScript {
+        ShutdownScript {
+            local_features,
+            ..ShutdownScript::default()
+        }
+    }
+
+    /// Negotiates shutdown-related features with the features announced by
+    /// the remote peer in its `init` message.
+    pub fn negotiate(&mut self, remote_features: &InitFeatures) {
+        self.upfront_shutdown_script =
+            self.local_features.option_upfront_shutdown_script.is_some()
+                && remote_features.option_upfront_shutdown_script.is_some();
+        self.shutdown_anysegwit =
+            self.local_features.option_shutdown_anysegwit.is_some()
+                && remote_features.option_shutdown_anysegwit.is_some();
+    }
+
+    fn check_form(&self, script: &PubkeyScript) -> Result<(), Error> {
+        if !is_valid_shutdown_script(script, self.shutdown_anysegwit) {
+            return Err(ShutdownScriptError::InvalidForm(script.clone()).into());
+        }
+        Ok(())
+    }
+
+    /// Validates upfront script provided in `open_channel` or
+    /// `accept_channel`, which may be empty if the peer does not want to
+    /// commit to a specific script
+    fn check_upfront(
+        &self,
+        script: &Option<PubkeyScript>,
+    ) -> Result<Option<PubkeyScript>, Error> {
+        match script {
+            Some(script) if !script.as_inner().is_empty() => {
+                self.check_form(script)?;
+                Ok(Some(script.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Validates script provided in `shutdown` message against the script
+    /// from the previous `shutdown` message and the upfront script, if
+    /// `option_upfront_shutdown_script` was negotiated
+    fn check_shutdown(
+        &self,
+        script: &PubkeyScript,
+        upfront: &Option<PubkeyScript>,
+        sent: &Option<PubkeyScript>,
+    ) -> Result<(), Error> {
+        self.check_form(script)?;
+        match (upfront, sent) {
+            (_, Some(sent)) if sent != script => {
+                Err(ShutdownScriptError::ScriptChanged {
+                    sent: sent.clone(),
+                    proposed: script.clone(),
+                }
+                .into())
+            }
+            (Some(upfront), _)
+                if self.upfront_shutdown_script && upfront != script =>
+            {
+                Err(ShutdownScriptError::UpfrontMismatch {
+                    upfront: upfront.clone(),
+                    proposed: script.clone(),
+                }
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Extension<BoltExt> for ShutdownScript {
+    #[inline]
+    fn identity(&self) -> BoltExt {
+        BoltExt::ShutdownScript
+    }
+
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        message: &mut Messages,
+    ) -> Result<(), Error> {
+        match (request, message) {
+            (UpdateReq::OpenChannel, Messages::OpenChannel(open_channel)) => {
+                self.local_upfront_script =
+                    self.check_upfront(&open_channel.shutdown_scriptpubkey)?;
+                // If the option is negotiated, the field must be present even
+                // if the node does not commit to a specific script
+                if self.upfront_shutdown_script
+                    && open_channel.shutdown_scriptpubkey.is_none()
+                {
+                    open_channel.shutdown_scriptpubkey = Some(empty!());
+                }
+            }
+            (
+                UpdateReq::AcceptChannel,
+                Messages::AcceptChannel(accept_channel),
+            ) => {
+                self.local_upfront_script =
+                    self.check_upfront(&accept_channel.shutdown_scriptpubkey)?;
+                if self.upfront_shutdown_script
+                    && accept_channel.shutdown_scriptpubkey.is_none()
+                {
+                    accept_channel.shutdown_scriptpubkey = Some(empty!());
+                }
+            }
+            (UpdateReq::Shutdown, Messages::Shutdown(shutdown)) => {
+                self.check_shutdown(
+                    &shutdown.scriptpubkey,
+                    &self.local_upfront_script,
+                    &self.local_shutdown_script,
+                )?;
+                self.local_shutdown_script =
+                    Some(shutdown.scriptpubkey.clone());
+            }
+            (UpdateReq::Shutdown, _) => {
+                return Err(ShutdownScriptError::NotShutdownMessage.into())
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_from_local(&mut self, _message: &()) -> Result<(), Error> {
+        // Nothing to do here so far
+        Ok(())
+    }
+
+    fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
+        match message {
+            Messages::Init(init) => self.negotiate(&init.local_features),
+            Messages::OpenChannel(open_channel) => {
+                self.remote_upfront_script =
+                    self.check_upfront(&open_channel.shutdown_scriptpubkey)?;
+            }
+            Messages::AcceptChannel(accept_channel) => {
+                self.remote_upfront_script =
+                    self.check_upfront(&accept_channel.shutdown_scriptpubkey)?;
+            }
+            Messages::Shutdown(shutdown) => {
+                self.check_shutdown(
+                    &shutdown.scriptpubkey,
+                    &self.remote_upfront_script,
+                    &self.remote_shutdown_script,
+                )?;
+                self.remote_shutdown_script =
+                    Some(shutdown.scriptpubkey.clone());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, state: &ChannelState) {
+        self.local_features = state.local_features.clone();
+        self.upfront_shutdown_script = state.option_upfront_shutdown_script;
+        self.shutdown_anysegwit = state.option_shutdown_anysegwit;
+        self.local_upfront_script = state
+            .local_keys
+            .shutdown_scriptpubkey
+            .clone()
+            .filter(|script| !script.as_inner().is_empty());
+        self.remote_upfront_script = state
+            .remote_keys
+            .shutdown_scriptpubkey
+            .clone()
+            .filter(|script| !script.as_inner().is_empty());
+        self.local_shutdown_script = state.local_shutdown_scriptpubkey.clone();
+        self.remote_shutdown_script =
+            state.remote_shutdown_scriptpubkey.clone();
+    }
+
+    fn store_state(&self, state: &mut ChannelState) {
+        state.option_upfront_shutdown_script = self.upfront_shutdown_script;
+        state.option_shutdown_anysegwit = self.shutdown_anysegwit;
+        state.local_shutdown_scriptpubkey = self.local_shutdown_script.clone();
+        state.remote_shutdown_scriptpubkey =
+            self.remote_shutdown_script.clone();
+    }
+}
+
+impl ChannelExtension<BoltExt> for ShutdownScript {
+    #[inline]
+    fn new() -> Box<dyn ChannelExtension<BoltExt>>
+    where
+        Self: Sized,
+    {
+        Box::default() as Box<ShutdownScript>
+    }
+
+    #[inline]
+    fn build_graph(
+        &self,
+        _tx_graph: &mut TxGraph,
+        _as_remote_node: bool,
+    ) -> Result<(), Error> {
+        // Shutdown scripts do not affect commitment transactions
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::DumbDefault;
+    use bitcoin::blockdata::opcodes::all::OP_RETURN;
+    use bitcoin::blockdata::script::Builder;
+    use bitcoin::hashes::Hash;
+    use bitcoin::util::address::WitnessVersion;
+    use bitcoin::{PubkeyHash, Script, ScriptHash, WPubkeyHash, WScriptHash};
+    use p2p::bolt::{ChannelId, OpenChannel, Shutdown};
+
+    use super::*;
+
+    fn witness_program(version: WitnessVersion, len: usize) -> PubkeyScript {
+        Script::new_witness_program(version, &vec![0xAB; len]).into()
+    }
+
+    fn shutdown(script: &PubkeyScript) -> Messages {
+        Messages::Shutdown(Shutdown {
+            channel_id: ChannelId::default(),
+            scriptpubkey: script.clone(),
+        })
+    }
+
+    #[test]
+    fn shutdown_script_forms() {
+        let p2pkh = Script::new_p2pkh(&PubkeyHash::hash(b"p2pkh")).into();
+        let p2sh = Script::new_p2sh(&ScriptHash::hash(b"p2sh")).into();
+        let p2wpkh =
+            Script::new_v0_p2wpkh(&WPubkeyHash::hash(b"p2wpkh")).into();
+        let p2wsh = Script::new_v0_p2wsh(&WScriptHash::hash(b"p2wsh")).into();
+        for script in [p2pkh, p2sh, p2wpkh, p2wsh] {
+            assert!(is_valid_shutdown_script(&script, false));
+            assert!(is_valid_shutdown_script(&script, true));
+        }
+
+        for script in [
+            witness_program(WitnessVersion::V1, 32),
+            witness_program(WitnessVersion::V16, 2),
+            witness_program(WitnessVersion::V2, 40),
+        ] {
+            assert!(!is_valid_shutdown_script(&script, false));
+            assert!(is_valid_shutdown_script(&script, true));
+        }
+
+        for script in [
+            witness_program(WitnessVersion::V0, 25),
+            witness_program(WitnessVersion::V1, 41),
+            Builder::new().push_opcode(OP_RETURN).into_script().into(),
+            PubkeyScript::default(),
+        ] {
+            assert!(!is_valid_shutdown_script(&script, false));
+            assert!(!is_valid_shutdown_script(&script, true));
+        }
+    }
+
+    #[test]
+    fn upfront_shutdown_script() {
+        let features = InitFeatures {
+            option_upfront_shutdown_script: Some(false),
+            option_shutdown_anysegwit: Some(false),
+            ..default!()
+        };
+        let upfront = witness_program(WitnessVersion::V1, 32);
+        let other = witness_program(WitnessVersion::V0, 20);
+
+        let mut alice = ShutdownScript::with(features.clone());
+        let mut bob = ShutdownScript::with(features.clone());
+        alice.negotiate(&features);
+        bob.update_from_peer(&Messages::Init(p2p::bolt::Init {
+            global_features: none!(),
+            local_features: features,
+            assets: none!(),
+            unknown_tlvs: none!(),
+        }))
+        .unwrap();
+        assert!(*bob.upfront_shutdown_script());
+        assert!(*bob.shutdown_anysegwit());
+
+        // Upfront script field must be present once the option is negotiated
+        let mut open_channel = OpenChannel::dumb_default();
+        let mut message = Messages::OpenChannel(open_channel.clone());
+        alice
+            .state_change(&UpdateReq::OpenChannel, &mut message)
+            .unwrap();
+        assert!(matches!(
+            message,
+            Messages::OpenChannel(OpenChannel { shutdown_scriptpubkey: Some(ref script), .. })
+                if script.as_inner().is_empty()
+        ));
+
+        open_channel.shutdown_scriptpubkey = Some(upfront.clone());
+        let mut message = Messages::OpenChannel(open_channel);
+        alice
+            .state_change(&UpdateReq::OpenChannel, &mut message)
+            .unwrap();
+        bob.update_from_peer(&message).unwrap();
+
+        // The script is locked for the channel lifetime
+        assert_eq!(
+            alice.state_change(&UpdateReq::Shutdown, &mut shutdown(&other)),
+            Err(Error::ShutdownScript(
+                ShutdownScriptError::UpfrontMismatch {
+                    upfront: upfront.clone(),
+                    proposed: other.clone(),
+                }
+            ))
+        );
+        assert_eq!(
+            bob.update_from_peer(&shutdown(&other)),
+            Err(Error::ShutdownScript(
+                ShutdownScriptError::UpfrontMismatch {
+                    upfront: upfront.clone(),
+                    proposed: other,
+                }
+            ))
+        );
+        alice
+            .state_change(&UpdateReq::Shutdown, &mut shutdown(&upfront))
+            .unwrap();
+        bob.update_from_peer(&shutdown(&upfront)).unwrap();
+        assert_eq!(alice.local_shutdown_script(), &Some(upfront.clone()));
+        assert_eq!(bob.remote_shutdown_script(), &Some(upfront));
+
+        // Shutdown request must come with `shutdown` message
+        let mut message = Messages::OpenChannel(OpenChannel::dumb_default());
+        assert_eq!(
+            alice.state_change(&UpdateReq::Shutdown, &mut message),
+            Err(Error::ShutdownScript(
+                ShutdownScriptError::NotShutdownMessage
+            ))
+        );
+
+        // Future segwit versions require `option_shutdown_anysegwit`
+        let mut carol = ShutdownScript::default();
+        let script = witness_program(WitnessVersion::V1, 32);
+        assert_eq!(
+            carol.update_from_peer(&shutdown(&script)),
+            Err(Error::ShutdownScript(ShutdownScriptError::InvalidForm(
+                script
+            )))
+        );
+    }
+}
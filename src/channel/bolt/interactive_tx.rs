@@ -0,0 +1,742 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Interactive transaction construction protocol used by v2 channel
+//! establishment (dual-funded channels) to collaboratively build the funding
+//! transaction.
+
+use std::collections::BTreeMap;
+
+use amplify::Wrapper;
+use bitcoin::{OutPoint, Transaction, TxIn, TxOut, Txid, Witness};
+use bitcoin_scripts::PubkeyScript;
+use lnp2p::bolt::{
+    ChannelId, Messages, TxAbort, TxAddInput, TxAddOutput, TxComplete,
+    TxRemoveInput, TxRemoveOutput, TxSignatures,
+};
+use wallet::psbt::{Psbt, PsbtVersion};
+
+use crate::channel::funding::PsbtLnpFunding;
+
+/// Maximum number of inputs and outputs in the interactively constructed
+/// transaction
+pub const INTERACTIVE_TX_MAX_INPUTS_OUTPUTS: usize = 252;
+
+/// Maximum number of `tx_add_input` and `tx_add_output` messages which may be
+/// received from the remote peer during a single negotiation
+pub const INTERACTIVE_TX_MAX_ADD_MESSAGES: usize = 4096;
+
+/// Maximum sequence number of the input which still signals opt-in RBF
+pub const INTERACTIVE_TX_MAX_SEQUENCE: u32 = 0xFFFF_FFFD;
+
+/// Errors of the interactive transaction construction protocol
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum InteractiveTxError {
+    /// message for channel {received} does not belong to the interactive
+    /// transaction construction for channel {expected}
+    ChannelMismatch {
+        expected: ChannelId,
+        received: ChannelId,
+    },
+
+    /// serial id {0} has a parity which must not be used by the peer which
+    /// has provided it
+    SerialIdParity(u64),
+
+    /// serial id {0} is already used in the constructed transaction
+    DuplicateSerialId(u64),
+
+    /// serial id {0} is not known or belongs to the other peer
+    UnknownSerialId(u64),
+
+    /// previous transaction {txid} does not contain output #{vout}
+    NoPrevout { txid: Txid, vout: u32 },
+
+    /// input spending {0} does not spend a segwit output
+    NonSegwitInput(OutPoint),
+
+    /// output {0} is already spent by the constructed transaction
+    DuplicateInput(OutPoint),
+
+    /// input sequence number {0:#x} does not signal replaceability
+    NonReplaceableInput(u32),
+
+    /// the number of transaction inputs exceeds the limit of 252
+    TooManyInputs,
+
+    /// the number of transaction outputs exceeds the limit of 252
+    TooManyOutputs,
+
+    /// remote peer has sent too many `tx_add_input` and `tx_add_output`
+    /// messages
+    TooManyMessages,
+
+    /// the transaction construction was already completed
+    AlreadyComplete,
+
+    /// the transaction construction is not completed yet
+    NotComplete,
+
+    /// the constructed transaction does not contain channel funding output
+    NoFundingOutput,
+
+    /// transaction id {received} provided by the remote peer does not match
+    /// the constructed transaction id {expected}
+    TxidMismatch { expected: Txid, received: Txid },
+
+    /// the number of provided witnesses {provided} does not match the number
+    /// of inputs {required} added by the peer
+    WitnessCountMismatch { required: usize, provided: usize },
+
+    /// remote peer has aborted the interactive transaction construction: {0}
+    Aborted(String),
+}
+
+/// Input of the interactively constructed transaction
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InteractiveInput {
+    /// Transaction containing the spent output
+    pub prevtx: Transaction,
+
+    /// Index of the spent output within `prevtx`
+    pub prevtx_vout: u32,
+
+    /// Sequence number of the input
+    pub sequence: u32,
+}
+
+impl InteractiveInput {
+    /// Returns outpoint spent by the input
+    #[inline]
+    pub fn prevout(&self) -> OutPoint {
+        OutPoint::new(self.prevtx.txid(), self.prevtx_vout)
+    }
+
+    /// Returns transaction output spent by the input
+    #[inline]
+    pub fn prev_txout(&self) -> &TxOut {
+        &self.prevtx.output[self.prevtx_vout as usize]
+    }
+}
+
+/// State machine for the interactive construction of the channel funding
+/// transaction.
+///
+/// Each peer adds and removes its own inputs and outputs, identified by serial
+/// ids (even for the channel initiator and odd for the non-initiator), until
+/// both peers send `tx_complete` messages one after another. After that the
+/// transaction can be exported as a PSBT with [`InteractiveTx::to_psbt`] and
+/// used as a channel funding.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct InteractiveTx {
+    channel_id: ChannelId,
+    is_initiator: bool,
+    locktime: u32,
+    funding_script: PubkeyScript,
+    inputs: BTreeMap<u64, InteractiveInput>,
+    outputs: BTreeMap<u64, TxOut>,
+    next_serial_id: u64,
+    local_complete: bool,
+    remote_complete: bool,
+    remote_add_messages: usize,
+    remote_witnesses: Option<Vec<Witness>>,
+}
+
+impl InteractiveTx {
+    /// Starts new interactive transaction construction.
+    ///
+    /// # Arguments
+    /// - `channel_id`: channel for which the transaction is constructed;
+    /// - `is_initiator`: whether the local node has initiated the channel
+    ///   opening;
+    /// - `locktime`: locktime of the constructed transaction;
+    /// - `funding_script`: `scriptPubkey` of the channel funding output, used
+    ///   to detect the funding output in the constructed transaction.
+    pub fn new(
+        channel_id: ChannelId,
+        is_initiator: bool,
+        locktime: u32,
+        funding_script: PubkeyScript,
+    ) -> InteractiveTx {
+        InteractiveTx {
+            channel_id,
+            is_initiator,
+            locktime,
+            funding_script,
+            inputs: empty!(),
+            outputs: empty!(),
+            next_serial_id: if is_initiator { 0 } else { 1 },
+            local_complete: false,
+            remote_complete: false,
+            remote_add_messages: 0,
+            remote_witnesses: None,
+        }
+    }
+
+    /// Returns id of the channel for which the transaction is constructed
+    #[inline]
+    pub fn channel_id(&self) -> ChannelId {
+        self.channel_id
+    }
+
+    /// Detects whether the local node is the initiator of the construction
+    #[inline]
+    pub fn is_initiator(&self) -> bool {
+        self.is_initiator
+    }
+
+    /// Returns locktime of the constructed transaction
+    #[inline]
+    pub fn locktime(&self) -> u32 {
+        self.locktime
+    }
+
+    /// Returns all inputs of the constructed transaction, indexed by their
+    /// serial ids
+    #[inline]
+    pub fn inputs(&self) -> &BTreeMap<u64, InteractiveInput> {
+        &self.inputs
+    }
+
+    /// Returns all outputs of the constructed transaction, indexed by their
+    /// serial ids
+    #[inline]
+    pub fn outputs(&self) -> &BTreeMap<u64, TxOut> {
+        &self.outputs
+    }
+
+    /// Detects whether both peers have completed the negotiation
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.local_complete && self.remote_complete
+    }
+
+    /// Returns serial ids of the inputs added by the local node, in the order
+    /// of their appearance in the constructed transaction
+    pub fn local_input_ids(&self) -> Vec<u64> {
+        self.inputs
+            .keys()
+            .copied()
+            .filter(|serial_id| self.is_local_serial_id(*serial_id))
+            .collect()
+    }
+
+    /// Returns serial ids of the inputs added by the remote node, in the order
+    /// of their appearance in the constructed transaction
+    pub fn remote_input_ids(&self) -> Vec<u64> {
+        self.inputs
+            .keys()
+            .copied()
+            .filter(|serial_id| !self.is_local_serial_id(*serial_id))
+            .collect()
+    }
+
+    /// Adds local input to the constructed transaction
+    pub fn add_input(
+        &mut self,
+        prevtx: Transaction,
+        prevtx_vout: u32,
+        sequence: u32,
+    ) -> Result<TxAddInput, InteractiveTxError> {
+        self.check_not_complete()?;
+        let serial_id = self.next_serial_id;
+        let input = InteractiveInput {
+            prevtx,
+            prevtx_vout,
+            sequence,
+        };
+        self.insert_input(serial_id, input.clone())?;
+        self.next_serial_id += 2;
+        self.reset_complete();
+        Ok(TxAddInput {
+            channel_id: self.channel_id,
+            serial_id,
+            prevtx: input.prevtx,
+            prevtx_vout,
+            sequence,
+        })
+    }
+
+    /// Adds local output to the constructed transaction
+    pub fn add_output(
+        &mut self,
+        sats: u64,
+        script: PubkeyScript,
+    ) -> Result<TxAddOutput, InteractiveTxError> {
+        self.check_not_complete()?;
+        let serial_id = self.next_serial_id;
+        self.insert_output(serial_id, TxOut {
+            value: sats,
+            script_pubkey: script.clone().into(),
+        })?;
+        self.next_serial_id += 2;
+        self.reset_complete();
+        Ok(TxAddOutput {
+            channel_id: self.channel_id,
+            serial_id,
+            sats,
+            script,
+        })
+    }
+
+    /// Removes previously added local input
+    pub fn remove_input(
+        &mut self,
+        serial_id: u64,
+    ) -> Result<TxRemoveInput, InteractiveTxError> {
+        self.check_not_complete()?;
+        if !self.is_local_serial_id(serial_id) {
+            return Err(InteractiveTxError::UnknownSerialId(serial_id));
+        }
+        self.inputs
+            .remove(&serial_id)
+            .ok_or(InteractiveTxError::UnknownSerialId(serial_id))?;
+        self.reset_complete();
+        Ok(TxRemoveInput {
+            channel_id: self.channel_id,
+            serial_id,
+        })
+    }
+
+    /// Removes previously added local output
+    pub fn remove_output(
+        &mut self,
+        serial_id: u64,
+    ) -> Result<TxRemoveOutput, InteractiveTxError> {
+        self.check_not_complete()?;
+        if !self.is_local_serial_id(serial_id) {
+            return Err(InteractiveTxError::UnknownSerialId(serial_id));
+        }
+        self.outputs
+            .remove(&serial_id)
+            .ok_or(InteractiveTxError::UnknownSerialId(serial_id))?;
+        self.reset_complete();
+        Ok(TxRemoveOutput {
+            channel_id: self.channel_id,
+            serial_id,
+        })
+    }
+
+    /// Signals that the local node has no more inputs or outputs to
+    /// contribute
+    pub fn complete(&mut self) -> Result<TxComplete, InteractiveTxError> {
+        self.check_not_complete()?;
+        self.local_complete = true;
+        Ok(TxComplete {
+            channel_id: self.channel_id,
+        })
+    }
+
+    /// Composes `tx_abort` message with the provided reason
+    #[inline]
+    pub fn compose_abort(&self, reason: &str) -> TxAbort {
+        TxAbort {
+            channel_id: self.channel_id,
+            data: reason.as_bytes().to_vec(),
+        }
+    }
+
+    /// Composes `tx_signatures` message containing witnesses for all local
+    /// inputs, ordered by their serial ids
+    pub fn compose_signatures(
+        &self,
+        witnesses: Vec<Witness>,
+    ) -> Result<TxSignatures, InteractiveTxError> {
+        let required = self.local_input_ids().len();
+        if witnesses.len() != required {
+            return Err(InteractiveTxError::WitnessCountMismatch {
+                required,
+                provided: witnesses.len(),
+            });
+        }
+        Ok(TxSignatures {
+            channel_id: self.channel_id,
+            txid: self.to_unsigned_tx()?.txid(),
+            witnesses,
+        })
+    }
+
+    /// Processes interactive transaction construction message received from
+    /// the remote peer. Messages unrelated to the interactive transaction
+    /// construction are ignored.
+    pub fn update_from_peer(
+        &mut self,
+        message: &Messages,
+    ) -> Result<(), InteractiveTxError> {
+        match message {
+            Messages::TxAddInput(tx_add_input) => {
+                self.check_channel_id(tx_add_input.channel_id)?;
+                self.check_not_complete()?;
+                self.check_remote_serial_id(tx_add_input.serial_id)?;
+                self.count_remote_add_message()?;
+                self.insert_input(tx_add_input.serial_id, InteractiveInput {
+                    prevtx: tx_add_input.prevtx.clone(),
+                    prevtx_vout: tx_add_input.prevtx_vout,
+                    sequence: tx_add_input.sequence,
+                })?;
+                self.reset_complete();
+            }
+            Messages::TxAddOutput(tx_add_output) => {
+                self.check_channel_id(tx_add_output.channel_id)?;
+                self.check_not_complete()?;
+                self.check_remote_serial_id(tx_add_output.serial_id)?;
+                self.count_remote_add_message()?;
+                self.insert_output(tx_add_output.serial_id, TxOut {
+                    value: tx_add_output.sats,
+                    script_pubkey: tx_add_output.script.clone().into(),
+                })?;
+                self.reset_complete();
+            }
+            Messages::TxRemoveInput(tx_remove_input) => {
+                let serial_id = tx_remove_input.serial_id;
+                self.check_channel_id(tx_remove_input.channel_id)?;
+                self.check_not_complete()?;
+                self.check_remote_serial_id(serial_id)?;
+                self.inputs
+                    .remove(&serial_id)
+                    .ok_or(InteractiveTxError::UnknownSerialId(serial_id))?;
+                self.reset_complete();
+            }
+            Messages::TxRemoveOutput(tx_remove_output) => {
+                let serial_id = tx_remove_output.serial_id;
+                self.check_channel_id(tx_remove_output.channel_id)?;
+                self.check_not_complete()?;
+                self.check_remote_serial_id(serial_id)?;
+                self.outputs
+                    .remove(&serial_id)
+                    .ok_or(InteractiveTxError::UnknownSerialId(serial_id))?;
+                self.reset_complete();
+            }
+            Messages::TxComplete(tx_complete) => {
+                self.check_channel_id(tx_complete.channel_id)?;
+                self.check_not_complete()?;
+                self.remote_complete = true;
+            }
+            Messages::TxSignatures(tx_signatures) => {
+                self.check_channel_id(tx_signatures.channel_id)?;
+                if !self.is_complete() {
+                    return Err(InteractiveTxError::NotComplete);
+                }
+                let expected = self.to_unsigned_tx()?.txid();
+                if tx_signatures.txid != expected {
+                    return Err(InteractiveTxError::TxidMismatch {
+                        expected,
+                        received: tx_signatures.txid,
+                    });
+                }
+                let required = self.remote_input_ids().len();
+                if tx_signatures.witnesses.len() != required {
+                    return Err(InteractiveTxError::WitnessCountMismatch {
+                        required,
+                        provided: tx_signatures.witnesses.len(),
+                    });
+                }
+                self.remote_witnesses = Some(tx_signatures.witnesses.clone());
+            }
+            Messages::TxAbort(tx_abort) => {
+                self.check_channel_id(tx_abort.channel_id)?;
+                return Err(InteractiveTxError::Aborted(
+                    String::from_utf8_lossy(&tx_abort.data).to_string(),
+                ));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Constructs unsigned transaction from the negotiated inputs and outputs
+    /// ordered by their serial ids.
+    ///
+    /// Fails if the negotiation is not complete yet.
+    pub fn to_unsigned_tx(&self) -> Result<Transaction, InteractiveTxError> {
+        if !self.is_complete() {
+            return Err(InteractiveTxError::NotComplete);
+        }
+        Ok(Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(self.locktime),
+            input: self
+                .inputs
+                .values()
+                .map(|input| TxIn {
+                    previous_output: input.prevout(),
+                    script_sig: none!(),
+                    sequence: bitcoin::Sequence(input.sequence),
+                    witness: empty!(),
+                })
+                .collect(),
+            output: self.outputs.values().cloned().collect(),
+        })
+    }
+
+    /// Exports the constructed transaction as a PSBT with the channel funding
+    /// output marked, which can be used for setting channel funding.
+    ///
+    /// If the remote peer has already provided witnesses for its inputs, they
+    /// are added to the PSBT as final witnesses.
+    pub fn to_psbt(&self) -> Result<Psbt, InteractiveTxError> {
+        let tx = self.to_unsigned_tx()?;
+        let funding_vout = tx
+            .output
+            .iter()
+            .position(|txout| {
+                txout.script_pubkey == *self.funding_script.as_inner()
+            })
+            .ok_or(InteractiveTxError::NoFundingOutput)?;
+
+        let mut psbt = Psbt::with(tx, PsbtVersion::V0)
+            .expect("unsigned transaction is always a valid PSBT source");
+        for (input, interactive_input) in
+            psbt.inputs.iter_mut().zip(self.inputs.values())
+        {
+            input.witness_utxo = Some(interactive_input.prev_txout().clone());
+            input.non_witness_utxo = Some(interactive_input.prevtx.clone());
+        }
+        if let Some(ref witnesses) = self.remote_witnesses {
+            let remote_ids = self.remote_input_ids();
+            for (index, serial_id) in self.inputs.keys().enumerate() {
+                if let Some(pos) =
+                    remote_ids.iter().position(|id| id == serial_id)
+                {
+                    psbt.inputs[index].final_script_witness =
+                        Some(witnesses[pos].clone());
+                }
+            }
+        }
+        psbt.set_channel_funding_output(funding_vout as u16)
+            .map_err(|_| InteractiveTxError::NoFundingOutput)?;
+        Ok(psbt)
+    }
+}
+
+impl InteractiveTx {
+    #[inline]
+    fn is_local_serial_id(&self, serial_id: u64) -> bool {
+        (serial_id % 2 == 0) == self.is_initiator
+    }
+
+    #[inline]
+    fn reset_complete(&mut self) {
+        self.local_complete = false;
+        self.remote_complete = false;
+    }
+
+    fn check_channel_id(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<(), InteractiveTxError> {
+        if channel_id != self.channel_id {
+            return Err(InteractiveTxError::ChannelMismatch {
+                expected: self.channel_id,
+                received: channel_id,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_not_complete(&self) -> Result<(), InteractiveTxError> {
+        if self.is_complete() {
+            return Err(InteractiveTxError::AlreadyComplete);
+        }
+        Ok(())
+    }
+
+    fn check_remote_serial_id(
+        &self,
+        serial_id: u64,
+    ) -> Result<(), InteractiveTxError> {
+        if self.is_local_serial_id(serial_id) {
+            return Err(InteractiveTxError::SerialIdParity(serial_id));
+        }
+        Ok(())
+    }
+
+    fn count_remote_add_message(&mut self) -> Result<(), InteractiveTxError> {
+        self.remote_add_messages += 1;
+        if self.remote_add_messages > INTERACTIVE_TX_MAX_ADD_MESSAGES {
+            return Err(InteractiveTxError::TooManyMessages);
+        }
+        Ok(())
+    }
+
+    fn insert_input(
+        &mut self,
+        serial_id: u64,
+        input: InteractiveInput,
+    ) -> Result<(), InteractiveTxError> {
+        if self.inputs.contains_key(&serial_id) {
+            return Err(InteractiveTxError::DuplicateSerialId(serial_id));
+        }
+        if self.inputs.len() >= INTERACTIVE_TX_MAX_INPUTS_OUTPUTS {
+            return Err(InteractiveTxError::TooManyInputs);
+        }
+        let prevout = input.prevout();
+        let txout = input.prevtx.output.get(input.prevtx_vout as usize).ok_or(
+            InteractiveTxError::NoPrevout {
+                txid: prevout.txid,
+                vout: prevout.vout,
+            },
+        )?;
+        if !txout.script_pubkey.is_witness_program() {
+            return Err(InteractiveTxError::NonSegwitInput(prevout));
+        }
+        if input.sequence > INTERACTIVE_TX_MAX_SEQUENCE {
+            return Err(InteractiveTxError::NonReplaceableInput(
+                input.sequence,
+            ));
+        }
+        if self.inputs.values().any(|other| other.prevout() == prevout) {
+            return Err(InteractiveTxError::DuplicateInput(prevout));
+        }
+        self.inputs.insert(serial_id, input);
+        Ok(())
+    }
+
+    fn insert_output(
+        &mut self,
+        serial_id: u64,
+        txout: TxOut,
+    ) -> Result<(), InteractiveTxError> {
+        if self.outputs.contains_key(&serial_id) {
+            return Err(InteractiveTxError::DuplicateSerialId(serial_id));
+        }
+        if self.outputs.len() >= INTERACTIVE_TX_MAX_INPUTS_OUTPUTS {
+            return Err(InteractiveTxError::TooManyOutputs);
+        }
+        self.outputs.insert(serial_id, txout);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{PackedLockTime, Script, WPubkeyHash, WScriptHash};
+
+    use super::*;
+
+    fn prevtx(seed: u8) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime(seed as u32),
+            input: vec![],
+            output: vec![TxOut {
+                value: 1_000_000,
+                script_pubkey: Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros()),
+            }],
+        }
+    }
+
+    fn funding_script() -> PubkeyScript {
+        Script::new_v0_p2wsh(&WScriptHash::all_zeros()).into()
+    }
+
+    fn pair() -> (InteractiveTx, InteractiveTx) {
+        let channel_id = ChannelId::with(Txid::all_zeros(), 0);
+        (
+            InteractiveTx::new(channel_id, true, 100, funding_script()),
+            InteractiveTx::new(channel_id, false, 100, funding_script()),
+        )
+    }
+
+    #[test]
+    fn two_party_negotiation() {
+        let (mut alice, mut bob) = pair();
+
+        let msg = alice.add_input(prevtx(1), 0, 0xFFFF_FFFD).unwrap();
+        bob.update_from_peer(&Messages::TxAddInput(msg)).unwrap();
+        let msg = bob.add_input(prevtx(2), 0, 0xFFFF_FFFD).unwrap();
+        alice.update_from_peer(&Messages::TxAddInput(msg)).unwrap();
+        let msg = alice.add_output(1_500_000, funding_script()).unwrap();
+        bob.update_from_peer(&Messages::TxAddOutput(msg)).unwrap();
+        let change: PubkeyScript =
+            Script::new_v0_p2wpkh(&WPubkeyHash::all_zeros()).into();
+        let msg = bob.add_output(499_000, change).unwrap();
+        alice.update_from_peer(&Messages::TxAddOutput(msg)).unwrap();
+
+        let msg = alice.complete().unwrap();
+        bob.update_from_peer(&Messages::TxComplete(msg)).unwrap();
+        assert!(!bob.is_complete());
+        let msg = bob.complete().unwrap();
+        alice.update_from_peer(&Messages::TxComplete(msg)).unwrap();
+        assert!(alice.is_complete());
+        assert!(bob.is_complete());
+
+        let psbt = alice.to_psbt().unwrap();
+        assert_eq!(psbt, bob.to_psbt().unwrap());
+        assert_eq!(psbt.channel_funding_output(), Some(0));
+        assert_eq!(psbt.inputs.len(), 2);
+        assert_eq!(alice.local_input_ids(), vec![0]);
+        assert_eq!(bob.local_input_ids(), vec![1]);
+
+        let witness = Witness::from_vec(vec![vec![0x30; 71], vec![0x02; 33]]);
+        let msg = bob.compose_signatures(vec![witness.clone()]).unwrap();
+        alice
+            .update_from_peer(&Messages::TxSignatures(msg))
+            .unwrap();
+        let psbt = alice.to_psbt().unwrap();
+        assert_eq!(psbt.inputs[1].final_script_witness, Some(witness));
+        assert_eq!(psbt.inputs[0].final_script_witness, None);
+    }
+
+    #[test]
+    fn serial_id_parity() {
+        let (mut alice, mut bob) = pair();
+        let msg = bob.add_input(prevtx(1), 0, 0).unwrap();
+        assert_eq!(
+            bob.update_from_peer(&Messages::TxAddInput(msg.clone())),
+            Err(InteractiveTxError::SerialIdParity(1))
+        );
+        alice
+            .update_from_peer(&Messages::TxAddInput(msg.clone()))
+            .unwrap();
+        assert_eq!(
+            alice.update_from_peer(&Messages::TxAddInput(msg)),
+            Err(InteractiveTxError::DuplicateSerialId(1))
+        );
+    }
+
+    #[test]
+    fn input_validation() {
+        let (mut alice, _) = pair();
+        alice.add_input(prevtx(1), 0, 0).unwrap();
+        assert_eq!(
+            alice.add_input(prevtx(1), 0, 0),
+            Err(InteractiveTxError::DuplicateInput(OutPoint::new(
+                prevtx(1).txid(),
+                0
+            )))
+        );
+        assert_eq!(
+            alice.add_input(prevtx(2), 0, 0xFFFF_FFFF),
+            Err(InteractiveTxError::NonReplaceableInput(0xFFFF_FFFF))
+        );
+        assert_eq!(
+            alice.add_input(prevtx(2), 1, 0),
+            Err(InteractiveTxError::NoPrevout {
+                txid: prevtx(2).txid(),
+                vout: 1
+            })
+        );
+        let mut legacy = prevtx(3);
+        legacy.output[0].script_pubkey = Script::new();
+        assert_eq!(
+            alice.add_input(legacy.clone(), 0, 0),
+            Err(InteractiveTxError::NonSegwitInput(OutPoint::new(
+                legacy.txid(),
+                0
+            )))
+        );
+    }
+}
@@ -16,12 +16,98 @@ use std::collections::BTreeMap;
 use amplify::DumbDefault;
 #[cfg(feature = "serde")]
 use amplify::ToYamlString;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
 use bitcoin::util::bip32::{ChildNumber, ExtendedPrivKey, KeySource};
 use bitcoin_scripts::PubkeyScript;
 use p2p::bolt::{AcceptChannel, ChannelType, OpenChannel};
 use secp256k1::{PublicKey, Secp256k1, SecretKey};
 use wallet::hd::HardenedIndex;
 
+/// Derives per-commitment public key from a basepoint according to BOLT-3:
+///
+/// `pubkey = basepoint + SHA256(per_commitment_point || basepoint) * G`
+///
+/// Used for `localpubkey`, `remotepubkey`, `local_htlcpubkey`,
+/// `remote_htlcpubkey`, `local_delayedpubkey` and `remote_delayedpubkey`.
+pub fn derive_pubkey(
+    basepoint: PublicKey,
+    per_commitment_point: PublicKey,
+) -> PublicKey {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&per_commitment_point.serialize());
+    engine.input(&basepoint.serialize());
+    let tweak = sha256::Hash::from_engine(engine);
+    let tweak = secp256k1::Scalar::from_be_bytes(tweak.into_inner())
+        .expect("negligible probability");
+
+    basepoint
+        .add_exp_tweak(secp256k1::SECP256K1, &tweak)
+        .expect("negligible probability")
+}
+
+/// Derives per-commitment private key from a basepoint secret according to
+/// BOLT-3:
+///
+/// `privkey = basepoint_secret + SHA256(per_commitment_point || basepoint)`
+///
+/// This is a counterpart of [`derive_pubkey`], used for signing spendings of
+/// the outputs locked to the per-commitment keys.
+pub fn derive_privkey(
+    basepoint_secret: SecretKey,
+    per_commitment_point: PublicKey,
+) -> SecretKey {
+    let basepoint =
+        PublicKey::from_secret_key(secp256k1::SECP256K1, &basepoint_secret);
+    let mut engine = sha256::Hash::engine();
+    engine.input(&per_commitment_point.serialize());
+    engine.input(&basepoint.serialize());
+    let tweak = sha256::Hash::from_engine(engine);
+    let tweak = secp256k1::Scalar::from_be_bytes(tweak.into_inner())
+        .expect("negligible probability");
+
+    basepoint_secret
+        .add_tweak(&tweak)
+        .expect("negligible probability")
+}
+
+/// Derives per-commitment revocation public key according to BOLT-3:
+///
+/// `revocationpubkey = revocation_basepoint * SHA256(revocation_basepoint ||
+/// per_commitment_point) + per_commitment_point *
+/// SHA256(per_commitment_point || revocation_basepoint)`
+pub fn derive_revocationpubkey(
+    revocation_basepoint: PublicKey,
+    per_commitment_point: PublicKey,
+) -> PublicKey {
+    let secp = secp256k1::SECP256K1;
+
+    let mut engine = sha256::Hash::engine();
+    engine.input(&revocation_basepoint.serialize());
+    engine.input(&per_commitment_point.serialize());
+    let revocation_tweak = sha256::Hash::from_engine(engine);
+    let revocation_tweak =
+        secp256k1::Scalar::from_be_bytes(revocation_tweak.into_inner())
+            .expect("negligible probability");
+    let tweaked_revocation_basepoint = revocation_basepoint
+        .mul_tweak(secp, &revocation_tweak)
+        .expect("negligible probability");
+
+    let mut engine = sha256::Hash::engine();
+    engine.input(&per_commitment_point.serialize());
+    engine.input(&revocation_basepoint.serialize());
+    let per_commitment_tweak = sha256::Hash::from_engine(engine);
+    let per_commitment_tweak =
+        secp256k1::Scalar::from_be_bytes(per_commitment_tweak.into_inner())
+            .expect("negligible probability");
+    let tweaked_per_commitment_point = per_commitment_point
+        .mul_tweak(secp, &per_commitment_tweak)
+        .expect("negligible probability");
+
+    tweaked_revocation_basepoint
+        .combine(&tweaked_per_commitment_point)
+        .expect("negligible probability")
+}
+
 /// Key + information about its derivation
 #[derive(Clone, PartialEq, Eq, Debug, StrictEncode, StrictDecode)]
 #[cfg_attr(
@@ -132,7 +218,10 @@ impl From<&OpenChannel> for RemoteKeyset {
             htlc_basepoint: open_channel.htlc_basepoint,
             first_per_commitment_point: open_channel.first_per_commitment_point,
             shutdown_scriptpubkey: open_channel.shutdown_scriptpubkey.clone(),
-            static_remotekey: false,
+            static_remotekey: open_channel
+                .channel_type
+                .map(ChannelType::has_static_remotekey)
+                .unwrap_or_default(),
         }
     }
 }
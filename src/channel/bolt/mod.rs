@@ -11,17 +11,53 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
+mod acceptor;
+mod adaptor;
 mod keyset;
+mod musig2;
 mod policy;
+mod policy_book;
+mod splice;
 mod state;
+mod taproot;
 mod util;
 
 mod channel;
 mod extensions;
+mod interactive_tx;
 
+pub use acceptor::{
+    AcceptorDecision, AcceptorHook, ChannelAcceptor, ChannelProposal,
+    ParamsOverride,
+};
+pub use adaptor::{AdaptorError, SchnorrAdaptor};
 pub use channel::{BoltChannel, Direction, Error, ScriptGenerators};
-pub use extensions::{AnchorOutputs, Htlc, HtlcKnown, HtlcSecret};
-pub use keyset::{LocalKeyset, LocalPubkey, RemoteKeyset};
-pub use policy::{CommonParams, PeerParams, Policy, PolicyError};
+pub use extensions::{
+    asset_tweak, attestation_scalar, is_valid_shutdown_script, AnchorOutputs,
+    CommitmentKeys, CommitmentPolicy, CommitmentPolicyError, Dlc, DlcContract,
+    DlcError, Htlc, HtlcKnown, HtlcSecret, Offerer, Ptlc, PtlcKnown,
+    PtlcLeaves, PtlcScriptGenerators, PtlcSecret, Rgb, RgbError,
+    ShutdownScript, ShutdownScriptError,
+};
+pub use interactive_tx::{
+    InteractiveInput, InteractiveTx, InteractiveTxError,
+    INTERACTIVE_TX_MAX_ADD_MESSAGES, INTERACTIVE_TX_MAX_INPUTS_OUTPUTS,
+    INTERACTIVE_TX_MAX_SEQUENCE,
+};
+pub use keyset::{
+    derive_pubkey, derive_revocationpubkey, LocalKeyset, LocalPubkey,
+    RemoteKeyset,
+};
+pub use musig2::{KeyAggContext, MuSig2Error, SecretNonce};
+pub use policy::{
+    dual_funded_reserve, CommonParams, DustExposure, PeerParams, Policy,
+    PolicyError, PolicyReport,
+};
+pub use policy_book::{PolicyBook, PolicyConfigError, PolicyOverride};
+pub use splice::{Quiescence, SpliceContributions, SpliceError, SpliceParams};
 pub use state::ChannelState;
+pub use taproot::{
+    funding_key_agg, nums_point, TaprootLeaves, TaprootScriptGenerators,
+    NUMS_POINT,
+};
 pub use util::{AssetsBalance, BoltExt, Lifecycle, TxType};
@@ -0,0 +1,698 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! MuSig2 multi-signatures according to BIP-327, used by simple taproot
+//! channels for the key-path spendings of the 2-of-2 funding output.
+
+use std::fmt::{self, Debug, Formatter};
+
+use amplify::Wrapper;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::util::taproot::{TapBranchHash, TapTweakHash};
+use p2p::bolt::{PartialSignature, PublicNonce};
+use secp256k1::constants::CURVE_ORDER;
+use secp256k1::{
+    schnorr, Parity, PublicKey, Scalar, SecretKey, XOnlyPublicKey, SECP256K1,
+};
+
+/// Errors happening during MuSig2 key aggregation or signing
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum MuSig2Error {
+    /// no public keys were provided for the MuSig2 key aggregation
+    NoKeys,
+
+    /// the public key {0} does not participate in the MuSig2 key aggregation
+    UnknownSigner(PublicKey),
+
+    /// MuSig2 public nonce is not a valid pair of curve points
+    InvalidNonce,
+
+    /// MuSig2 partial signature from {0} is invalid
+    InvalidPartialSig(PublicKey),
+
+    /// MuSig2 computation has produced zero scalar or point at infinity
+    InvalidScalar,
+}
+
+/// Context of the MuSig2 key aggregation, holding all data required for
+/// producing and verifying partial signatures and aggregating them.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyAggContext {
+    /// Signer public keys in the order of aggregation
+    keys: Vec<PublicKey>,
+
+    /// Hash of all signer public keys (`L` in BIP-327)
+    keys_hash: [u8; 32],
+
+    /// The first of the keys which differs from the first key in the list
+    second_key: Option<PublicKey>,
+
+    /// Aggregated (and possibly tweaked) public key (`Q` in BIP-327)
+    aggregated_key: PublicKey,
+
+    /// Whether the accumulated sign factor (`gacc` in BIP-327) is negative
+    gacc_negated: bool,
+
+    /// Accumulated tweak (`tacc` in BIP-327); `None` represents zero
+    tacc: Option<SecretKey>,
+}
+
+impl KeyAggContext {
+    /// Aggregates public keys into a single MuSig2 public key. The keys are
+    /// sorted before aggregation, so their order does not matter.
+    pub fn new(
+        keys: impl IntoIterator<Item = PublicKey>,
+    ) -> Result<KeyAggContext, MuSig2Error> {
+        let mut keys = keys.into_iter().collect::<Vec<_>>();
+        keys.sort_by_key(PublicKey::serialize);
+        KeyAggContext::with_ordered_keys(keys)
+    }
+
+    /// Aggregates public keys in the provided order (`KeyAgg` algorithm of
+    /// BIP-327 without `KeySort`)
+    fn with_ordered_keys(
+        keys: Vec<PublicKey>,
+    ) -> Result<KeyAggContext, MuSig2Error> {
+        let first_key = *keys.first().ok_or(MuSig2Error::NoKeys)?;
+        let second_key = keys.iter().find(|pk| **pk != first_key).copied();
+
+        let key_list = keys
+            .iter()
+            .flat_map(PublicKey::serialize)
+            .collect::<Vec<_>>();
+        let keys_hash = tagged_hash("KeyAgg list", &[&key_list]);
+
+        let mut ctx = KeyAggContext {
+            keys,
+            keys_hash,
+            second_key,
+            aggregated_key: first_key,
+            gacc_negated: false,
+            tacc: None,
+        };
+        let points = ctx
+            .keys
+            .iter()
+            .map(|pk| {
+                pk.mul_tweak(SECP256K1, &Scalar::from(ctx.coefficient(*pk)?))
+                    .map_err(|_| MuSig2Error::InvalidScalar)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        ctx.aggregated_key =
+            PublicKey::combine_keys(&points.iter().collect::<Vec<_>>())
+                .map_err(|_| MuSig2Error::InvalidScalar)?;
+        Ok(ctx)
+    }
+
+    /// Applies BIP-341 taproot tweak to the aggregated key, such that the
+    /// aggregated signature will be valid for the taproot output key with
+    /// the aggregated key used as an internal key. If `merkle_root` is `None`
+    /// the tweak is done according to BIP-86.
+    pub fn with_taproot_tweak(
+        mut self,
+        merkle_root: Option<TapBranchHash>,
+    ) -> Result<KeyAggContext, MuSig2Error> {
+        let internal_key = self.x_only_key();
+        let tweak = TapTweakHash::from_key_and_tweak(internal_key, merkle_root)
+            .to_scalar();
+        let tweak = SecretKey::from_slice(&tweak.to_be_bytes())
+            .map_err(|_| MuSig2Error::InvalidScalar)?;
+
+        // x-only tweaking requires the key to have even Y coordinate
+        if !has_even_y(self.aggregated_key) {
+            self.aggregated_key = self.aggregated_key.negate(SECP256K1);
+            self.gacc_negated = !self.gacc_negated;
+            self.tacc = self.tacc.map(SecretKey::negate);
+        }
+        self.aggregated_key = self
+            .aggregated_key
+            .add_exp_tweak(SECP256K1, &Scalar::from(tweak))
+            .map_err(|_| MuSig2Error::InvalidScalar)?;
+        self.tacc = Some(match self.tacc {
+            Some(tacc) => add(tacc, tweak)?,
+            None => tweak,
+        });
+        Ok(self)
+    }
+
+    /// Returns aggregated public key
+    #[inline]
+    pub fn aggregated_key(&self) -> PublicKey {
+        self.aggregated_key
+    }
+
+    /// Returns x-only version of the aggregated public key, which is used
+    /// in taproot outputs
+    #[inline]
+    pub fn x_only_key(&self) -> XOnlyPublicKey {
+        self.aggregated_key.x_only_public_key().0
+    }
+
+    /// Returns signer public keys in the order they were aggregated
+    #[inline]
+    pub fn keys(&self) -> &[PublicKey] {
+        &self.keys
+    }
+
+    /// Produces partial signature for the `msg` with the `secret_key` of one
+    /// of the signers. The secret nonce is consumed, since its reuse leaks
+    /// the secret key; `nonces` must contain public nonces of all signers,
+    /// including the local one.
+    pub fn partial_sign(
+        &self,
+        secret_nonce: SecretNonce,
+        secret_key: SecretKey,
+        nonces: &[PublicNonce],
+        msg: [u8; 32],
+    ) -> Result<PartialSignature, MuSig2Error> {
+        let pubkey = PublicKey::from_secret_key(SECP256K1, &secret_key);
+        if pubkey != secret_nonce.pubkey {
+            return Err(MuSig2Error::UnknownSigner(pubkey));
+        }
+        let session = self.session(nonces, msg)?;
+
+        let (mut k1, mut k2) = (secret_nonce.k1, secret_nonce.k2);
+        if !has_even_y(session.nonce) {
+            k1 = k1.negate();
+            k2 = k2.negate();
+        }
+        let mut d = secret_key;
+        if self.sign_negated() {
+            d = d.negate();
+        }
+        let a = self.coefficient(pubkey)?;
+
+        let s = add(k1, mul(session.b, k2))?;
+        let s = add(s, mul(mul(session.e, a), d))?;
+        Ok(PartialSignature::from_inner(s.secret_bytes()))
+    }
+
+    /// Verifies partial signature produced by the signer with the given
+    /// `pubkey` using `nonce`
+    pub fn partial_verify(
+        &self,
+        partial_sig: PartialSignature,
+        nonce: PublicNonce,
+        pubkey: PublicKey,
+        nonces: &[PublicNonce],
+        msg: [u8; 32],
+    ) -> Result<(), MuSig2Error> {
+        if !self.keys.contains(&pubkey) {
+            return Err(MuSig2Error::UnknownSigner(pubkey));
+        }
+        let session = self.session(nonces, msg)?;
+        let s = SecretKey::from_slice(partial_sig.as_inner())
+            .map_err(|_| MuSig2Error::InvalidPartialSig(pubkey))?;
+
+        let (r1, r2) = nonce_points(nonce)?;
+        let r2 = r2
+            .mul_tweak(SECP256K1, &Scalar::from(session.b))
+            .map_err(|_| MuSig2Error::InvalidScalar)?;
+        let mut re = r1.combine(&r2).map_err(|_| MuSig2Error::InvalidScalar)?;
+        if !has_even_y(session.nonce) {
+            re = re.negate(SECP256K1);
+        }
+        let ea = mul(session.e, self.coefficient(pubkey)?);
+        let mut p = pubkey
+            .mul_tweak(SECP256K1, &Scalar::from(ea))
+            .map_err(|_| MuSig2Error::InvalidScalar)?;
+        if self.sign_negated() {
+            p = p.negate(SECP256K1);
+        }
+        let expected =
+            re.combine(&p).map_err(|_| MuSig2Error::InvalidScalar)?;
+
+        if PublicKey::from_secret_key(SECP256K1, &s) != expected {
+            return Err(MuSig2Error::InvalidPartialSig(pubkey));
+        }
+        Ok(())
+    }
+
+    /// Aggregates partial signatures from all signers into a BIP-340 Schnorr
+    /// signature valid for the aggregated key
+    pub fn aggregate(
+        &self,
+        partial_sigs: &[PartialSignature],
+        nonces: &[PublicNonce],
+        msg: [u8; 32],
+    ) -> Result<schnorr::Signature, MuSig2Error> {
+        let session = self.session(nonces, msg)?;
+
+        let mut s = match self.tacc {
+            Some(tacc) if has_even_y(self.aggregated_key) => {
+                Some(mul(session.e, tacc))
+            }
+            Some(tacc) => Some(mul(session.e, tacc).negate()),
+            None => None,
+        };
+        for partial_sig in partial_sigs {
+            let s_i = SecretKey::from_slice(partial_sig.as_inner())
+                .map_err(|_| MuSig2Error::InvalidScalar)?;
+            s = Some(match s {
+                Some(s) => add(s, s_i)?,
+                None => s_i,
+            });
+        }
+        let s = s.ok_or(MuSig2Error::InvalidScalar)?;
+
+        let mut sig = [0u8; 64];
+        sig[..32]
+            .copy_from_slice(&session.nonce.x_only_public_key().0.serialize());
+        sig[32..].copy_from_slice(&s.secret_bytes());
+        schnorr::Signature::from_slice(&sig)
+            .map_err(|_| MuSig2Error::InvalidScalar)
+    }
+
+    /// Computes key aggregation coefficient for the signer key
+    fn coefficient(&self, pubkey: PublicKey) -> Result<SecretKey, MuSig2Error> {
+        if Some(pubkey) == self.second_key {
+            return Ok(SecretKey::from_slice(&Scalar::ONE.to_be_bytes())
+                .expect("one is a valid secret key"));
+        }
+        hash_to_scalar(tagged_hash("KeyAgg coefficient", &[
+            &self.keys_hash,
+            &pubkey.serialize(),
+        ]))
+    }
+
+    /// Detects whether the signing keys must be negated, which happens when
+    /// the aggregated key has odd Y coordinate or if it was negated during
+    /// the tweaking
+    fn sign_negated(&self) -> bool {
+        !has_even_y(self.aggregated_key) ^ self.gacc_negated
+    }
+
+    fn session(
+        &self,
+        nonces: &[PublicNonce],
+        msg: [u8; 32],
+    ) -> Result<Session, MuSig2Error> {
+        let (r1, r2) = aggregate_nonces(nonces)?;
+        let agg_pk = self.x_only_key().serialize();
+
+        let b = hash_to_scalar(tagged_hash("MuSig/noncecoef", &[
+            &r1.serialize(),
+            &r2.serialize(),
+            &agg_pk,
+            &msg,
+        ]))?;
+        let r2 = r2
+            .mul_tweak(SECP256K1, &Scalar::from(b))
+            .map_err(|_| MuSig2Error::InvalidScalar)?;
+        let nonce = r1.combine(&r2).map_err(|_| MuSig2Error::InvalidScalar)?;
+        let e = hash_to_scalar(tagged_hash("BIP0340/challenge", &[
+            &nonce.x_only_public_key().0.serialize(),
+            &agg_pk,
+            &msg,
+        ]))?;
+
+        Ok(Session { b, nonce, e })
+    }
+}
+
+/// Values shared by all signers for a given message and set of nonces
+struct Session {
+    /// Nonce coefficient
+    b: SecretKey,
+    /// Final nonce point
+    nonce: PublicKey,
+    /// Schnorr signature challenge
+    e: SecretKey,
+}
+
+/// Secret MuSig2 nonce, which must be used for a single signature only and
+/// never persisted. For this reason the nonce can't be cloned or encoded.
+#[derive(PartialEq, Eq, Debug)]
+pub struct SecretNonce {
+    k1: SecretKey,
+    k2: SecretKey,
+    pubkey: PublicKey,
+}
+
+impl SecretNonce {
+    /// Generates secret nonce for the signer `pubkey` from a fresh 32-byte
+    /// randomness, which must not be reused. The aggregated key `agg_key` is
+    /// optional, since it may be not known yet at the time of nonce
+    /// generation (like for the nonces sent in `open_channel`).
+    pub fn generate(
+        entropy: [u8; 32],
+        pubkey: PublicKey,
+        agg_key: Option<XOnlyPublicKey>,
+    ) -> Result<SecretNonce, MuSig2Error> {
+        let agg_key = agg_key
+            .as_ref()
+            .map(XOnlyPublicKey::serialize)
+            .map(Vec::from)
+            .unwrap_or_default();
+        let k = |i: u8| {
+            hash_to_scalar(tagged_hash("MuSig/nonce", &[
+                &entropy,
+                &[33],
+                &pubkey.serialize(),
+                &[agg_key.len() as u8],
+                &agg_key,
+                // message is not known at the nonce generation time
+                &[0],
+                // no extra input
+                &[0, 0, 0, 0],
+                &[i],
+            ]))
+        };
+        Ok(SecretNonce {
+            k1: k(0)?,
+            k2: k(1)?,
+            pubkey,
+        })
+    }
+
+    /// Returns public nonce corresponding to this secret nonce
+    pub fn public_nonce(&self) -> PublicNonce {
+        let mut nonce = [0u8; 66];
+        nonce[..33].copy_from_slice(
+            &PublicKey::from_secret_key(SECP256K1, &self.k1).serialize(),
+        );
+        nonce[33..].copy_from_slice(
+            &PublicKey::from_secret_key(SECP256K1, &self.k2).serialize(),
+        );
+        PublicNonce::from_inner(nonce)
+    }
+}
+
+/// Slot for the secret nonce of the local node, kept between sending its
+/// public part to the remote peer and signing. The slot is never persisted and
+/// its clones are always empty, such that the nonce can't be used twice.
+#[derive(Default)]
+pub(super) struct NonceSlot(Option<SecretNonce>);
+
+impl NonceSlot {
+    /// Puts a new nonce into the slot, dropping the previous one
+    #[inline]
+    pub fn put(&mut self, nonce: SecretNonce) {
+        self.0 = Some(nonce)
+    }
+
+    /// Takes the nonce out of the slot, leaving it empty
+    #[inline]
+    pub fn take(&mut self) -> Option<SecretNonce> {
+        self.0.take()
+    }
+}
+
+impl Clone for NonceSlot {
+    #[inline]
+    fn clone(&self) -> Self {
+        NonceSlot(None)
+    }
+}
+
+impl PartialEq for NonceSlot {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_ref().map(SecretNonce::public_nonce)
+            == other.0.as_ref().map(SecretNonce::public_nonce)
+    }
+}
+
+impl Eq for NonceSlot {}
+
+impl Debug for NonceSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NonceSlot")
+            .field(&self.0.as_ref().map(SecretNonce::public_nonce))
+            .finish()
+    }
+}
+
+fn nonce_points(
+    nonce: PublicNonce,
+) -> Result<(PublicKey, PublicKey), MuSig2Error> {
+    let nonce = nonce.as_inner();
+    let r1 = PublicKey::from_slice(&nonce[..33])
+        .map_err(|_| MuSig2Error::InvalidNonce)?;
+    let r2 = PublicKey::from_slice(&nonce[33..])
+        .map_err(|_| MuSig2Error::InvalidNonce)?;
+    Ok((r1, r2))
+}
+
+fn aggregate_nonces(
+    nonces: &[PublicNonce],
+) -> Result<(PublicKey, PublicKey), MuSig2Error> {
+    let points = nonces
+        .iter()
+        .copied()
+        .map(nonce_points)
+        .collect::<Result<Vec<_>, _>>()?;
+    let r1 = points.iter().map(|(r1, _)| r1).collect::<Vec<_>>();
+    let r2 = points.iter().map(|(_, r2)| r2).collect::<Vec<_>>();
+    Ok((
+        PublicKey::combine_keys(&r1).map_err(|_| MuSig2Error::InvalidNonce)?,
+        PublicKey::combine_keys(&r2).map_err(|_| MuSig2Error::InvalidNonce)?,
+    ))
+}
+
+pub(super) fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+/// Converts hash value into a scalar reducing it modulo the curve order, as
+/// required by BIP-327. Since the hash is less than twice the curve order, a
+/// single subtraction is enough for the reduction. Zero scalar can't be
+/// represented by [`SecretKey`] and fails with [`MuSig2Error::InvalidScalar`].
+fn hash_to_scalar(mut hash: [u8; 32]) -> Result<SecretKey, MuSig2Error> {
+    // Big-endian byte arrays of the same length are ordered numerically
+    if hash >= CURVE_ORDER {
+        let mut borrow = false;
+        for (byte, order) in hash.iter_mut().zip(CURVE_ORDER).rev() {
+            let (value, overflow1) = byte.overflowing_sub(order);
+            let (value, overflow2) = value.overflowing_sub(borrow as u8);
+            *byte = value;
+            borrow = overflow1 || overflow2;
+        }
+    }
+    SecretKey::from_slice(&hash).map_err(|_| MuSig2Error::InvalidScalar)
+}
+
+pub(super) fn has_even_y(pubkey: PublicKey) -> bool {
+    pubkey.x_only_public_key().1 == Parity::Even
+}
+
+fn add(a: SecretKey, b: SecretKey) -> Result<SecretKey, MuSig2Error> {
+    a.add_tweak(&Scalar::from(b))
+        .map_err(|_| MuSig2Error::InvalidScalar)
+}
+
+fn mul(a: SecretKey, b: SecretKey) -> SecretKey {
+    a.mul_tweak(&Scalar::from(b))
+        .expect("product of non-zero scalars modulo prime order is non-zero")
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use amplify::hex::{FromHex, ToHex};
+    use bitcoin::hashes::sha256;
+
+    use super::*;
+
+    fn signers() -> (SecretKey, SecretKey) {
+        (
+            SecretKey::from_slice(&[0x11; 32]).unwrap(),
+            SecretKey::from_slice(&[0x22; 32]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn hash_reduction() {
+        let scalar = |n: u8| {
+            let mut bytes = [0u8; 32];
+            bytes[31] = n;
+            SecretKey::from_slice(&bytes).unwrap()
+        };
+        assert_eq!(hash_to_scalar([0u8; 32]), Err(MuSig2Error::InvalidScalar));
+        assert_eq!(hash_to_scalar(scalar(5).secret_bytes()), Ok(scalar(5)));
+
+        let mut hash = CURVE_ORDER;
+        assert_eq!(hash_to_scalar(hash), Err(MuSig2Error::InvalidScalar));
+        hash[31] += 5;
+        assert_eq!(hash_to_scalar(hash), Ok(scalar(5)));
+
+        // 2^256 - 1 - n
+        let reduced = SecretKey::from_str(
+            "000000000000000000000000000000014551231950b75fc4402da1732fc9bebe",
+        )
+        .unwrap();
+        assert_eq!(hash_to_scalar([0xFF; 32]), Ok(reduced));
+    }
+
+    #[test]
+    fn aggregated_signature() {
+        let (sk1, sk2) = signers();
+        let pk1 = PublicKey::from_secret_key(SECP256K1, &sk1);
+        let pk2 = PublicKey::from_secret_key(SECP256K1, &sk2);
+        assert_eq!(
+            KeyAggContext::new([pk1, pk2]).unwrap(),
+            KeyAggContext::new([pk2, pk1]).unwrap()
+        );
+        let ctx = KeyAggContext::new([pk1, pk2])
+            .unwrap()
+            .with_taproot_tweak(None)
+            .unwrap();
+        let msg = sha256::Hash::hash(b"commitment").into_inner();
+
+        let nonce1 = SecretNonce::generate([1u8; 32], pk1, None).unwrap();
+        let nonce2 =
+            SecretNonce::generate([2u8; 32], pk2, Some(ctx.x_only_key()))
+                .unwrap();
+        let nonces = [nonce1.public_nonce(), nonce2.public_nonce()];
+
+        let other_nonce = SecretNonce::generate([3u8; 32], pk1, None).unwrap();
+        assert_eq!(
+            ctx.partial_sign(other_nonce, sk2, &nonces, msg),
+            Err(MuSig2Error::UnknownSigner(pk2))
+        );
+        let sig1 = ctx.partial_sign(nonce1, sk1, &nonces, msg).unwrap();
+        let sig2 = ctx.partial_sign(nonce2, sk2, &nonces, msg).unwrap();
+        ctx.partial_verify(sig1, nonces[0], pk1, &nonces, msg)
+            .unwrap();
+        ctx.partial_verify(sig2, nonces[1], pk2, &nonces, msg)
+            .unwrap();
+        assert_eq!(
+            ctx.partial_verify(sig1, nonces[1], pk2, &nonces, msg),
+            Err(MuSig2Error::InvalidPartialSig(pk2))
+        );
+
+        let sig = ctx.aggregate(&[sig1, sig2], &nonces, msg).unwrap();
+        let msg = secp256k1::Message::from_slice(&msg).unwrap();
+        SECP256K1
+            .verify_schnorr(&sig, &msg, &ctx.x_only_key())
+            .unwrap();
+    }
+
+    fn pubkey(hex: &str) -> PublicKey {
+        PublicKey::from_str(hex).unwrap()
+    }
+
+    /// Test vectors from `key_agg_vectors.json` of BIP-327
+    #[test]
+    fn bip327_key_agg_vectors() {
+        let keys = [
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+        ]
+        .map(pubkey);
+        for (indices, expected) in [
+            (
+                &[0, 1, 2][..],
+                "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c",
+            ),
+            (
+                &[2, 1, 0][..],
+                "6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b",
+            ),
+            (
+                &[0, 0, 0][..],
+                "b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935",
+            ),
+            (
+                &[0, 0, 1, 1][..],
+                "69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e",
+            ),
+        ] {
+            let keys = indices.iter().map(|i| keys[*i]).collect();
+            let ctx = KeyAggContext::with_ordered_keys(keys).unwrap();
+            assert_eq!(ctx.x_only_key().serialize().to_hex(), expected);
+        }
+
+        // Sorted keys give the same result regardless of the original order
+        let sorted =
+            KeyAggContext::with_ordered_keys(vec![keys[2], keys[0], keys[1]])
+                .unwrap();
+        assert_eq!(KeyAggContext::new(keys).unwrap(), sorted);
+    }
+
+    fn nonce(hex: &str) -> PublicNonce {
+        let mut nonce = [0u8; 66];
+        nonce.copy_from_slice(&Vec::<u8>::from_hex(hex).unwrap());
+        PublicNonce::from_inner(nonce)
+    }
+
+    fn seckey(hex: &str) -> SecretKey {
+        SecretKey::from_str(hex).unwrap()
+    }
+
+    /// Test vectors from `sign_verify_vectors.json` of BIP-327
+    #[test]
+    fn bip327_sign_verify_vectors() {
+        let sk = seckey(
+            "7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671",
+        );
+        let keys = [
+            "03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA661",
+        ]
+        .map(pubkey);
+        let nonces = [
+            "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+        ]
+        .map(nonce);
+        let msg = <[u8; 32]>::from_hex(
+            "F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF",
+        )
+        .unwrap();
+        assert_eq!(PublicKey::from_secret_key(SECP256K1, &sk), keys[0]);
+
+        for (indices, expected) in [
+            (
+                [0, 1, 2],
+                "012abbcb52b3016ac03ad82395a1a415c48b93def78718e62a7a90052fe224fb",
+            ),
+            (
+                [1, 0, 2],
+                "9ff2f7aaa856150cc8819254218d3adeeb0535269051897724f9db3789513a52",
+            ),
+            (
+                [1, 2, 0],
+                "fa23c359f6fac4e7796bb93bc9f0532a95468c539ba20ff86d7c76ed92227900",
+            ),
+        ] {
+            let ctx = KeyAggContext::with_ordered_keys(
+                indices.iter().map(|i| keys[*i]).collect(),
+            )
+            .unwrap();
+            let nonces = indices.map(|i| nonces[i]);
+            let secret_nonce = SecretNonce {
+                k1: seckey("508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61"),
+                k2: seckey("FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F7"),
+                pubkey: keys[0],
+            };
+            let local_nonce = secret_nonce.public_nonce();
+            let partial_sig =
+                ctx.partial_sign(secret_nonce, sk, &nonces, msg).unwrap();
+            assert_eq!(partial_sig.as_inner().to_hex(), expected);
+            ctx.partial_verify(partial_sig, local_nonce, keys[0], &nonces, msg)
+                .unwrap();
+        }
+    }
+}
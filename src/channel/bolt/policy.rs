@@ -11,12 +11,14 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Formatter};
 use std::ops::Range;
 
 #[cfg(feature = "serde")]
 use amplify::ToYamlString;
-use lnp2p::bolt::{AcceptChannel, ChannelType, OpenChannel};
+use lnp2p::bolt::{
+    AcceptChannel, AcceptChannel2, ChannelType, OpenChannel, OpenChannel2,
+};
 
 /// Limit for the maximum number of the accepted HTLCs towards some node
 pub const BOLT3_MAX_ACCEPTED_HTLC_LIMIT: u16 = 483;
@@ -24,6 +26,49 @@ pub const BOLT3_MAX_ACCEPTED_HTLC_LIMIT: u16 = 483;
 /// BOLT-3 dust limit
 pub const BOLT3_DUST_LIMIT: u64 = 354;
 
+/// Computes channel reserve for the channels established with v2 protocol,
+/// which is not negotiated by the peers but is fixed to 1% of the total
+/// channel funding, but not less than the dust limit of the peer.
+#[inline]
+pub fn dual_funded_reserve(total_funding_sat: u64, dust_limit_sat: u64) -> u64 {
+    (total_funding_sat / 100).max(dust_limit_sat)
+}
+
+/// Limit on the total amount of dust HTLCs (HTLCs which are trimmed from a
+/// commitment transaction and added to its fees) the local node is exposed to
+/// within a single commitment transaction.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+pub enum DustExposure {
+    /// Fixed limit, in millisatoshis
+    #[display("{0} msat")]
+    FixedLimitMsat(u64),
+
+    /// Limit proportional to the current commitment fee rate: the fee rate
+    /// in sat/kw multiplied by the given factor gives the limit in
+    /// millisatoshis
+    #[display("{0} x feerate")]
+    FeeRateMultiplier(u64),
+}
+
+impl DustExposure {
+    /// Computes the exposure limit in millisatoshis for the provided fee rate
+    #[inline]
+    pub fn limit_msat(self, feerate_per_kw: u32) -> u64 {
+        match self {
+            DustExposure::FixedLimitMsat(limit) => limit,
+            DustExposure::FeeRateMultiplier(multiplier) => {
+                multiplier.saturating_mul(feerate_per_kw as u64)
+            }
+        }
+    }
+}
+
 /// Errors from [BOLT-2] policy validations for `open_channel` and
 /// `accept_channel` messages.
 ///
@@ -126,6 +171,68 @@ pub enum PolicyError {
         channel_reserve: u64,
         dust_limit: u64,
     },
+
+    /// channel funding of {0} sat overflows the amount in millisatoshis;
+    /// rejecting the channel
+    FundingOverflow(u64),
+
+    /// `push_msat` ({push_msat}) is greater than the channel funding of
+    /// {funding_msat} msat; rejecting the channel according to BOLT-2
+    PushExceedsFunding { push_msat: u64, funding_msat: u64 },
+}
+
+/// Report listing all violations of the local node policy by the channel
+/// parameters proposed by a remote peer.
+///
+/// Each of the violations is reported as [`PolicyError`] containing both the
+/// value proposed by the remote peer and the value required by the policy.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct PolicyReport {
+    violations: Vec<PolicyError>,
+}
+
+impl fmt::Display for PolicyReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.violations.is_empty() {
+            return f.write_str("channel parameters match the local policy");
+        }
+        for (no, violation) in self.violations.iter().enumerate() {
+            if no > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "- {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl PolicyReport {
+    /// Returns list of the policy violations in the order of the policy rules
+    #[inline]
+    pub fn violations(&self) -> &[PolicyError] {
+        &self.violations
+    }
+
+    /// Detects whether the parameters match all the policy rules
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    #[inline]
+    fn push(&mut self, violation: PolicyError) {
+        self.violations.push(violation)
+    }
+
+    /// Converts report into a result, which fails with the first of the
+    /// policy violations, if any.
+    #[inline]
+    pub fn into_result<T>(self, value: T) -> Result<T, PolicyError> {
+        match self.violations.into_iter().next() {
+            Some(violation) => Err(violation),
+            None => Ok(value),
+        }
+    }
 }
 
 /// Policy to validate channel parameters proposed by a remote peer.
@@ -137,7 +244,7 @@ pub enum PolicyError {
 #[cfg_attr(
     feature = "serde",
     derive(Display, Serialize, Deserialize),
-    serde(crate = "serde_crate"),
+    serde(crate = "serde_crate", default),
     display(Policy::to_yaml_string)
 )]
 pub struct Policy {
@@ -180,6 +287,16 @@ pub struct Policy {
 
     /// Maximum value for the dust limit required by a remote node.
     pub dust_limit_satoshis_max: Option<u64>,
+
+    /// Maximum total amount of dust HTLCs in each of the commitment
+    /// transactions, which are lost to miners if the channel is force-closed.
+    pub max_dust_htlc_exposure: Option<DustExposure>,
+
+    /// Accept channels from the remote node without waiting for the funding
+    /// transaction to be mined (zero-conf channels), trusting the remote node
+    /// not to double-spend it. Should be enabled only in the policies used
+    /// for the channels with trusted peers.
+    pub accept_zero_conf: bool,
 }
 
 #[cfg(feature = "serde")]
@@ -213,6 +330,12 @@ impl Default for Policy {
             // we do not want to require too large `to_local` / `to_remote`
             // outputs
             dust_limit_satoshis_max: Some(1000),
+            // limit funds which may be burned to fees on force-close
+            max_dust_htlc_exposure: Some(DustExposure::FixedLimitMsat(
+                50_000_000,
+            )),
+            // we do not trust unknown peers
+            accept_zero_conf: false,
         }
     }
 }
@@ -233,6 +356,11 @@ impl Policy {
             // c-lightning uses 10% of the channel funding as a reserve
             channel_reserve_satoshis_max_percent: Some(10),
             dust_limit_satoshis_max: Some(546),
+            // c-lightning uses 50k sats as a `max-dust-htlc-exposure-msat`
+            max_dust_htlc_exposure: Some(DustExposure::FixedLimitMsat(
+                50_000_000,
+            )),
+            accept_zero_conf: false,
         }
     }
 
@@ -255,6 +383,11 @@ impl Policy {
             // size 546 is the biggest value for p2pkh
             // https://github.com/lightningnetwork/lnd/pull/5781
             dust_limit_satoshis_max: Some(546),
+            // LND uses 500k sats as a `dust-threshold`
+            max_dust_htlc_exposure: Some(DustExposure::FixedLimitMsat(
+                500_000_000,
+            )),
+            accept_zero_conf: false,
         }
     }
 
@@ -273,16 +406,52 @@ impl Policy {
             // Eclair uses 5% of the channel funding as a reserve
             channel_reserve_satoshis_max_percent: Some(5),
             dust_limit_satoshis_max: Some(546),
+            // Eclair uses 50k sats as a `max-exposure-satoshis`
+            max_dust_htlc_exposure: Some(DustExposure::FixedLimitMsat(
+                50_000_000,
+            )),
+            accept_zero_conf: false,
         }
     }
 
-    fn validate_peer_params(
+    /// Returns minimum depth of the funding transaction required from the
+    /// remote node for the channels proposed by it, which is zero for the
+    /// zero-conf channels.
+    #[inline]
+    pub fn inbound_minimum_depth(&self) -> u32 {
+        if self.accept_zero_conf {
+            0
+        } else {
+            self.minimum_depth
+        }
+    }
+
+    /// Computes maximum channel reserve allowed by the policy for the channel
+    /// with the given funding, in satoshis
+    fn channel_reserve_percent_limit(
+        &self,
+        funding_satoshis: u64,
+    ) -> Option<u64> {
+        self.channel_reserve_satoshis_max_percent.map(|percents| {
+            (funding_satoshis as f32 * (percents as f32 / 100.)) as u64
+        })
+    }
+
+    /// Validates channel parameters required by a remote peer against the
+    /// policy, failing on the first violated rule.
+    pub fn validate_peer_params(
         &self,
         params: PeerParams,
     ) -> Result<(), PolicyError> {
+        let mut report = PolicyReport::default();
+        self.check_peer_params(params, &mut report);
+        report.into_result(())
+    }
+
+    fn check_peer_params(&self, params: PeerParams, report: &mut PolicyReport) {
         // if `to_self_delay` is unreasonably large.
         if params.to_self_delay > self.to_self_delay_max {
-            return Err(PolicyError::ToSelfDelayUnreasonablyLarge {
+            report.push(PolicyError::ToSelfDelayUnreasonablyLarge {
                 proposed: params.to_self_delay,
                 allowed_maximum: self.to_self_delay_max,
             });
@@ -290,14 +459,14 @@ impl Policy {
 
         // if `max_accepted_htlcs` is greater than 483.
         if params.max_accepted_htlcs > BOLT3_MAX_ACCEPTED_HTLC_LIMIT {
-            return Err(PolicyError::MaxAcceptedHtlcLimitExceeded(
+            report.push(PolicyError::MaxAcceptedHtlcLimitExceeded(
                 params.max_accepted_htlcs,
             ));
         }
 
         // if `dust_limit_satoshis` is greater than `channel_reserve_satoshis`.
         if params.dust_limit_satoshis > params.channel_reserve_satoshis {
-            return Err(PolicyError::ChannelReserveLessDust {
+            report.push(PolicyError::ChannelReserveLessDust {
                 reserve: params.channel_reserve_satoshis,
                 dust_limit: params.dust_limit_satoshis,
             });
@@ -305,7 +474,7 @@ impl Policy {
 
         // if `dust_limit_satoshis` is smaller than 354 satoshis
         if params.dust_limit_satoshis < BOLT3_DUST_LIMIT {
-            return Err(PolicyError::DustLimitTooSmall(
+            report.push(PolicyError::DustLimitTooSmall(
                 params.dust_limit_satoshis,
             ));
         }
@@ -313,7 +482,7 @@ impl Policy {
         // if we consider `htlc_minimum_msat` too large
         if let Some(limit) = self.htlc_minimum_msat_max {
             if params.htlc_minimum_msat > limit {
-                return Err(PolicyError::HtlcMinimumTooLarge {
+                report.push(PolicyError::HtlcMinimumTooLarge {
                     proposed: params.htlc_minimum_msat,
                     allowed_maximum: limit,
                 });
@@ -323,7 +492,7 @@ impl Policy {
         // if we consider `max_htlc_value_in_flight_msat` too small
         if let Some(limit) = self.max_htlc_value_in_flight_msat_min {
             if params.max_htlc_value_in_flight_msat < limit {
-                return Err(PolicyError::HtlcInFlightMaximumTooSmall {
+                report.push(PolicyError::HtlcInFlightMaximumTooSmall {
                     proposed: params.max_htlc_value_in_flight_msat,
                     required_minimum: limit,
                 });
@@ -334,7 +503,7 @@ impl Policy {
         // values
         if let Some(limit) = self.channel_reserve_satoshis_max_abs {
             if params.channel_reserve_satoshis > limit {
-                return Err(PolicyError::ChannelReserveTooLarge {
+                report.push(PolicyError::ChannelReserveTooLarge {
                     proposed: params.channel_reserve_satoshis,
                     allowed_maximum: limit,
                 });
@@ -344,7 +513,7 @@ impl Policy {
         // if we consider `max_accepted_htlcs` too small
         if let Some(limit) = self.max_accepted_htlcs_min {
             if params.max_accepted_htlcs < limit {
-                return Err(PolicyError::MaxAcceptedHtlcsTooSmall {
+                report.push(PolicyError::MaxAcceptedHtlcsTooSmall {
                     proposed: params.max_accepted_htlcs,
                     required_minimum: limit,
                 });
@@ -354,14 +523,12 @@ impl Policy {
         // if we consider `dust_limit_satoshis` too large
         if let Some(limit) = self.dust_limit_satoshis_max {
             if params.dust_limit_satoshis > limit {
-                return Err(PolicyError::DustLimitTooLarge {
+                report.push(PolicyError::DustLimitTooLarge {
                     proposed: params.dust_limit_satoshis,
                     allowed_maximum: limit,
                 });
             }
         }
-
-        Ok(())
     }
 
     /// Validates parameters proposed by remote peer in `open_channel` message
@@ -378,13 +545,22 @@ impl Policy {
         &self,
         open_channel: &OpenChannel,
     ) -> Result<PeerParams, PolicyError> {
+        self.report_inbound(open_channel)
+            .into_result(PeerParams::from(open_channel))
+    }
+
+    /// Validates parameters proposed by remote peer in `open_channel` message
+    /// against the policy, reporting all the rules which were violated.
+    pub fn report_inbound(&self, open_channel: &OpenChannel) -> PolicyReport {
+        let mut report = PolicyReport::default();
+
         // if we consider `feerate_per_kw` too small for timely processing or
         // unreasonably large.
         if !self
             .feerate_per_kw_range
             .contains(&open_channel.feerate_per_kw)
         {
-            return Err(PolicyError::FeeRateUnreasonable {
+            report.push(PolicyError::FeeRateUnreasonable {
                 proposed: open_channel.feerate_per_kw,
                 lowest_accepted: self.feerate_per_kw_range.start,
                 highest_accepted: self.feerate_per_kw_range.end,
@@ -394,7 +570,7 @@ impl Policy {
         // if `funding_satoshis` is too small
         if let Some(limit) = self.funding_satoshis_min {
             if open_channel.funding_satoshis < limit {
-                return Err(PolicyError::ChannelFundingTooSmall {
+                report.push(PolicyError::ChannelFundingTooSmall {
                     proposed: open_channel.funding_satoshis,
                     required_minimum: limit,
                 });
@@ -403,21 +579,84 @@ impl Policy {
 
         // if we consider `channel_reserve_satoshis` too large in relative
         // values
-        if let Some(percents) = self.channel_reserve_satoshis_max_percent {
-            let limit =
-                open_channel.funding_satoshis as f32 * (percents as f32 / 100.);
-            let limit = limit as u64;
+        if let Some(limit) =
+            self.channel_reserve_percent_limit(open_channel.funding_satoshis)
+        {
             if open_channel.channel_reserve_satoshis > limit {
-                return Err(PolicyError::ChannelReserveTooLarge {
+                report.push(PolicyError::ChannelReserveTooLarge {
                     proposed: open_channel.channel_reserve_satoshis,
                     allowed_maximum: limit,
                 });
             }
         }
 
-        let peer_params = PeerParams::from(open_channel);
-        self.validate_peer_params(peer_params)?;
-        Ok(peer_params)
+        self.check_peer_params(PeerParams::from(open_channel), &mut report);
+        report
+    }
+
+    /// Computes parameters nearest to the ones proposed by the remote peer in
+    /// `open_channel` message, which would be accepted by the local policy.
+    /// Parameters matching the policy are left intact, so the returned message
+    /// may be used to inform the remote peer about acceptable values.
+    ///
+    /// If the policy rules contradict each other (for instance, the maximum
+    /// dust limit is below the BOLT-3 minimum), the returned parameters may
+    /// still violate the policy; use [`Policy::report_inbound`] to check them.
+    pub fn nearest_inbound(&self, open_channel: &OpenChannel) -> OpenChannel {
+        let mut proposal = open_channel.clone();
+
+        let feerate_range = &self.feerate_per_kw_range;
+        if !feerate_range.is_empty() {
+            proposal.feerate_per_kw = proposal
+                .feerate_per_kw
+                .clamp(feerate_range.start, feerate_range.end - 1);
+        }
+
+        if let Some(limit) = self.funding_satoshis_min {
+            proposal.funding_satoshis = proposal.funding_satoshis.max(limit);
+        }
+
+        proposal.to_self_delay =
+            proposal.to_self_delay.min(self.to_self_delay_max);
+
+        let max_accepted_htlcs_min = self
+            .max_accepted_htlcs_min
+            .unwrap_or_default()
+            .min(BOLT3_MAX_ACCEPTED_HTLC_LIMIT);
+        proposal.max_accepted_htlcs = proposal
+            .max_accepted_htlcs
+            .clamp(max_accepted_htlcs_min, BOLT3_MAX_ACCEPTED_HTLC_LIMIT);
+
+        proposal.dust_limit_satoshis =
+            proposal.dust_limit_satoshis.max(BOLT3_DUST_LIMIT);
+        if let Some(limit) = self.dust_limit_satoshis_max {
+            proposal.dust_limit_satoshis =
+                proposal.dust_limit_satoshis.min(limit);
+        }
+
+        if let Some(limit) = self.htlc_minimum_msat_max {
+            proposal.htlc_minimum_msat = proposal.htlc_minimum_msat.min(limit);
+        }
+
+        if let Some(limit) = self.max_htlc_value_in_flight_msat_min {
+            proposal.max_htlc_value_in_flight_msat =
+                proposal.max_htlc_value_in_flight_msat.max(limit);
+        }
+
+        // Channel reserve must not be below the dust limit, and must not
+        // exceed both absolute and relative policy limits
+        let reserve_limit = self
+            .channel_reserve_percent_limit(proposal.funding_satoshis)
+            .into_iter()
+            .chain(self.channel_reserve_satoshis_max_abs)
+            .min()
+            .unwrap_or(u64::MAX);
+        proposal.channel_reserve_satoshis = proposal
+            .channel_reserve_satoshis
+            .max(proposal.dust_limit_satoshis)
+            .min(reserve_limit);
+
+        proposal
     }
 
     /// Confirms that parameters which were asked by a remote node via
@@ -437,12 +676,26 @@ impl Policy {
         our_params: PeerParams,
         accept_channel: &AcceptChannel,
     ) -> Result<PeerParams, PolicyError> {
+        self.report_outbound(our_params, accept_channel)
+            .into_result(PeerParams::from(accept_channel))
+    }
+
+    /// Checks parameters which were asked by a remote node via
+    /// `accept_channel` message against our policy, reporting all the rules
+    /// which were violated.
+    pub fn report_outbound(
+        &self,
+        our_params: PeerParams,
+        accept_channel: &AcceptChannel,
+    ) -> PolicyReport {
+        let mut report = PolicyReport::default();
+
         // if `minimum_depth` is unreasonably large:
         //
         //     MAY reject the channel.
         if let Some(limit) = self.maximum_depth {
             if accept_channel.minimum_depth > limit {
-                return Err(PolicyError::UnreasonableMinDepth {
+                report.push(PolicyError::UnreasonableMinDepth {
                     proposed: accept_channel.minimum_depth,
                     allowed_maximum: limit,
                 });
@@ -456,7 +709,7 @@ impl Policy {
         if accept_channel.channel_reserve_satoshis
             < our_params.dust_limit_satoshis
         {
-            return Err(PolicyError::LocalDustExceedsRemoteReserve {
+            report.push(PolicyError::LocalDustExceedsRemoteReserve {
                 channel_reserve: accept_channel.channel_reserve_satoshis,
                 dust_limit: our_params.dust_limit_satoshis,
             });
@@ -469,15 +722,176 @@ impl Policy {
         if our_params.channel_reserve_satoshis
             < accept_channel.dust_limit_satoshis
         {
-            return Err(PolicyError::RemoteDustExceedsLocalReserve {
+            report.push(PolicyError::RemoteDustExceedsLocalReserve {
                 channel_reserve: our_params.channel_reserve_satoshis,
                 dust_limit: accept_channel.dust_limit_satoshis,
             });
         }
 
-        let peer_params = PeerParams::from(accept_channel);
-        self.validate_peer_params(peer_params)?;
-        Ok(peer_params)
+        self.check_peer_params(PeerParams::from(accept_channel), &mut report);
+        report
+    }
+
+    /// Validates parameters proposed by remote peer in `open_channel2`
+    /// message against the policy.
+    ///
+    /// Since v2 protocol does not negotiate channel reserve, the returned
+    /// [`PeerParams`] has it computed from the opener contribution only; it
+    /// must be updated with [`PeerParams::set_dual_funded_reserve`] once the
+    /// total channel funding becomes known.
+    pub fn validate_inbound_v2(
+        &self,
+        open_channel: &OpenChannel2,
+    ) -> Result<PeerParams, PolicyError> {
+        self.report_inbound_v2(open_channel)
+            .into_result(PeerParams::from(open_channel))
+    }
+
+    /// Validates parameters proposed by remote peer in `open_channel2`
+    /// message against the policy, reporting all the rules which were
+    /// violated.
+    pub fn report_inbound_v2(
+        &self,
+        open_channel: &OpenChannel2,
+    ) -> PolicyReport {
+        let mut report = PolicyReport::default();
+
+        // if we consider `commitment_feerate_perkw` too small for timely
+        // processing or unreasonably large.
+        if !self
+            .feerate_per_kw_range
+            .contains(&open_channel.commitment_feerate_perkw)
+        {
+            report.push(PolicyError::FeeRateUnreasonable {
+                proposed: open_channel.commitment_feerate_perkw,
+                lowest_accepted: self.feerate_per_kw_range.start,
+                highest_accepted: self.feerate_per_kw_range.end,
+            });
+        }
+
+        // if `funding_satoshis` is too small
+        if let Some(limit) = self.funding_satoshis_min {
+            if open_channel.funding_satoshis < limit {
+                report.push(PolicyError::ChannelFundingTooSmall {
+                    proposed: open_channel.funding_satoshis,
+                    required_minimum: limit,
+                });
+            }
+        }
+
+        self.check_peer_params(PeerParams::from(open_channel), &mut report);
+        report
+    }
+
+    /// Confirms that parameters which were asked by a remote node via
+    /// `accept_channel2` message are confirming our policy.
+    ///
+    /// Channel reserve of the returned [`PeerParams`] is computed from the
+    /// total channel funding, which is the sum of `local_funding_sat` and
+    /// the remote node contribution.
+    pub fn confirm_outbound_v2(
+        &self,
+        local_funding_sat: u64,
+        accept_channel: &AcceptChannel2,
+    ) -> Result<PeerParams, PolicyError> {
+        let mut peer_params = PeerParams::from(accept_channel);
+        peer_params.set_dual_funded_reserve(
+            local_funding_sat + accept_channel.funding_satoshis,
+        );
+        self.report_outbound_v2(local_funding_sat, accept_channel)
+            .into_result(peer_params)
+    }
+
+    /// Checks parameters which were asked by a remote node via
+    /// `accept_channel2` message against our policy, reporting all the rules
+    /// which were violated.
+    pub fn report_outbound_v2(
+        &self,
+        local_funding_sat: u64,
+        accept_channel: &AcceptChannel2,
+    ) -> PolicyReport {
+        let mut report = PolicyReport::default();
+
+        // if `minimum_depth` is unreasonably large:
+        //
+        //     MAY reject the channel.
+        if let Some(limit) = self.maximum_depth {
+            if accept_channel.minimum_depth > limit {
+                report.push(PolicyError::UnreasonableMinDepth {
+                    proposed: accept_channel.minimum_depth,
+                    allowed_maximum: limit,
+                });
+            }
+        }
+
+        let mut peer_params = PeerParams::from(accept_channel);
+        peer_params.set_dual_funded_reserve(
+            local_funding_sat + accept_channel.funding_satoshis,
+        );
+        self.check_peer_params(peer_params, &mut report);
+        report
+    }
+
+    /// Validates local channel parameters, which are required from the remote
+    /// peer, against parameters the remote peer requires from the local node.
+    ///
+    /// Used to check local parameters overridden for a specific inbound
+    /// channel by a [`super::ChannelAcceptor`], failing on the first
+    /// violated rule.
+    pub fn validate_local_params(
+        &self,
+        local_params: PeerParams,
+        remote_params: PeerParams,
+    ) -> Result<(), PolicyError> {
+        let mut report = PolicyReport::default();
+
+        // if `max_accepted_htlcs` is greater than 483.
+        if local_params.max_accepted_htlcs > BOLT3_MAX_ACCEPTED_HTLC_LIMIT {
+            report.push(PolicyError::MaxAcceptedHtlcLimitExceeded(
+                local_params.max_accepted_htlcs,
+            ));
+        }
+
+        // if `dust_limit_satoshis` is greater than `channel_reserve_satoshis`.
+        if local_params.dust_limit_satoshis
+            > local_params.channel_reserve_satoshis
+        {
+            report.push(PolicyError::ChannelReserveLessDust {
+                reserve: local_params.channel_reserve_satoshis,
+                dust_limit: local_params.dust_limit_satoshis,
+            });
+        }
+
+        // if `dust_limit_satoshis` is smaller than 354 satoshis
+        if local_params.dust_limit_satoshis < BOLT3_DUST_LIMIT {
+            report.push(PolicyError::DustLimitTooSmall(
+                local_params.dust_limit_satoshis,
+            ));
+        }
+
+        // BOLT-2 requires the sender of `accept_channel` not to set
+        // `dust_limit_satoshis` above the channel reserve of the remote peer
+        if remote_params.channel_reserve_satoshis
+            < local_params.dust_limit_satoshis
+        {
+            report.push(PolicyError::LocalDustExceedsRemoteReserve {
+                channel_reserve: remote_params.channel_reserve_satoshis,
+                dust_limit: local_params.dust_limit_satoshis,
+            });
+        }
+
+        // ... and not to set `channel_reserve_satoshis` below the dust limit
+        // of the remote peer
+        if local_params.channel_reserve_satoshis
+            < remote_params.dust_limit_satoshis
+        {
+            report.push(PolicyError::RemoteDustExceedsLocalReserve {
+                channel_reserve: local_params.channel_reserve_satoshis,
+                dust_limit: remote_params.dust_limit_satoshis,
+            });
+        }
+
+        report.into_result(())
     }
 }
 
@@ -539,6 +953,18 @@ impl Default for CommonParams {
 }
 
 impl CommonParams {
+    /// Extracts common parameters from the incoming `open_channel2` message
+    /// and local default requirement for the minimum depth.
+    #[inline]
+    pub fn with_v2(open_channel: &OpenChannel2, minimum_depth: u32) -> Self {
+        CommonParams {
+            minimum_depth,
+            feerate_per_kw: open_channel.commitment_feerate_perkw,
+            announce_channel: open_channel.should_announce_channel(),
+            channel_type: open_channel.channel_type.unwrap_or_default(),
+        }
+    }
+
     /// Extracts common parameters from the incoming `open_channel` message and
     /// local default requirement for the minimum depth.
     #[inline]
@@ -646,6 +1072,61 @@ impl From<&OpenChannel> for PeerParams {
     }
 }
 
+impl PeerParams {
+    /// Sets channel reserve according to the rules of v2 channel establishment
+    /// protocol, i.e. to 1% of the total channel funding, but not less than
+    /// the dust limit
+    #[inline]
+    pub fn set_dual_funded_reserve(&mut self, total_funding_sat: u64) {
+        self.channel_reserve_satoshis =
+            dual_funded_reserve(total_funding_sat, self.dust_limit_satoshis);
+    }
+}
+
+impl From<&OpenChannel2> for PeerParams {
+    /// Extracts peer-specific parameters from the incoming `open_channel2`
+    /// message. These parameters are applied to the local node.
+    #[inline]
+    fn from(open_channel: &OpenChannel2) -> Self {
+        PeerParams {
+            dust_limit_satoshis: open_channel.dust_limit_satoshis,
+            to_self_delay: open_channel.to_self_delay,
+            htlc_minimum_msat: open_channel.htlc_minimum_msat,
+            max_htlc_value_in_flight_msat: open_channel
+                .max_htlc_value_in_flight_msat,
+            channel_reserve_satoshis: dual_funded_reserve(
+                open_channel.funding_satoshis,
+                open_channel.dust_limit_satoshis,
+            ),
+            max_accepted_htlcs: open_channel.max_accepted_htlcs,
+        }
+    }
+}
+
+impl From<&AcceptChannel2> for PeerParams {
+    /// Extracts peer-specific parameters from the incoming `accept_channel2`
+    /// message. These parameters are applied to the local node.
+    ///
+    /// Channel reserve is computed from the accepter contribution only; it
+    /// must be updated with [`PeerParams::set_dual_funded_reserve`] using the
+    /// total channel funding.
+    #[inline]
+    fn from(accept_channel: &AcceptChannel2) -> Self {
+        PeerParams {
+            dust_limit_satoshis: accept_channel.dust_limit_satoshis,
+            to_self_delay: accept_channel.to_self_delay,
+            htlc_minimum_msat: accept_channel.htlc_minimum_msat,
+            max_htlc_value_in_flight_msat: accept_channel
+                .max_htlc_value_in_flight_msat,
+            channel_reserve_satoshis: dual_funded_reserve(
+                accept_channel.funding_satoshis,
+                accept_channel.dust_limit_satoshis,
+            ),
+            max_accepted_htlcs: accept_channel.max_accepted_htlcs,
+        }
+    }
+}
+
 impl From<&AcceptChannel> for PeerParams {
     /// Extracts peer-specific parameters from the incoming `accept_channel`
     /// message. These parameters are applied to the local node.
@@ -964,4 +1445,89 @@ mod test {
             })
         );
     }
+
+    #[test]
+    fn test_validate_local_params() {
+        let policy = Policy::default();
+        let remote_params = PeerParams::from(&get_open_channel());
+        let mut local_params = PeerParams::default();
+        assert_eq!(
+            policy.validate_local_params(local_params, remote_params),
+            Ok(())
+        );
+
+        local_params.channel_reserve_satoshis =
+            local_params.dust_limit_satoshis - 1;
+        assert_eq!(
+            policy.validate_local_params(local_params, remote_params),
+            Err(PolicyError::ChannelReserveLessDust {
+                reserve: local_params.channel_reserve_satoshis,
+                dust_limit: local_params.dust_limit_satoshis,
+            })
+        );
+
+        local_params.channel_reserve_satoshis = 20_000;
+        local_params.dust_limit_satoshis =
+            remote_params.channel_reserve_satoshis + 1;
+        assert_eq!(
+            policy.validate_local_params(local_params, remote_params),
+            Err(PolicyError::LocalDustExceedsRemoteReserve {
+                channel_reserve: remote_params.channel_reserve_satoshis,
+                dust_limit: local_params.dust_limit_satoshis,
+            })
+        );
+    }
+
+    #[test]
+    fn test_report_all_violations() {
+        let policy = Policy::default();
+        let mut open_channel = get_open_channel();
+        open_channel.funding_satoshis = 1_000_000;
+        open_channel.feerate_per_kw = 1000;
+        open_channel.channel_reserve_satoshis = 200_000;
+        open_channel.to_self_delay = 1000;
+        open_channel.dust_limit_satoshis = 100;
+        open_channel.max_accepted_htlcs = 5;
+
+        let report = policy.report_inbound(&open_channel);
+        assert!(!report.is_ok());
+        assert_eq!(report.violations(), &[
+            PolicyError::FeeRateUnreasonable {
+                proposed: 1000,
+                lowest_accepted: 1,
+                highest_accepted: 500,
+            },
+            PolicyError::ChannelReserveTooLarge {
+                proposed: 200_000,
+                allowed_maximum: 100_000,
+            },
+            PolicyError::ToSelfDelayUnreasonablyLarge {
+                proposed: 1000,
+                allowed_maximum: 250,
+            },
+            PolicyError::DustLimitTooSmall(100),
+            PolicyError::MaxAcceptedHtlcsTooSmall {
+                proposed: 5,
+                required_minimum: 10,
+            },
+        ]);
+        assert_eq!(
+            policy.validate_inbound(&open_channel),
+            Err(report.violations()[0])
+        );
+
+        let nearest = policy.nearest_inbound(&open_channel);
+        assert_eq!(nearest.funding_satoshis, 1_000_000);
+        assert_eq!(nearest.feerate_per_kw, 499);
+        assert_eq!(nearest.channel_reserve_satoshis, 100_000);
+        assert_eq!(nearest.to_self_delay, 250);
+        assert_eq!(nearest.dust_limit_satoshis, BOLT3_DUST_LIMIT);
+        assert_eq!(nearest.max_accepted_htlcs, 10);
+        assert!(policy.report_inbound(&nearest).is_ok());
+
+        let mut valid = get_open_channel();
+        valid.funding_satoshis = 1_000_000;
+        assert_eq!(policy.nearest_inbound(&valid), valid);
+        assert!(policy.report_inbound(&valid).is_ok());
+    }
 }
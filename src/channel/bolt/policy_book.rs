@@ -0,0 +1,391 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use internet2::addr::NodeId;
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr, Same};
+
+use super::policy::{BOLT3_DUST_LIMIT, BOLT3_MAX_ACCEPTED_HTLC_LIMIT};
+use super::{DustExposure, Policy};
+
+/// Errors in the policy configuration
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum PolicyConfigError {
+    /// fee rate range {start}..{end} sat/kw does not contain any values
+    EmptyFeeRateRange { start: u32, end: u32 },
+
+    /// minimum funding depth of {minimum} blocks required from the remote
+    /// peers exceeds maximum depth of {maximum} blocks the local node agrees
+    /// to
+    DepthRangeInverted { minimum: u32, maximum: u32 },
+
+    /// maximum dust limit of {0} sat is less than BOLT-3 minimum of 354 sat
+    DustLimitMaxTooSmall(u64),
+
+    /// maximum channel reserve of {0} sat is less than BOLT-3 minimum dust
+    /// limit of 354 sat
+    ChannelReserveMaxTooSmall(u64),
+
+    /// maximum channel reserve of {0}% exceeds channel funding
+    ChannelReservePercentTooLarge(u8),
+
+    /// minimum for the limit of accepted HTLCs {0} exceeds BOLT-3 limit of
+    /// 483 HTLCs
+    MaxAcceptedHtlcsMinTooLarge(u16),
+
+    /// policy for the peer {remote_node} is invalid: {error}
+    InvalidPeerPolicy {
+        remote_node: NodeId,
+        error: Box<PolicyConfigError>,
+    },
+
+    /// unable to parse policy configuration: {0}
+    #[cfg(feature = "serde")]
+    Config(String),
+}
+
+impl Policy {
+    /// Checks consistency of the policy rules
+    pub fn check(&self) -> Result<(), PolicyConfigError> {
+        let range = &self.feerate_per_kw_range;
+        if range.is_empty() {
+            return Err(PolicyConfigError::EmptyFeeRateRange {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        if let Some(maximum) = self.maximum_depth {
+            if self.minimum_depth > maximum {
+                return Err(PolicyConfigError::DepthRangeInverted {
+                    minimum: self.minimum_depth,
+                    maximum,
+                });
+            }
+        }
+        if let Some(limit) = self.dust_limit_satoshis_max {
+            if limit < BOLT3_DUST_LIMIT {
+                return Err(PolicyConfigError::DustLimitMaxTooSmall(limit));
+            }
+        }
+        if let Some(limit) = self.channel_reserve_satoshis_max_abs {
+            if limit < BOLT3_DUST_LIMIT {
+                return Err(PolicyConfigError::ChannelReserveMaxTooSmall(
+                    limit,
+                ));
+            }
+        }
+        if let Some(percents) = self.channel_reserve_satoshis_max_percent {
+            if percents > 100 {
+                return Err(PolicyConfigError::ChannelReservePercentTooLarge(
+                    percents,
+                ));
+            }
+        }
+        if let Some(limit) = self.max_accepted_htlcs_min {
+            if limit > BOLT3_MAX_ACCEPTED_HTLC_LIMIT {
+                return Err(PolicyConfigError::MaxAcceptedHtlcsMinTooLarge(
+                    limit,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses policy from YAML document and checks its consistency. Fields
+    /// which are not present in the document are taken from
+    /// [`Policy::default`].
+    #[cfg(feature = "serde")]
+    pub fn from_yaml_str(s: &str) -> Result<Policy, PolicyConfigError> {
+        let policy: Policy = serde_yaml::from_str(s)
+            .map_err(|err| PolicyConfigError::Config(err.to_string()))?;
+        policy.check()?;
+        Ok(policy)
+    }
+}
+
+/// Overrides for the policy rules applied to a specific peer. Rules which are
+/// not set are taken from the default policy.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", default)
+)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct PolicyOverride {
+    /// Reasonable limit to check value of `to_self_delay` required by a remote
+    /// node, in blocks.
+    pub to_self_delay_max: Option<u16>,
+
+    /// Range of acceptable channel fees.
+    pub feerate_per_kw_range: Option<Range<u32>>,
+
+    /// Minimum funding transaction mining depth required from the remote node
+    /// for a channel proposed by it.
+    pub minimum_depth: Option<u32>,
+
+    /// Maximum funding transaction mining depth which may be required by a
+    /// remote node for a channel opened by a local node.
+    pub maximum_depth: Option<u32>,
+
+    /// Minimum funding for a channel by this node.
+    pub funding_satoshis_min: Option<u64>,
+
+    /// The maximum acceptable limit on the value stored in a single HTLC.
+    pub htlc_minimum_msat_max: Option<u64>,
+
+    /// Minimum boundary for the upper limit of in-flight HTLC funds.
+    pub max_htlc_value_in_flight_msat_min: Option<u64>,
+
+    /// Maximum reserve for a channel from a local node required by the remote
+    /// node in absolute value.
+    pub channel_reserve_satoshis_max_abs: Option<u64>,
+
+    /// Maximum reserve for a channel from a local node required by the remote
+    /// node in persents from the channel funding.
+    pub channel_reserve_satoshis_max_percent: Option<u8>,
+
+    /// Minimum boundary to the limit of HTLCs offered to a remote peer.
+    pub max_accepted_htlcs_min: Option<u16>,
+
+    /// Maximum value for the dust limit required by a remote node.
+    pub dust_limit_satoshis_max: Option<u64>,
+
+    /// Maximum total amount of dust HTLCs in each of the commitment
+    /// transactions.
+    pub max_dust_htlc_exposure: Option<DustExposure>,
+
+    /// Accept zero-conf channels from the remote node.
+    pub accept_zero_conf: Option<bool>,
+}
+
+impl PolicyOverride {
+    /// Applies overrides to the provided policy
+    pub fn apply(&self, policy: &mut Policy) {
+        if let Some(value) = self.to_self_delay_max {
+            policy.to_self_delay_max = value;
+        }
+        if let Some(value) = &self.feerate_per_kw_range {
+            policy.feerate_per_kw_range = value.clone();
+        }
+        if let Some(value) = self.minimum_depth {
+            policy.minimum_depth = value;
+        }
+        if self.maximum_depth.is_some() {
+            policy.maximum_depth = self.maximum_depth;
+        }
+        if self.funding_satoshis_min.is_some() {
+            policy.funding_satoshis_min = self.funding_satoshis_min;
+        }
+        if self.htlc_minimum_msat_max.is_some() {
+            policy.htlc_minimum_msat_max = self.htlc_minimum_msat_max;
+        }
+        if self.max_htlc_value_in_flight_msat_min.is_some() {
+            policy.max_htlc_value_in_flight_msat_min =
+                self.max_htlc_value_in_flight_msat_min;
+        }
+        if self.channel_reserve_satoshis_max_abs.is_some() {
+            policy.channel_reserve_satoshis_max_abs =
+                self.channel_reserve_satoshis_max_abs;
+        }
+        if self.channel_reserve_satoshis_max_percent.is_some() {
+            policy.channel_reserve_satoshis_max_percent =
+                self.channel_reserve_satoshis_max_percent;
+        }
+        if self.max_accepted_htlcs_min.is_some() {
+            policy.max_accepted_htlcs_min = self.max_accepted_htlcs_min;
+        }
+        if self.dust_limit_satoshis_max.is_some() {
+            policy.dust_limit_satoshis_max = self.dust_limit_satoshis_max;
+        }
+        if self.max_dust_htlc_exposure.is_some() {
+            policy.max_dust_htlc_exposure = self.max_dust_htlc_exposure;
+        }
+        if let Some(value) = self.accept_zero_conf {
+            policy.accept_zero_conf = value;
+        }
+    }
+}
+
+/// Set of channel policies used by the local node: the default policy
+/// profile and overrides for specific remote peers.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", default)
+)]
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PolicyBook {
+    /// Policy applied to all peers which do not have overrides
+    pub default: Policy,
+
+    /// Overrides of the default policy for specific peers
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "As::<BTreeMap<DisplayFromStr, Same>>")
+    )]
+    pub peers: BTreeMap<NodeId, PolicyOverride>,
+}
+
+impl PolicyBook {
+    /// Constructs policy book with the given default policy and no per-peer
+    /// overrides
+    #[inline]
+    pub fn with(default: Policy) -> PolicyBook {
+        PolicyBook {
+            default,
+            peers: empty!(),
+        }
+    }
+
+    /// Sets policy overrides for the remote peer, returning previous
+    /// overrides, if any
+    #[inline]
+    pub fn set_override(
+        &mut self,
+        remote_node: NodeId,
+        overrides: PolicyOverride,
+    ) -> Option<PolicyOverride> {
+        self.peers.insert(remote_node, overrides)
+    }
+
+    /// Resolves effective policy for the remote peer
+    pub fn policy_for(&self, remote_node: NodeId) -> Policy {
+        let mut policy = self.default.clone();
+        if let Some(overrides) = self.peers.get(&remote_node) {
+            overrides.apply(&mut policy);
+        }
+        policy
+    }
+
+    /// Checks consistency of the default policy and of the effective policies
+    /// for all peers having overrides
+    pub fn check(&self) -> Result<(), PolicyConfigError> {
+        self.default.check()?;
+        for remote_node in self.peers.keys() {
+            self.policy_for(*remote_node).check().map_err(|error| {
+                PolicyConfigError::InvalidPeerPolicy {
+                    remote_node: *remote_node,
+                    error: Box::new(error),
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Parses policy book from YAML document and checks its consistency
+    #[cfg(feature = "serde")]
+    pub fn from_yaml_str(s: &str) -> Result<PolicyBook, PolicyConfigError> {
+        let book: PolicyBook = serde_yaml::from_str(s)
+            .map_err(|err| PolicyConfigError::Config(err.to_string()))?;
+        book.check()?;
+        Ok(book)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node(byte: u8) -> NodeId {
+        let seckey = secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from(secp256k1::PublicKey::from_secret_key(
+            secp256k1::SECP256K1,
+            &seckey,
+        ))
+    }
+
+    #[test]
+    fn per_peer_overrides() {
+        let mut book = PolicyBook::with(Policy::with_lnd_defaults());
+        book.set_override(node(1), PolicyOverride {
+            minimum_depth: Some(1),
+            accept_zero_conf: Some(true),
+            ..default!()
+        });
+        book.check().unwrap();
+
+        assert_eq!(book.policy_for(node(2)), Policy::with_lnd_defaults());
+        let policy = book.policy_for(node(1));
+        assert_eq!(policy.minimum_depth, 1);
+        assert!(policy.accept_zero_conf);
+        assert_eq!(policy.funding_satoshis_min, Some(20000));
+
+        book.set_override(node(2), PolicyOverride {
+            minimum_depth: Some(10),
+            ..default!()
+        });
+        assert_eq!(
+            book.check(),
+            Err(PolicyConfigError::InvalidPeerPolicy {
+                remote_node: node(2),
+                error: Box::new(PolicyConfigError::DepthRangeInverted {
+                    minimum: 10,
+                    maximum: 6
+                })
+            })
+        );
+
+        let policy = Policy {
+            feerate_per_kw_range: 10..10,
+            ..default!()
+        };
+        assert_eq!(
+            policy.check(),
+            Err(PolicyConfigError::EmptyFeeRateRange { start: 10, end: 10 })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn yaml_config() {
+        let yaml = format!(
+            "default:
+  minimum_depth: 2
+  feerate_per_kw_range:
+    start: 1
+    end: 2000
+  max_dust_htlc_exposure: !FeeRateMultiplier 5000
+peers:
+  {}:
+    accept_zero_conf: true
+    dust_limit_satoshis_max: 600
+",
+            node(1)
+        );
+        let book = PolicyBook::from_yaml_str(&yaml).unwrap();
+        assert_eq!(book.default, Policy {
+            minimum_depth: 2,
+            feerate_per_kw_range: 1..2000,
+            max_dust_htlc_exposure: Some(DustExposure::FeeRateMultiplier(5000)),
+            ..default!()
+        });
+        let policy = book.policy_for(node(1));
+        assert!(policy.accept_zero_conf);
+        assert_eq!(policy.dust_limit_satoshis_max, Some(600));
+        assert_eq!(policy.minimum_depth, 2);
+
+        let yaml = "default:\n  dust_limit_satoshis_max: 100\n";
+        assert_eq!(
+            PolicyBook::from_yaml_str(yaml),
+            Err(PolicyConfigError::DustLimitMaxTooSmall(100))
+        );
+        assert!(matches!(
+            Policy::from_yaml_str("minimum_depth: many"),
+            Err(PolicyConfigError::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,501 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Data structures used in channel quiescence and splicing protocols.
+
+use bitcoin::Txid;
+use lnp2p::bolt::Messages;
+
+/// Errors happening during channel quiescence and splicing
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum SpliceError {
+    /// the operation requires channel to be quiescent
+    NotQuiescent,
+
+    /// splice can be initiated only by the quiescence initiator
+    NotQuiescenceInitiator,
+
+    /// splice can be acknowledged only by the quiescence non-initiator
+    QuiescenceInitiator,
+
+    /// no splice negotiation is in progress
+    NoSplice,
+
+    /// remote peer has requested to change the channel funding key during
+    /// splice, which is not supported
+    FundingKeyChange,
+
+    /// splice contribution of {contribution} sat exceeds channel balance of
+    /// {balance} sat
+    ContributionExceedsBalance { balance: u64, contribution: i64 },
+
+    /// splice transaction {0} is not known as a pending funding candidate
+    UnknownCandidate(Txid),
+
+    /// channel updates are not allowed once `stfu` was sent
+    UpdateWhileQuiescent,
+}
+
+/// Detects messages updating channel commitments, which must not be sent
+/// after `stfu`
+pub(super) fn is_update_message(message: &Messages) -> bool {
+    matches!(
+        message,
+        Messages::UpdateAddHtlc(_)
+            | Messages::UpdateFulfillHtlc(_)
+            | Messages::UpdateFailHtlc(_)
+            | Messages::UpdateFailMalformedHtlc(_)
+            | Messages::UpdateFee(_)
+            | Messages::UpdateAddPtlc(_)
+            | Messages::UpdateFulfillPtlc(_)
+            | Messages::UpdateFailPtlc(_)
+    )
+}
+
+/// Progress of channel quiescence negotiation (`stfu` messages exchange)
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct Quiescence {
+    /// Local node has sent `stfu`
+    pub sent: bool,
+
+    /// Remote node has sent `stfu`
+    pub received: bool,
+
+    /// Local node is the quiescence initiator, i.e. the node which may start
+    /// splicing
+    pub initiator: bool,
+}
+
+impl Quiescence {
+    /// Detects whether both peers have sent `stfu`
+    #[inline]
+    pub fn is_quiescent(self) -> bool {
+        self.sent && self.received
+    }
+}
+
+/// Parameters of a splice under negotiation
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct SpliceParams {
+    /// Amount in satoshis added (positive) or removed (negative) by the local
+    /// node
+    pub local_contribution_sat: i64,
+
+    /// Amount in satoshis added (positive) or removed (negative) by the remote
+    /// node
+    pub remote_contribution_sat: i64,
+
+    /// The fee rate per 1000-weight for the splice transaction
+    pub funding_feerate_perkw: u32,
+
+    /// The locktime for the splice transaction
+    pub locktime: u32,
+}
+
+/// Changes to the channel balances introduced by a negotiated splice
+/// transaction, applied once the splice is locked by both peers
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct SpliceContributions {
+    /// Amount in satoshis added (positive) or removed (negative) by the local
+    /// node
+    pub local_sat: i64,
+
+    /// Amount in satoshis added (positive) or removed (negative) by the remote
+    /// node
+    pub remote_sat: i64,
+}
+
+impl SpliceContributions {
+    /// Applies contributions to the channel balances, returning new local and
+    /// remote balances in millisatoshis
+    pub fn apply(self, local_msat: u64, remote_msat: u64) -> (u64, u64) {
+        let apply = |msat: u64, sat: i64| {
+            (msat as i128 + sat as i128 * 1000).max(0) as u64
+        };
+        (
+            apply(local_msat, self.local_sat),
+            apply(remote_msat, self.remote_sat),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::DumbDefault;
+    use bitcoin::hashes::Hash;
+    use lnp2p::bolt::{ActiveChannelId, ChannelId, Stfu, UpdateFee};
+    use secp256k1::{PublicKey, SecretKey, SECP256K1};
+
+    use super::*;
+    use crate::channel::bolt::{
+        BoltExt, ChannelState, Direction, Error, Lifecycle,
+    };
+    use crate::channel::Channel;
+    use crate::Extension;
+
+    fn key(n: u8) -> PublicKey {
+        PublicKey::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(&[n; 32]).unwrap(),
+        )
+    }
+
+    /// Creates active channel with the given local and remote balances, in
+    /// satoshis, and funding keys
+    fn channel(
+        direction: Direction,
+        local_sat: u64,
+        remote_sat: u64,
+        local_key: PublicKey,
+        remote_key: PublicKey,
+    ) -> Channel<BoltExt> {
+        let mut state = ChannelState::dumb_default();
+        state.stage = Lifecycle::Active;
+        state.direction = direction;
+        state.active_channel_id = ActiveChannelId::Static(ChannelId::default());
+        state.local_amount_msat = local_sat * 1000;
+        state.remote_amount_msat = remote_sat * 1000;
+        state.local_keys.funding_pubkey.key = local_key;
+        state.remote_keys.funding_pubkey = remote_key;
+        let mut channel = Channel::<BoltExt>::default();
+        channel.load_state(&state);
+        channel
+    }
+
+    /// Alice is the channel funder with 600 000 sat; Bob has 400 000 sat
+    fn channels() -> (Channel<BoltExt>, Channel<BoltExt>) {
+        let alice =
+            channel(Direction::Outbount, 600_000, 400_000, key(1), key(2));
+        let bob = channel(Direction::Inbound, 400_000, 600_000, key(2), key(1));
+        (alice, bob)
+    }
+
+    /// Makes channels quiescent, with Alice being the quiescence initiator
+    fn quiescent_channels() -> (Channel<BoltExt>, Channel<BoltExt>) {
+        let (mut alice, mut bob) = channels();
+        let msg = alice.compose_stfu().unwrap();
+        bob.update_from_peer(&Messages::Stfu(msg)).unwrap();
+        let msg = bob.compose_stfu().unwrap();
+        alice.update_from_peer(&Messages::Stfu(msg)).unwrap();
+        (alice, bob)
+    }
+
+    #[test]
+    fn update_messages() {
+        let update_fee = Messages::UpdateFee(UpdateFee {
+            channel_id: ChannelId::default(),
+            feerate_per_kw: 253,
+        });
+        assert!(is_update_message(&update_fee));
+
+        let stfu = Messages::Stfu(Stfu {
+            channel_id: ChannelId::default(),
+            initiator: 1,
+        });
+        assert!(!is_update_message(&stfu));
+    }
+
+    #[test]
+    fn quiescence_sequential() {
+        let (mut alice, mut bob) = channels();
+
+        let msg = alice.compose_stfu().unwrap();
+        assert!(msg.is_initiator());
+        assert_eq!(alice.constructor().quiescence(), Quiescence {
+            sent: true,
+            received: false,
+            initiator: true,
+        });
+        assert_eq!(alice.constructor().stage(), Lifecycle::Active);
+
+        bob.update_from_peer(&Messages::Stfu(msg)).unwrap();
+        assert_eq!(bob.constructor().quiescence(), Quiescence {
+            sent: false,
+            received: true,
+            initiator: false,
+        });
+        assert_eq!(bob.constructor().stage(), Lifecycle::Active);
+
+        // Updates are rejected once `stfu` was sent or received
+        assert_eq!(
+            alice.compose_update_fee(300).unwrap_err(),
+            SpliceError::UpdateWhileQuiescent.into()
+        );
+        let update_fee = Messages::UpdateFee(UpdateFee {
+            channel_id: ChannelId::default(),
+            feerate_per_kw: 300,
+        });
+        assert_eq!(
+            bob.update_from_peer(&update_fee).unwrap_err(),
+            SpliceError::UpdateWhileQuiescent.into()
+        );
+
+        let msg = bob.compose_stfu().unwrap();
+        assert!(!msg.is_initiator());
+        alice.update_from_peer(&Messages::Stfu(msg)).unwrap();
+
+        assert!(alice.constructor().quiescence().is_quiescent());
+        assert!(bob.constructor().quiescence().is_quiescent());
+        assert!(alice.constructor().quiescence().initiator);
+        assert!(!bob.constructor().quiescence().initiator);
+        assert_eq!(alice.constructor().stage(), Lifecycle::Quiescent);
+        assert_eq!(bob.constructor().stage(), Lifecycle::Quiescent);
+    }
+
+    #[test]
+    fn quiescence_simultaneous() {
+        // The initiator of simultaneous quiescence is the channel funder,
+        // independently of the order in which `stfu` messages are processed
+        for funder_first in [true, false] {
+            let (mut alice, mut bob) = channels();
+            let alice_stfu = alice.compose_stfu().unwrap();
+            let bob_stfu = bob.compose_stfu().unwrap();
+            assert!(alice_stfu.is_initiator());
+            assert!(bob_stfu.is_initiator());
+
+            if funder_first {
+                bob.update_from_peer(&Messages::Stfu(alice_stfu)).unwrap();
+                alice.update_from_peer(&Messages::Stfu(bob_stfu)).unwrap();
+            } else {
+                alice.update_from_peer(&Messages::Stfu(bob_stfu)).unwrap();
+                bob.update_from_peer(&Messages::Stfu(alice_stfu)).unwrap();
+            }
+
+            assert!(alice.constructor().quiescence().initiator);
+            assert!(!bob.constructor().quiescence().initiator);
+            assert_eq!(alice.constructor().stage(), Lifecycle::Quiescent);
+            assert_eq!(bob.constructor().stage(), Lifecycle::Quiescent);
+        }
+    }
+
+    #[test]
+    fn quiescence_lifecycle() {
+        let mut state = ChannelState::dumb_default();
+        state.stage = Lifecycle::Funded;
+        state.active_channel_id = ActiveChannelId::Static(ChannelId::default());
+        let mut channel = Channel::<BoltExt>::default();
+        channel.load_state(&state);
+
+        assert!(matches!(
+            channel.compose_stfu(),
+            Err(Error::LifecycleMismatch { .. })
+        ));
+        let stfu = Messages::Stfu(Stfu {
+            channel_id: ChannelId::default(),
+            initiator: 1,
+        });
+        assert!(matches!(
+            channel.update_from_peer(&stfu),
+            Err(Error::LifecycleMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn splice_init() {
+        let (mut alice, mut bob) = channels();
+        assert_eq!(
+            alice.compose_splice_init(100_000, 253, 0),
+            Err(SpliceError::NotQuiescent.into())
+        );
+
+        let (mut quiescent_alice, mut quiescent_bob) = quiescent_channels();
+        let msg = quiescent_alice
+            .compose_splice_init(100_000, 253, 0)
+            .unwrap();
+        assert_eq!(msg.funding_pubkey, key(1));
+        assert_eq!(
+            bob.update_from_peer(&Messages::SpliceInit(msg.clone())),
+            Err(SpliceError::NotQuiescent.into())
+        );
+
+        // Only quiescence initiator may start the splice
+        assert_eq!(
+            quiescent_bob.compose_splice_init(100_000, 253, 0),
+            Err(SpliceError::NotQuiescenceInitiator.into())
+        );
+        let (mut initiator, _) = quiescent_channels();
+        assert_eq!(
+            initiator.update_from_peer(&Messages::SpliceInit(msg.clone())),
+            Err(SpliceError::QuiescenceInitiator.into())
+        );
+
+        // Funding key may not change
+        let mut wrong = msg.clone();
+        wrong.funding_pubkey = key(3);
+        assert_eq!(
+            quiescent_bob.update_from_peer(&Messages::SpliceInit(wrong)),
+            Err(SpliceError::FundingKeyChange.into())
+        );
+
+        // Splice-out may not exceed the balance of the contributor
+        assert_eq!(
+            quiescent_alice.compose_splice_init(-600_001, 253, 0),
+            Err(SpliceError::ContributionExceedsBalance {
+                balance: 600_000,
+                contribution: -600_001
+            }
+            .into())
+        );
+        let mut excessive = msg.clone();
+        excessive.funding_contribution_satoshis = (-600_001i64).into();
+        assert_eq!(
+            quiescent_bob.update_from_peer(&Messages::SpliceInit(excessive)),
+            Err(SpliceError::ContributionExceedsBalance {
+                balance: 600_000,
+                contribution: -600_001
+            }
+            .into())
+        );
+        assert!(quiescent_bob.constructor().splice().is_none());
+
+        quiescent_bob
+            .update_from_peer(&Messages::SpliceInit(msg))
+            .unwrap();
+        assert_eq!(
+            quiescent_bob.constructor().splice(),
+            Some(SpliceParams {
+                local_contribution_sat: 0,
+                remote_contribution_sat: 100_000,
+                funding_feerate_perkw: 253,
+                locktime: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn splice_ack() {
+        let (mut alice, mut bob) = quiescent_channels();
+
+        // Splice can't be acknowledged before it was initiated
+        assert_eq!(
+            bob.compose_splice_ack(0),
+            Err(SpliceError::NoSplice.into())
+        );
+        assert_eq!(
+            alice.compose_splice_ack(0),
+            Err(SpliceError::QuiescenceInitiator.into())
+        );
+
+        let (mut uninitiated, _) = quiescent_channels();
+        let msg = alice.compose_splice_init(-100_000, 253, 0).unwrap();
+        bob.update_from_peer(&Messages::SpliceInit(msg)).unwrap();
+        assert_eq!(
+            bob.compose_splice_ack(-400_001),
+            Err(SpliceError::ContributionExceedsBalance {
+                balance: 400_000,
+                contribution: -400_001
+            }
+            .into())
+        );
+        let msg = bob.compose_splice_ack(-50_000).unwrap();
+        assert_eq!(msg.funding_pubkey, key(2));
+
+        // Splice ack is accepted only by the splice initiator
+        assert_eq!(
+            bob.update_from_peer(&Messages::SpliceAck(msg.clone())),
+            Err(SpliceError::NotQuiescenceInitiator.into())
+        );
+        assert_eq!(
+            uninitiated.update_from_peer(&Messages::SpliceAck(msg.clone())),
+            Err(SpliceError::NoSplice.into())
+        );
+
+        let mut wrong = msg.clone();
+        wrong.funding_pubkey = key(3);
+        assert_eq!(
+            alice.update_from_peer(&Messages::SpliceAck(wrong)),
+            Err(SpliceError::FundingKeyChange.into())
+        );
+        let mut excessive = msg.clone();
+        excessive.funding_contribution_satoshis = (-400_001i64).into();
+        assert_eq!(
+            alice.update_from_peer(&Messages::SpliceAck(excessive)),
+            Err(SpliceError::ContributionExceedsBalance {
+                balance: 400_000,
+                contribution: -400_001
+            }
+            .into())
+        );
+
+        alice.update_from_peer(&Messages::SpliceAck(msg)).unwrap();
+        let splice = SpliceParams {
+            local_contribution_sat: -100_000,
+            remote_contribution_sat: -50_000,
+            funding_feerate_perkw: 253,
+            locktime: 0,
+        };
+        assert_eq!(alice.constructor().splice(), Some(splice));
+        assert_eq!(
+            bob.constructor().splice(),
+            Some(SpliceParams {
+                local_contribution_sat: splice.remote_contribution_sat,
+                remote_contribution_sat: splice.local_contribution_sat,
+                ..splice
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_candidate() {
+        let (mut alice, _) = channels();
+        let txid = Txid::all_zeros();
+        assert_eq!(
+            alice.compose_splice_locked(txid),
+            Err(SpliceError::UnknownCandidate(txid).into())
+        );
+    }
+
+    #[test]
+    fn contributions() {
+        let local_msat = 600_000_000;
+        let remote_msat = 400_000_000;
+
+        let splice_in = SpliceContributions {
+            local_sat: 100_000,
+            remote_sat: 0,
+        };
+        assert_eq!(
+            splice_in.apply(local_msat, remote_msat),
+            (700_000_000, 400_000_000)
+        );
+
+        let splice_out = SpliceContributions {
+            local_sat: -100_000,
+            remote_sat: -400_000,
+        };
+        assert_eq!(splice_out.apply(local_msat, remote_msat), (500_000_000, 0));
+
+        // Balances never become negative
+        let excessive = SpliceContributions {
+            local_sat: 0,
+            remote_sat: -400_001,
+        };
+        assert_eq!(excessive.apply(local_msat, remote_msat), (600_000_000, 0));
+
+        assert_eq!(
+            SpliceContributions::default().apply(local_msat, remote_msat),
+            (local_msat, remote_msat)
+        );
+    }
+}
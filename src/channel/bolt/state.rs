@@ -11,18 +11,24 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[cfg(feature = "serde")]
 use amplify::ToYamlString;
 use amplify::{DumbDefault, Slice32};
-use p2p::bolt::{ActiveChannelId, TempChannelId};
+use bitcoin::Txid;
+use bitcoin_scripts::PubkeyScript;
+use lnpbp::chain::AssetId;
+use p2p::bolt::{
+    ActiveChannelId, AssetAmount, InitFeatures, ShortChannelId, TempChannelId,
+};
 use secp256k1::ecdsa::Signature;
-use secp256k1::PublicKey;
+use secp256k1::{PublicKey, SecretKey};
 
 use super::{
-    CommonParams, Direction, HtlcKnown, HtlcSecret, Lifecycle, LocalKeyset,
-    PeerParams, Policy, RemoteKeyset,
+    AssetsBalance, CommonParams, Direction, DlcContract, HtlcKnown, HtlcSecret,
+    Lifecycle, LocalKeyset, PeerParams, Policy, PtlcKnown, PtlcSecret,
+    RemoteKeyset, SpliceContributions,
 };
 use crate::channel::{Funding, State};
 
@@ -37,6 +43,10 @@ use crate::channel::{Funding, State};
 pub struct ChannelState {
     pub funding: Funding,
 
+    /// Pending funding candidates (like splice transactions), which are not
+    /// a part of the strict encoding of [`Funding`]
+    pub funding_candidates: Vec<Funding>,
+
     /// Current channel lifecycle stage
     pub stage: Lifecycle,
 
@@ -71,6 +81,9 @@ pub struct ChannelState {
     /// The policy for accepting remote node params
     pub policy: Policy,
 
+    /// Features announced by the local node in its `init` message
+    pub local_features: InitFeatures,
+
     /// Common parameters applying for both nodes
     pub common_params: CommonParams,
 
@@ -99,15 +112,49 @@ pub struct ChannelState {
     pub resolved_htlcs: BTreeMap<u64, HtlcKnown>,
     pub last_received_htlc_id: u64,
     pub last_offered_htlc_id: u64,
+
+    pub offered_ptlcs: BTreeMap<u64, PtlcSecret>,
+    pub received_ptlcs: BTreeMap<u64, PtlcSecret>,
+    pub resolved_ptlcs: BTreeMap<u64, PtlcKnown>,
+    pub last_received_ptlc_id: u64,
+    pub last_offered_ptlc_id: u64,
+
+    pub dlc_contract: Option<DlcContract>,
+    pub dlc_offerer: bool,
+    pub dlc_attestation: Option<(u16, SecretKey)>,
+
+    pub local_asset_list: BTreeSet<AssetId>,
+    pub remote_asset_list: Option<BTreeSet<AssetId>>,
+    pub funding_assets: AssetsBalance,
+    pub local_assets: AssetsBalance,
+    pub remote_assets: AssetsBalance,
+    pub offered_asset_htlcs: BTreeMap<u64, AssetAmount>,
+    pub received_asset_htlcs: BTreeMap<u64, AssetAmount>,
+
+    pub option_upfront_shutdown_script: bool,
+    pub option_shutdown_anysegwit: bool,
+    pub local_shutdown_scriptpubkey: Option<PubkeyScript>,
+    pub remote_shutdown_scriptpubkey: Option<PubkeyScript>,
+
+    pub splice_candidates: BTreeMap<Txid, SpliceContributions>,
+    pub local_splice_locked: Option<Txid>,
+    pub remote_splice_locked: Option<Txid>,
+
+    pub short_channel_id: Option<ShortChannelId>,
+    pub local_alias: Option<ShortChannelId>,
+    pub remote_alias: Option<ShortChannelId>,
 }
 
 impl State for ChannelState {
     fn to_funding(&self) -> Funding {
-        self.funding.clone()
+        let mut funding = self.funding.clone();
+        funding.set_candidates(self.funding_candidates.clone());
+        funding
     }
 
     fn set_funding(&mut self, funding: &Funding) {
-        self.funding = funding.clone()
+        self.funding = funding.clone();
+        self.funding_candidates = funding.candidates().clone();
     }
 }
 
@@ -118,6 +165,7 @@ impl DumbDefault for ChannelState {
     fn dumb_default() -> Self {
         ChannelState {
             funding: Funding::new(),
+            funding_candidates: vec![],
             stage: Default::default(),
             chain_hash: Default::default(),
             active_channel_id: ActiveChannelId::Temporary(
@@ -128,6 +176,7 @@ impl DumbDefault for ChannelState {
             commitment_number: 0,
             commitment_sigs: vec![],
             policy: Default::default(),
+            local_features: Default::default(),
             common_params: Default::default(),
             local_params: Default::default(),
             remote_params: Default::default(),
@@ -141,6 +190,31 @@ impl DumbDefault for ChannelState {
             resolved_htlcs: none!(),
             last_received_htlc_id: 0,
             last_offered_htlc_id: 0,
+            offered_ptlcs: none!(),
+            received_ptlcs: none!(),
+            resolved_ptlcs: none!(),
+            last_received_ptlc_id: 0,
+            last_offered_ptlc_id: 0,
+            dlc_contract: None,
+            dlc_offerer: false,
+            dlc_attestation: None,
+            local_asset_list: none!(),
+            remote_asset_list: None,
+            funding_assets: none!(),
+            local_assets: none!(),
+            remote_assets: none!(),
+            offered_asset_htlcs: none!(),
+            received_asset_htlcs: none!(),
+            option_upfront_shutdown_script: false,
+            option_shutdown_anysegwit: false,
+            local_shutdown_scriptpubkey: None,
+            remote_shutdown_scriptpubkey: None,
+            splice_candidates: none!(),
+            local_splice_locked: None,
+            remote_splice_locked: None,
+            short_channel_id: None,
+            local_alias: None,
+            remote_alias: None,
         }
     }
 }
@@ -0,0 +1,694 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! Output scripts of simple taproot channels. All commitment outputs are
+//! P2TR outputs; the funding output uses MuSig2 aggregated key of both
+//! parties as the internal key and has no script path.
+
+use bitcoin::blockdata::opcodes::all::*;
+use bitcoin::blockdata::script;
+use bitcoin::hashes::{ripemd160, Hash};
+use bitcoin::psbt::TapTree;
+use bitcoin::util::taproot::{TaprootBuilder, TaprootSpendInfo};
+use bitcoin::{Script, TxOut};
+use bitcoin_scripts::hlc::HashLock;
+use bitcoin_scripts::PubkeyScript;
+use secp256k1::{PublicKey, XOnlyPublicKey, SECP256K1};
+use wallet::psbt;
+
+use super::keyset::LocalPubkey;
+use super::musig2::{KeyAggContext, MuSig2Error};
+
+/// Provably unspendable internal key (point `H` from BIP-341) used by the
+/// outputs which must be spent only through the script path
+pub const NUMS_POINT: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60,
+    0x35, 0xe9, 0x7a, 0x5e, 0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5,
+    0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Returns the provably unspendable internal key
+pub fn nums_point() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(&NUMS_POINT)
+        .expect("NUMS point is a valid x-only public key")
+}
+
+/// Constructs MuSig2 key aggregation context for the channel funding output,
+/// which is used both for computing funding output key and for signing
+/// commitment transactions
+pub fn funding_key_agg(
+    local_pubkey: PublicKey,
+    remote_pubkey: PublicKey,
+) -> Result<KeyAggContext, MuSig2Error> {
+    KeyAggContext::new([local_pubkey, remote_pubkey])?.with_taproot_tweak(None)
+}
+
+/// Script path leaves of the taproot channel outputs
+pub trait TaprootLeaves {
+    /// `to_local` leaf spendable by the commitment owner after a delay
+    fn ln_to_delay_leaf(
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self;
+
+    /// `to_local` leaf spendable by the counterparty with the revocation key
+    fn ln_revoke_leaf(
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+    ) -> Self;
+
+    /// `to_remote` leaf spendable by the counterparty after 1-block delay
+    fn ln_to_remote_leaf(remote_pubkey: PublicKey) -> Self;
+
+    /// Offered HTLC leaf spendable through the HTLC-timeout transaction
+    fn ln_offered_timeout_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self;
+
+    /// Offered HTLC leaf spendable by the counterparty with the preimage
+    fn ln_offered_success_leaf(
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self;
+
+    /// Received HTLC leaf spendable by the counterparty after the timeout
+    fn ln_received_timeout_leaf(
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+    ) -> Self;
+
+    /// Received HTLC leaf spendable through the HTLC-success transaction
+    fn ln_received_success_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self;
+}
+
+impl TaprootLeaves for Script {
+    fn ln_to_delay_leaf(
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&local_delayedpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .push_int(to_self_delay as i64)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .into_script()
+    }
+
+    fn ln_revoke_leaf(
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+    ) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&local_delayedpubkey.x_only_public_key().0)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&revocationpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    fn ln_to_remote_leaf(remote_pubkey: PublicKey) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&remote_pubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .into_script()
+    }
+
+    fn ln_offered_timeout_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+    ) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&local_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_x_only_key(&remote_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    fn ln_offered_success_leaf(
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self {
+        script::Builder::new()
+            .push_opcode(OP_SIZE)
+            .push_int(32)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_HASH160)
+            .push_slice(&ripemd160::Hash::hash(payment_hash.as_ref()))
+            .push_opcode(OP_EQUALVERIFY)
+            .push_x_only_key(&remote_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .into_script()
+    }
+
+    fn ln_received_timeout_leaf(
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+    ) -> Self {
+        script::Builder::new()
+            .push_x_only_key(&remote_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .push_int(1)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_int(cltv_expiry as i64)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_DROP)
+            .into_script()
+    }
+
+    fn ln_received_success_leaf(
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self {
+        script::Builder::new()
+            .push_opcode(OP_SIZE)
+            .push_int(32)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_HASH160)
+            .push_slice(&ripemd160::Hash::hash(payment_hash.as_ref()))
+            .push_opcode(OP_EQUALVERIFY)
+            .push_x_only_key(&local_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_x_only_key(&remote_htlcpubkey.x_only_public_key().0)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+}
+
+/// Generators of the simple taproot channel outputs; a counterpart of
+/// [`super::ScriptGenerators`] for P2WSH-based channels
+pub trait TaprootScriptGenerators {
+    fn ln_taproot_funding(
+        amount: u64,
+        local_pubkey: &LocalPubkey,
+        remote_pubkey: PublicKey,
+    ) -> Self;
+
+    /// NB: We use argument named `local_delayedpubkey`, but in fact the source
+    /// for this key is the remote node key, since we generate a transaction
+    /// which we will sign for the remote node.
+    fn ln_taproot_to_local(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self;
+
+    fn ln_taproot_to_remote(amount: u64, remote_pubkey: PublicKey) -> Self;
+
+    fn ln_taproot_offered_htlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self;
+
+    fn ln_taproot_received_htlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+        payment_hash: HashLock,
+    ) -> Self;
+
+    /// Output of the second-level HTLC transactions, spendable by the
+    /// commitment owner after a delay or by the counterparty with the
+    /// revocation key as the internal key
+    fn ln_taproot_htlc_output(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self;
+}
+
+pub(super) fn two_leaves(first: Script, second: Script) -> TaprootBuilder {
+    TaprootBuilder::new()
+        .add_leaf(1, first)
+        .and_then(|builder| builder.add_leaf(1, second))
+        .expect("two leaves at depth 1 always form a complete tree")
+}
+
+fn spend_info(
+    builder: TaprootBuilder,
+    internal_key: XOnlyPublicKey,
+) -> TaprootSpendInfo {
+    builder
+        .finalize(SECP256K1, internal_key)
+        .expect("complete tree can always be finalized")
+}
+
+/// Builds P2TR output from the internal key and optional script tree
+pub(super) fn taproot_output(
+    amount: u64,
+    internal_key: XOnlyPublicKey,
+    builder: Option<TaprootBuilder>,
+) -> psbt::Output {
+    let (script_pubkey, tap_tree) = match builder {
+        Some(builder) => {
+            let info = spend_info(builder.clone(), internal_key);
+            let tree = TapTree::try_from(builder)
+                .expect("complete tree is always convertible");
+            (Script::new_v1_p2tr_tweaked(info.output_key()), Some(tree))
+        }
+        None => (Script::new_v1_p2tr(SECP256K1, internal_key, None), None),
+    };
+    let txout = TxOut {
+        value: amount,
+        script_pubkey,
+    };
+    let output = bitcoin::psbt::Output {
+        tap_internal_key: Some(internal_key),
+        tap_tree,
+        ..Default::default()
+    };
+    psbt::Output::with(0, output, txout)
+}
+
+impl TaprootScriptGenerators for psbt::Output {
+    fn ln_taproot_funding(
+        amount: u64,
+        local_pubkey: &LocalPubkey,
+        remote_pubkey: PublicKey,
+    ) -> Self {
+        let key_agg = KeyAggContext::new([local_pubkey.key, remote_pubkey])
+            .expect("two valid public keys can always be aggregated");
+        let mut output = taproot_output(amount, key_agg.x_only_key(), None);
+        output.bip32_derivation = local_pubkey.to_bip32_derivation_map();
+        output
+    }
+
+    fn ln_taproot_to_local(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        let builder = two_leaves(
+            Script::ln_to_delay_leaf(local_delayedpubkey, to_self_delay),
+            Script::ln_revoke_leaf(revocationpubkey, local_delayedpubkey),
+        );
+        taproot_output(amount, nums_point(), Some(builder))
+    }
+
+    fn ln_taproot_to_remote(amount: u64, remote_pubkey: PublicKey) -> Self {
+        let builder = TaprootBuilder::new()
+            .add_leaf(0, Script::ln_to_remote_leaf(remote_pubkey))
+            .expect("single leaf always forms a complete tree");
+        taproot_output(amount, nums_point(), Some(builder))
+    }
+
+    fn ln_taproot_offered_htlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self {
+        let builder = two_leaves(
+            Script::ln_offered_timeout_leaf(
+                local_htlcpubkey,
+                remote_htlcpubkey,
+            ),
+            Script::ln_offered_success_leaf(remote_htlcpubkey, payment_hash),
+        );
+        taproot_output(
+            amount,
+            revocationpubkey.x_only_public_key().0,
+            Some(builder),
+        )
+    }
+
+    fn ln_taproot_received_htlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+        payment_hash: HashLock,
+    ) -> Self {
+        let builder = two_leaves(
+            Script::ln_received_timeout_leaf(remote_htlcpubkey, cltv_expiry),
+            Script::ln_received_success_leaf(
+                local_htlcpubkey,
+                remote_htlcpubkey,
+                payment_hash,
+            ),
+        );
+        taproot_output(
+            amount,
+            revocationpubkey.x_only_public_key().0,
+            Some(builder),
+        )
+    }
+
+    fn ln_taproot_htlc_output(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        let builder = TaprootBuilder::new()
+            .add_leaf(
+                0,
+                Script::ln_to_delay_leaf(local_delayedpubkey, to_self_delay),
+            )
+            .expect("single leaf always forms a complete tree");
+        taproot_output(
+            amount,
+            revocationpubkey.x_only_public_key().0,
+            Some(builder),
+        )
+    }
+}
+
+impl TaprootScriptGenerators for PubkeyScript {
+    #[inline]
+    fn ln_taproot_funding(
+        amount: u64,
+        local_pubkey: &LocalPubkey,
+        remote_pubkey: PublicKey,
+    ) -> Self {
+        psbt::Output::ln_taproot_funding(amount, local_pubkey, remote_pubkey)
+            .script
+    }
+
+    #[inline]
+    fn ln_taproot_to_local(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        psbt::Output::ln_taproot_to_local(
+            amount,
+            revocationpubkey,
+            local_delayedpubkey,
+            to_self_delay,
+        )
+        .script
+    }
+
+    #[inline]
+    fn ln_taproot_to_remote(amount: u64, remote_pubkey: PublicKey) -> Self {
+        psbt::Output::ln_taproot_to_remote(amount, remote_pubkey).script
+    }
+
+    #[inline]
+    fn ln_taproot_offered_htlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        payment_hash: HashLock,
+    ) -> Self {
+        psbt::Output::ln_taproot_offered_htlc(
+            amount,
+            revocationpubkey,
+            local_htlcpubkey,
+            remote_htlcpubkey,
+            payment_hash,
+        )
+        .script
+    }
+
+    #[inline]
+    fn ln_taproot_received_htlc(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_htlcpubkey: PublicKey,
+        remote_htlcpubkey: PublicKey,
+        cltv_expiry: u32,
+        payment_hash: HashLock,
+    ) -> Self {
+        psbt::Output::ln_taproot_received_htlc(
+            amount,
+            revocationpubkey,
+            local_htlcpubkey,
+            remote_htlcpubkey,
+            cltv_expiry,
+            payment_hash,
+        )
+        .script
+    }
+
+    #[inline]
+    fn ln_taproot_htlc_output(
+        amount: u64,
+        revocationpubkey: PublicKey,
+        local_delayedpubkey: PublicKey,
+        to_self_delay: u16,
+    ) -> Self {
+        psbt::Output::ln_taproot_htlc_output(
+            amount,
+            revocationpubkey,
+            local_delayedpubkey,
+            to_self_delay,
+        )
+        .script
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::{DumbDefault, Wrapper};
+    use bitcoin::hashes::sha256;
+    use bitcoin::schnorr::TweakedPublicKey;
+    use bitcoin::util::taproot::{LeafVersion, TapBranchHash, TapLeafHash};
+    use secp256k1::SecretKey;
+
+    use super::*;
+
+    fn key(n: u8) -> PublicKey {
+        PublicKey::from_secret_key(
+            SECP256K1,
+            &SecretKey::from_slice(&[n; 32]).unwrap(),
+        )
+    }
+
+    fn leaf_hash(script: &Script) -> sha256::Hash {
+        let hash = TapLeafHash::from_script(script, LeafVersion::TapScript);
+        sha256::Hash::from_inner(hash.into_inner())
+    }
+
+    /// Output script committing to the provided leaves, computed without
+    /// the use of the taproot builder
+    fn p2tr(internal_key: XOnlyPublicKey, leaves: &[Script]) -> Script {
+        let merkle_root = match leaves {
+            [leaf] => TapBranchHash::from_inner(leaf_hash(leaf).into_inner()),
+            [first, second] => TapBranchHash::from_node_hashes(
+                leaf_hash(first),
+                leaf_hash(second),
+            ),
+            _ => unreachable!("only one- and two-leaf trees are used"),
+        };
+        Script::new_v1_p2tr(SECP256K1, internal_key, Some(merkle_root))
+    }
+
+    #[test]
+    fn nums() {
+        // BIP-341: hash of the uncompressed encoding of the generator point
+        let one = SecretKey::from_slice(&[
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        ])
+        .unwrap();
+        let generator = PublicKey::from_secret_key(SECP256K1, &one);
+        assert_eq!(
+            sha256::Hash::hash(&generator.serialize_uncompressed())
+                .into_inner(),
+            NUMS_POINT
+        );
+        assert_eq!(nums_point().serialize(), NUMS_POINT);
+    }
+
+    #[test]
+    fn funding_output() {
+        let local_pubkey = LocalPubkey {
+            key: key(1),
+            ..LocalPubkey::dumb_default()
+        };
+        let output =
+            psbt::Output::ln_taproot_funding(1_000_000, &local_pubkey, key(2));
+        assert_eq!(output.amount, 1_000_000);
+
+        // Funding output has no script path and its key is spendable with
+        // MuSig2 signatures over the tweaked aggregated key
+        let key_agg = funding_key_agg(key(1), key(2)).unwrap();
+        let output_key =
+            TweakedPublicKey::dangerous_assume_tweaked(key_agg.x_only_key());
+        assert_eq!(
+            output.script.as_inner(),
+            &Script::new_v1_p2tr_tweaked(output_key)
+        );
+        assert_eq!(output.tap_tree, None);
+        let internal_key =
+            KeyAggContext::new([key(1), key(2)]).unwrap().x_only_key();
+        assert_eq!(output.tap_internal_key, Some(internal_key));
+
+        // Both peers construct the same funding output
+        let remote_pubkey = LocalPubkey {
+            key: key(2),
+            ..LocalPubkey::dumb_default()
+        };
+        assert_eq!(
+            PubkeyScript::ln_taproot_funding(1_000_000, &remote_pubkey, key(1)),
+            output.script
+        );
+    }
+
+    #[test]
+    fn commitment_outputs() {
+        let revocationpubkey = key(1);
+        let delayedpubkey = key(2);
+        let local_htlcpubkey = key(3);
+        let remote_htlcpubkey = key(4);
+        let payment_hash = HashLock::from_inner([0xAB; 32].into());
+        let hash160 = ripemd160::Hash::hash(payment_hash.as_ref());
+        let x_only = |pubkey: PublicKey| pubkey.x_only_public_key().0;
+
+        // `to_local` is spendable only through the script path
+        let to_delay = Script::ln_to_delay_leaf(delayedpubkey, 144);
+        let revoke = Script::ln_revoke_leaf(revocationpubkey, delayedpubkey);
+        assert_eq!(
+            to_delay.to_string(),
+            format!(
+                "Script(OP_PUSHBYTES_32 {} OP_CHECKSIG OP_PUSHBYTES_2 9000 \
+                 OP_CSV OP_DROP)",
+                x_only(delayedpubkey)
+            )
+        );
+        assert_eq!(
+            revoke.to_string(),
+            format!(
+                "Script(OP_PUSHBYTES_32 {} OP_DROP OP_PUSHBYTES_32 {} \
+                 OP_CHECKSIG)",
+                x_only(delayedpubkey),
+                x_only(revocationpubkey)
+            )
+        );
+        let output = psbt::Output::ln_taproot_to_local(
+            1000,
+            revocationpubkey,
+            delayedpubkey,
+            144,
+        );
+        assert_eq!(output.tap_internal_key, Some(nums_point()));
+        assert_eq!(
+            output.script.as_inner(),
+            &p2tr(nums_point(), &[to_delay, revoke])
+        );
+
+        // `to_remote` has a single leaf with 1-block delay
+        let to_remote = Script::ln_to_remote_leaf(key(5));
+        assert_eq!(
+            to_remote.to_string(),
+            format!(
+                "Script(OP_PUSHBYTES_32 {} OP_CHECKSIG OP_PUSHNUM_1 OP_CSV \
+                 OP_DROP)",
+                x_only(key(5))
+            )
+        );
+        let script = PubkeyScript::ln_taproot_to_remote(1000, key(5));
+        assert_eq!(script.as_inner(), &p2tr(nums_point(), &[to_remote]));
+
+        // HTLC outputs use revocation key as the internal key
+        let timeout = Script::ln_offered_timeout_leaf(
+            local_htlcpubkey,
+            remote_htlcpubkey,
+        );
+        let success =
+            Script::ln_offered_success_leaf(remote_htlcpubkey, payment_hash);
+        assert_eq!(
+            success.to_string(),
+            format!(
+                "Script(OP_SIZE OP_PUSHBYTES_1 20 OP_EQUALVERIFY OP_HASH160 \
+                 OP_PUSHBYTES_20 {} OP_EQUALVERIFY OP_PUSHBYTES_32 {} \
+                 OP_CHECKSIG OP_PUSHNUM_1 OP_CSV OP_DROP)",
+                hash160,
+                x_only(remote_htlcpubkey)
+            )
+        );
+        let output = psbt::Output::ln_taproot_offered_htlc(
+            1000,
+            revocationpubkey,
+            local_htlcpubkey,
+            remote_htlcpubkey,
+            payment_hash,
+        );
+        assert_eq!(output.tap_internal_key, Some(x_only(revocationpubkey)));
+        assert_eq!(
+            output.script.as_inner(),
+            &p2tr(x_only(revocationpubkey), &[timeout, success])
+        );
+
+        let timeout =
+            Script::ln_received_timeout_leaf(remote_htlcpubkey, 500_000);
+        let success = Script::ln_received_success_leaf(
+            local_htlcpubkey,
+            remote_htlcpubkey,
+            payment_hash,
+        );
+        assert_eq!(
+            timeout.to_string(),
+            format!(
+                "Script(OP_PUSHBYTES_32 {} OP_CHECKSIG OP_PUSHNUM_1 OP_CSV \
+                 OP_DROP OP_PUSHBYTES_3 20a107 OP_CLTV OP_DROP)",
+                x_only(remote_htlcpubkey)
+            )
+        );
+        let script = PubkeyScript::ln_taproot_received_htlc(
+            1000,
+            revocationpubkey,
+            local_htlcpubkey,
+            remote_htlcpubkey,
+            500_000,
+            payment_hash,
+        );
+        assert_eq!(
+            script.as_inner(),
+            &p2tr(x_only(revocationpubkey), &[timeout, success])
+        );
+
+        // Second-level HTLC output
+        let script = PubkeyScript::ln_taproot_htlc_output(
+            1000,
+            revocationpubkey,
+            delayedpubkey,
+            144,
+        );
+        assert_eq!(
+            script.as_inner(),
+            &p2tr(x_only(revocationpubkey), &[Script::ln_to_delay_leaf(
+                delayedpubkey,
+                144
+            )])
+        );
+    }
+}
@@ -19,11 +19,15 @@ use internet2::presentation::sphinx::Hop;
 use lnp2p::bolt::Messages;
 use lnpbp::chain::AssetId;
 use p2p::bolt::PaymentOnion;
+use secp256k1::SecretKey;
 use strict_encoding::{
     self, strict_deserialize, strict_serialize, StrictDecode, StrictEncode,
 };
 
-use super::{AnchorOutputs, BoltChannel, ChannelState, Error, Htlc};
+use super::{
+    AnchorOutputs, BoltChannel, ChannelState, CommitmentPolicy, Error, Htlc,
+    ShutdownScript,
+};
 use crate::channel::shared_ext::Bip96;
 use crate::channel::tx_graph::TxRole;
 use crate::channel::{self, Channel};
@@ -43,6 +47,10 @@ pub enum BoltExt {
     Bolt3 = 1,
     /// HTLC payments
     Htlc = 2,
+    /// Experimental PTLC payments
+    Ptlc = 3,
+    /// Experimental discreet log contracts
+    Dlc = 4,
 
     /// BOLT-9 feature: shutdown script
     ShutdownScript = 10,
@@ -56,6 +64,12 @@ pub enum BoltExt {
     /// NB: Policy must always be applied after other extenders
     Policy = 100,
 
+    /// RGB asset allocations
+    ///
+    /// NB: Must be applied before BIP96 output ordering, since it modifies
+    /// commitment outputs
+    Rgb = 200,
+
     /// Deterministic transaction ordering
     Bip96 = 1000,
 }
@@ -98,7 +112,7 @@ impl channel::Nomenclature for BoltExt {
 
     #[inline]
     fn default_extenders() -> Vec<Box<dyn ChannelExtension<Self>>> {
-        vec![Htlc::new()]
+        vec![Htlc::new(), ShutdownScript::new(), CommitmentPolicy::new()]
     }
 
     #[inline]
@@ -110,22 +124,51 @@ impl channel::Nomenclature for BoltExt {
         channel: &mut Channel<Self>,
         message: &Messages,
     ) -> Result<(), Error> {
-        #[allow(clippy::single_match)] // We'll add more code in the future
         match message {
-            Messages::OpenChannel(open_channel) => {
-                if open_channel.has_anchor_outputs() {
-                    channel.add_extender(AnchorOutputs::new())
+            Messages::OpenChannel(open_channel)
+                if open_channel.has_anchor_outputs() =>
+            {
+                channel.add_extender(AnchorOutputs::new())
+            }
+            Messages::SpliceLocked(splice_locked) => {
+                let txid = splice_locked.splice_txid;
+                if channel.constructor().local_splice_locked() == Some(txid) {
+                    channel.promote_splice(txid)?;
                 }
             }
             _ => {}
         }
         Ok(())
     }
+
+    fn finalize_update_from_peer(
+        channel: &mut Channel<Self>,
+        message: &Messages,
+    ) -> Result<(), Error> {
+        if let Messages::OpenChannel(_) | Messages::OpenChannel2(_) = message {
+            // Channel acceptor may have overridden local channel parameters,
+            // which must be seen by the rest of the extensions
+            channel.update_constructor(|_| ());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum UpdateReq {
+    OpenChannel,
+    AcceptChannel,
+    Shutdown,
+    UpdateFee,
     PayBolt(Vec<Hop<PaymentOnion>>),
+    FulfillHtlc,
+    FailHtlc,
+    PayPtlc(Vec<Hop<PaymentOnion>>),
+    FulfillPtlc,
+    FailPtlc,
+    OfferDlc,
+    SignDlc(SecretKey),
+    SettleDlc,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
@@ -134,6 +177,9 @@ pub enum UpdateReq {
 pub enum TxType {
     HtlcSuccess,
     HtlcTimeout,
+    PtlcSuccess,
+    PtlcTimeout,
+    DlcCet,
     Unknown(u16),
 }
 
@@ -142,6 +188,9 @@ impl From<TxType> for u16 {
         match ty {
             TxType::HtlcSuccess => 0x0,
             TxType::HtlcTimeout => 0x1,
+            TxType::PtlcSuccess => 0x2,
+            TxType::PtlcTimeout => 0x3,
+            TxType::DlcCet => 0x4,
             TxType::Unknown(x) => x,
         }
     }
@@ -152,6 +201,9 @@ impl From<u16> for TxType {
         match ty {
             0x00 => TxType::HtlcSuccess,
             0x01 => TxType::HtlcTimeout,
+            0x02 => TxType::PtlcSuccess,
+            0x03 => TxType::PtlcTimeout,
+            0x04 => TxType::DlcCet,
             x => TxType::Unknown(x),
         }
     }
@@ -229,6 +281,11 @@ pub enum Lifecycle {
     /// Channel non-operational and closed
     #[display("CLOSED")]
     Closed,
+
+    /// Both peers have sent `stfu` and the channel does not accept any updates
+    /// until the end of the operation requiring quiescence (like splicing)
+    #[display("QUIESCENT")]
+    Quiescent,
 }
 
 impl Default for Lifecycle {
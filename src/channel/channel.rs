@@ -14,8 +14,10 @@
 use std::any::Any;
 use std::collections::BTreeMap;
 use std::io::{Read, Write};
+use std::iter;
 
 use amplify::DumbDefault;
+use bitcoin::Txid;
 use strict_encoding::{StrictDecode, StrictEncode};
 use wallet::psbt::Psbt;
 
@@ -51,6 +53,17 @@ where
         channel: &mut Channel<Self>,
         message: &Self::PeerMessage,
     ) -> Result<(), <Self as extension::Nomenclature>::Error>;
+
+    /// Finalizes update of the channel extension structure from peer message.
+    /// Processed after each of the registered extensions gets
+    /// [`Extension::update_from_peer`]
+    #[allow(unused_variables)]
+    fn finalize_update_from_peer(
+        channel: &mut Channel<Self>,
+        message: &Self::PeerMessage,
+    ) -> Result<(), <Self as extension::Nomenclature>::Error> {
+        Ok(())
+    }
 }
 
 /// Trait for any data that can be used as a part of the channel state
@@ -223,10 +236,55 @@ where
         Ok(tx_graph.render_cmt())
     }
 
+    /// Constructs current version of commitment transactions for the current
+    /// channel funding and for each of the pending funding candidates (like
+    /// unconfirmed splices), in this order
+    pub fn commitment_txs(
+        &mut self,
+        remote: bool,
+    ) -> Result<Vec<Psbt>, <N as extension::Nomenclature>::Error> {
+        let fundings =
+            iter::once(&self.funding).chain(self.funding.candidates());
+        let mut txs = Vec::with_capacity(1 + self.funding.candidates().len());
+        for funding in fundings {
+            let mut tx_graph = TxGraph::from_funding(funding);
+            self.build_graph(&mut tx_graph, remote)?;
+            txs.push(tx_graph.render_cmt());
+        }
+        Ok(txs)
+    }
+
     #[inline]
     pub fn set_funding_amount(&mut self, amount: u64) {
         self.funding = Funding::preliminary(amount)
     }
+
+    /// Returns current channel state composed by all channel extensions, as
+    /// it is persisted in the channel storage
+    pub fn state(&self) -> N::State {
+        let mut state = N::State::dumb_default();
+        self.store_state(&mut state);
+        state
+    }
+
+    /// Updates channel constructor and propagates its new data (keys,
+    /// parameters etc) to the rest of the extensions through
+    /// [`Extension::load_state`], the same way as a channel is restored from
+    /// its persisted state.
+    pub fn update_constructor<T>(
+        &mut self,
+        update: impl FnOnce(&mut N::Constructor) -> T,
+    ) -> T {
+        let result = update(&mut self.constructor);
+        let state = self.state();
+        for extension in self.extenders.values_mut() {
+            extension.load_state(&state);
+        }
+        for extension in self.modifiers.values_mut() {
+            extension.load_state(&state);
+        }
+        result
+    }
 }
 
 impl<N> Channel<N>
@@ -256,6 +314,32 @@ where
         self.funding = Funding::with(psbt)?;
         Ok(())
     }
+
+    /// Registers new funding transaction spending the current channel funding
+    /// output (like a splice transaction), which is not confirmed yet. Until
+    /// it gets promoted with [`Channel::promote_funding_candidate`],
+    /// commitments are constructed for both current and candidate fundings.
+    pub fn add_funding_candidate(
+        &mut self,
+        mut psbt: Psbt,
+    ) -> Result<Txid, <N as extension::Nomenclature>::Error> {
+        self.constructor.enrich_funding(&mut psbt, &self.funding)?;
+        let candidate = Funding::with(psbt)?;
+        let txid = candidate.txid();
+        self.funding.add_candidate(candidate)?;
+        Ok(txid)
+    }
+
+    /// Replaces current channel funding with the candidate funding transaction
+    /// having a given txid, discarding all other candidates.
+    #[inline]
+    pub fn promote_funding_candidate(
+        &mut self,
+        txid: Txid,
+    ) -> Result<(), <N as extension::Nomenclature>::Error> {
+        self.funding.promote_candidate(txid)?;
+        Ok(())
+    }
 }
 
 impl<N> Default for Channel<N>
@@ -281,9 +365,7 @@ where
         &self,
         e: E,
     ) -> Result<usize, strict_encoding::Error> {
-        let mut state = N::State::dumb_default();
-        self.store_state(&mut state);
-        state.strict_encode(e)
+        self.state().strict_encode(e)
     }
 }
 
@@ -320,7 +402,7 @@ where
         for extension in self.extenders.values_mut() {
             extension.state_change(request, message)?;
         }
-        for extension in self.extenders.values_mut() {
+        for extension in self.modifiers.values_mut() {
             extension.state_change(request, message)?;
         }
         Ok(())
@@ -340,10 +422,25 @@ where
         Ok(())
     }
 
+    fn validate_peer_message(
+        &self,
+        message: &<N as extension::Nomenclature>::PeerMessage,
+    ) -> Result<(), <N as extension::Nomenclature>::Error> {
+        self.constructor.validate_peer_message(message)?;
+        self.extenders
+            .iter()
+            .try_for_each(|(_, e)| e.validate_peer_message(message))?;
+        self.modifiers
+            .iter()
+            .try_for_each(|(_, e)| e.validate_peer_message(message))?;
+        Ok(())
+    }
+
     fn update_from_peer(
         &mut self,
         message: &<N as extension::Nomenclature>::PeerMessage,
     ) -> Result<(), <N as extension::Nomenclature>::Error> {
+        self.validate_peer_message(message)?;
         N::update_from_peer(self, message)?;
         self.constructor.update_from_peer(message)?;
         self.extenders
@@ -352,7 +449,7 @@ where
         self.modifiers
             .iter_mut()
             .try_for_each(|(_, e)| e.update_from_peer(message))?;
-        Ok(())
+        N::finalize_update_from_peer(self, message)
     }
 
     fn load_state(&mut self, state: &N::State) {
@@ -361,7 +458,7 @@ where
         for extension in self.extenders.values_mut() {
             extension.load_state(state);
         }
-        for extension in self.extenders.values_mut() {
+        for extension in self.modifiers.values_mut() {
             extension.load_state(state);
         }
     }
@@ -372,7 +469,7 @@ where
         for extension in self.extenders.values() {
             extension.store_state(state);
         }
-        for extension in self.extenders.values() {
+        for extension in self.modifiers.values() {
             extension.store_state(state);
         }
     }
@@ -31,6 +31,13 @@ pub enum Error {
     /// funding transaction does not contain output #{0} specified as a
     /// funding outpoint
     WrongOutput(u16),
+
+    /// funding candidate {0} does not spend the current channel funding
+    /// output
+    CandidateNotSpendingFunding(Txid),
+
+    /// funding candidate {0} is not known
+    UnknownCandidate(Txid),
 }
 
 /// Information about channel funding
@@ -65,6 +72,18 @@ pub struct Funding {
 
     #[getter(as_copy)]
     signing_threshold: u8,
+
+    /// Funding transactions spending the current funding output (like splice
+    /// transactions) which were negotiated with the remote peer, but are not
+    /// confirmed yet. Until one of them gets confirmed, channel commitments
+    /// must be signed for each of them, in addition to the current funding.
+    ///
+    /// Candidates are not a part of the strict encoding, keeping it
+    /// compatible with the funding data persisted before splicing was
+    /// supported; channel states persist them separately.
+    #[strict_encoding(skip)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    candidates: Vec<Funding>,
 }
 
 impl Funding {
@@ -107,6 +126,68 @@ impl Funding {
     pub fn outpoint(&self) -> OutPoint {
         OutPoint::new(self.txid, self.output as u32)
     }
+
+    /// Detects whether there are pending funding candidates which are not
+    /// confirmed yet
+    #[inline]
+    pub fn has_candidates(&self) -> bool {
+        !self.candidates.is_empty()
+    }
+
+    /// Returns candidate funding with a given transaction id, if known
+    #[inline]
+    pub fn candidate(&self, txid: Txid) -> Option<&Funding> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.txid == txid)
+    }
+
+    /// Returns outpoints of the current funding and all pending funding
+    /// candidates
+    pub fn outpoints(&self) -> Vec<OutPoint> {
+        std::iter::once(self.outpoint())
+            .chain(self.candidates.iter().map(Funding::outpoint))
+            .collect()
+    }
+
+    /// Adds new pending funding candidate, which must spend the current
+    /// funding output. Adding the same candidate twice has no effect.
+    pub fn add_candidate(&mut self, candidate: Funding) -> Result<(), Error> {
+        let outpoint = self.outpoint();
+        if !candidate
+            .psbt
+            .inputs
+            .iter()
+            .any(|input| input.previous_outpoint == outpoint)
+        {
+            return Err(Error::CandidateNotSpendingFunding(candidate.txid));
+        }
+        if self.candidate(candidate.txid).is_none() {
+            self.candidates.push(candidate);
+        }
+        Ok(())
+    }
+
+    /// Restores pending funding candidates persisted separately from the
+    /// funding data
+    #[inline]
+    pub(crate) fn set_candidates(&mut self, candidates: Vec<Funding>) {
+        self.candidates = candidates
+    }
+
+    /// Replaces the current funding with the confirmed candidate, discarding
+    /// all other candidates.
+    pub fn promote_candidate(&mut self, txid: Txid) -> Result<(), Error> {
+        let pos = self
+            .candidates
+            .iter()
+            .position(|candidate| candidate.txid == txid)
+            .ok_or(Error::UnknownCandidate(txid))?;
+        let mut candidate = self.candidates.swap_remove(pos);
+        candidate.candidates = vec![];
+        *self = candidate;
+        Ok(())
+    }
 }
 
 fn lnp_out_channel_funding_key() -> ProprietaryKey {
@@ -166,6 +247,7 @@ impl PsbtLnpFunding for Psbt {
             amount,
             signing_parties: 2,
             signing_threshold: 2,
+            candidates: vec![],
         })
     }
 }
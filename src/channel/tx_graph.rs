@@ -142,6 +142,7 @@ where
             script_pubkey: funding_output.script.clone().into(),
         });
         psbt.inputs[0].witness_script = funding_output.witness_script.clone();
+        psbt.inputs[0].tap_internal_key = funding_output.tap_internal_key;
         psbt.inputs[0].bip32_derivation =
             funding_output.bip32_derivation.clone();
         for (index, output) in psbt.outputs.iter_mut().enumerate() {
@@ -22,6 +22,7 @@ use wallet::psbt::Psbt;
 
 use crate::channel::tx_graph::TxGraph;
 use crate::channel::Funding;
+use crate::router::RouteParams;
 use crate::{channel, extension, router};
 
 /// Marker trait for creating extension nomenclatures, defining order in which
@@ -66,6 +67,21 @@ pub trait Extension<N: Nomenclature> {
         Ok(())
     }
 
+    /// Checks whether the message received from the remote peer can be
+    /// accepted by the extension without changing its state.
+    ///
+    /// All extensions validate the message before any of them gets updated
+    /// with it, so a message rejected by one of the extensions is not
+    /// partially applied by the others.
+    #[allow(dead_code, unused_variables)]
+    fn validate_peer_message(
+        &self,
+        message: &<N as extension::Nomenclature>::PeerMessage,
+    ) -> Result<(), <N as extension::Nomenclature>::Error> {
+        // Accept everything by default
+        Ok(())
+    }
+
     /// Updates extension state from the data taken from the message received
     /// from the remote peer
     fn update_from_peer(
@@ -95,11 +111,30 @@ where
     where
         Self: Sized;
 
+    /// Constructs route for the payment satisfying provided route
+    /// parameters. Extensions which are unable to construct such route must
+    /// leave `route` intact.
     fn build_route(
         &mut self,
-        payment: PaymentRequest,
+        payment: &PaymentRequest,
+        params: &RouteParams,
         route: &mut Vec<Hop<<N as router::Nomenclature>::HopPayload>>,
     );
+
+    /// Constructs routes for the parts of a multi-part payment, which
+    /// together deliver the whole payment amount. Extensions which do not
+    /// support multi-part payments or are unable to route the whole amount
+    /// must leave `routes` intact.
+    ///
+    /// Parts carry payment secret of the `payment` request, which is required
+    /// by BOLT-4 `basic_mpp`.
+    fn build_multipath_route(
+        &mut self,
+        _payment: &PaymentRequest,
+        _params: &RouteParams,
+        _routes: &mut Vec<Vec<Hop<<N as router::Nomenclature>::HopPayload>>>,
+    ) {
+    }
 }
 
 pub trait ChannelExtension<N>
@@ -11,8 +11,28 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
+mod mpp;
+mod onion;
+mod path;
+mod queries;
 mod router;
+mod scorer;
 mod util;
+mod utxo;
 
-pub use router::{DirectRouter, Error, GossipExt, GossipRouter, UpdateMsg};
-pub use util::{GossipChannelInfo, LocalChannelInfo};
+pub use mpp::{MAX_PAYMENT_PARTS, MIN_PAYMENT_PART_MSAT};
+pub use path::FoundRoute;
+pub use queries::{ChannelGossip, GossipQueries, MAX_SHORT_IDS_PER_MESSAGE};
+pub use router::{
+    DirectRouter, Error, GossipExt, GossipRouter, UpdateMsg, UpdateReq,
+};
+pub use scorer::{
+    effective_capacity_msat, LiquidityBounds, ProbabilisticScorer,
+    ScorerParams, MAX_CHANNEL_CAPACITY_MSAT,
+};
+pub use util::{
+    DirectionalInfo, GossipChannelInfo, GossipNodeInfo, LocalChannelInfo,
+};
+pub use utxo::{
+    funding_script_pubkey, UtxoLookup, UtxoLookupError, UtxoOracle,
+};
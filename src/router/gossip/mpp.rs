@@ -0,0 +1,475 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use internet2::addr::NodeId;
+use internet2::presentation::sphinx::Hop;
+use p2p::bolt::{
+    HopRealm, InitFeatures, PaymentOnion, PaymentRequest, ShortChannelId,
+};
+
+use super::onion::select_realms;
+use super::path::{find_route, FoundRoute};
+use super::scorer::ProbabilisticScorer;
+use super::{GossipChannelInfo, LocalChannelInfo};
+use crate::router::{FeeLimit, RouteParams};
+
+/// Maximum number of parts a single payment can be split into
+pub const MAX_PAYMENT_PARTS: usize = 16;
+
+/// Minimal amount of a payment part, unless it is the remainder of the
+/// payment
+pub const MIN_PAYMENT_PART_MSAT: u64 = 10_000;
+
+/// Splits payment into multiple parts routed through different direct
+/// channels and paths, following BOLT-4 `basic_mpp` rules. Returns `None` if
+/// the whole payment amount can't be routed, the payment request has no
+/// payment secret or its destination does not support `basic_mpp`, as known
+/// from the invoice or node `features`.
+///
+/// Parts are allocated greedily, starting with direct channels having the
+/// largest outbound capacity. Once a part is allocated, the outbound
+/// capacity of the direct channel and known capacities of the gossip
+/// channels used by the part are reduced, so the following parts do not
+/// rely on the same liquidity.
+///
+/// Hops of the parts get payloads matching features of their nodes; the
+/// destination gets payment data with the total payment amount.
+pub(super) fn split_payment<'features>(
+    channels: &BTreeMap<ShortChannelId, GossipChannelInfo>,
+    direct_channels: &[LocalChannelInfo],
+    payment: &PaymentRequest,
+    params: &RouteParams,
+    scorer: &ProbabilisticScorer,
+    now: u64,
+    features: impl Fn(NodeId) -> Option<&'features InitFeatures>,
+) -> Option<Vec<Vec<Hop<PaymentOnion>>>> {
+    let destination = payment
+        .features
+        .as_ref()
+        .or_else(|| features(payment.node_id));
+    if payment.payment_secret.is_none()
+        || !matches!(destination, Some(features) if features.basic_mpp.is_some())
+    {
+        return None;
+    }
+
+    let mut channels = channels.clone();
+    let mut direct_channels = direct_channels.to_vec();
+    direct_channels
+        .sort_by_key(|channel| Reverse(channel.outbound_capacity_msat));
+    let params = part_params(params, payment.amount_msat);
+
+    let mut parts = vec![];
+    let mut remaining = payment.amount_msat;
+    while remaining > 0 {
+        if parts.len() >= MAX_PAYMENT_PARTS {
+            return None;
+        }
+        let (index, mut route) = allocate_part(
+            &channels,
+            &direct_channels,
+            payment,
+            remaining,
+            &params,
+            scorer,
+            now,
+        )?;
+
+        let part_msat = route
+            .hops
+            .last()
+            .expect("route always has at least one hop")
+            .payload
+            .amt_to_forward;
+        remaining -= part_msat;
+        let direct = &mut direct_channels[index];
+        direct.outbound_capacity_msat -= route.amount_msat;
+        for hop in &route.hops[..route.hops.len() - 1] {
+            if let HopRealm::Legacy(short_channel_id) = hop.payload.realm {
+                if let Some(capacity) = channels
+                    .get_mut(&short_channel_id)
+                    .and_then(|info| info.capacity_sats.as_mut())
+                {
+                    let used_sats = (hop.payload.amt_to_forward + 999) / 1000;
+                    *capacity = capacity.saturating_sub(used_sats);
+                }
+            }
+        }
+        if !select_realms(&mut route.hops, payment, &features) {
+            return None;
+        }
+        parts.push(route.hops);
+    }
+
+    Some(parts)
+}
+
+/// Finds route for the largest possible part of the `remaining` amount,
+/// trying direct channels one by one. If the fees of the found route exceed
+/// direct channel outbound capacity, the part is reduced by the excess; if no
+/// route was found, the part amount is halved. Returns index of the used
+/// direct channel and the route.
+fn allocate_part(
+    channels: &BTreeMap<ShortChannelId, GossipChannelInfo>,
+    direct_channels: &[LocalChannelInfo],
+    payment: &PaymentRequest,
+    remaining: u64,
+    params: &RouteParams,
+    scorer: &ProbabilisticScorer,
+    now: u64,
+) -> Option<(usize, FoundRoute)> {
+    for (index, direct) in direct_channels.iter().enumerate() {
+        // Route search must not be limited by the outbound capacity, since we
+        // need to know the amount of fees exceeding it
+        let unlimited = LocalChannelInfo {
+            outbound_capacity_msat: u64::MAX,
+            ..*direct
+        };
+        let mut part_msat = remaining.min(direct.outbound_capacity_msat);
+        while part_msat > 0
+            && (part_msat >= MIN_PAYMENT_PART_MSAT || part_msat == remaining)
+        {
+            let part = PaymentRequest {
+                amount_msat: part_msat,
+                ..payment.clone()
+            };
+            let route = if direct.remote_node == payment.node_id {
+                direct_part(direct, &part, params)
+            } else {
+                find_route(
+                    channels.values(),
+                    &[unlimited],
+                    &part,
+                    params,
+                    scorer,
+                    now,
+                )
+            };
+            match route {
+                None => part_msat /= 2,
+                Some(route)
+                    if route.amount_msat > direct.outbound_capacity_msat =>
+                {
+                    part_msat = part_msat.saturating_sub(
+                        route.amount_msat - direct.outbound_capacity_msat,
+                    )
+                }
+                Some(route) => return Some((index, route)),
+            }
+        }
+    }
+    None
+}
+
+/// Distributes absolute fee limit across the payment parts proportionally to
+/// their amounts
+fn part_params(params: &RouteParams, total_msat: u64) -> RouteParams {
+    let mut params = params.clone();
+    if let Some(FeeLimit::AbsoluteMsat(limit)) = params.max_fee {
+        let ppm = limit as u128 * 1_000_000 / total_msat.max(1) as u128;
+        params.max_fee = Some(FeeLimit::ProportionalMillionths(
+            ppm.min(u32::MAX as u128) as u32,
+        ));
+    }
+    params
+}
+
+/// Constructs single-hop part sent directly to the destination
+fn direct_part(
+    direct: &LocalChannelInfo,
+    payment: &PaymentRequest,
+    params: &RouteParams,
+) -> Option<FoundRoute> {
+    if !params.is_node_allowed(payment.node_id)
+        || !params.is_outbound_channel_allowed(
+            direct.short_channel_id,
+            direct.remote_node,
+        )
+        || !params.check_limits(
+            payment.amount_msat,
+            0,
+            payment.min_final_cltv_expiry,
+            1,
+        )
+        || payment.amount_msat < direct.htlc_minimum_msat
+        || payment.amount_msat > direct.htlc_maximum_msat
+        || payment.amount_msat > direct.outbound_capacity_msat
+    {
+        return None;
+    }
+    let cltv_expiry = params
+        .block_height
+        .saturating_add(payment.min_final_cltv_expiry);
+    Some(FoundRoute {
+        hops: vec![Hop::with(payment.node_id, PaymentOnion {
+            realm: HopRealm::Legacy(ShortChannelId::default()),
+            amt_to_forward: payment.amount_msat,
+            outgoing_cltv_value: cltv_expiry,
+        })],
+        amount_msat: payment.amount_msat,
+        cltv_expiry,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Slice32;
+    use bitcoin_scripts::hlc::{HashLock, HashPreimage};
+    use p2p::bolt::{ChannelFeatures, ChannelId, PaymentData};
+    use secp256k1::{PublicKey, SecretKey};
+
+    use super::*;
+    use crate::router::gossip::DirectionalInfo;
+
+    fn node(byte: u8) -> NodeId {
+        let seckey = SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from(PublicKey::from_secret_key(secp256k1::SECP256K1, &seckey))
+    }
+
+    fn scid(index: u32) -> ShortChannelId {
+        ShortChannelId::with(700_000, index, 0).unwrap()
+    }
+
+    fn graph() -> BTreeMap<ShortChannelId, GossipChannelInfo> {
+        // Direct peers 1 and 5 have channels with destination 4
+        [(1, 1), (2, 5)]
+            .into_iter()
+            .map(|(index, from)| {
+                (scid(index), GossipChannelInfo {
+                    nodes: (node(from), node(4)),
+                    chain_hash: Slice32::default(),
+                    short_channel_id: scid(index),
+                    directions: (
+                        Some(DirectionalInfo {
+                            timestamp: 1,
+                            message_flags: 0,
+                            channel_flags: 0,
+                            cltv_expiry_delta: 40,
+                            htlc_minimum_msat: 1000,
+                            fee_base_msat: 1000,
+                            fee_proportional_millionths: 0,
+                            htlc_maximum_msat: 0,
+                        }),
+                        None,
+                    ),
+                    capacity_sats: None,
+                    features: ChannelFeatures::default(),
+                })
+            })
+            .collect()
+    }
+
+    fn direct_channel(
+        remote: u8,
+        index: u32,
+        outbound_capacity_msat: u64,
+    ) -> LocalChannelInfo {
+        LocalChannelInfo {
+            remote_node: node(remote),
+            channel_id: ChannelId::default(),
+            short_channel_id: scid(index),
+            chain_hash: Slice32::default(),
+            inbound_capacity_msat: 0,
+            outbound_capacity_msat,
+            cltv_expiry: 144,
+            htlc_minimum_msat: 1,
+            htlc_maximum_msat: u64::MAX,
+        }
+    }
+
+    fn payment(amount_msat: u64) -> PaymentRequest {
+        PaymentRequest {
+            amount_msat,
+            payment_hash: HashLock::from(Slice32::from([0xAB; 32])),
+            node_id: node(4),
+            min_final_cltv_expiry: 18,
+            route_hints: vec![],
+            features: Some(InitFeatures {
+                var_onion_optin: Some(true),
+                payment_secret: Some(true),
+                basic_mpp: Some(false),
+                ..default!()
+            }),
+            payment_secret: Some(secret()),
+        }
+    }
+
+    fn secret() -> HashPreimage {
+        HashPreimage::from(Slice32::from([0xCD; 32]))
+    }
+
+    fn hop(node_byte: u8, realm: HopRealm, amount: u64) -> Hop<PaymentOnion> {
+        Hop::with(node(node_byte), PaymentOnion {
+            realm,
+            amt_to_forward: amount,
+            outgoing_cltv_value: 18,
+        })
+    }
+
+    #[test]
+    fn split_by_capacity() {
+        let data = PaymentData {
+            payment_secret: secret(),
+            total_msat: 1_000_000,
+        };
+        let receiver = HopRealm::TlvReceiver(Some(data));
+        let channels = graph();
+        let params = RouteParams::default();
+        let scorer = ProbabilisticScorer::default();
+        let tlv = InitFeatures {
+            var_onion_optin: Some(true),
+            ..default!()
+        };
+        let known = bmap! { node(1) => tlv.clone(), node(5) => tlv };
+        let features = |node_id| known.get(&node_id);
+
+        // First part is reduced by the forwarding fee to fit into the
+        // outbound capacity of the first channel
+        let direct = vec![
+            direct_channel(1, 101, 600_000),
+            direct_channel(5, 102, 600_000),
+        ];
+        assert_eq!(
+            split_payment(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &params,
+                &scorer,
+                0,
+                features
+            ),
+            Some(vec![
+                vec![
+                    hop(1, HopRealm::TlvIntermediary(scid(1)), 599_000),
+                    hop(4, receiver, 599_000),
+                ],
+                vec![
+                    hop(5, HopRealm::TlvIntermediary(scid(2)), 401_000),
+                    hop(4, receiver, 401_000),
+                ],
+            ])
+        );
+
+        // Fees do not allow to spend the whole outbound capacity
+        assert_eq!(
+            split_payment(
+                &channels,
+                &direct,
+                &payment(1_200_000),
+                &params,
+                &scorer,
+                0,
+                features
+            ),
+            None
+        );
+
+        // Direct channel with the destination is used without fees
+        let direct = vec![
+            direct_channel(1, 101, 600_000),
+            direct_channel(4, 103, 700_000),
+        ];
+        assert_eq!(
+            split_payment(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &params,
+                &scorer,
+                0,
+                features
+            ),
+            Some(vec![vec![hop(4, receiver, 700_000)], vec![
+                hop(1, HopRealm::TlvIntermediary(scid(1)), 300_000),
+                hop(4, receiver, 300_000),
+            ],])
+        );
+
+        // Known channel capacity is shared by the parts
+        let mut channels = channels;
+        channels.get_mut(&scid(1)).unwrap().capacity_sats = Some(500);
+        let direct = vec![direct_channel(1, 101, 1_000_000)];
+        let parts = split_payment(
+            &channels,
+            &direct,
+            &payment(500_000),
+            &params,
+            &scorer,
+            0,
+            features,
+        )
+        .unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(
+            split_payment(
+                &channels,
+                &direct,
+                &payment(600_000),
+                &params,
+                &scorer,
+                0,
+                features
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn mpp_requirements() {
+        let channels = graph();
+        let params = RouteParams::default();
+        let scorer = ProbabilisticScorer::default();
+        let direct = vec![
+            direct_channel(1, 101, 600_000),
+            direct_channel(5, 102, 600_000),
+        ];
+        let unknown = BTreeMap::<NodeId, InitFeatures>::new();
+        let announced = bmap! { node(4) => InitFeatures {
+            basic_mpp: Some(false),
+            ..default!()
+        }};
+        let split =
+            |request: &PaymentRequest,
+             known: &BTreeMap<NodeId, InitFeatures>| {
+                split_payment(
+                    &channels,
+                    &direct,
+                    request,
+                    &params,
+                    &scorer,
+                    0,
+                    |node_id| known.get(&node_id),
+                )
+            };
+
+        // Forwarding nodes with unknown features get legacy payloads
+        let mut request = payment(1_000_000);
+        let parts = split(&request, &unknown).unwrap();
+        assert_eq!(parts[0][0].payload.realm, HopRealm::Legacy(scid(1)));
+
+        // Payment secret is required
+        request.payment_secret = None;
+        assert_eq!(split(&request, &unknown), None);
+
+        // Destination must support `basic_mpp`, as known either from the
+        // invoice or from its announcement
+        request.payment_secret = Some(secret());
+        request.features = None;
+        assert_eq!(split(&request, &unknown), None);
+        assert!(split(&request, &announced).is_some());
+    }
+}
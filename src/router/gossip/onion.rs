@@ -0,0 +1,177 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use internet2::addr::NodeId;
+use internet2::presentation::sphinx::Hop;
+use p2p::bolt::{
+    HopRealm, InitFeatures, PaymentData, PaymentOnion, PaymentRequest,
+    ShortChannelId,
+};
+
+/// Detects whether the node understands TLV onion payloads
+/// (`var_onion_optin` feature)
+fn supports_tlv(features: Option<&InitFeatures>) -> bool {
+    matches!(features, Some(features) if features.var_onion_optin.is_some())
+}
+
+/// Selects payload realm for the forwarding node basing on its features.
+/// Nodes which features are unknown get legacy payloads.
+pub(super) fn intermediary_realm(
+    features: Option<&InitFeatures>,
+    short_channel_id: ShortChannelId,
+) -> HopRealm {
+    if supports_tlv(features) {
+        HopRealm::TlvIntermediary(short_channel_id)
+    } else {
+        HopRealm::Legacy(short_channel_id)
+    }
+}
+
+/// Selects payload realm for the payment destination. Features provided by
+/// the invoice take precedence over the `announced` ones.
+///
+/// Payment data are attached whenever the payment secret is known, which
+/// requires TLV payload. Legacy payload of the destination has zero short
+/// channel id, as required by BOLT-4. Returns `None` if the destination
+/// requires payment secret which is not known.
+pub(super) fn receiver_realm(
+    payment: &PaymentRequest,
+    announced: Option<&InitFeatures>,
+) -> Option<HopRealm> {
+    let features = payment.features.as_ref().or(announced);
+    if matches!(features, Some(features) if features.payment_secret == Some(true))
+        && payment.payment_secret.is_none()
+    {
+        return None;
+    }
+    Some(match payment.payment_secret {
+        Some(payment_secret) => HopRealm::TlvReceiver(Some(PaymentData {
+            payment_secret,
+            total_msat: payment.amount_msat,
+        })),
+        None if supports_tlv(features) => HopRealm::TlvReceiver(None),
+        None => HopRealm::Legacy(ShortChannelId::default()),
+    })
+}
+
+/// Replaces legacy payloads of the route hops with the payloads matching
+/// features of the hop nodes, known from `node_announcement` messages and
+/// the invoice. Returns `false` if the destination requirements can't be
+/// satisfied.
+pub(super) fn select_realms<'features>(
+    route: &mut [Hop<PaymentOnion>],
+    payment: &PaymentRequest,
+    features: impl Fn(NodeId) -> Option<&'features InitFeatures>,
+) -> bool {
+    let (last, forwarding) = match route.split_last_mut() {
+        None => return true,
+        Some(split) => split,
+    };
+    for hop in forwarding {
+        if let HopRealm::Legacy(short_channel_id) = hop.payload.realm {
+            hop.payload.realm =
+                intermediary_realm(features(hop.node_id), short_channel_id);
+        }
+    }
+    if let HopRealm::Legacy(_) = last.payload.realm {
+        match receiver_realm(payment, features(last.node_id)) {
+            Some(realm) => last.payload.realm = realm,
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Slice32;
+    use bitcoin_scripts::hlc::{HashLock, HashPreimage};
+    use secp256k1::{PublicKey, SecretKey};
+
+    use super::*;
+
+    fn node(byte: u8) -> NodeId {
+        let seckey = SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from(PublicKey::from_secret_key(secp256k1::SECP256K1, &seckey))
+    }
+
+    fn scid(index: u32) -> ShortChannelId {
+        ShortChannelId::with(700_000, index, 0).unwrap()
+    }
+
+    fn hop(node_byte: u8, index: u32) -> Hop<PaymentOnion> {
+        Hop::with(node(node_byte), PaymentOnion {
+            realm: HopRealm::Legacy(scid(index)),
+            amt_to_forward: 1_000_000,
+            outgoing_cltv_value: 18,
+        })
+    }
+
+    #[test]
+    fn realms_by_features() {
+        let tlv = InitFeatures {
+            var_onion_optin: Some(true),
+            ..default!()
+        };
+        let known = bmap! { node(2) => tlv.clone(), node(3) => tlv };
+        let features = |node_id| known.get(&node_id);
+        let payment_secret = HashPreimage::from(Slice32::from([0xCD; 32]));
+        let mut payment = PaymentRequest {
+            amount_msat: 1_000_000,
+            payment_hash: HashLock::from(Slice32::from([0xAB; 32])),
+            node_id: node(3),
+            min_final_cltv_expiry: 18,
+            route_hints: vec![],
+            features: None,
+            payment_secret: Some(payment_secret),
+        };
+
+        let mut route = vec![hop(1, 1), hop(2, 2), hop(3, 2)];
+        assert!(select_realms(&mut route, &payment, features));
+        assert_eq!(
+            route
+                .iter()
+                .map(|hop| hop.payload.realm)
+                .collect::<Vec<_>>(),
+            vec![
+                HopRealm::Legacy(scid(1)),
+                HopRealm::TlvIntermediary(scid(2)),
+                HopRealm::TlvReceiver(Some(PaymentData {
+                    payment_secret,
+                    total_msat: 1_000_000
+                })),
+            ]
+        );
+
+        // Destination with unknown features gets legacy payload with zero
+        // short channel id
+        payment.payment_secret = None;
+        payment.node_id = node(4);
+        let mut route = vec![hop(1, 1), hop(2, 2), hop(4, 2)];
+        assert!(select_realms(&mut route, &payment, features));
+        assert_eq!(
+            route[2].payload.realm,
+            HopRealm::Legacy(ShortChannelId::default())
+        );
+
+        // Invoice features override the announced ones
+        payment.node_id = node(3);
+        payment.features = Some(InitFeatures {
+            var_onion_optin: Some(true),
+            payment_secret: Some(true),
+            ..default!()
+        });
+        let mut route = vec![hop(1, 1), hop(2, 2), hop(3, 2)];
+        assert!(!select_realms(&mut route, &payment, features));
+    }
+}
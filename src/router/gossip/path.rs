@@ -0,0 +1,891 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::iter;
+
+use internet2::addr::NodeId;
+use internet2::presentation::sphinx::Hop;
+use p2p::bolt::{HopRealm, PaymentOnion, PaymentRequest, ShortChannelId};
+
+use super::scorer::{effective_capacity_msat, ProbabilisticScorer};
+use super::{DirectionalInfo, GossipChannelInfo, LocalChannelInfo};
+use crate::router::{DirectedChannel, RouteParams};
+
+/// Best known way of reaching the payment destination from a node with a
+/// path of the given length
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct PathState {
+    /// Amount which has to be received by the node
+    amount_msat: u64,
+
+    /// CLTV expiry of the HTLC which has to be received by the node, relative
+    /// to the current block height
+    cltv_expiry: u32,
+
+    /// Cost of the path from the node to the destination: fees paid to the
+    /// forwarding nodes together with the scorer penalties of the channels
+    cost_msat: u64,
+
+    /// Channel and the next node towards the destination; `None` for the
+    /// destination itself
+    next: Option<(ShortChannelId, NodeId)>,
+}
+
+/// Computes amount which has to be sent over the channel direction in order
+/// to forward `amount_msat` further, or `None` if the direction can't
+/// forward such amount
+fn forward(
+    direction: &DirectionalInfo,
+    capacity_sats: Option<u64>,
+    amount_msat: u64,
+) -> Option<u64> {
+    if direction.is_disabled() || amount_msat < direction.htlc_minimum_msat {
+        return None;
+    }
+    if matches!(direction.max_htlc_msat(), Some(max) if amount_msat > max) {
+        return None;
+    }
+    if matches!(capacity_sats, Some(capacity) if amount_msat > capacity * 1000)
+    {
+        return None;
+    }
+    // Computed with 128-bit integers, since proportional fee of an arbitrary
+    // channel may overflow 64 bits; such channels are skipped
+    let fee_msat = direction.fee_base_msat as u128
+        + amount_msat as u128 * direction.fee_proportional_millionths as u128
+            / 1_000_000;
+    u64::try_from(amount_msat as u128 + fee_msat).ok()
+}
+
+/// Route found across the gossip graph
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FoundRoute {
+    /// Route hops, starting with the direct peer
+    pub hops: Vec<Hop<PaymentOnion>>,
+
+    /// Amount of the HTLC which has to be offered to the direct peer
+    pub amount_msat: u64,
+
+    /// CLTV expiry of the HTLC which has to be offered to the direct peer
+    pub cltv_expiry: u32,
+}
+
+/// Finds the cheapest route to the payment destination across the gossip
+/// graph.
+///
+/// The search runs backwards, from the destination towards any of the nodes
+/// we have direct channels with, accumulating fees and CLTV deltas of the
+/// forwarding nodes. Each node is visited once per path length, so the
+/// cheapest route is found even if it has to be shorter than the cheapest
+/// path through some of the nodes. Route cost includes penalties given by the
+/// `scorer` to the channels at the moment `now`; channels the scorer knows to
+/// lack liquidity are not used. The direct channel is not a part of the
+/// returned route, which starts with the hop for the direct peer; the HTLC
+/// offered to that peer must cover the peer forwarding fee and CLTV delta on
+/// top of the `amt_to_forward` and `outgoing_cltv_value` of the first hop.
+///
+/// Paths violating route parameters are pruned during the search, so the
+/// returned route always satisfies them. Returned [`FoundRoute`] contains the
+/// amount and CLTV expiry of the HTLC which has to be offered to the direct
+/// peer. All CLTV values are absolute block heights, computed on top of
+/// [`RouteParams::block_height`].
+pub(super) fn find_route<'channels>(
+    channels: impl IntoIterator<Item = &'channels GossipChannelInfo>,
+    direct_channels: &[LocalChannelInfo],
+    payment: &PaymentRequest,
+    params: &RouteParams,
+    scorer: &ProbabilisticScorer,
+    now: u64,
+) -> Option<FoundRoute> {
+    if !params.is_node_allowed(payment.node_id) {
+        return None;
+    }
+
+    // Inbound edges for each of the nodes: the node which forwards payment,
+    // the channel and its forwarding direction
+    let mut inbound = BTreeMap::<
+        NodeId,
+        Vec<(NodeId, &GossipChannelInfo, DirectionalInfo)>,
+    >::new();
+    let mut known = BTreeMap::new();
+    for info in channels {
+        known.insert(info.short_channel_id, info);
+        add_edges(&mut inbound, info);
+    }
+    // Route hints are merged into the graph as temporary channels; for the
+    // channels already known from the gossip they provide only directions
+    // which were not announced
+    let hinted = hint_channels(payment);
+    for hint in &hinted {
+        match known.get(&hint.short_channel_id) {
+            None => add_edges(&mut inbound, hint),
+            Some(info) => merge_hint(&mut inbound, info, hint),
+        }
+    }
+
+    // Paths are tracked per node and path length, so a cheaper but longer
+    // path does not hide the shorter ones which fit into the length limit
+    let mut best = BTreeMap::<(NodeId, usize), PathState>::new();
+    let mut queue = BinaryHeap::new();
+    best.insert((payment.node_id, 1), PathState {
+        amount_msat: payment.amount_msat,
+        cltv_expiry: payment.min_final_cltv_expiry,
+        cost_msat: 0,
+        next: None,
+    });
+    queue.push(Reverse((
+        0,
+        payment.min_final_cltv_expiry,
+        payment.node_id,
+        1,
+    )));
+
+    while let Some(Reverse((cost_msat, cltv_expiry, node_id, path_length))) =
+        queue.pop()
+    {
+        let state = best[&(node_id, path_length)];
+        if state.cost_msat != cost_msat || state.cltv_expiry != cltv_expiry {
+            continue; // Outdated queue entry
+        }
+        let amount_msat = state.amount_msat;
+
+        if state.next.is_some()
+            && direct_channels.iter().any(|channel| {
+                channel.remote_node == node_id
+                    && params.is_outbound_channel_allowed(
+                        channel.short_channel_id,
+                        node_id,
+                    )
+                    && channel.outbound_capacity_msat >= amount_msat
+                    && channel.htlc_minimum_msat <= amount_msat
+                    && channel.htlc_maximum_msat >= amount_msat
+            })
+        {
+            return Some(FoundRoute {
+                hops: compose_hops(
+                    (node_id, path_length),
+                    &best,
+                    payment,
+                    params.block_height,
+                ),
+                amount_msat,
+                cltv_expiry: params.block_height.saturating_add(cltv_expiry),
+            });
+        }
+
+        for (prev_node, info, direction) in
+            inbound.get(&node_id).into_iter().flatten()
+        {
+            if !params.is_node_allowed(*prev_node)
+                || !params.is_channel_allowed(info.short_channel_id, *prev_node)
+            {
+                continue;
+            }
+            let prev_amount =
+                match forward(direction, info.capacity_sats, amount_msat) {
+                    Some(amount) => amount,
+                    None => continue,
+                };
+            let channel = DirectedChannel {
+                short_channel_id: info.short_channel_id,
+                source: *prev_node,
+            };
+            let penalty_msat = match scorer.channel_penalty_msat(
+                channel,
+                effective_capacity_msat(info, direction),
+                amount_msat,
+                now,
+            ) {
+                Some(penalty) => penalty,
+                None => continue,
+            };
+            let prev_cost = (prev_amount - amount_msat)
+                .saturating_add(penalty_msat)
+                .saturating_add(cost_msat);
+            let prev_cltv =
+                cltv_expiry.saturating_add(direction.cltv_expiry_delta as u32);
+            let prev_length = path_length + 1;
+            if !params.check_limits(
+                payment.amount_msat,
+                prev_amount - payment.amount_msat,
+                prev_cltv,
+                prev_length,
+            ) {
+                continue;
+            }
+            // Path is useless if a shorter one through the same node is
+            // neither more expensive nor has larger CLTV expiry
+            let dominated = best
+                .range((*prev_node, 1)..(*prev_node, prev_length))
+                .any(|(_, known)| {
+                    known.cost_msat <= prev_cost
+                        && known.cltv_expiry <= prev_cltv
+                });
+            let improves = !dominated
+                && match best.get(&(*prev_node, prev_length)) {
+                    None => true,
+                    Some(known) => {
+                        (prev_cost, prev_cltv)
+                            < (known.cost_msat, known.cltv_expiry)
+                    }
+                };
+            if improves {
+                best.insert((*prev_node, prev_length), PathState {
+                    amount_msat: prev_amount,
+                    cltv_expiry: prev_cltv,
+                    cost_msat: prev_cost,
+                    next: Some((info.short_channel_id, node_id)),
+                });
+                queue.push(Reverse((
+                    prev_cost,
+                    prev_cltv,
+                    *prev_node,
+                    prev_length,
+                )));
+            }
+        }
+    }
+
+    None
+}
+
+/// Adds known directions of the channel to the inbound edges of the nodes
+fn add_edges<'channels>(
+    inbound: &mut BTreeMap<
+        NodeId,
+        Vec<(NodeId, &'channels GossipChannelInfo, DirectionalInfo)>,
+    >,
+    info: &'channels GossipChannelInfo,
+) {
+    let (node1, node2) = info.nodes;
+    if let Some(direction) = info.directions.0 {
+        inbound
+            .entry(node2)
+            .or_default()
+            .push((node1, info, direction));
+    }
+    if let Some(direction) = info.directions.1 {
+        inbound
+            .entry(node1)
+            .or_default()
+            .push((node2, info, direction));
+    }
+}
+
+/// Adds direction of the route `hint` to the inbound edges of the nodes if the
+/// hinted channel is known from the gossip, but not in this direction. Hints
+/// which do not match the nodes of the known channel are ignored.
+fn merge_hint<'channels>(
+    inbound: &mut BTreeMap<
+        NodeId,
+        Vec<(NodeId, &'channels GossipChannelInfo, DirectionalInfo)>,
+    >,
+    info: &'channels GossipChannelInfo,
+    hint: &GossipChannelInfo,
+) {
+    let (from, to) = hint.nodes;
+    let direction = match hint.directions.0 {
+        Some(direction) => direction,
+        None => return,
+    };
+    let unknown = if info.nodes == (from, to) {
+        info.directions.0.is_none()
+    } else if info.nodes == (to, from) {
+        info.directions.1.is_none()
+    } else {
+        false
+    };
+    if unknown {
+        inbound.entry(to).or_default().push((from, info, direction));
+    }
+}
+
+/// Converts route hints of the payment into the private channels leading to
+/// the payment destination
+fn hint_channels(payment: &PaymentRequest) -> Vec<GossipChannelInfo> {
+    payment
+        .route_hints
+        .iter()
+        .flat_map(|hint| {
+            let next_nodes = hint
+                .hops
+                .iter()
+                .skip(1)
+                .map(|hop| hop.node_id)
+                .chain(iter::once(payment.node_id));
+            hint.hops.iter().zip(next_nodes).map(|(hop, next_node)| {
+                GossipChannelInfo::with_route_hint(hop, next_node)
+            })
+        })
+        .collect()
+}
+
+/// Composes route hops following the best path from the `first` node and
+/// path length state. CLTV values of the hops are absolute block heights,
+/// computed on top of the current `block_height`.
+fn compose_hops(
+    first: (NodeId, usize),
+    best: &BTreeMap<(NodeId, usize), PathState>,
+    payment: &PaymentRequest,
+    block_height: u32,
+) -> Vec<Hop<PaymentOnion>> {
+    let mut hops = vec![];
+    let (mut node_id, mut path_length) = first;
+    while let Some((short_channel_id, next_node)) =
+        best[&(node_id, path_length)].next
+    {
+        path_length -= 1;
+        let next_state = best[&(next_node, path_length)];
+        hops.push(Hop::with(node_id, PaymentOnion {
+            realm: HopRealm::Legacy(short_channel_id),
+            amt_to_forward: next_state.amount_msat,
+            outgoing_cltv_value: block_height
+                .saturating_add(next_state.cltv_expiry),
+        }));
+        node_id = next_node;
+    }
+    // BOLT-4 requires legacy payload of the final node to have zero short
+    // channel id
+    hops.push(Hop::with(payment.node_id, PaymentOnion {
+        realm: HopRealm::Legacy(ShortChannelId::default()),
+        amt_to_forward: payment.amount_msat,
+        outgoing_cltv_value: block_height
+            .saturating_add(payment.min_final_cltv_expiry),
+    }));
+    hops
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Slice32;
+    use bitcoin_scripts::hlc::HashLock;
+    use p2p::bolt::{ChannelFeatures, ChannelId, RouteHint, RouteHintHop};
+    use secp256k1::{PublicKey, SecretKey};
+
+    use super::*;
+    use crate::router::{FeeLimit, MAX_ROUTE_LENGTH};
+
+    fn node(byte: u8) -> NodeId {
+        let seckey = SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from(PublicKey::from_secret_key(secp256k1::SECP256K1, &seckey))
+    }
+
+    fn scid(index: u32) -> ShortChannelId {
+        ShortChannelId::with(700_000, index, 0).unwrap()
+    }
+
+    fn channel(
+        index: u32,
+        from: u8,
+        to: u8,
+        fee_base_msat: u32,
+        fee_proportional_millionths: u32,
+        cltv_expiry_delta: u16,
+    ) -> GossipChannelInfo {
+        GossipChannelInfo {
+            nodes: (node(from), node(to)),
+            chain_hash: Slice32::default(),
+            short_channel_id: scid(index),
+            directions: (
+                Some(DirectionalInfo {
+                    timestamp: 1,
+                    message_flags: 0,
+                    channel_flags: 0,
+                    cltv_expiry_delta,
+                    htlc_minimum_msat: 1000,
+                    fee_base_msat,
+                    fee_proportional_millionths,
+                    htlc_maximum_msat: 0,
+                }),
+                None,
+            ),
+            capacity_sats: None,
+            features: ChannelFeatures::default(),
+        }
+    }
+
+    fn direct_channel(
+        remote: u8,
+        outbound_capacity_msat: u64,
+    ) -> LocalChannelInfo {
+        LocalChannelInfo {
+            remote_node: node(remote),
+            channel_id: ChannelId::default(),
+            short_channel_id: scid(100),
+            chain_hash: Slice32::default(),
+            inbound_capacity_msat: 0,
+            outbound_capacity_msat,
+            cltv_expiry: 144,
+            htlc_minimum_msat: 1,
+            htlc_maximum_msat: u64::MAX,
+        }
+    }
+
+    fn payment(amount_msat: u64) -> PaymentRequest {
+        PaymentRequest {
+            amount_msat,
+            payment_hash: HashLock::from(Slice32::from([0xAB; 32])),
+            node_id: node(4),
+            min_final_cltv_expiry: 18,
+            route_hints: vec![],
+            features: None,
+            payment_secret: None,
+        }
+    }
+
+    fn hop(
+        node_byte: u8,
+        index: u32,
+        amt_to_forward: u64,
+        outgoing_cltv_value: u32,
+    ) -> Hop<PaymentOnion> {
+        Hop::with(node(node_byte), PaymentOnion {
+            realm: HopRealm::Legacy(scid(index)),
+            amt_to_forward,
+            outgoing_cltv_value,
+        })
+    }
+
+    fn final_hop(
+        node_byte: u8,
+        amt_to_forward: u64,
+        outgoing_cltv_value: u32,
+    ) -> Hop<PaymentOnion> {
+        Hop::with(node(node_byte), PaymentOnion {
+            realm: HopRealm::Legacy(ShortChannelId::default()),
+            amt_to_forward,
+            outgoing_cltv_value,
+        })
+    }
+
+    fn find_hops(
+        channels: &[GossipChannelInfo],
+        direct_channels: &[LocalChannelInfo],
+        payment: &PaymentRequest,
+        params: &RouteParams,
+        scorer: &ProbabilisticScorer,
+        now: u64,
+    ) -> Option<Vec<Hop<PaymentOnion>>> {
+        find_route(channels, direct_channels, payment, params, scorer, now)
+            .map(|route| route.hops)
+    }
+
+    fn graph() -> Vec<GossipChannelInfo> {
+        // Direct peer 1 reaches destination 4 either through node 2 or node 3
+        vec![
+            channel(1, 1, 2, 1000, 0, 144),
+            channel(2, 2, 4, 1000, 1000, 40),
+            channel(3, 1, 3, 5000, 0, 144),
+            channel(4, 3, 4, 500, 0, 40),
+        ]
+    }
+
+    #[test]
+    fn cheapest_route() {
+        let scorer = ProbabilisticScorer::default();
+        let mut channels = graph();
+        let direct = vec![direct_channel(1, 10_000_000)];
+
+        assert_eq!(
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &default!(),
+                &scorer,
+                0
+            ),
+            Some(vec![
+                hop(1, 1, 1_002_000, 58),
+                hop(2, 2, 1_000_000, 18),
+                final_hop(4, 1_000_000, 18),
+            ])
+        );
+        // HTLC offered to the direct peer covers its fee and CLTV delta
+        let route = find_route(
+            &channels,
+            &direct,
+            &payment(1_000_000),
+            &default!(),
+            &scorer,
+            0,
+        )
+        .unwrap();
+        assert_eq!(route.amount_msat, 1_003_000);
+        assert_eq!(route.cltv_expiry, 202);
+
+        // CLTV values are absolute block heights, while the route limits
+        // apply to the relative CLTV delta
+        let params = RouteParams {
+            block_height: 700_000,
+            max_total_cltv_expiry_delta: Some(202),
+            ..default!()
+        };
+        let route = find_route(
+            &channels,
+            &direct,
+            &payment(1_000_000),
+            &params,
+            &scorer,
+            0,
+        )
+        .unwrap();
+        assert_eq!(route.hops, vec![
+            hop(1, 1, 1_002_000, 700_058),
+            hop(2, 2, 1_000_000, 700_018),
+            final_hop(4, 1_000_000, 700_018),
+        ]);
+        assert_eq!(route.cltv_expiry, 700_202);
+
+        // Disabled direction is avoided
+        channels[1].directions.0.as_mut().unwrap().channel_flags = 0b10;
+        assert_eq!(
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &default!(),
+                &scorer,
+                0
+            ),
+            Some(vec![
+                hop(1, 3, 1_000_500, 58),
+                hop(3, 4, 1_000_000, 18),
+                final_hop(4, 1_000_000, 18),
+            ])
+        );
+
+        // Amounts below HTLC minimum can't be routed
+        assert_eq!(
+            find_hops(
+                &channels,
+                &direct,
+                &payment(999),
+                &default!(),
+                &scorer,
+                0
+            ),
+            None
+        );
+
+        // HTLC maximum is respected only when announced in message flags
+        channels[3].directions.0.as_mut().unwrap().htlc_maximum_msat = 999_999;
+        assert!(find_hops(
+            &channels,
+            &direct,
+            &payment(1_000_000),
+            &default!(),
+            &scorer,
+            0
+        )
+        .is_some());
+        channels[3].directions.0.as_mut().unwrap().message_flags = 0b01;
+        assert_eq!(
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &default!(),
+                &scorer,
+                0
+            ),
+            None
+        );
+        channels[3].directions.0.as_mut().unwrap().message_flags = 0;
+
+        // Channel capacity limits forwarded amount
+        channels[2].capacity_sats = Some(1000);
+        assert_eq!(
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &default!(),
+                &scorer,
+                0
+            ),
+            None
+        );
+        channels[2].capacity_sats = Some(1001);
+        assert!(find_hops(
+            &channels,
+            &direct,
+            &payment(1_000_000),
+            &default!(),
+            &scorer,
+            0
+        )
+        .is_some());
+
+        // Direct channel must have enough outbound liquidity
+        let direct = vec![direct_channel(1, 1_005_499)];
+        assert_eq!(
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &default!(),
+                &scorer,
+                0
+            ),
+            None
+        );
+        let direct = vec![direct_channel(1, 1_005_500)];
+        assert!(find_hops(
+            &channels,
+            &direct,
+            &payment(1_000_000),
+            &default!(),
+            &scorer,
+            0
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn max_fee_edge() {
+        let scorer = ProbabilisticScorer::default();
+        let mut channels = graph();
+        let direct = vec![direct_channel(1, u64::MAX)];
+        channels[1]
+            .directions
+            .0
+            .as_mut()
+            .unwrap()
+            .fee_proportional_millionths = u32::MAX;
+
+        // Fee which does not fit into 64 bits makes the edge unusable
+        let direction = channels[1].directions.0.unwrap();
+        assert_eq!(forward(&direction, None, u64::MAX / 1000), None);
+
+        // Proportional fee of large payments is computed without overflow
+        let route = find_hops(
+            &channels,
+            &direct,
+            &payment(5_000_000_000),
+            &default!(),
+            &scorer,
+            0,
+        );
+        assert_eq!(route.map(|hops| hops[1].node_id), Some(node(3)));
+    }
+
+    #[test]
+    fn route_constraints() {
+        let scorer = ProbabilisticScorer::default();
+        let channels = graph();
+        let direct = vec![direct_channel(1, 10_000_000)];
+        let route = |params: &RouteParams| {
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                params,
+                &scorer,
+                0,
+            )
+            .map(|hops| hops[1].node_id)
+        };
+        let mut params = RouteParams::default();
+        assert_eq!(route(&params), Some(node(2)));
+
+        // Route through node 2 costs 3000 msat in fees and has total CLTV
+        // delta of 202 blocks; route through node 3 costs 5500 msat
+        params.max_fee = Some(FeeLimit::AbsoluteMsat(3000));
+        assert_eq!(route(&params), Some(node(2)));
+        params.max_fee = Some(FeeLimit::AbsoluteMsat(2999));
+        assert_eq!(route(&params), None);
+        params.max_fee = Some(FeeLimit::ProportionalMillionths(3000));
+        assert_eq!(route(&params), Some(node(2)));
+        params.max_fee = Some(FeeLimit::ProportionalMillionths(2999));
+        assert_eq!(route(&params), None);
+        params.max_fee = None;
+
+        params.max_total_cltv_expiry_delta = Some(201);
+        assert_eq!(route(&params), None);
+        params.max_total_cltv_expiry_delta = Some(202);
+        assert_eq!(route(&params), Some(node(2)));
+
+        params.max_path_length = 2;
+        assert_eq!(route(&params), None);
+        params.max_path_length = MAX_ROUTE_LENGTH;
+
+        // Banning channel direction not used by the route has no effect
+        params.exclude_channel(scid(2), node(4));
+        assert_eq!(route(&params), Some(node(2)));
+        params.exclude_channel(scid(2), node(2));
+        assert_eq!(route(&params), Some(node(3)));
+        params.excluded_channels.clear();
+
+        params.exclude_node(node(2));
+        assert_eq!(route(&params), Some(node(3)));
+        params.exclude_node(node(3));
+        assert_eq!(route(&params), None);
+        params.excluded_nodes.clear();
+
+        // Direct channel is banned with our own node as the forwarding one
+        params.exclude_channel(scid(100), node(1));
+        assert_eq!(route(&params), Some(node(2)));
+        params.exclude_channel(scid(100), node(9));
+        assert_eq!(route(&params), None);
+    }
+
+    #[test]
+    fn path_length_limit() {
+        let scorer = ProbabilisticScorer::default();
+        let mut channels = graph();
+        // Node 2 reaches destination cheaper through node 5
+        channels.push(channel(5, 2, 5, 100, 0, 40));
+        channels.push(channel(6, 5, 4, 100, 0, 40));
+        let direct = vec![direct_channel(1, 10_000_000)];
+        let mut params = RouteParams::default();
+        params.exclude_node(node(3));
+        let route = |params: &RouteParams| {
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                params,
+                &scorer,
+                0,
+            )
+            .map(|hops| hops.iter().map(|hop| hop.node_id).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            route(&params),
+            Some(vec![node(1), node(2), node(5), node(4)])
+        );
+
+        // Shorter path through node 2 is used when the cheapest one is too
+        // long
+        params.max_path_length = 3;
+        assert_eq!(route(&params), Some(vec![node(1), node(2), node(4)]));
+        params.max_path_length = 2;
+        assert_eq!(route(&params), None);
+    }
+
+    #[test]
+    fn scored_route() {
+        let channels = graph();
+        let direct = vec![direct_channel(1, 10_000_000)];
+        let mut scorer = ProbabilisticScorer::default();
+        let route = |scorer: &ProbabilisticScorer, now: u64| {
+            find_hops(
+                &channels,
+                &direct,
+                &payment(1_000_000),
+                &default!(),
+                scorer,
+                now,
+            )
+            .map(|hops| hops[1].node_id)
+        };
+        assert_eq!(route(&scorer, 0), Some(node(2)));
+
+        // Channel which failed to forward the payment is avoided until the
+        // knowledge of its liquidity decays
+        let known = channels
+            .iter()
+            .map(|info| (info.short_channel_id, *info))
+            .collect();
+        let failed = find_hops(
+            &channels,
+            &direct,
+            &payment(1_000_000),
+            &default!(),
+            &scorer,
+            0,
+        )
+        .unwrap();
+        scorer.payment_path_failed(&known, &failed, Some(scid(2)), 0);
+        assert_eq!(route(&scorer, 0), Some(node(3)));
+        let half_life = scorer.params().liquidity_half_life_secs;
+        assert_eq!(route(&scorer, half_life * 64), Some(node(2)));
+    }
+
+    #[test]
+    fn route_hints() {
+        let scorer = ProbabilisticScorer::default();
+        let mut channels = graph();
+        let direct = vec![direct_channel(1, 10_000_000)];
+        let mut payment = PaymentRequest {
+            node_id: node(6),
+            ..payment(1_000_000)
+        };
+        let route = |channels: &[GossipChannelInfo],
+                     payment: &PaymentRequest| {
+            find_hops(channels, &direct, payment, &default!(), &scorer, 0)
+        };
+
+        // Destination behind a private channel is unreachable without hints
+        assert_eq!(route(&channels, &payment), None);
+
+        payment.route_hints = vec![RouteHint {
+            hops: vec![RouteHintHop {
+                node_id: node(4),
+                short_channel_id: scid(10),
+                fee_base_msat: 100,
+                fee_proportional_millionths: 0,
+                cltv_expiry_delta: 20,
+            }],
+        }];
+        assert_eq!(
+            route(&channels, &payment),
+            Some(vec![
+                hop(1, 1, 1_002_100, 78),
+                hop(2, 2, 1_000_100, 38),
+                hop(4, 10, 1_000_000, 18),
+                final_hop(6, 1_000_000, 18),
+            ])
+        );
+
+        // Hints which do not match channels known from the gossip are ignored
+        payment.route_hints[0].hops[0].short_channel_id = scid(2);
+        assert_eq!(route(&channels, &payment), None);
+
+        // Hint provides direction of the known channel which was not announced
+        channels.push(channel(5, 6, 4, 0, 0, 40));
+        let unhinted = PaymentRequest {
+            route_hints: vec![],
+            ..payment.clone()
+        };
+        assert_eq!(route(&channels, &unhinted), None);
+        payment.route_hints[0].hops[0].short_channel_id = scid(5);
+        assert_eq!(
+            route(&channels, &payment),
+            Some(vec![
+                hop(1, 1, 1_002_100, 78),
+                hop(2, 2, 1_000_100, 38),
+                hop(4, 5, 1_000_000, 18),
+                final_hop(6, 1_000_000, 18),
+            ])
+        );
+
+        // ... but does not override the announced one
+        channels[4].directions.1 = Some(DirectionalInfo {
+            fee_base_msat: 300,
+            ..channels[4].directions.0.unwrap()
+        });
+        assert_eq!(
+            route(&channels, &payment),
+            Some(vec![
+                hop(1, 1, 1_002_300, 98),
+                hop(2, 2, 1_000_300, 58),
+                hop(4, 5, 1_000_000, 18),
+                final_hop(6, 1_000_000, 18),
+            ])
+        );
+    }
+}
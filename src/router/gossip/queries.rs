@@ -0,0 +1,917 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+//! BOLT-7 gossip queries: answering `query_channel_range` and
+//! `query_short_channel_ids` requests and synchronizing channels with the
+//! remote peer.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use amplify::{Slice32, Wrapper};
+use internet2::addr::NodeId;
+use internet2::presentation::sphinx::Hop;
+use p2p::bolt::{
+    ChannelAnnouncement, ChannelUpdate, ChecksumList, EncodedQueryFlags,
+    EncodedShortIds, EncodedTimestamps, EncodingType, Messages,
+    NodeAnnouncements, PaymentOnion, PaymentRequest, QueryChannelRange,
+    QueryFlags, QueryOption, QueryShortChannelIds, ReplyChannelRange,
+    ReplyShortChannelIdsEnd, ShortChannelId, UpdateChecksums, UpdateTimestamps,
+};
+
+use super::router::{mainnet_chain_hash, RouterState};
+use super::{Error, GossipExt, UpdateMsg, UpdateReq};
+use crate::router::RouteParams;
+use crate::{Extension, RouterExtension};
+
+/// Maximum number of short channel ids put into a single
+/// `reply_channel_range` or `query_short_channel_ids` message, keeping
+/// uncompressed messages with timestamps and checksums within the 65535-byte
+/// message size limit
+pub const MAX_SHORT_IDS_PER_MESSAGE: usize = 2000;
+
+/// Signed gossip messages known for a channel
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChannelGossip {
+    /// Channel announcement
+    pub announcement: ChannelAnnouncement,
+
+    /// The latest update from the first channel node
+    pub update_1: Option<ChannelUpdate>,
+
+    /// The latest update from the second channel node
+    pub update_2: Option<ChannelUpdate>,
+}
+
+impl From<ChannelAnnouncement> for ChannelGossip {
+    fn from(announcement: ChannelAnnouncement) -> Self {
+        ChannelGossip {
+            announcement,
+            update_1: None,
+            update_2: None,
+        }
+    }
+}
+
+impl ChannelGossip {
+    /// Timestamps of the channel updates, using zero for the unknown ones
+    pub fn timestamps(&self) -> UpdateTimestamps {
+        UpdateTimestamps {
+            timestamp_node_id_1: self
+                .update_1
+                .as_ref()
+                .map(|update| update.timestamp)
+                .unwrap_or_default(),
+            timestamp_node_id_2: self
+                .update_2
+                .as_ref()
+                .map(|update| update.timestamp)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Checksums of the channel updates, using zero for the unknown ones
+    pub fn checksums(&self) -> UpdateChecksums {
+        UpdateChecksums {
+            checksum_node_id_1: self
+                .update_1
+                .as_ref()
+                .map(|update| update.checksum())
+                .unwrap_or_default(),
+            checksum_node_id_2: self
+                .update_2
+                .as_ref()
+                .map(|update| update.checksum())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Computes which of the gossip messages for the channel should be
+    /// requested from the remote peer basing on the timestamps and checksums
+    /// of its channel updates
+    fn outdated(
+        &self,
+        timestamps: Option<UpdateTimestamps>,
+        checksums: Option<UpdateChecksums>,
+    ) -> QueryFlags {
+        let timestamps = match timestamps {
+            // Without timestamps we can't tell whether updates are outdated
+            None => return QueryFlags::default(),
+            Some(timestamps) => timestamps,
+        };
+        let local_timestamps = self.timestamps();
+        let local_checksums = self.checksums();
+        let mut flags = QueryFlags::default();
+        // Updates with the same checksum differ only by timestamp and are not
+        // worth downloading
+        if timestamps.timestamp_node_id_1 > local_timestamps.timestamp_node_id_1
+            && checksums.map(|checksums| checksums.checksum_node_id_1)
+                != Some(local_checksums.checksum_node_id_1)
+        {
+            flags |= QueryFlags::CHANNEL_UPDATE_1;
+        }
+        if timestamps.timestamp_node_id_2 > local_timestamps.timestamp_node_id_2
+            && checksums.map(|checksums| checksums.checksum_node_id_2)
+                != Some(local_checksums.checksum_node_id_2)
+        {
+            flags |= QueryFlags::CHANNEL_UPDATE_2;
+        }
+        flags
+    }
+}
+
+/// Gossip queries extension, added to the router once the remote peer
+/// advertises `gossip_queries` feature.
+///
+/// Keeps signed gossip messages accepted by [`super::GossipRouter`] to
+/// answer the peer queries and drives synchronization of the channels, which
+/// is started with [`UpdateReq::SyncChannels`]. Messages which should be sent
+/// to the peer are queued and retrieved with [`UpdateReq::NextMessage`].
+///
+/// Signed gossip is not a part of the router state and is re-collected
+/// during the synchronization once the router is restored.
+#[derive(Getters, Clone, Debug)]
+pub struct GossipQueries {
+    /// Hash of the genesis block of the chain which gossip is queried for
+    chain_hash: Slice32,
+
+    /// Whether the remote peer supports `gossip_queries_ex` feature, i.e.
+    /// timestamps, checksums and query flags
+    extended: bool,
+
+    /// Signed gossip of the known channels
+    channels: BTreeMap<ShortChannelId, ChannelGossip>,
+
+    /// Signed announcements of the nodes having known channels
+    nodes: BTreeMap<NodeId, NodeAnnouncements>,
+
+    /// End of the block range requested with `query_channel_range` for which
+    /// replies are still expected
+    #[getter(skip)]
+    range_end: Option<u32>,
+
+    /// Missing or outdated channels which gossip has to be queried
+    missing: BTreeMap<ShortChannelId, QueryFlags>,
+
+    /// Whether `query_short_channel_ids` was sent and its reply is not
+    /// completed yet
+    #[getter(skip)]
+    awaiting_ids: bool,
+
+    /// Messages to be sent to the remote peer
+    #[getter(skip)]
+    outbox: VecDeque<Messages>,
+}
+
+impl Default for GossipQueries {
+    fn default() -> Self {
+        GossipQueries::with(mainnet_chain_hash())
+    }
+}
+
+impl GossipQueries {
+    /// Constructs gossip queries extension for the chain with the given
+    /// genesis hash
+    pub fn with(chain_hash: Slice32) -> GossipQueries {
+        GossipQueries {
+            chain_hash,
+            extended: false,
+            channels: empty!(),
+            nodes: empty!(),
+            range_end: None,
+            missing: empty!(),
+            awaiting_ids: false,
+            outbox: empty!(),
+        }
+    }
+
+    /// Detects whether the channel synchronization is in progress
+    pub fn is_syncing(&self) -> bool {
+        self.range_end.is_some() || self.awaiting_ids
+    }
+
+    /// Returns the next message which has to be sent to the remote peer
+    pub fn pop_message(&mut self) -> Option<Messages> {
+        self.outbox.pop_front()
+    }
+
+    fn has_channels(&self, node_id: NodeId) -> bool {
+        self.channels.values().any(|gossip| {
+            gossip.announcement.node_id_1 == node_id
+                || gossip.announcement.node_id_2 == node_id
+        })
+    }
+
+    fn add_channel(&mut self, announcement: &ChannelAnnouncement) {
+        if announcement.chain_hash != self.chain_hash {
+            return;
+        }
+        self.channels
+            .entry(announcement.short_channel_id)
+            .or_insert_with(|| ChannelGossip::from(announcement.clone()));
+    }
+
+    fn update_channel(&mut self, update: &ChannelUpdate) {
+        let gossip = match self.channels.get_mut(&update.short_channel_id) {
+            None => return,
+            Some(gossip) => gossip,
+        };
+        let known = if update.channel_flags & 0b01 == 0 {
+            &mut gossip.update_1
+        } else {
+            &mut gossip.update_2
+        };
+        match known {
+            Some(known) if known.timestamp >= update.timestamp => {}
+            _ => *known = Some(update.clone()),
+        }
+    }
+
+    fn update_node(&mut self, announcement: &NodeAnnouncements) {
+        if !self.has_channels(announcement.node_id) {
+            return;
+        }
+        match self.nodes.get(&announcement.node_id) {
+            Some(known) if known.timestamp >= announcement.timestamp => {}
+            _ => {
+                self.nodes
+                    .insert(announcement.node_id, announcement.clone());
+            }
+        }
+    }
+
+    /// Starts channel synchronization for the block range, returning
+    /// `query_channel_range` message which has to be sent to the peer
+    pub fn sync_channels(
+        &mut self,
+        first_blocknum: u32,
+        number_of_blocks: u32,
+    ) -> QueryChannelRange {
+        self.range_end = Some(first_blocknum.saturating_add(number_of_blocks));
+        self.missing.clear();
+        self.awaiting_ids = false;
+        QueryChannelRange {
+            chain_hash: self.chain_hash,
+            first_blocknum,
+            number_of_blocks,
+            query_option: if self.extended {
+                Some(QueryOption {
+                    want_timestamps: true,
+                    want_checksums: true,
+                })
+            } else {
+                None
+            },
+            unknown_tlvs: none!(),
+        }
+    }
+
+    fn reply_channel_range(&mut self, query: &QueryChannelRange) {
+        let first_blocknum = query.first_blocknum;
+        let range_end = first_blocknum.saturating_add(query.number_of_blocks);
+        let query_option = query.query_option.unwrap_or_default();
+        let full_information = query.chain_hash == self.chain_hash;
+        let channels = self
+            .channels
+            .iter()
+            .filter(|(short_channel_id, _)| {
+                let block_height = u32::from(short_channel_id.block_height);
+                full_information
+                    && block_height >= first_blocknum
+                    && block_height < range_end
+            })
+            .collect::<Vec<_>>();
+
+        let chunks = channels.chunks(MAX_SHORT_IDS_PER_MESSAGE);
+        let count = chunks.len().max(1);
+        let mut replies = chunks
+            .map(|chunk| chunk.to_vec())
+            .chain(if channels.is_empty() {
+                Some(vec![])
+            } else {
+                None
+            })
+            .enumerate()
+            .map(|(index, chunk)| {
+                let chunk_first = match (index, chunk.first()) {
+                    (0, _) | (_, None) => first_blocknum,
+                    (_, Some((short_channel_id, _))) => {
+                        u32::from(short_channel_id.block_height)
+                    }
+                };
+                let chunk_end = match (index + 1 == count, chunk.last()) {
+                    (true, _) | (_, None) => range_end,
+                    (false, Some((short_channel_id, _))) => {
+                        u32::from(short_channel_id.block_height) + 1
+                    }
+                };
+                ReplyChannelRange {
+                    chain_hash: query.chain_hash,
+                    first_blocknum: chunk_first,
+                    number_of_blocks: chunk_end - chunk_first,
+                    full_information: full_information as u8,
+                    encoded_short_ids: EncodedShortIds::uncompressed(
+                        chunk.iter().map(|(id, _)| **id).collect(),
+                    ),
+                    timestamps: if query_option.want_timestamps {
+                        Some(EncodedTimestamps {
+                            encoding: EncodingType::Uncompressed,
+                            timestamps: chunk
+                                .iter()
+                                .map(|(_, gossip)| gossip.timestamps())
+                                .collect(),
+                        })
+                    } else {
+                        None
+                    },
+                    checksums: if query_option.want_checksums {
+                        Some(ChecksumList::from(
+                            chunk
+                                .iter()
+                                .map(|(_, gossip)| gossip.checksums())
+                                .collect::<Vec<_>>(),
+                        ))
+                    } else {
+                        None
+                    },
+                    unknown_tlvs: none!(),
+                }
+            })
+            .map(Messages::ReplyChannelRange)
+            .collect::<VecDeque<_>>();
+        self.outbox.append(&mut replies);
+    }
+
+    fn reply_short_channel_ids(
+        &mut self,
+        query: &QueryShortChannelIds,
+    ) -> Result<(), Error> {
+        let short_ids = &query.short_ids.short_ids;
+        let flags = match query.query_flags {
+            Some(ref query_flags)
+                if query_flags.flags.len() != short_ids.len() =>
+            {
+                return Err(Error::QueryFlagsMismatch {
+                    short_ids: short_ids.len(),
+                    flags: query_flags.flags.len(),
+                })
+            }
+            Some(ref query_flags) => query_flags.flags.clone(),
+            None => vec![QueryFlags::ALL; short_ids.len()],
+        };
+
+        let full_information = query.chain_hash == self.chain_hash;
+        let mut sent_nodes = BTreeSet::new();
+        for (short_channel_id, flags) in short_ids.iter().zip(flags) {
+            let gossip = match self.channels.get(short_channel_id) {
+                Some(gossip) if full_information => gossip,
+                _ => continue,
+            };
+            let announcement = &gossip.announcement;
+            if flags.contains(QueryFlags::CHANNEL_ANNOUNCEMENT) {
+                self.outbox.push_back(Messages::ChannelAnnouncement(
+                    announcement.clone(),
+                ));
+            }
+            for (flag, update) in [
+                (QueryFlags::CHANNEL_UPDATE_1, &gossip.update_1),
+                (QueryFlags::CHANNEL_UPDATE_2, &gossip.update_2),
+            ] {
+                if let (true, Some(update)) = (flags.contains(flag), update) {
+                    self.outbox
+                        .push_back(Messages::ChannelUpdate(update.clone()));
+                }
+            }
+            for (flag, node_id) in [
+                (QueryFlags::NODE_ANNOUNCEMENT_1, announcement.node_id_1),
+                (QueryFlags::NODE_ANNOUNCEMENT_2, announcement.node_id_2),
+            ] {
+                if !flags.contains(flag) || !sent_nodes.insert(node_id) {
+                    continue;
+                }
+                if let Some(node) = self.nodes.get(&node_id) {
+                    self.outbox
+                        .push_back(Messages::NodeAnnouncements(node.clone()));
+                }
+            }
+        }
+        self.outbox.push_back(Messages::ReplyShortChannelIdsEnd(
+            ReplyShortChannelIdsEnd {
+                chain_hash: query.chain_hash,
+                full_information: full_information as u8,
+            },
+        ));
+        Ok(())
+    }
+
+    fn process_channel_range(&mut self, reply: &ReplyChannelRange) {
+        let range_end = match self.range_end {
+            Some(range_end) if reply.chain_hash == self.chain_hash => range_end,
+            _ => return, // Unsolicited reply
+        };
+        let timestamps = reply
+            .timestamps
+            .as_ref()
+            .map(|timestamps| &timestamps.timestamps[..])
+            .unwrap_or_default();
+        let checksums = reply
+            .checksums
+            .as_ref()
+            .map(|checksums| &checksums.as_inner()[..])
+            .unwrap_or_default();
+        for (index, short_channel_id) in
+            reply.encoded_short_ids.short_ids.iter().enumerate()
+        {
+            let flags = match self.channels.get(short_channel_id) {
+                None => QueryFlags::ALL,
+                Some(gossip) => gossip.outdated(
+                    timestamps.get(index).copied(),
+                    checksums.get(index).copied(),
+                ),
+            };
+            if flags != QueryFlags::default() {
+                *self.missing.entry(*short_channel_id).or_default() |= flags;
+            }
+        }
+        if reply.first_blocknum.saturating_add(reply.number_of_blocks)
+            >= range_end
+        {
+            self.range_end = None;
+            self.query_missing();
+        }
+    }
+
+    /// Queries the next batch of missing channels, unless the previous query
+    /// is still in progress
+    fn query_missing(&mut self) {
+        if self.awaiting_ids || self.missing.is_empty() {
+            return;
+        }
+        let batch = self
+            .missing
+            .keys()
+            .take(MAX_SHORT_IDS_PER_MESSAGE)
+            .copied()
+            .collect::<Vec<_>>();
+        let flags = batch
+            .iter()
+            .filter_map(|short_channel_id| {
+                self.missing.remove(short_channel_id)
+            })
+            .collect();
+        self.outbox.push_back(Messages::QueryShortChannelIds(
+            QueryShortChannelIds {
+                chain_hash: self.chain_hash,
+                short_ids: EncodedShortIds::uncompressed(batch),
+                query_flags: if self.extended {
+                    Some(EncodedQueryFlags {
+                        encoding: EncodingType::Uncompressed,
+                        flags,
+                    })
+                } else {
+                    None
+                },
+                unknown_tlvs: none!(),
+            },
+        ));
+        self.awaiting_ids = true;
+    }
+}
+
+impl Extension<GossipExt> for GossipQueries {
+    fn identity(&self) -> GossipExt {
+        GossipExt::GossipQueries
+    }
+
+    fn state_change(
+        &mut self,
+        request: &UpdateReq,
+        message: &mut Messages,
+    ) -> Result<(), Error> {
+        match request {
+            UpdateReq::SyncChannels {
+                first_blocknum,
+                number_of_blocks,
+            } => {
+                *message = Messages::QueryChannelRange(
+                    self.sync_channels(*first_blocknum, *number_of_blocks),
+                );
+            }
+            UpdateReq::NextMessage => {
+                *message = self.pop_message().ok_or(Error::NoPendingGossip)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_from_local(&mut self, _message: &UpdateMsg) -> Result<(), Error> {
+        // Nothing to do here so far
+        Ok(())
+    }
+
+    fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
+        // Gossip messages reach this extension only after they were validated
+        // by the gossip router
+        match message {
+            Messages::Init(init) => {
+                self.extended = init.local_features.gossip_queries_ex.is_some()
+            }
+            Messages::ChannelAnnouncement(announcement) => {
+                self.add_channel(announcement)
+            }
+            Messages::ChannelUpdate(update) => self.update_channel(update),
+            Messages::NodeAnnouncements(announcement) => {
+                self.update_node(announcement)
+            }
+            Messages::QueryChannelRange(query) => {
+                self.reply_channel_range(query)
+            }
+            Messages::QueryShortChannelIds(query) => {
+                self.reply_short_channel_ids(query)?
+            }
+            Messages::ReplyChannelRange(reply) => {
+                self.process_channel_range(reply)
+            }
+            Messages::ReplyShortChannelIdsEnd(reply)
+                if reply.chain_hash == self.chain_hash && self.awaiting_ids =>
+            {
+                self.awaiting_ids = false;
+                self.query_missing();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn load_state(&mut self, state: &RouterState) {
+        self.chain_hash = state.chain_hash;
+        // Gossip of the channels and nodes removed from the router, like
+        // pruned spent channels, must not be served to the peer
+        self.channels.retain(|short_channel_id, _| {
+            state.remote_channels.contains_key(short_channel_id)
+        });
+        self.nodes
+            .retain(|node_id, _| state.remote_nodes.contains_key(node_id));
+    }
+
+    fn store_state(&self, _state: &mut RouterState) {
+        // Signed gossip is not persisted
+    }
+}
+
+impl RouterExtension<GossipExt> for GossipQueries {
+    #[inline]
+    fn new() -> Box<dyn RouterExtension<GossipExt>>
+    where
+        Self: Sized,
+    {
+        Box::new(GossipQueries::default())
+    }
+
+    fn build_route(
+        &mut self,
+        _payment: &PaymentRequest,
+        _params: &RouteParams,
+        _route: &mut Vec<Hop<PaymentOnion>>,
+    ) {
+        // Gossip queries do not participate in route construction
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use p2p::bolt::{
+        AddressList, Alias, ChannelFeatures, Init, InitFeatures, NodeColor,
+    };
+    use secp256k1::ecdsa::Signature;
+    use secp256k1::{PublicKey, SecretKey};
+
+    use super::*;
+    use crate::router::Router;
+
+    fn node(byte: u8) -> NodeId {
+        NodeId::from(PublicKey::from_secret_key(
+            secp256k1::SECP256K1,
+            &SecretKey::from_slice(&[byte; 32]).unwrap(),
+        ))
+    }
+
+    fn dumb_sig() -> Signature {
+        Signature::from_compact(&[1u8; 64]).unwrap()
+    }
+
+    fn scid(block_height: u32) -> ShortChannelId {
+        ShortChannelId::with(block_height, 1, 0).unwrap()
+    }
+
+    fn announcement(block_height: u32, node_byte: u8) -> Messages {
+        Messages::ChannelAnnouncement(ChannelAnnouncement {
+            node_signature_1: dumb_sig(),
+            node_signature_2: dumb_sig(),
+            bitcoin_signature_1: dumb_sig(),
+            bitcoin_signature_2: dumb_sig(),
+            features: ChannelFeatures::default(),
+            chain_hash: mainnet_chain_hash(),
+            short_channel_id: scid(block_height),
+            node_id_1: node(1),
+            node_id_2: node(node_byte),
+            bitcoin_key_1: node(3),
+            bitcoin_key_2: node(4),
+            extra_data: none!(),
+        })
+    }
+
+    fn update(
+        block_height: u32,
+        channel_flags: u8,
+        timestamp: u32,
+        fee_base_msat: u32,
+    ) -> Messages {
+        Messages::ChannelUpdate(ChannelUpdate {
+            signature: dumb_sig(),
+            chain_hash: mainnet_chain_hash(),
+            short_channel_id: scid(block_height),
+            timestamp,
+            message_flags: 1,
+            channel_flags,
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: 1000,
+            fee_base_msat,
+            fee_proportional_millionths: 100,
+            htlc_maximum_msat: 100_000_000,
+            extra_data: none!(),
+        })
+    }
+
+    fn node_msg(node_byte: u8, timestamp: u32) -> Messages {
+        Messages::NodeAnnouncements(NodeAnnouncements {
+            signature: dumb_sig(),
+            features: InitFeatures::default(),
+            timestamp,
+            node_id: node(node_byte),
+            rgb_color: NodeColor::from([node_byte; 3]),
+            alias: Alias::from(Slice32::from([node_byte; 32])),
+            addresses: AddressList::default(),
+            extra_data: none!(),
+        })
+    }
+
+    fn init(extended: bool) -> Messages {
+        Messages::Init(Init {
+            global_features: none!(),
+            local_features: InitFeatures {
+                gossip_queries: Some(false),
+                gossip_queries_ex: if extended { Some(false) } else { None },
+                ..default!()
+            },
+            assets: none!(),
+            unknown_tlvs: none!(),
+        })
+    }
+
+    fn peers(extended: bool) -> (GossipQueries, GossipQueries) {
+        let mut alice = GossipQueries::default();
+        let mut bob = GossipQueries::default();
+        alice.update_from_peer(&init(extended)).unwrap();
+        bob.update_from_peer(&init(extended)).unwrap();
+        for message in [
+            announcement(700_000, 2),
+            announcement(700_001, 5),
+            announcement(700_020, 6),
+            update(700_000, 0, 20, 1),
+            update(700_000, 1, 10, 1),
+            update(700_001, 0, 10, 1),
+            node_msg(1, 10),
+            node_msg(5, 10),
+        ] {
+            alice.update_from_peer(&message).unwrap();
+        }
+        // Bob knows outdated update for the first direction and the update
+        // for the second direction which differs only by its timestamp
+        for message in [
+            announcement(700_000, 2),
+            update(700_000, 0, 10, 2),
+            update(700_000, 1, 5, 1),
+        ] {
+            bob.update_from_peer(&message).unwrap();
+        }
+        (alice, bob)
+    }
+
+    /// Delivers all messages queued by the sender to the receiver, returning
+    /// their number
+    fn deliver(from: &mut GossipQueries, to: &mut GossipQueries) -> usize {
+        let mut count = 0;
+        while let Some(message) = from.pop_message() {
+            to.update_from_peer(&message).unwrap();
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn channel_range_replies() {
+        let (mut alice, _) = peers(true);
+        let query = QueryChannelRange {
+            chain_hash: mainnet_chain_hash(),
+            first_blocknum: 700_000,
+            number_of_blocks: 10,
+            query_option: Some(QueryOption {
+                want_timestamps: true,
+                want_checksums: false,
+            }),
+            unknown_tlvs: none!(),
+        };
+        alice
+            .update_from_peer(&Messages::QueryChannelRange(query.clone()))
+            .unwrap();
+        let reply = match alice.pop_message() {
+            Some(Messages::ReplyChannelRange(reply)) => reply,
+            _ => panic!("reply_channel_range expected"),
+        };
+        assert!(alice.pop_message().is_none());
+        assert_eq!(reply.first_blocknum, 700_000);
+        assert_eq!(reply.number_of_blocks, 10);
+        assert_eq!(reply.full_information, 1);
+        assert_eq!(reply.encoded_short_ids.short_ids, vec![
+            scid(700_000),
+            scid(700_001)
+        ]);
+        assert_eq!(reply.timestamps.unwrap().timestamps, vec![
+            UpdateTimestamps {
+                timestamp_node_id_1: 20,
+                timestamp_node_id_2: 10,
+            },
+            UpdateTimestamps {
+                timestamp_node_id_1: 10,
+                timestamp_node_id_2: 0,
+            }
+        ]);
+        assert_eq!(reply.checksums, None);
+
+        // Queries for a foreign chain get no channels
+        let mut foreign = query;
+        foreign.chain_hash = Slice32::from([0xAA; 32]);
+        alice
+            .update_from_peer(&Messages::QueryChannelRange(foreign))
+            .unwrap();
+        match alice.pop_message() {
+            Some(Messages::ReplyChannelRange(reply)) => {
+                assert_eq!(reply.full_information, 0);
+                assert!(reply.encoded_short_ids.short_ids.is_empty());
+            }
+            _ => panic!("reply_channel_range expected"),
+        }
+    }
+
+    #[test]
+    fn extended_sync() {
+        let (mut alice, mut bob) = peers(true);
+
+        let query = bob.sync_channels(700_000, 100);
+        assert!(bob.is_syncing());
+        alice
+            .update_from_peer(&Messages::QueryChannelRange(query))
+            .unwrap();
+        assert_eq!(deliver(&mut alice, &mut bob), 1);
+
+        // Only the missing channels and the outdated update are requested
+        match bob.pop_message() {
+            Some(Messages::QueryShortChannelIds(query)) => {
+                assert_eq!(query.short_ids.short_ids, vec![
+                    scid(700_000),
+                    scid(700_001),
+                    scid(700_020)
+                ]);
+                assert_eq!(query.query_flags.as_ref().unwrap().flags, vec![
+                    QueryFlags::CHANNEL_UPDATE_1,
+                    QueryFlags::ALL,
+                    QueryFlags::ALL
+                ]);
+                alice
+                    .update_from_peer(&Messages::QueryShortChannelIds(query))
+                    .unwrap();
+            }
+            _ => panic!("query_short_channel_ids expected"),
+        }
+        // Update, two announcements, one update, two node announcements and
+        // reply end
+        assert_eq!(deliver(&mut alice, &mut bob), 7);
+        assert!(!bob.is_syncing());
+        assert!(bob.pop_message().is_none());
+        assert_eq!(
+            bob.channels()[&scid(700_000)].timestamps(),
+            UpdateTimestamps {
+                timestamp_node_id_1: 20,
+                timestamp_node_id_2: 5,
+            }
+        );
+        assert_eq!(bob.channels().len(), 3);
+        assert_eq!(bob.nodes(), alice.nodes());
+    }
+
+    #[test]
+    fn plain_sync() {
+        let (mut alice, mut bob) = peers(false);
+
+        let query = bob.sync_channels(700_000, 100);
+        assert_eq!(query.query_option, None);
+        alice
+            .update_from_peer(&Messages::QueryChannelRange(query))
+            .unwrap();
+        deliver(&mut alice, &mut bob);
+
+        // Without timestamps only unknown channels are requested, and all of
+        // their gossip is returned
+        match bob.pop_message() {
+            Some(Messages::QueryShortChannelIds(query)) => {
+                assert_eq!(query.short_ids.short_ids, vec![
+                    scid(700_001),
+                    scid(700_020)
+                ]);
+                assert_eq!(query.query_flags, None);
+                alice
+                    .update_from_peer(&Messages::QueryShortChannelIds(query))
+                    .unwrap();
+            }
+            _ => panic!("query_short_channel_ids expected"),
+        }
+        assert_eq!(deliver(&mut alice, &mut bob), 6);
+        assert!(!bob.is_syncing());
+        assert_eq!(
+            bob.channels()[&scid(700_001)],
+            alice.channels()[&scid(700_001)]
+        );
+
+        // Query flags must match short channel ids
+        let query = QueryShortChannelIds {
+            chain_hash: mainnet_chain_hash(),
+            short_ids: EncodedShortIds::uncompressed(vec![scid(700_000)]),
+            query_flags: Some(EncodedQueryFlags::default()),
+            unknown_tlvs: none!(),
+        };
+        assert_eq!(
+            alice.update_from_peer(&Messages::QueryShortChannelIds(query)),
+            Err(Error::QueryFlagsMismatch {
+                short_ids: 1,
+                flags: 0
+            })
+        );
+    }
+
+    #[test]
+    fn router_extension() {
+        let mut router = Router::<GossipExt>::default();
+        let has_queries = |router: &Router<GossipExt>| {
+            router
+                .extensions()
+                .any(|(id, _)| *id == GossipExt::GossipQueries)
+        };
+        router
+            .update_from_peer(&Messages::Init(Init {
+                global_features: none!(),
+                local_features: none!(),
+                assets: none!(),
+                unknown_tlvs: none!(),
+            }))
+            .unwrap();
+        assert!(!has_queries(&router));
+        router.update_from_peer(&init(true)).unwrap();
+        assert!(has_queries(&router));
+
+        let mut message =
+            Messages::ReplyShortChannelIdsEnd(ReplyShortChannelIdsEnd {
+                chain_hash: mainnet_chain_hash(),
+                full_information: 1,
+            });
+        assert_eq!(
+            router.state_change(&UpdateReq::NextMessage, &mut message),
+            Err(Error::NoPendingGossip)
+        );
+        router
+            .state_change(
+                &UpdateReq::SyncChannels {
+                    first_blocknum: 700_000,
+                    number_of_blocks: 10,
+                },
+                &mut message,
+            )
+            .unwrap();
+        match message {
+            Messages::QueryChannelRange(query) => {
+                assert_eq!(query.first_blocknum, 700_000);
+                assert!(query.query_option.is_some());
+            }
+            _ => panic!("query_channel_range expected"),
+        }
+    }
+}
@@ -11,29 +11,140 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/MIT>.
 
-use amplify::DumbDefault;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use amplify::{DumbDefault, Slice32, Wrapper};
+use bitcoin::hashes::{sha256d, Hash};
+use internet2::addr::NodeId;
 use internet2::presentation::sphinx::Hop;
+use lightning_encoding::LightningEncode;
+use lnpbp::chain::Chain;
 use p2p::bolt::{
-    ChannelId, HopRealm, Messages, PaymentOnion, PaymentRequest, ShortChannelId,
+    ChannelAnnouncement, ChannelId, ChannelUpdate, Messages, NodeAnnouncements,
+    PaymentOnion, PaymentRequest, ShortChannelId,
 };
+use secp256k1::ecdsa::Signature;
+use secp256k1::Message;
 use strict_encoding::{strict_deserialize, strict_serialize};
 
-use super::GossipChannelInfo;
+use super::mpp::split_payment;
+use super::onion::{receiver_realm, select_realms};
+use super::path::{find_route, FoundRoute};
+use super::queries::GossipQueries;
+use super::{
+    funding_script_pubkey, GossipChannelInfo, GossipNodeInfo,
+    ProbabilisticScorer, ScorerParams, UtxoLookup, UtxoLookupError, UtxoOracle,
+};
 use crate::router::gossip::LocalChannelInfo;
-use crate::router::Router;
+use crate::router::{RouteParams, Router};
 use crate::{extension, router, Extension, RouterExtension};
 
 #[derive(
-    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error,
+    From
 )]
 #[display(doc_comments)]
-pub enum Error {}
+pub enum Error {
+    /// gossip message for channel {short_channel_id} belongs to the chain
+    /// {chain_hash}, which is not used by the router
+    ChainMismatch {
+        short_channel_id: ShortChannelId,
+        chain_hash: Slice32,
+    },
+
+    /// `channel_announcement` for channel {short_channel_id} has invalid
+    /// signature made with key {key}
+    ChannelAnnouncementSignature {
+        short_channel_id: ShortChannelId,
+        key: NodeId,
+    },
+
+    /// `channel_update` for channel {short_channel_id} has invalid signature
+    /// of node {node_id}
+    ChannelUpdateSignature {
+        short_channel_id: ShortChannelId,
+        node_id: NodeId,
+    },
+
+    /// `node_announcement` has invalid signature of node {0}
+    NodeAnnouncementSignature(NodeId),
+
+    /// announced channel is not backed by a funding output: {0}
+    #[from]
+    Utxo(UtxoLookupError),
+
+    /// funding output of channel {0} does not match bitcoin keys from
+    /// `channel_announcement`
+    FundingScriptMismatch(ShortChannelId),
+
+    /// `query_short_channel_ids` contains {flags} query flags for {short_ids}
+    /// short channel ids
+    QueryFlagsMismatch { short_ids: usize, flags: usize },
+
+    /// there are no pending gossip messages to be sent to the peer
+    NoPendingGossip,
+}
+
+/// Returns chain hash of the bitcoin mainnet, used by routers by default
+pub(super) fn mainnet_chain_hash() -> Slice32 {
+    Slice32::from_inner(Chain::Mainnet.as_genesis_hash().into_inner())
+}
+
+/// Returns current UNIX timestamp in seconds, used for decaying knowledge
+/// of the channel liquidity
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Verifies signature over the double-SHA256 of a gossip message data
+/// following its `sig_count` leading signatures, as required by BOLT-7.
+///
+/// Gossip messages keep extension data following their known fields, so
+/// their encoding reproduces the bytes signed by the origin node.
+fn verify_gossip(
+    message: &impl LightningEncode,
+    sig_count: usize,
+    signature: &Signature,
+    key: NodeId,
+) -> bool {
+    let data = message
+        .lightning_serialize()
+        .expect("in-memory encoding of gossip message can't fail");
+    let digest = sha256d::Hash::hash(&data[sig_count * 64..]);
+    let msg = Message::from_slice(&digest[..])
+        .expect("hash is always a valid secp256k1 message");
+    secp256k1::SECP256K1
+        .verify_ecdsa(&msg, signature, &key.public_key())
+        .is_ok()
+}
 
-#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 #[derive(StrictEncode, StrictDecode)]
 pub struct RouterState {
-    remote_channels: Vec<GossipChannelInfo>,
+    pub(super) chain_hash: Slice32,
+    pub(super) remote_channels: BTreeMap<ShortChannelId, GossipChannelInfo>,
+    pub(super) remote_nodes: BTreeMap<NodeId, GossipNodeInfo>,
     direct_channels: Vec<LocalChannelInfo>,
+    direct_nodes: BTreeMap<NodeId, GossipNodeInfo>,
+    scorer: ProbabilisticScorer,
+}
+
+impl Default for RouterState {
+    fn default() -> Self {
+        RouterState {
+            chain_hash: mainnet_chain_hash(),
+            remote_channels: empty!(),
+            remote_nodes: empty!(),
+            direct_channels: empty!(),
+            direct_nodes: empty!(),
+            scorer: ProbabilisticScorer::default(),
+        }
+    }
 }
 
 impl DumbDefault for RouterState {
@@ -49,6 +160,7 @@ pub enum GossipExt {
     MainRouter = 0,
     DirectRouter = 1,
     GossipRouter = 2,
+    GossipQueries = 3,
 }
 
 impl Default for GossipExt {
@@ -81,7 +193,7 @@ impl extension::Nomenclature for GossipExt {
     type Error = Error;
     type PeerMessage = lnp2p::bolt::Messages;
     type UpdateMessage = UpdateMsg;
-    type UpdateRequest = ();
+    type UpdateRequest = UpdateReq;
 }
 
 impl router::Nomenclature for GossipExt {
@@ -95,13 +207,56 @@ impl router::Nomenclature for GossipExt {
     }
 
     fn update_from_peer(
-        _router: &mut Router<Self>,
-        _message: &Messages,
+        router: &mut Router<Self>,
+        message: &Messages,
     ) -> Result<(), Error> {
-        // TODO: Add support for gossip queries by adding query extension if
-        //       we are getting corresponding feature flag
+        match message {
+            Messages::Init(init)
+                if init.local_features.gossip_queries.is_some()
+                    && !router
+                        .extensions()
+                        .any(|(id, _)| *id == GossipExt::GossipQueries) =>
+            {
+                let mut state = RouterState::default();
+                router.store_state(&mut state);
+                let mut queries = GossipQueries::default();
+                queries.load_state(&state);
+                router.add_extension(Box::new(queries));
+            }
+            _ => {}
+        }
         Ok(())
     }
+
+    fn update_from_local(
+        router: &mut Router<Self>,
+        message: &UpdateMsg,
+    ) -> Result<(), Error> {
+        if let UpdateMsg::PruneSpentChannels = message {
+            // Propagates removal of the spent channels from the gossip router
+            // to the other extensions
+            let mut state = RouterState::default();
+            router.store_state(&mut state);
+            router.load_state(&state);
+        }
+        Ok(())
+    }
+}
+
+/// Requests producing messages which should be sent to the peer supporting
+/// gossip queries
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum UpdateReq {
+    /// Starts synchronization of channels opened within the block range,
+    /// producing `query_channel_range` message
+    SyncChannels {
+        first_blocknum: u32,
+        number_of_blocks: u32,
+    },
+    /// Takes the next queued gossip message, which may be a reply to the
+    /// peer query or a query for the missing channels. Fails with
+    /// [`Error::NoPendingGossip`] if there are no queued messages.
+    NextMessage,
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
@@ -113,6 +268,26 @@ pub enum UpdateMsg {
         local_amount_msat: u64,
         remote_amount_msat: u64,
     },
+    /// Replaces short channel id alias with the real short channel id once
+    /// the funding transaction is mined
+    DirectChannelScid {
+        channel_id: ChannelId,
+        short_channel_id: ShortChannelId,
+    },
+    /// Requests removal of the announced channels which funding outputs were
+    /// spent. Should be sent each time a new block is mined.
+    PruneSpentChannels,
+    /// Reports failed payment attempt over the route, so the router can
+    /// learn channel liquidity. `short_channel_id` is the channel which
+    /// failed to forward the payment, or `None` if the payment was rejected
+    /// by its destination.
+    PaymentPathFailed {
+        path: Vec<Hop<PaymentOnion>>,
+        short_channel_id: Option<ShortChannelId>,
+    },
+    /// Reports payment which has successfully reached its destination over
+    /// the route
+    PaymentPathSuccessful(Vec<Hop<PaymentOnion>>),
 }
 
 /// Router for direct channels (between this node and other nodes) for
@@ -120,32 +295,74 @@ pub enum UpdateMsg {
 #[derive(Getters, Clone, PartialEq, Eq, Debug, Default)]
 pub struct DirectRouter {
     channels: Vec<LocalChannelInfo>,
+
+    /// Announcements of the nodes we have direct channels with, used to
+    /// detect features supported by them
+    nodes: BTreeMap<NodeId, GossipNodeInfo>,
 }
 
-impl DirectRouter {
-    fn add_direct_channel(
-        &mut self,
-        info: LocalChannelInfo,
-    ) -> Option<LocalChannelInfo> {
-        let prev_info = self.remove_direct_channel(info.channel_id);
-        self.channels.push(info);
-        prev_info
+/// Applies local update of the direct channels to the list of their
+/// descriptions; other updates are ignored
+fn update_direct_channels(
+    channels: &mut Vec<LocalChannelInfo>,
+    message: &UpdateMsg,
+) {
+    match message {
+        UpdateMsg::DirectChannelAdd(info) => {
+            channels.retain(|ch| ch.channel_id != info.channel_id);
+            channels.push(*info);
+        }
+        UpdateMsg::DirectChannelRemove(channel_id) => {
+            channels.retain(|ch| ch.channel_id != *channel_id);
+        }
+        UpdateMsg::DirectChannelUpdate {
+            channel_id,
+            local_amount_msat,
+            remote_amount_msat,
+        } => {
+            channels.iter_mut().for_each(|ch| {
+                if ch.channel_id == *channel_id {
+                    ch.outbound_capacity_msat = *local_amount_msat;
+                    ch.inbound_capacity_msat = *remote_amount_msat;
+                };
+            });
+        }
+        UpdateMsg::DirectChannelScid {
+            channel_id,
+            short_channel_id,
+        } => {
+            channels.iter_mut().for_each(|ch| {
+                if ch.channel_id == *channel_id {
+                    ch.short_channel_id = *short_channel_id;
+                };
+            });
+        }
+        UpdateMsg::PruneSpentChannels
+        | UpdateMsg::PaymentPathFailed { .. }
+        | UpdateMsg::PaymentPathSuccessful(_) => {}
     }
+}
 
-    fn remove_direct_channel(
+impl DirectRouter {
+    fn update_node(
         &mut self,
-        channel_id: ChannelId,
-    ) -> Option<LocalChannelInfo> {
-        if let Some((index, _)) = self
-            .channels
-            .iter()
-            .enumerate()
-            .find(|(_, info)| info.channel_id == channel_id)
-        {
-            Some(self.channels.remove(index))
-        } else {
-            None
+        announcement: &NodeAnnouncements,
+    ) -> Result<(), Error> {
+        let node_id = announcement.node_id;
+        if !self.channels.iter().any(|info| info.remote_node == node_id) {
+            return Ok(());
+        }
+        if !verify_gossip(announcement, 1, &announcement.signature, node_id) {
+            return Err(Error::NodeAnnouncementSignature(node_id));
+        }
+        match self.nodes.get(&node_id) {
+            Some(info) if info.timestamp >= announcement.timestamp => {}
+            _ => {
+                self.nodes
+                    .insert(node_id, GossipNodeInfo::from(announcement));
+            }
         }
+        Ok(())
     }
 }
 
@@ -155,7 +372,6 @@ impl Extension<GossipExt> for DirectRouter {
     }
 
     fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
-        #[allow(clippy::match_single_binding)] // temporarily
         match message {
             /*
             Messages::FundingLocked(FundingLocked { channel_id, .. }) => {}
@@ -164,42 +380,26 @@ impl Extension<GossipExt> for DirectRouter {
             Messages::CommitmentSigned(_) => {}
             Messages::RevokeAndAck(_) => {}
              */
-            _ => {} // Nothing to do here
+            Messages::NodeAnnouncements(announcement) => {
+                self.update_node(announcement)
+            }
+            _ => Ok(()), // Nothing to do here
         }
-
-        Ok(())
     }
 
     fn update_from_local(&mut self, message: &UpdateMsg) -> Result<(), Error> {
-        match message {
-            UpdateMsg::DirectChannelAdd(info) => {
-                self.add_direct_channel(*info);
-            }
-            UpdateMsg::DirectChannelRemove(channel_id) => {
-                self.remove_direct_channel(*channel_id);
-            }
-            UpdateMsg::DirectChannelUpdate {
-                channel_id,
-                local_amount_msat,
-                remote_amount_msat,
-            } => {
-                self.channels.iter_mut().for_each(|ch| {
-                    if ch.channel_id == *channel_id {
-                        ch.outbound_capacity_msat = *local_amount_msat;
-                        ch.inbound_capacity_msat = *remote_amount_msat;
-                    };
-                });
-            }
-        }
+        update_direct_channels(&mut self.channels, message);
         Ok(())
     }
 
     fn load_state(&mut self, state: &RouterState) {
         self.channels = state.direct_channels.clone();
+        self.nodes = state.direct_nodes.clone();
     }
 
     fn store_state(&self, state: &mut RouterState) {
         state.direct_channels = self.channels.clone();
+        state.direct_nodes = self.nodes.clone();
     }
 }
 
@@ -214,32 +414,338 @@ impl RouterExtension<GossipExt> for DirectRouter {
 
     fn build_route(
         &mut self,
-        payment: PaymentRequest,
+        payment: &PaymentRequest,
+        params: &RouteParams,
         route: &mut Vec<Hop<PaymentOnion>>,
     ) {
-        if let Some(channel) = self
-            .channels
-            .iter()
-            .find(|info| info.remote_node == payment.node_id)
+        if !params.is_node_allowed(payment.node_id)
+            || !params.check_limits(
+                payment.amount_msat,
+                0,
+                payment.min_final_cltv_expiry,
+                1,
+            )
         {
+            return;
+        }
+        if let Some(channel) = self.channels.iter().find(|info| {
+            info.remote_node == payment.node_id
+                && params.is_outbound_channel_allowed(
+                    info.short_channel_id,
+                    info.remote_node,
+                )
+        }) {
             if channel.outbound_capacity_msat < payment.amount_msat {
                 return; // We do not have enough funds
             }
+            let announced =
+                self.nodes.get(&payment.node_id).map(|info| &info.features);
+            let realm = match receiver_realm(payment, announced) {
+                Some(realm) => realm,
+                None => return, // Payment secret required but not known
+            };
 
             *route = vec![Hop::with(payment.node_id, PaymentOnion {
-                // TODO: Choose realm basing on the destination configuration
-                realm: HopRealm::Legacy(ShortChannelId::default()),
+                realm,
                 amt_to_forward: payment.amount_msat,
-                outgoing_cltv_value: payment.min_final_cltv_expiry,
+                outgoing_cltv_value: params
+                    .block_height
+                    .saturating_add(payment.min_final_cltv_expiry),
             })];
         }
     }
 }
 
 /// BOLT-7 gossip-based router
-#[derive(Getters, Clone, PartialEq, Eq, Debug, Default)]
+/// Router building network graph out of the gossip messages
+#[derive(Getters, Clone, PartialEq, Eq, Debug)]
 pub struct GossipRouter {
-    channels: Vec<GossipChannelInfo>,
+    /// Hash of the genesis block of the chain which gossip is accepted for
+    chain_hash: Slice32,
+
+    /// Announced channels, indexed by their short channel id
+    channels: BTreeMap<ShortChannelId, GossipChannelInfo>,
+
+    /// Announced nodes; contains only nodes which have at least one known
+    /// channel, including direct channels of the local node
+    nodes: BTreeMap<NodeId, GossipNodeInfo>,
+
+    /// Source of on-chain data for checking funding outputs of the announced
+    /// channels. If absent, channels are accepted basing on their signatures
+    /// only.
+    #[getter(skip)]
+    utxo_oracle: Option<UtxoOracle>,
+
+    /// Direct channels of the local node, used as the first hops of the
+    /// computed routes
+    direct_channels: Vec<LocalChannelInfo>,
+
+    /// Liquidity knowledge learned from the payment attempts
+    scorer: ProbabilisticScorer,
+}
+
+impl Default for GossipRouter {
+    fn default() -> Self {
+        GossipRouter::with(mainnet_chain_hash())
+    }
+}
+
+impl GossipRouter {
+    /// Constructs router accepting gossip for the chain with the given
+    /// genesis hash
+    pub fn with(chain_hash: Slice32) -> GossipRouter {
+        GossipRouter {
+            chain_hash,
+            channels: empty!(),
+            nodes: empty!(),
+            utxo_oracle: None,
+            direct_channels: empty!(),
+            scorer: ProbabilisticScorer::default(),
+        }
+    }
+
+    /// Replaces parameters of the liquidity scorer, dropping all liquidity
+    /// knowledge learned so far
+    #[inline]
+    pub fn set_scorer_params(&mut self, params: ScorerParams) {
+        self.scorer = ProbabilisticScorer::with(params)
+    }
+
+    /// Installs UTXO lookup used to check funding outputs of the newly
+    /// announced channels and to prune channels which were closed
+    #[inline]
+    pub fn set_utxo_lookup(&mut self, lookup: Arc<dyn UtxoLookup>) {
+        self.utxo_oracle = Some(UtxoOracle::with(lookup))
+    }
+
+    /// Checks that the announced channel is backed by an unspent 2-of-2
+    /// funding output, returning channel capacity in satoshis. If the router
+    /// has no UTXO lookup installed, returns `Ok(None)`.
+    pub fn verify_funding(
+        &self,
+        announcement: &ChannelAnnouncement,
+    ) -> Result<Option<u64>, Error> {
+        let oracle = match self.utxo_oracle {
+            None => return Ok(None),
+            Some(ref oracle) => oracle,
+        };
+        let short_channel_id = announcement.short_channel_id;
+        let txout = oracle.lookup_utxo(short_channel_id)?;
+        let script_pubkey = funding_script_pubkey(
+            announcement.bitcoin_key_1,
+            announcement.bitcoin_key_2,
+        );
+        if txout.script_pubkey != script_pubkey.into_inner() {
+            return Err(Error::FundingScriptMismatch(short_channel_id));
+        }
+        Ok(Some(txout.value))
+    }
+
+    /// Removes channels which funding outputs were spent, together with the
+    /// nodes left without channels. Returns short ids of the removed
+    /// channels.
+    pub fn prune_spent_channels(&mut self) -> Vec<ShortChannelId> {
+        let oracle = match self.utxo_oracle {
+            None => return vec![],
+            Some(ref oracle) => oracle,
+        };
+        let spent = self
+            .channels
+            .keys()
+            .copied()
+            .filter(|short_channel_id| {
+                matches!(
+                    oracle.lookup_utxo(*short_channel_id),
+                    Err(UtxoLookupError::Spent(_))
+                )
+            })
+            .collect::<Vec<_>>();
+        for short_channel_id in &spent {
+            self.channels.remove(short_channel_id);
+        }
+        let channels = &self.channels;
+        let direct_channels = &self.direct_channels;
+        self.nodes.retain(|node_id, _| {
+            channels.values().any(|info| {
+                info.nodes.0 == *node_id || info.nodes.1 == *node_id
+            }) || direct_channels
+                .iter()
+                .any(|info| info.remote_node == *node_id)
+        });
+        spent
+    }
+
+    fn check_chain(
+        &self,
+        short_channel_id: ShortChannelId,
+        chain_hash: Slice32,
+    ) -> Result<(), Error> {
+        if chain_hash != self.chain_hash {
+            return Err(Error::ChainMismatch {
+                short_channel_id,
+                chain_hash,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that `channel_announcement` belongs to the router chain and
+    /// carries valid signatures of both nodes and both bitcoin keys
+    pub fn verify_channel_announcement(
+        &self,
+        announcement: &ChannelAnnouncement,
+    ) -> Result<(), Error> {
+        let short_channel_id = announcement.short_channel_id;
+        self.check_chain(short_channel_id, announcement.chain_hash)?;
+        [
+            (&announcement.node_signature_1, announcement.node_id_1),
+            (&announcement.node_signature_2, announcement.node_id_2),
+            (
+                &announcement.bitcoin_signature_1,
+                announcement.bitcoin_key_1,
+            ),
+            (
+                &announcement.bitcoin_signature_2,
+                announcement.bitcoin_key_2,
+            ),
+        ]
+        .into_iter()
+        .try_for_each(|(signature, key)| {
+            if verify_gossip(announcement, 4, signature, key) {
+                Ok(())
+            } else {
+                Err(Error::ChannelAnnouncementSignature {
+                    short_channel_id,
+                    key,
+                })
+            }
+        })
+    }
+
+    /// Checks that `channel_update` belongs to the router chain and is signed
+    /// by the node originating the updated channel direction. The channel
+    /// must be already known to the router.
+    pub fn verify_channel_update(
+        &self,
+        info: &GossipChannelInfo,
+        update: &ChannelUpdate,
+    ) -> Result<(), Error> {
+        let short_channel_id = update.short_channel_id;
+        self.check_chain(short_channel_id, update.chain_hash)?;
+        let node_id = if update.channel_flags & 0b01 == 0 {
+            info.nodes.0
+        } else {
+            info.nodes.1
+        };
+        if !verify_gossip(update, 1, &update.signature, node_id) {
+            return Err(Error::ChannelUpdateSignature {
+                short_channel_id,
+                node_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that `node_announcement` is signed by the announced node
+    pub fn verify_node_announcement(
+        &self,
+        announcement: &NodeAnnouncements,
+    ) -> Result<(), Error> {
+        let node_id = announcement.node_id;
+        if !verify_gossip(announcement, 1, &announcement.signature, node_id) {
+            return Err(Error::NodeAnnouncementSignature(node_id));
+        }
+        Ok(())
+    }
+
+    fn add_channel(
+        &mut self,
+        announcement: &ChannelAnnouncement,
+    ) -> Result<(), Error> {
+        self.verify_channel_announcement(announcement)?;
+        // Repeated announcements must not reset already known channel
+        // directions
+        if self.channels.contains_key(&announcement.short_channel_id) {
+            return Ok(());
+        }
+        let mut info = GossipChannelInfo::from(announcement);
+        info.capacity_sats = self.verify_funding(announcement)?;
+        self.channels.insert(announcement.short_channel_id, info);
+        Ok(())
+    }
+
+    fn update_channel(&mut self, update: &ChannelUpdate) -> Result<(), Error> {
+        self.check_chain(update.short_channel_id, update.chain_hash)?;
+        // Updates for channels which were not announced yet are ignored
+        if let Some(info) = self.channels.get(&update.short_channel_id) {
+            self.verify_channel_update(info, update)?;
+        }
+        if let Some(info) = self.channels.get_mut(&update.short_channel_id) {
+            info.update_direction(update);
+        }
+        Ok(())
+    }
+
+    fn update_node(
+        &mut self,
+        announcement: &NodeAnnouncements,
+    ) -> Result<(), Error> {
+        // To avoid trivial denial of service attacks, nodes not associated
+        // with an already known channel are ignored
+        if !self.has_channels(announcement.node_id)
+            && !self
+                .direct_channels
+                .iter()
+                .any(|info| info.remote_node == announcement.node_id)
+        {
+            return Ok(());
+        }
+        self.verify_node_announcement(announcement)?;
+        match self.nodes.get(&announcement.node_id) {
+            Some(info) if info.timestamp >= announcement.timestamp => {}
+            _ => {
+                self.nodes.insert(
+                    announcement.node_id,
+                    GossipNodeInfo::from(announcement),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Detects whether the node participates in any of the known channels
+    pub fn has_channels(&self, node_id: NodeId) -> bool {
+        self.channels
+            .values()
+            .any(|info| info.nodes.0 == node_id || info.nodes.1 == node_id)
+    }
+
+    /// Finds the cheapest route to the payment destination across the gossip
+    /// graph, satisfying route parameters.
+    ///
+    /// Unlike [`crate::router::Router::compute_route`], returns also the amount
+    /// and CLTV expiry of the HTLC which has to be offered to the direct
+    /// peer starting the route.
+    pub fn find_route(
+        &self,
+        payment: &PaymentRequest,
+        params: &RouteParams,
+    ) -> Option<FoundRoute> {
+        let mut route = find_route(
+            self.channels.values(),
+            &self.direct_channels,
+            payment,
+            params,
+            &self.scorer,
+            unix_time(),
+        )?;
+        let features =
+            |node_id| self.nodes.get(&node_id).map(|info| &info.features);
+        if !select_realms(&mut route.hops, payment, features) {
+            return None;
+        }
+        Some(route)
+    }
 }
 
 impl Extension<GossipExt> for GossipRouter {
@@ -247,28 +753,55 @@ impl Extension<GossipExt> for GossipRouter {
         GossipExt::GossipRouter
     }
 
-    fn update_from_local(&mut self, _message: &UpdateMsg) -> Result<(), Error> {
-        // Nothing to do here so far
+    fn update_from_local(&mut self, message: &UpdateMsg) -> Result<(), Error> {
+        match message {
+            UpdateMsg::PruneSpentChannels => {
+                self.prune_spent_channels();
+            }
+            UpdateMsg::PaymentPathFailed {
+                path,
+                short_channel_id,
+            } => self.scorer.payment_path_failed(
+                &self.channels,
+                path,
+                *short_channel_id,
+                unix_time(),
+            ),
+            UpdateMsg::PaymentPathSuccessful(path) => self
+                .scorer
+                .payment_path_successful(&self.channels, path, unix_time()),
+            _ => update_direct_channels(&mut self.direct_channels, message),
+        }
         Ok(())
     }
 
     fn update_from_peer(&mut self, message: &Messages) -> Result<(), Error> {
         match message {
-            // TODO: Extract routing information from gossip messages
-            Messages::UpdateFee(_) => {}
-            Messages::ChannelAnnouncement(_) => {}
-            Messages::ChannelUpdate(_) => {}
-            _ => {}
+            Messages::ChannelAnnouncement(announcement) => {
+                self.add_channel(announcement)
+            }
+            Messages::ChannelUpdate(update) => self.update_channel(update),
+            Messages::NodeAnnouncements(announcement) => {
+                self.update_node(announcement)
+            }
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     fn load_state(&mut self, state: &RouterState) {
-        self.channels = state.remote_channels.clone()
+        self.chain_hash = state.chain_hash;
+        self.direct_channels = state.direct_channels.clone();
+        self.channels = state.remote_channels.clone();
+        self.nodes = state.remote_nodes.clone();
+        self.scorer = state.scorer.clone();
     }
 
     fn store_state(&self, state: &mut RouterState) {
-        state.remote_channels = self.channels.clone()
+        state.chain_hash = self.chain_hash;
+        state.direct_channels = self.direct_channels.clone();
+        state.remote_channels = self.channels.clone();
+        state.remote_nodes = self.nodes.clone();
+        state.scorer = self.scorer.clone();
     }
 }
 
@@ -283,9 +816,639 @@ impl RouterExtension<GossipExt> for GossipRouter {
 
     fn build_route(
         &mut self,
-        _payment: PaymentRequest,
-        _route: &mut Vec<Hop<PaymentOnion>>,
+        payment: &PaymentRequest,
+        params: &RouteParams,
+        route: &mut Vec<Hop<PaymentOnion>>,
+    ) {
+        if !route.is_empty() {
+            return; // Route was already found by other router
+        }
+        if let Some(found) = self.find_route(payment, params) {
+            *route = found.hops;
+        }
+    }
+
+    fn build_multipath_route(
+        &mut self,
+        payment: &PaymentRequest,
+        params: &RouteParams,
+        routes: &mut Vec<Vec<Hop<PaymentOnion>>>,
     ) {
-        // TODO: Implement route computing
+        if !routes.is_empty() {
+            return; // Routes were already found by other router
+        }
+        if let Some(parts) = split_payment(
+            &self.channels,
+            &self.direct_channels,
+            payment,
+            params,
+            &self.scorer,
+            unix_time(),
+            |node_id| self.nodes.get(&node_id).map(|info| &info.features),
+        ) {
+            *routes = parts;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use bitcoin::{Script, TxOut};
+    use bitcoin_scripts::hlc::{HashLock, HashPreimage};
+    use lightning_encoding::LightningDecode;
+    use p2p::bolt::{
+        AddressList, Alias, ChannelFeatures, ExtraData, HopRealm, InitFeatures,
+        NodeColor, PaymentData, QueryChannelRange,
+    };
+    use secp256k1::{PublicKey, SecretKey};
+
+    use super::*;
+
+    fn seckey(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn node(byte: u8) -> NodeId {
+        NodeId::from(PublicKey::from_secret_key(
+            secp256k1::SECP256K1,
+            &seckey(byte),
+        ))
+    }
+
+    fn sign(
+        message: &impl LightningEncode,
+        sig_count: usize,
+        byte: u8,
+    ) -> Signature {
+        let data = message.lightning_serialize().unwrap();
+        let digest = sha256d::Hash::hash(&data[sig_count * 64..]);
+        let msg = Message::from_slice(&digest[..]).unwrap();
+        secp256k1::SECP256K1.sign_ecdsa(&msg, &seckey(byte))
+    }
+
+    fn channel_announcement(scid: ShortChannelId) -> ChannelAnnouncement {
+        let dumb_sig = Signature::from_compact(&[1u8; 64]).unwrap();
+        let mut announcement = ChannelAnnouncement {
+            node_signature_1: dumb_sig,
+            node_signature_2: dumb_sig,
+            bitcoin_signature_1: dumb_sig,
+            bitcoin_signature_2: dumb_sig,
+            features: ChannelFeatures::default(),
+            chain_hash: mainnet_chain_hash(),
+            short_channel_id: scid,
+            node_id_1: node(1),
+            node_id_2: node(2),
+            bitcoin_key_1: node(3),
+            bitcoin_key_2: node(4),
+            extra_data: none!(),
+        };
+        announcement.node_signature_1 = sign(&announcement, 4, 1);
+        announcement.node_signature_2 = sign(&announcement, 4, 2);
+        announcement.bitcoin_signature_1 = sign(&announcement, 4, 3);
+        announcement.bitcoin_signature_2 = sign(&announcement, 4, 4);
+        announcement
+    }
+
+    fn announcement(scid: ShortChannelId) -> Messages {
+        Messages::ChannelAnnouncement(channel_announcement(scid))
+    }
+
+    fn channel_update(
+        scid: ShortChannelId,
+        channel_flags: u8,
+        timestamp: u32,
+        fee_base_msat: u32,
+    ) -> ChannelUpdate {
+        let mut update = ChannelUpdate {
+            signature: Signature::from_compact(&[1u8; 64]).unwrap(),
+            chain_hash: mainnet_chain_hash(),
+            short_channel_id: scid,
+            timestamp,
+            message_flags: 1,
+            channel_flags,
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: 1000,
+            fee_base_msat,
+            fee_proportional_millionths: 100,
+            htlc_maximum_msat: 100_000_000,
+            extra_data: none!(),
+        };
+        update.signature = sign(&update, 1, 1 + (channel_flags & 0b01));
+        update
+    }
+
+    fn update(
+        scid: ShortChannelId,
+        channel_flags: u8,
+        timestamp: u32,
+        fee_base_msat: u32,
+    ) -> Messages {
+        Messages::ChannelUpdate(channel_update(
+            scid,
+            channel_flags,
+            timestamp,
+            fee_base_msat,
+        ))
+    }
+
+    fn node_announcement(
+        node_byte: u8,
+        timestamp: u32,
+        alias: u8,
+    ) -> NodeAnnouncements {
+        let mut announcement = NodeAnnouncements {
+            signature: Signature::from_compact(&[1u8; 64]).unwrap(),
+            features: InitFeatures::default(),
+            timestamp,
+            node_id: node(node_byte),
+            rgb_color: NodeColor::from([alias; 3]),
+            alias: Alias::from(Slice32::from([alias; 32])),
+            addresses: AddressList::default(),
+            extra_data: none!(),
+        };
+        announcement.signature = sign(&announcement, 1, node_byte);
+        announcement
+    }
+
+    fn node_msg(node_byte: u8, timestamp: u32, alias: u8) -> Messages {
+        Messages::NodeAnnouncements(node_announcement(
+            node_byte, timestamp, alias,
+        ))
+    }
+
+    #[test]
+    fn network_graph() {
+        let scid = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let mut router = GossipRouter::default();
+
+        // Updates and node announcements for unknown channels are ignored
+        router.update_from_peer(&update(scid, 0, 10, 1)).unwrap();
+        router.update_from_peer(&node_msg(1, 10, 1)).unwrap();
+        assert!(router.channels().is_empty());
+        assert!(router.nodes().is_empty());
+
+        router.update_from_peer(&announcement(scid)).unwrap();
+        assert!(router.has_channels(node(1)));
+        assert!(!router.has_channels(node(3)));
+
+        // Direction is selected by the lowest bit of channel flags
+        router.update_from_peer(&update(scid, 0, 10, 1)).unwrap();
+        router.update_from_peer(&update(scid, 1, 10, 2)).unwrap();
+        let info = router.channels()[&scid];
+        assert_eq!(info.direction_from(node(1)).unwrap().fee_base_msat, 1);
+        assert_eq!(info.direction_from(node(2)).unwrap().fee_base_msat, 2);
+        assert_eq!(info.direction_from(node(3)), None);
+
+        // Only newer updates replace the known ones
+        router.update_from_peer(&update(scid, 0, 9, 3)).unwrap();
+        router.update_from_peer(&update(scid, 0, 10, 3)).unwrap();
+        router.update_from_peer(&update(scid, 0b11, 11, 4)).unwrap();
+        let info = router.channels()[&scid];
+        assert_eq!(info.directions.0.unwrap().fee_base_msat, 1);
+        assert_eq!(info.directions.1.unwrap().fee_base_msat, 4);
+        assert!(info.directions.1.unwrap().is_disabled());
+
+        // Repeated announcement keeps the known directions
+        router.update_from_peer(&announcement(scid)).unwrap();
+        assert_eq!(router.channels()[&scid], info);
+
+        router.update_from_peer(&node_msg(1, 10, 1)).unwrap();
+        router.update_from_peer(&node_msg(1, 9, 2)).unwrap();
+        assert_eq!(router.nodes()[&node(1)].rgb_color, NodeColor::from([1; 3]));
+        router.update_from_peer(&node_msg(1, 11, 3)).unwrap();
+        let node_info = &router.nodes()[&node(1)];
+        assert_eq!(node_info.timestamp, 11);
+        assert_eq!(node_info.alias, Alias::from(Slice32::from([3; 32])));
+
+        // Graph survives state persistence
+        let mut state = RouterState::default();
+        router.store_state(&mut state);
+        let state = strict_deserialize::<RouterState>(
+            strict_serialize(&state).unwrap(),
+        )
+        .unwrap();
+        let mut restored = GossipRouter::default();
+        restored.load_state(&state);
+        assert_eq!(restored, router);
+    }
+
+    #[test]
+    fn direct_peers() {
+        let scid = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let mut router = GossipRouter::default();
+        router.update_from_peer(&announcement(scid)).unwrap();
+        router.update_from_peer(&update(scid, 0, 10, 1)).unwrap();
+        let direct_channel = |remote: u8| LocalChannelInfo {
+            remote_node: node(remote),
+            channel_id: ChannelId::default(),
+            short_channel_id: ShortChannelId::with(700_000, 9, 0).unwrap(),
+            chain_hash: mainnet_chain_hash(),
+            inbound_capacity_msat: 0,
+            outbound_capacity_msat: 10_000_000,
+            cltv_expiry: 144,
+            htlc_minimum_msat: 1,
+            htlc_maximum_msat: u64::MAX,
+        };
+
+        // Announcements of the direct peers are kept even if they have no
+        // channels known from the gossip
+        router.update_from_peer(&node_msg(7, 10, 7)).unwrap();
+        assert!(router.nodes().is_empty());
+        router
+            .update_from_local(&UpdateMsg::DirectChannelAdd(direct_channel(7)))
+            .unwrap();
+        router.update_from_peer(&node_msg(7, 10, 7)).unwrap();
+        assert!(router.nodes().contains_key(&node(7)));
+
+        // Direct channels are used as the first hops of the routes
+        let payment = PaymentRequest {
+            amount_msat: 1_000_000,
+            payment_hash: HashLock::from(Slice32::from([0xAB; 32])),
+            node_id: node(2),
+            min_final_cltv_expiry: 18,
+            route_hints: vec![],
+            features: None,
+            payment_secret: None,
+        };
+        let params = RouteParams::default();
+        assert_eq!(router.find_route(&payment, &params), None);
+        router
+            .update_from_local(&UpdateMsg::DirectChannelAdd(direct_channel(1)))
+            .unwrap();
+        assert_eq!(router.direct_channels(), &[direct_channel(1)]);
+        let route = router.find_route(&payment, &params).unwrap();
+        assert_eq!(route.hops.len(), 2);
+        assert_eq!(route.amount_msat, 1_000_101);
+
+        let mut state = RouterState::default();
+        router.store_state(&mut state);
+        let mut restored = GossipRouter::default();
+        restored.load_state(&state);
+        assert_eq!(restored, router);
+    }
+
+    #[test]
+    fn gossip_validation() {
+        let scid = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let testnet =
+            Slice32::from_inner(Chain::Testnet3.as_genesis_hash().into_inner());
+        let mut router = GossipRouter::default();
+
+        let mut foreign = channel_announcement(scid);
+        foreign.chain_hash = testnet;
+        assert_eq!(
+            router.update_from_peer(&Messages::ChannelAnnouncement(
+                foreign.clone()
+            )),
+            Err(Error::ChainMismatch {
+                short_channel_id: scid,
+                chain_hash: testnet
+            })
+        );
+        // Router configured for testnet rejects signatures made over the
+        // mainnet announcement
+        assert_eq!(
+            GossipRouter::with(testnet).verify_channel_announcement(&foreign),
+            Err(Error::ChannelAnnouncementSignature {
+                short_channel_id: scid,
+                key: node(1)
+            })
+        );
+
+        let mut forged = channel_announcement(scid);
+        forged.bitcoin_signature_2 = sign(&forged, 4, 5);
+        assert_eq!(
+            router.update_from_peer(&Messages::ChannelAnnouncement(forged)),
+            Err(Error::ChannelAnnouncementSignature {
+                short_channel_id: scid,
+                key: node(4)
+            })
+        );
+        assert!(router.channels().is_empty());
+
+        router.update_from_peer(&announcement(scid)).unwrap();
+
+        let mut update = channel_update(scid, 0, 10, 1);
+        update.chain_hash = testnet;
+        assert_eq!(
+            router.update_from_peer(&Messages::ChannelUpdate(update)),
+            Err(Error::ChainMismatch {
+                short_channel_id: scid,
+                chain_hash: testnet
+            })
+        );
+        // Update for the first direction signed by the second node
+        let mut update = channel_update(scid, 0, 10, 1);
+        update.signature = sign(&update, 1, 2);
+        assert_eq!(
+            router.update_from_peer(&Messages::ChannelUpdate(update)),
+            Err(Error::ChannelUpdateSignature {
+                short_channel_id: scid,
+                node_id: node(1)
+            })
+        );
+        assert_eq!(router.channels()[&scid].directions, (None, None));
+
+        // Extension data from the newer nodes are covered by the signature
+        // over the received bytes
+        let mut wire = channel_update(scid, 0, 10, 1)
+            .lightning_serialize()
+            .unwrap();
+        wire.extend([0xDE, 0xAD]);
+        let digest = sha256d::Hash::hash(&wire[64..]);
+        let msg = Message::from_slice(&digest[..]).unwrap();
+        let signature = secp256k1::SECP256K1.sign_ecdsa(&msg, &seckey(1));
+        wire[..64].copy_from_slice(&signature.serialize_compact());
+        let update = ChannelUpdate::lightning_deserialize(&wire).unwrap();
+        assert_eq!(update.extra_data, ExtraData::from(vec![0xDE, 0xAD]));
+        let mut tampered = update.clone();
+        tampered.extra_data = ExtraData::from(vec![0xDE, 0xAE]);
+        assert_eq!(
+            router.update_from_peer(&Messages::ChannelUpdate(tampered)),
+            Err(Error::ChannelUpdateSignature {
+                short_channel_id: scid,
+                node_id: node(1)
+            })
+        );
+        router
+            .update_from_peer(&Messages::ChannelUpdate(update.clone()))
+            .unwrap();
+        assert!(router.channels()[&scid].directions.0.is_some());
+
+        let mut node_announcement = node_announcement(2, 10, 1);
+        node_announcement.timestamp = 11;
+        assert_eq!(
+            router.update_from_peer(&Messages::NodeAnnouncements(
+                node_announcement
+            )),
+            Err(Error::NodeAnnouncementSignature(node(2)))
+        );
+        assert!(router.nodes().is_empty());
+    }
+
+    #[derive(Default)]
+    struct MockChain(Mutex<BTreeMap<ShortChannelId, (TxOut, bool)>>);
+
+    impl MockChain {
+        fn add(&self, short_channel_id: ShortChannelId, txout: TxOut) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(short_channel_id, (txout, false));
+        }
+
+        fn spend(&self, short_channel_id: ShortChannelId) {
+            self.0.lock().unwrap().get_mut(&short_channel_id).unwrap().1 = true;
+        }
+    }
+
+    impl UtxoLookup for MockChain {
+        fn lookup_utxo(
+            &self,
+            short_channel_id: ShortChannelId,
+        ) -> Result<TxOut, UtxoLookupError> {
+            match self.0.lock().unwrap().get(&short_channel_id) {
+                None => Err(UtxoLookupError::UnknownOutput(short_channel_id)),
+                Some((_, true)) => {
+                    Err(UtxoLookupError::Spent(short_channel_id))
+                }
+                Some((txout, false)) => Ok(txout.clone()),
+            }
+        }
+    }
+
+    #[test]
+    fn utxo_lookup() {
+        let scid1 = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let scid2 = ShortChannelId::with(700_000, 2, 1).unwrap();
+        let chain = Arc::new(MockChain::default());
+        let mut router = GossipRouter::default();
+        router.set_utxo_lookup(chain.clone());
+
+        assert_eq!(
+            router.update_from_peer(&announcement(scid1)),
+            Err(Error::Utxo(UtxoLookupError::UnknownOutput(scid1)))
+        );
+
+        chain.add(scid1, TxOut {
+            value: 1_000_000,
+            script_pubkey: Script::new(),
+        });
+        assert_eq!(
+            router.update_from_peer(&announcement(scid1)),
+            Err(Error::FundingScriptMismatch(scid1))
+        );
+        assert!(router.channels().is_empty());
+
+        let script_pubkey = funding_script_pubkey(node(3), node(4));
+        chain.spend(scid1);
+        chain.add(scid1, TxOut {
+            value: 1_000_000,
+            script_pubkey: script_pubkey.to_inner(),
+        });
+        chain.add(scid2, TxOut {
+            value: 2_000_000,
+            script_pubkey: script_pubkey.to_inner(),
+        });
+        router.update_from_peer(&announcement(scid1)).unwrap();
+        router.update_from_peer(&announcement(scid2)).unwrap();
+        assert_eq!(router.channels()[&scid1].capacity_sats, Some(1_000_000));
+        assert_eq!(router.channels()[&scid2].capacity_sats, Some(2_000_000));
+        router.update_from_peer(&node_msg(1, 10, 1)).unwrap();
+
+        chain.spend(scid1);
+        router
+            .update_from_local(&UpdateMsg::PruneSpentChannels)
+            .unwrap();
+        assert_eq!(router.channels().keys().collect::<Vec<_>>(), vec![&scid2]);
+        assert!(router.nodes().contains_key(&node(1)));
+
+        chain.spend(scid2);
+        assert_eq!(router.prune_spent_channels(), vec![scid2]);
+        assert!(router.channels().is_empty());
+        assert!(router.nodes().is_empty());
+    }
+
+    #[test]
+    fn pruned_gossip_queries() {
+        let scid = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let chain = Arc::new(MockChain::default());
+        chain.add(scid, TxOut {
+            value: 1_000_000,
+            script_pubkey: funding_script_pubkey(node(3), node(4)).to_inner(),
+        });
+        let mut gossip = GossipRouter::default();
+        gossip.set_utxo_lookup(chain.clone());
+        let mut router = Router::<GossipExt>::new([
+            Box::new(gossip) as Box<dyn RouterExtension<GossipExt>>,
+            Box::new(GossipQueries::default()),
+        ]);
+        router.update_from_peer(&announcement(scid)).unwrap();
+
+        let replied_ids = |router: &mut Router<GossipExt>| {
+            let query = Messages::QueryChannelRange(QueryChannelRange {
+                chain_hash: mainnet_chain_hash(),
+                first_blocknum: 700_000,
+                number_of_blocks: 10,
+                query_option: None,
+                unknown_tlvs: none!(),
+            });
+            router.update_from_peer(&query).unwrap();
+            let mut message = query;
+            router
+                .state_change(&UpdateReq::NextMessage, &mut message)
+                .unwrap();
+            match message {
+                Messages::ReplyChannelRange(reply) => {
+                    reply.encoded_short_ids.short_ids
+                }
+                _ => panic!("reply_channel_range expected"),
+            }
+        };
+        assert_eq!(replied_ids(&mut router), vec![scid]);
+
+        // Gossip of the spent channels is not served to the peers anymore
+        chain.spend(scid);
+        router
+            .update_from_local(&UpdateMsg::PruneSpentChannels)
+            .unwrap();
+        assert!(replied_ids(&mut router).is_empty());
+    }
+
+    #[test]
+    fn direct_route_constraints() {
+        let scid = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let mut router = DirectRouter::default();
+        router
+            .update_from_local(&UpdateMsg::DirectChannelAdd(LocalChannelInfo {
+                remote_node: node(2),
+                channel_id: ChannelId::default(),
+                short_channel_id: scid,
+                chain_hash: mainnet_chain_hash(),
+                inbound_capacity_msat: 0,
+                outbound_capacity_msat: 10_000_000,
+                cltv_expiry: 144,
+                htlc_minimum_msat: 1,
+                htlc_maximum_msat: u64::MAX,
+            }))
+            .unwrap();
+        let payment = PaymentRequest {
+            amount_msat: 1_000_000,
+            payment_hash: HashLock::from(Slice32::from([0xAB; 32])),
+            node_id: node(2),
+            min_final_cltv_expiry: 18,
+            route_hints: vec![],
+            features: None,
+            payment_secret: None,
+        };
+        let mut route = |params: &RouteParams| {
+            let mut route = vec![];
+            router.build_route(&payment, params, &mut route);
+            route.len()
+        };
+
+        let mut params = RouteParams::default();
+        assert_eq!(route(&params), 1);
+        params.max_total_cltv_expiry_delta = Some(17);
+        assert_eq!(route(&params), 0);
+        params.max_total_cltv_expiry_delta = None;
+
+        params.exclude_channel(scid, node(2));
+        assert_eq!(route(&params), 1);
+        params.exclude_channel(scid, node(1));
+        assert_eq!(route(&params), 0);
+        params.excluded_channels.clear();
+
+        params.exclude_node(node(2));
+        assert_eq!(route(&params), 0);
+    }
+
+    #[test]
+    fn direct_route_realm() {
+        let scid = ShortChannelId::with(700_000, 1, 0).unwrap();
+        let mut router = DirectRouter::default();
+        router
+            .update_from_local(&UpdateMsg::DirectChannelAdd(LocalChannelInfo {
+                remote_node: node(2),
+                channel_id: ChannelId::default(),
+                short_channel_id: scid,
+                chain_hash: mainnet_chain_hash(),
+                inbound_capacity_msat: 0,
+                outbound_capacity_msat: 10_000_000,
+                cltv_expiry: 144,
+                htlc_minimum_msat: 1,
+                htlc_maximum_msat: u64::MAX,
+            }))
+            .unwrap();
+        let mut payment = PaymentRequest {
+            amount_msat: 1_000_000,
+            payment_hash: HashLock::from(Slice32::from([0xAB; 32])),
+            node_id: node(2),
+            min_final_cltv_expiry: 18,
+            route_hints: vec![],
+            features: None,
+            payment_secret: None,
+        };
+        let realm = |router: &mut DirectRouter, payment: &PaymentRequest| {
+            let mut route = vec![];
+            router.build_route(payment, &default!(), &mut route);
+            route.first().map(|hop| hop.payload.realm)
+        };
+
+        // Destinations with unknown features get legacy payloads with zero
+        // short channel id
+        let legacy = HopRealm::Legacy(ShortChannelId::default());
+        assert_eq!(realm(&mut router, &payment), Some(legacy));
+
+        // Features are taken from the invoice
+        let mut features = InitFeatures {
+            var_onion_optin: Some(false),
+            ..default!()
+        };
+        payment.features = Some(features.clone());
+        assert_eq!(
+            realm(&mut router, &payment),
+            Some(HopRealm::TlvReceiver(None))
+        );
+
+        // Payment secret is always passed to the destination
+        let payment_secret = HashPreimage::from(Slice32::from([0xCD; 32]));
+        payment.payment_secret = Some(payment_secret);
+        assert_eq!(
+            realm(&mut router, &payment),
+            Some(HopRealm::TlvReceiver(Some(PaymentData {
+                payment_secret,
+                total_msat: 1_000_000
+            })))
+        );
+
+        // Destination requiring payment secret can't be paid without it
+        features.payment_secret = Some(true);
+        payment.features = Some(features.clone());
+        payment.payment_secret = None;
+        assert_eq!(realm(&mut router, &payment), None);
+
+        // Features of the direct peers are learned from `node_announcement`
+        payment.features = None;
+        let mut announcement = node_announcement(2, 1, 0);
+        announcement.features = features;
+        announcement.signature = sign(&announcement, 1, 2);
+        let mut forged = announcement.clone();
+        forged.signature = sign(&announcement, 1, 3);
+        assert_eq!(
+            router.update_from_peer(&Messages::NodeAnnouncements(forged)),
+            Err(Error::NodeAnnouncementSignature(node(2)))
+        );
+        assert_eq!(realm(&mut router, &payment), Some(legacy));
+        router
+            .update_from_peer(&Messages::NodeAnnouncements(announcement))
+            .unwrap();
+        assert_eq!(realm(&mut router, &payment), None);
+
+        // Nodes we have no channels with are ignored
+        router.update_from_peer(&node_msg(3, 1, 0)).unwrap();
+        assert!(!router.nodes().contains_key(&node(3)));
     }
 }
@@ -0,0 +1,448 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::collections::BTreeMap;
+
+use internet2::presentation::sphinx::Hop;
+use p2p::bolt::{HopRealm, PaymentOnion, ShortChannelId};
+
+use super::{DirectionalInfo, GossipChannelInfo};
+use crate::router::DirectedChannel;
+
+/// Capacity assumed for channels with unknown capacity and HTLC maximum:
+/// the largest channel which can be opened without
+/// `option_support_large_channel`
+pub const MAX_CHANNEL_CAPACITY_MSAT: u64 = 16_777_215_000;
+
+/// Computes upper bound of the channel direction liquidity known without
+/// any payment attempts: channel capacity, HTLC maximum or the largest
+/// non-wumbo channel capacity, whichever is known first
+pub fn effective_capacity_msat(
+    info: &GossipChannelInfo,
+    direction: &DirectionalInfo,
+) -> u64 {
+    info.capacity_sats
+        .map(|capacity| capacity.saturating_mul(1000))
+        .or_else(|| direction.max_htlc_msat())
+        .unwrap_or(MAX_CHANNEL_CAPACITY_MSAT)
+}
+
+/// Parameters of the probabilistic scorer
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ScorerParams {
+    /// Penalty added to the channel cost for each order of magnitude the
+    /// payment success probability is below one (i.e. the penalty is
+    /// `-log10(probability) * liquidity_penalty_multiplier_msat`)
+    pub liquidity_penalty_multiplier_msat: u64,
+
+    /// Time, in seconds, after which the knowledge learned about the channel
+    /// liquidity is halved. Zero means that the knowledge is never
+    /// forgotten.
+    pub liquidity_half_life_secs: u64,
+}
+
+impl Default for ScorerParams {
+    fn default() -> Self {
+        ScorerParams {
+            liquidity_penalty_multiplier_msat: 30_000,
+            liquidity_half_life_secs: 6 * 60 * 60,
+        }
+    }
+}
+
+/// Liquidity bounds learned for a channel direction. Bounds are stored as
+/// offsets from zero and from the channel capacity, so they can be decayed
+/// towards the "no knowledge" state by halving the offsets.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct LiquidityBounds {
+    /// Lower bound of the available liquidity
+    pub min_liquidity_offset_msat: u64,
+
+    /// Difference between the channel capacity and the upper bound of the
+    /// available liquidity
+    pub max_liquidity_offset_msat: u64,
+
+    /// UNIX timestamp of the last update of the bounds, in seconds
+    pub last_updated: u64,
+}
+
+impl LiquidityBounds {
+    /// Returns bounds with the offsets halved for each half-life passed since
+    /// the last update
+    fn decayed(self, half_life_secs: u64, now: u64) -> LiquidityBounds {
+        let halvings = now
+            .saturating_sub(self.last_updated)
+            .checked_div(half_life_secs)
+            .unwrap_or_default();
+        let decay = |offset: u64| {
+            if halvings >= 64 {
+                0
+            } else {
+                offset >> halvings
+            }
+        };
+        LiquidityBounds {
+            min_liquidity_offset_msat: decay(self.min_liquidity_offset_msat),
+            max_liquidity_offset_msat: decay(self.max_liquidity_offset_msat),
+            last_updated: self.last_updated,
+        }
+    }
+
+    /// Returns lower and upper bounds of the channel direction liquidity
+    fn range(&self, capacity_msat: u64) -> (u64, u64) {
+        let max = capacity_msat.saturating_sub(self.max_liquidity_offset_msat);
+        (self.min_liquidity_offset_msat.min(max), max)
+    }
+
+    /// Constructs bounds from the lower and upper liquidity bounds
+    fn with_range(
+        min_msat: u64,
+        max_msat: u64,
+        capacity_msat: u64,
+        now: u64,
+    ) -> LiquidityBounds {
+        LiquidityBounds {
+            min_liquidity_offset_msat: min_msat,
+            max_liquidity_offset_msat: capacity_msat.saturating_sub(max_msat),
+            last_updated: now,
+        }
+    }
+}
+
+/// Scorer learning liquidity bounds of the channel directions from the
+/// results of payment attempts.
+///
+/// Channel liquidity is assumed to be uniformly distributed between the
+/// learned bounds, which gives probability of forwarding a given amount
+/// through the channel. The probability is converted into penalty, which is
+/// added to the channel fee during the route search, so routes through the
+/// channels known (or likely) to be depleted are avoided. Learned bounds
+/// decay with time, since channel liquidity changes as other payments are
+/// forwarded.
+#[derive(Getters, Clone, PartialEq, Eq, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+pub struct ProbabilisticScorer {
+    /// Scorer parameters
+    params: ScorerParams,
+
+    /// Learned liquidity bounds, indexed by channel direction
+    liquidity: BTreeMap<DirectedChannel, LiquidityBounds>,
+}
+
+impl ProbabilisticScorer {
+    /// Constructs scorer with no knowledge about the channel liquidity
+    pub fn with(params: ScorerParams) -> ProbabilisticScorer {
+        ProbabilisticScorer {
+            params,
+            liquidity: empty!(),
+        }
+    }
+
+    /// Returns lower and upper bounds of the channel direction liquidity
+    /// known at the moment `now`
+    pub fn liquidity_range(
+        &self,
+        channel: DirectedChannel,
+        capacity_msat: u64,
+        now: u64,
+    ) -> (u64, u64) {
+        self.bounds(channel, now).range(capacity_msat)
+    }
+
+    /// Computes penalty of forwarding `amount_msat` through the channel
+    /// direction, or returns `None` if the channel is known to have not
+    /// enough liquidity for that
+    pub fn channel_penalty_msat(
+        &self,
+        channel: DirectedChannel,
+        capacity_msat: u64,
+        amount_msat: u64,
+        now: u64,
+    ) -> Option<u64> {
+        let (min, max) = self.liquidity_range(channel, capacity_msat, now);
+        if amount_msat <= min {
+            return Some(0);
+        }
+        if amount_msat > max {
+            return None;
+        }
+        // Probability that the liquidity is not less than the amount, for
+        // the liquidity uniformly distributed among [min, max]
+        let probability =
+            (max - amount_msat + 1) as f64 / (max - min + 1) as f64;
+        Some(
+            (-probability.log10()
+                * self.params.liquidity_penalty_multiplier_msat as f64)
+                as u64,
+        )
+    }
+
+    /// Learns from a failed payment attempt. Channels preceding the failed
+    /// one have forwarded the payment and so had enough liquidity, while the
+    /// failed channel had not. If `failed_channel` is `None`, the payment was
+    /// rejected by the destination after being forwarded by all channels.
+    pub fn payment_path_failed(
+        &mut self,
+        channels: &BTreeMap<ShortChannelId, GossipChannelInfo>,
+        path: &[Hop<PaymentOnion>],
+        failed_channel: Option<ShortChannelId>,
+        now: u64,
+    ) {
+        for (channel, capacity_msat, amount_msat) in
+            path_channels(channels, path)
+        {
+            let (min, max) = self.liquidity_range(channel, capacity_msat, now);
+            if Some(channel.short_channel_id) == failed_channel {
+                let max = max.min(amount_msat.saturating_sub(1));
+                self.set_range(channel, min.min(max), max, capacity_msat, now);
+                break;
+            }
+            let min = min.max(amount_msat);
+            self.set_range(channel, min, max.max(min), capacity_msat, now);
+        }
+    }
+
+    /// Learns from a successful payment: the payment amount has moved
+    /// through each of the route channels, reducing their liquidity
+    pub fn payment_path_successful(
+        &mut self,
+        channels: &BTreeMap<ShortChannelId, GossipChannelInfo>,
+        path: &[Hop<PaymentOnion>],
+        now: u64,
+    ) {
+        for (channel, capacity_msat, amount_msat) in
+            path_channels(channels, path)
+        {
+            let (min, max) = self.liquidity_range(channel, capacity_msat, now);
+            self.set_range(
+                channel,
+                min.saturating_sub(amount_msat),
+                max.saturating_sub(amount_msat),
+                capacity_msat,
+                now,
+            );
+        }
+    }
+
+    fn bounds(&self, channel: DirectedChannel, now: u64) -> LiquidityBounds {
+        self.liquidity
+            .get(&channel)
+            .map(|bounds| {
+                bounds.decayed(self.params.liquidity_half_life_secs, now)
+            })
+            .unwrap_or_default()
+    }
+
+    fn set_range(
+        &mut self,
+        channel: DirectedChannel,
+        min_msat: u64,
+        max_msat: u64,
+        capacity_msat: u64,
+        now: u64,
+    ) {
+        let bounds =
+            LiquidityBounds::with_range(min_msat, max_msat, capacity_msat, now);
+        if bounds.min_liquidity_offset_msat == 0
+            && bounds.max_liquidity_offset_msat == 0
+        {
+            self.liquidity.remove(&channel);
+        } else {
+            self.liquidity.insert(channel, bounds);
+        }
+    }
+}
+
+/// Resolves forwarding hops of the route into the channel directions used,
+/// their capacities and the amounts forwarded through them. Hops over
+/// channels unknown to the router are skipped.
+fn path_channels(
+    channels: &BTreeMap<ShortChannelId, GossipChannelInfo>,
+    path: &[Hop<PaymentOnion>],
+) -> Vec<(DirectedChannel, u64, u64)> {
+    let forwarding = path.len().saturating_sub(1);
+    path[..forwarding]
+        .iter()
+        .filter_map(|hop| {
+            let short_channel_id = match hop.payload.realm {
+                HopRealm::Legacy(short_channel_id)
+                | HopRealm::TlvIntermediary(short_channel_id) => {
+                    short_channel_id
+                }
+                HopRealm::TlvReceiver(_) => return None,
+            };
+            let info = channels.get(&short_channel_id)?;
+            let direction = info.direction_from(hop.node_id)?;
+            let channel = DirectedChannel {
+                short_channel_id,
+                source: hop.node_id,
+            };
+            Some((
+                channel,
+                effective_capacity_msat(info, &direction),
+                hop.payload.amt_to_forward,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::Slice32;
+    use internet2::addr::NodeId;
+    use p2p::bolt::ChannelFeatures;
+    use secp256k1::{PublicKey, SecretKey};
+    use strict_encoding::{StrictDecode, StrictEncode};
+
+    use super::*;
+
+    const HALF_LIFE: u64 = 6 * 60 * 60;
+
+    fn node(byte: u8) -> NodeId {
+        let seckey = SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from(PublicKey::from_secret_key(secp256k1::SECP256K1, &seckey))
+    }
+
+    fn scid(index: u32) -> ShortChannelId {
+        ShortChannelId::with(700_000, index, 0).unwrap()
+    }
+
+    fn graph() -> BTreeMap<ShortChannelId, GossipChannelInfo> {
+        let direction = DirectionalInfo {
+            timestamp: 1,
+            message_flags: 0,
+            channel_flags: 0,
+            cltv_expiry_delta: 40,
+            htlc_minimum_msat: 1000,
+            fee_base_msat: 0,
+            fee_proportional_millionths: 0,
+            htlc_maximum_msat: 0,
+        };
+        // Node 1 forwards to node 2, which forwards to destination 3
+        [(1, 1, 2), (2, 2, 3)]
+            .into_iter()
+            .map(|(index, from, to)| {
+                (scid(index), GossipChannelInfo {
+                    nodes: (node(from), node(to)),
+                    chain_hash: Slice32::default(),
+                    short_channel_id: scid(index),
+                    directions: (Some(direction), None),
+                    capacity_sats: Some(1000),
+                    features: ChannelFeatures::default(),
+                })
+            })
+            .collect()
+    }
+
+    fn path(amount_msat: u64) -> Vec<Hop<PaymentOnion>> {
+        [(1, 1), (2, 2), (3, 2)]
+            .into_iter()
+            .map(|(node_byte, index)| {
+                Hop::with(node(node_byte), PaymentOnion {
+                    realm: HopRealm::Legacy(scid(index)),
+                    amt_to_forward: amount_msat,
+                    outgoing_cltv_value: 18,
+                })
+            })
+            .collect()
+    }
+
+    fn channel(index: u32, source: u8) -> DirectedChannel {
+        DirectedChannel {
+            short_channel_id: scid(index),
+            source: node(source),
+        }
+    }
+
+    #[test]
+    fn liquidity_learning() {
+        let channels = graph();
+        let first = channel(1, 1);
+        let second = channel(2, 2);
+        let mut scorer = ProbabilisticScorer::default();
+
+        // Without any knowledge the liquidity is anywhere within capacity,
+        // so larger amounts are penalized more
+        assert_eq!(scorer.liquidity_range(first, 1_000_000, 0), (0, 1_000_000));
+        assert_eq!(
+            scorer.channel_penalty_msat(first, 1_000_000, 1000, 0),
+            Some(13)
+        );
+        assert_eq!(
+            scorer.channel_penalty_msat(first, 1_000_000, 500_000, 0),
+            Some(9030)
+        );
+
+        // Channel preceding the failed one had enough liquidity, while the
+        // failed one had not
+        scorer.payment_path_failed(&channels, &path(400_000), Some(scid(2)), 0);
+        assert_eq!(
+            scorer.liquidity_range(first, 1_000_000, 0),
+            (400_000, 1_000_000)
+        );
+        assert_eq!(scorer.liquidity_range(second, 1_000_000, 0), (0, 399_999));
+        assert_eq!(
+            scorer.channel_penalty_msat(first, 1_000_000, 400_000, 0),
+            Some(0)
+        );
+        assert_eq!(
+            scorer.channel_penalty_msat(second, 1_000_000, 400_000, 0),
+            None
+        );
+
+        // Knowledge is halved with each half-life and forgotten eventually
+        assert_eq!(
+            scorer.liquidity_range(second, 1_000_000, HALF_LIFE - 1),
+            (0, 399_999)
+        );
+        assert_eq!(
+            scorer.liquidity_range(second, 1_000_000, HALF_LIFE),
+            (0, 700_000)
+        );
+        assert_eq!(
+            scorer.liquidity_range(first, 1_000_000, HALF_LIFE * 2),
+            (100_000, 1_000_000)
+        );
+        assert_eq!(
+            scorer.liquidity_range(second, 1_000_000, HALF_LIFE * 64),
+            (0, 1_000_000)
+        );
+
+        // Successful payment moves liquidity out of the channels
+        scorer.payment_path_successful(&channels, &path(100_000), HALF_LIFE);
+        assert_eq!(
+            scorer.liquidity_range(first, 1_000_000, HALF_LIFE),
+            (100_000, 900_000)
+        );
+        assert_eq!(
+            scorer.liquidity_range(second, 1_000_000, HALF_LIFE),
+            (0, 600_000)
+        );
+
+        // Payment rejected by the destination was forwarded by all channels
+        scorer.payment_path_failed(&channels, &path(300_000), None, HALF_LIFE);
+        assert_eq!(
+            scorer.liquidity_range(second, 1_000_000, HALF_LIFE),
+            (300_000, 600_000)
+        );
+
+        // Learned liquidity is persisted together with the scorer
+        let data = scorer.strict_serialize().unwrap();
+        assert_eq!(
+            ProbabilisticScorer::strict_deserialize(data).unwrap(),
+            scorer
+        );
+    }
+}
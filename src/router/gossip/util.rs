@@ -13,7 +13,11 @@
 
 use amplify::Slice32;
 use internet2::addr::NodeId;
-use p2p::bolt::{ChannelFeatures, ChannelId, ShortChannelId};
+use p2p::bolt::{
+    AddressList, Alias, ChannelAnnouncement, ChannelFeatures, ChannelId,
+    ChannelUpdate, InitFeatures, NodeAnnouncements, NodeColor, RouteHintHop,
+    ShortChannelId,
+};
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
 #[derive(StrictEncode, StrictDecode)]
@@ -45,6 +49,41 @@ pub struct DirectionalInfo {
     pub htlc_maximum_msat: u64,
 }
 
+impl From<&ChannelUpdate> for DirectionalInfo {
+    fn from(update: &ChannelUpdate) -> Self {
+        DirectionalInfo {
+            timestamp: update.timestamp,
+            message_flags: update.message_flags,
+            channel_flags: update.channel_flags,
+            cltv_expiry_delta: update.cltv_expiry_delta,
+            htlc_minimum_msat: update.htlc_minimum_msat,
+            fee_base_msat: update.fee_base_msat,
+            fee_proportional_millionths: update.fee_proportional_millionths,
+            htlc_maximum_msat: update.htlc_maximum_msat,
+        }
+    }
+}
+
+impl DirectionalInfo {
+    /// Detects whether the channel direction was disabled by its originating
+    /// node (`disable` bit of `channel_flags`)
+    #[inline]
+    pub fn is_disabled(&self) -> bool {
+        self.channel_flags & 0b10 != 0
+    }
+
+    /// Returns maximum HTLC amount accepted by the channel direction, if it
+    /// was announced (`option_channel_htlc_max` bit of `message_flags`)
+    #[inline]
+    pub fn max_htlc_msat(&self) -> Option<u64> {
+        if self.message_flags & 0b01 != 0 {
+            Some(self.htlc_maximum_msat)
+        } else {
+            None
+        }
+    }
+}
+
 /// Information about channel used for route construction and re-broadcasting
 /// gossip messages.
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Display)]
@@ -68,13 +107,130 @@ pub struct GossipChannelInfo {
     pub directions: (Option<DirectionalInfo>, Option<DirectionalInfo>),
 
     /// The channel capacity, known only for local channels - or if it can be
-    /// deduced from on-chain data, if they are available
+    /// deduced from on-chain data, if they are available via
+    /// [`super::UtxoLookup`]
     pub capacity_sats: Option<u64>,
 
     /// Channel features
     pub features: ChannelFeatures,
 }
 
+impl From<&ChannelAnnouncement> for GossipChannelInfo {
+    fn from(announcement: &ChannelAnnouncement) -> Self {
+        GossipChannelInfo {
+            nodes: (announcement.node_id_1, announcement.node_id_2),
+            chain_hash: announcement.chain_hash,
+            short_channel_id: announcement.short_channel_id,
+            directions: (None, None),
+            capacity_sats: None,
+            features: announcement.features,
+        }
+    }
+}
+
+impl GossipChannelInfo {
+    /// Constructs information about a private channel from the BOLT-11 route
+    /// hint hop. The channel has the only known direction, from the hop node
+    /// towards the `next_node`.
+    pub fn with_route_hint(
+        hop: &RouteHintHop,
+        next_node: NodeId,
+    ) -> GossipChannelInfo {
+        GossipChannelInfo {
+            nodes: (hop.node_id, next_node),
+            chain_hash: Slice32::default(),
+            short_channel_id: hop.short_channel_id,
+            directions: (
+                Some(DirectionalInfo {
+                    timestamp: 0,
+                    message_flags: 0,
+                    channel_flags: 0,
+                    cltv_expiry_delta: hop.cltv_expiry_delta,
+                    htlc_minimum_msat: 0,
+                    fee_base_msat: hop.fee_base_msat,
+                    fee_proportional_millionths: hop
+                        .fee_proportional_millionths,
+                    htlc_maximum_msat: 0,
+                }),
+                None,
+            ),
+            capacity_sats: None,
+            features: ChannelFeatures::default(),
+        }
+    }
+
+    /// Updates information about one of the channel directions, selected by
+    /// the `direction` bit of the `channel_update` flags. The update is
+    /// applied only if it is newer than the already known one.
+    ///
+    /// Returns whether the channel information was changed.
+    pub fn update_direction(&mut self, update: &ChannelUpdate) -> bool {
+        let direction = if update.channel_flags & 0b01 == 0 {
+            &mut self.directions.0
+        } else {
+            &mut self.directions.1
+        };
+        match direction {
+            Some(info) if info.timestamp >= update.timestamp => false,
+            _ => {
+                *direction = Some(DirectionalInfo::from(update));
+                true
+            }
+        }
+    }
+
+    /// Returns information about the channel direction originating from the
+    /// given node, if the node is one of the channel parties and the
+    /// direction was already announced.
+    pub fn direction_from(&self, node_id: NodeId) -> Option<DirectionalInfo> {
+        if self.nodes.0 == node_id {
+            self.directions.0
+        } else if self.nodes.1 == node_id {
+            self.directions.1
+        } else {
+            None
+        }
+    }
+}
+
+/// Information about a network node, announced with `node_announcement`
+/// message
+#[derive(Clone, Eq, PartialEq, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[display("{node_id}")]
+pub struct GossipNodeInfo {
+    /// Node identity
+    pub node_id: NodeId,
+
+    /// Time stamp of the latest node announcement
+    pub timestamp: u32,
+
+    /// Node features
+    pub features: InitFeatures,
+
+    /// RGB colour code
+    pub rgb_color: NodeColor,
+
+    /// Node alias
+    pub alias: Alias,
+
+    /// Node addresses
+    pub addresses: AddressList,
+}
+
+impl From<&NodeAnnouncements> for GossipNodeInfo {
+    fn from(announcement: &NodeAnnouncements) -> Self {
+        GossipNodeInfo {
+            node_id: announcement.node_id,
+            timestamp: announcement.timestamp,
+            features: announcement.features.clone(),
+            rgb_color: announcement.rgb_color.clone(),
+            alias: announcement.alias,
+            addresses: announcement.addresses.clone(),
+        }
+    }
+}
+
 /// Information about channel used for route construction and re-broadcasting
 /// gossip messages.
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Display)]
@@ -87,7 +243,7 @@ pub struct LocalChannelInfo {
     /// Full channel id
     pub channel_id: ChannelId,
 
-    /// Short Channel Id
+    /// Short Channel Id, or its alias if the channel funding is not mined yet
     pub short_channel_id: ShortChannelId,
 
     /// Chainhash
@@ -0,0 +1,162 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use bitcoin::blockdata::opcodes::all::OP_CHECKMULTISIG;
+use bitcoin::blockdata::script;
+use bitcoin::TxOut;
+use bitcoin_scripts::PubkeyScript;
+use internet2::addr::NodeId;
+use p2p::bolt::ShortChannelId;
+use wallet::lex_order::LexOrder;
+
+/// Errors resolving short channel id into a transaction output
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum UtxoLookupError {
+    /// transaction output referenced by the short channel id {0} does not
+    /// exist
+    UnknownOutput(ShortChannelId),
+
+    /// transaction output referenced by the short channel id {0} is already
+    /// spent
+    Spent(ShortChannelId),
+}
+
+/// Source of on-chain data used to check that announced channels are backed
+/// by unspent funding outputs
+pub trait UtxoLookup {
+    /// Resolves short channel id into the unspent transaction output it
+    /// refers to
+    fn lookup_utxo(
+        &self,
+        short_channel_id: ShortChannelId,
+    ) -> Result<TxOut, UtxoLookupError>;
+}
+
+/// UTXO lookup installed into the gossip router.
+///
+/// The lookup is a runtime configuration and is never persisted as a part of
+/// the router state.
+#[derive(Clone)]
+pub struct UtxoOracle(Arc<dyn UtxoLookup>);
+
+impl PartialEq for UtxoOracle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for UtxoOracle {}
+
+impl Debug for UtxoOracle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("UtxoOracle(..)")
+    }
+}
+
+impl UtxoOracle {
+    /// Constructs oracle from a (possibly shared) UTXO lookup
+    #[inline]
+    pub fn with(lookup: Arc<dyn UtxoLookup>) -> UtxoOracle {
+        UtxoOracle(lookup)
+    }
+
+    /// Resolves short channel id into the unspent transaction output
+    #[inline]
+    pub fn lookup_utxo(
+        &self,
+        short_channel_id: ShortChannelId,
+    ) -> Result<TxOut, UtxoLookupError> {
+        self.0.lookup_utxo(short_channel_id)
+    }
+}
+
+/// Constructs P2WSH script pubkey of the BOLT-3 2-of-2 funding output for
+/// the bitcoin keys from `channel_announcement`
+pub fn funding_script_pubkey(
+    bitcoin_key_1: NodeId,
+    bitcoin_key_2: NodeId,
+) -> PubkeyScript {
+    let pk = vec![
+        bitcoin::PublicKey::new(bitcoin_key_1.public_key()),
+        bitcoin::PublicKey::new(bitcoin_key_2.public_key()),
+    ]
+    .lex_ordered();
+
+    let witness_script = script::Builder::new()
+        .push_int(2)
+        .push_key(&pk[0])
+        .push_key(&pk[1])
+        .push_int(2)
+        .push_opcode(OP_CHECKMULTISIG)
+        .into_script();
+    witness_script.to_v0_p2wsh().into()
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::Script;
+    use secp256k1::PublicKey;
+
+    use super::*;
+
+    #[test]
+    fn bolt3_funding_script() {
+        // Funding keys and witness script from BOLT-3 test vectors
+        let local_funding_pubkey = NodeId::from(
+            PublicKey::from_str(
+                "023da092f6980e58d2c037173180e9a465476026ee50f96695963e8efe436f54eb",
+            )
+            .unwrap(),
+        );
+        let remote_funding_pubkey = NodeId::from(
+            PublicKey::from_str(
+                "030e9f7b623d2ccc7c9bd44d66d5ce21ce504c0acf6385a132cec6d3c39fa711c1",
+            )
+            .unwrap(),
+        );
+        let witness_script = Script::from_hex(
+            "5221023da092f6980e58d2c037173180e9a465476026ee50f96695963e8efe436f54eb21030e9f7b623d2ccc7c9bd44d66d5ce21ce504c0acf6385a132cec6d3c39fa711c152ae",
+        )
+        .unwrap();
+        let expected = PubkeyScript::from(witness_script.to_v0_p2wsh());
+        assert_eq!(
+            expected,
+            PubkeyScript::from(
+                Script::from_hex(
+                    "0020c015c4a6be010e21657068fc2e6a9d02b27ebe4d490a25846f7237f104d1a3cd"
+                )
+                .unwrap()
+            )
+        );
+
+        assert_eq!(
+            funding_script_pubkey(local_funding_pubkey, remote_funding_pubkey),
+            expected
+        );
+        // Keys are lexicographically ordered independently of the order in
+        // the announcement
+        assert_eq!(
+            funding_script_pubkey(remote_funding_pubkey, local_funding_pubkey),
+            expected
+        );
+    }
+}
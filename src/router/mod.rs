@@ -12,7 +12,9 @@
 // If not, see <https://opensource.org/licenses/MIT>.
 
 pub mod gossip;
+mod params;
 #[allow(clippy::module_inception)]
 mod router;
 
+pub use params::{DirectedChannel, FeeLimit, RouteParams, MAX_ROUTE_LENGTH};
 pub use router::{ExtensionQueue, Nomenclature, Router};
@@ -0,0 +1,254 @@
+// LNP/BP Core Library implementing LNPBP specifications & standards
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@pandoracore.com>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the MIT License
+// along with this software.
+// If not, see <https://opensource.org/licenses/MIT>.
+
+use std::collections::BTreeSet;
+
+use internet2::addr::NodeId;
+use p2p::bolt::ShortChannelId;
+
+/// Maximum number of hops which fit into BOLT-4 onion packet
+pub const MAX_ROUTE_LENGTH: u8 = 20;
+
+/// Limit for the total fee paid to the forwarding nodes of a route
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+pub enum FeeLimit {
+    /// Fixed amount in millisatoshis
+    #[display("{0} msat")]
+    AbsoluteMsat(u64),
+
+    /// Proportion of the payment amount, in millionths
+    #[display("{0} ppm")]
+    ProportionalMillionths(u32),
+}
+
+impl FeeLimit {
+    /// Computes maximal fee which can be paid for a payment of the given
+    /// amount
+    pub fn limit_msat(self, amount_msat: u64) -> u64 {
+        match self {
+            FeeLimit::AbsoluteMsat(limit) => limit,
+            FeeLimit::ProportionalMillionths(ppm) => {
+                amount_msat.saturating_mul(ppm as u64) / 1_000_000
+            }
+        }
+    }
+}
+
+/// Channel direction, identified by the node which forwards payments through
+/// it
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[display("{short_channel_id}->{source}")]
+pub struct DirectedChannel {
+    /// Short channel id
+    pub short_channel_id: ShortChannelId,
+
+    /// Node forwarding payments through the channel
+    pub source: NodeId,
+}
+
+/// Constraints which have to be satisfied by the route constructed for a
+/// payment
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RouteParams {
+    /// Current height of the blockchain. CLTV expiries of the route hops are
+    /// absolute block heights computed on top of it.
+    pub block_height: u32,
+
+    /// Maximum total fee paid to the forwarding nodes; no limit if `None`
+    pub max_fee: Option<FeeLimit>,
+
+    /// Maximum total CLTV expiry delta of the route, including
+    /// `min_final_cltv_expiry` required by the destination; no limit if
+    /// `None`
+    pub max_total_cltv_expiry_delta: Option<u32>,
+
+    /// Maximum number of hops in the route, including the destination
+    pub max_path_length: u8,
+
+    /// Nodes which must not be used for forwarding the payment
+    pub excluded_nodes: BTreeSet<NodeId>,
+
+    /// Channel directions which must not be used for forwarding the payment
+    pub excluded_channels: BTreeSet<DirectedChannel>,
+}
+
+impl Default for RouteParams {
+    fn default() -> Self {
+        RouteParams {
+            block_height: 0,
+            max_fee: None,
+            max_total_cltv_expiry_delta: None,
+            max_path_length: MAX_ROUTE_LENGTH,
+            excluded_nodes: empty!(),
+            excluded_channels: empty!(),
+        }
+    }
+}
+
+impl RouteParams {
+    /// Excludes node from the future routes; used to ban failing nodes
+    /// between the payment attempts
+    #[inline]
+    pub fn exclude_node(&mut self, node_id: NodeId) {
+        self.excluded_nodes.insert(node_id);
+    }
+
+    /// Excludes channel direction from the future routes; used to ban
+    /// failing channels between the payment attempts
+    #[inline]
+    pub fn exclude_channel(
+        &mut self,
+        short_channel_id: ShortChannelId,
+        source: NodeId,
+    ) {
+        self.excluded_channels.insert(DirectedChannel {
+            short_channel_id,
+            source,
+        });
+    }
+
+    /// Detects whether the node may be used in the route
+    #[inline]
+    pub fn is_node_allowed(&self, node_id: NodeId) -> bool {
+        !self.excluded_nodes.contains(&node_id)
+    }
+
+    /// Detects whether the channel direction may be used in the route
+    #[inline]
+    pub fn is_channel_allowed(
+        &self,
+        short_channel_id: ShortChannelId,
+        source: NodeId,
+    ) -> bool {
+        !self.excluded_channels.contains(&DirectedChannel {
+            short_channel_id,
+            source,
+        })
+    }
+
+    /// Detects whether any of the channel directions originating from
+    /// nodes other than `remote_node` was excluded. Used for direct channels,
+    /// where our own node forwards the payment to `remote_node`.
+    pub fn is_outbound_channel_allowed(
+        &self,
+        short_channel_id: ShortChannelId,
+        remote_node: NodeId,
+    ) -> bool {
+        !self.excluded_channels.iter().any(|channel| {
+            channel.short_channel_id == short_channel_id
+                && channel.source != remote_node
+        })
+    }
+
+    /// Checks whether the route fee, total CLTV expiry delta and length are
+    /// within the limits
+    pub fn check_limits(
+        &self,
+        amount_msat: u64,
+        fee_msat: u64,
+        total_cltv_expiry_delta: u32,
+        path_length: usize,
+    ) -> bool {
+        if matches!(self.max_fee, Some(limit) if fee_msat > limit.limit_msat(amount_msat))
+        {
+            return false;
+        }
+        if matches!(self.max_total_cltv_expiry_delta, Some(max) if total_cltv_expiry_delta > max)
+        {
+            return false;
+        }
+        path_length <= self.max_path_length as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use secp256k1::{PublicKey, SecretKey};
+
+    use super::*;
+
+    fn node(byte: u8) -> NodeId {
+        let seckey = SecretKey::from_slice(&[byte; 32]).unwrap();
+        NodeId::from(PublicKey::from_secret_key(secp256k1::SECP256K1, &seckey))
+    }
+
+    fn scid(index: u32) -> ShortChannelId {
+        ShortChannelId::with(700_000, index, 0).unwrap()
+    }
+
+    #[test]
+    fn fee_limit() {
+        assert_eq!(FeeLimit::AbsoluteMsat(1000).limit_msat(0), 1000);
+        assert_eq!(FeeLimit::AbsoluteMsat(1000).limit_msat(u64::MAX), 1000);
+
+        let limit = FeeLimit::ProportionalMillionths(10_000);
+        assert_eq!(limit.limit_msat(0), 0);
+        assert_eq!(limit.limit_msat(1_000_000), 10_000);
+        // Rounded down
+        assert_eq!(limit.limit_msat(199), 1);
+        // Saturated instead of overflowing
+        assert_eq!(limit.limit_msat(u64::MAX), u64::MAX / 1_000_000);
+    }
+
+    #[test]
+    fn limits() {
+        let params = RouteParams::default();
+        assert!(params.check_limits(1_000_000, u64::MAX, u32::MAX, 20));
+        assert!(!params.check_limits(1_000_000, 0, 0, 21));
+
+        let params = RouteParams {
+            max_fee: Some(FeeLimit::ProportionalMillionths(1000)),
+            max_total_cltv_expiry_delta: Some(1008),
+            max_path_length: 3,
+            ..default!()
+        };
+        assert!(params.check_limits(1_000_000, 1000, 1008, 3));
+        assert!(!params.check_limits(1_000_000, 1001, 1008, 3));
+        assert!(!params.check_limits(1_000_000, 1000, 1009, 3));
+        assert!(!params.check_limits(1_000_000, 1000, 1008, 4));
+
+        let params = RouteParams {
+            max_fee: Some(FeeLimit::AbsoluteMsat(500)),
+            ..default!()
+        };
+        assert!(params.check_limits(1, 500, 0, 1));
+        assert!(!params.check_limits(u64::MAX, 501, 0, 1));
+    }
+
+    #[test]
+    fn exclusions() {
+        let mut params = RouteParams::default();
+        assert!(params.is_node_allowed(node(1)));
+        assert!(params.is_channel_allowed(scid(1), node(1)));
+        assert!(params.is_outbound_channel_allowed(scid(1), node(2)));
+
+        params.exclude_node(node(1));
+        assert!(!params.is_node_allowed(node(1)));
+        assert!(params.is_node_allowed(node(2)));
+
+        params.exclude_channel(scid(1), node(1));
+        assert!(!params.is_channel_allowed(scid(1), node(1)));
+        assert!(params.is_channel_allowed(scid(1), node(2)));
+        assert!(params.is_channel_allowed(scid(2), node(1)));
+
+        // Payments to node 2 over a direct channel are forwarded by the local
+        // node, i.e. by a node other than node 2, and direction from node 1
+        // is excluded
+        assert!(!params.is_outbound_channel_allowed(scid(1), node(2)));
+        // Exclusion of the direction from the remote node does not affect
+        // payments sent to it
+        assert!(params.is_outbound_channel_allowed(scid(1), node(1)));
+        assert!(params.is_outbound_channel_allowed(scid(2), node(2)));
+    }
+}
@@ -21,6 +21,7 @@ use internet2::presentation::sphinx::{Hop, SphinxPayload};
 use p2p::bolt::PaymentRequest;
 use strict_encoding::{StrictDecode, StrictEncode};
 
+use super::RouteParams;
 use crate::{extension, Extension, RouterExtension};
 
 pub type ExtensionQueue<N> = BTreeMap<N, Box<dyn RouterExtension<N>>>;
@@ -44,6 +45,13 @@ where
         router: &mut Router<Self>,
         message: &Self::PeerMessage,
     ) -> Result<(), <Self as extension::Nomenclature>::Error>;
+
+    /// Updates router extension structure from local message. Processed after
+    /// each of the registered extensions gets [`Extension::update_from_local`]
+    fn update_from_local(
+        router: &mut Router<Self>,
+        message: &Self::UpdateMessage,
+    ) -> Result<(), <Self as extension::Nomenclature>::Error>;
 }
 
 /// Generic router consisting of a queue of routing extensions, implementing
@@ -94,14 +102,33 @@ where
         self.extensions.insert(extension.identity(), extension);
     }
 
+    /// Computes route for the payment satisfying route parameters. Returns
+    /// empty route if no such route was found.
     pub fn compute_route(
         &mut self,
-        payment: PaymentRequest,
+        payment: &PaymentRequest,
+        params: &RouteParams,
     ) -> Vec<Hop<N::HopPayload>> {
         let mut route = vec![];
-        self.build_route(payment, &mut route);
+        self.build_route(payment, params, &mut route);
         route
     }
+
+    /// Computes routes for the parts of multi-part payment satisfying route
+    /// parameters. Returns empty list if the whole payment amount can't be
+    /// routed.
+    ///
+    /// Payment destination must support `basic_mpp` feature and the payment
+    /// request must provide payment secret; otherwise no routes are found.
+    pub fn compute_multipath_route(
+        &mut self,
+        payment: &PaymentRequest,
+        params: &RouteParams,
+    ) -> Vec<Vec<Hop<N::HopPayload>>> {
+        let mut routes = vec![];
+        self.build_multipath_route(payment, params, &mut routes);
+        routes
+    }
 }
 
 impl<N> Default for Router<N>
@@ -168,7 +195,7 @@ where
         self.extensions
             .iter_mut()
             .try_for_each(|(_, e)| e.update_from_local(message))?;
-        Ok(())
+        N::update_from_local(self, message)
     }
 
     fn update_from_peer(
@@ -208,11 +235,23 @@ where
 
     fn build_route(
         &mut self,
-        payment: PaymentRequest,
+        payment: &PaymentRequest,
+        params: &RouteParams,
         route: &mut Vec<Hop<N::HopPayload>>,
     ) {
         for extension in self.extensions.values_mut() {
-            extension.build_route(payment, route);
+            extension.build_route(payment, params, route);
+        }
+    }
+
+    fn build_multipath_route(
+        &mut self,
+        payment: &PaymentRequest,
+        params: &RouteParams,
+        routes: &mut Vec<Vec<Hop<N::HopPayload>>>,
+    ) {
+        for extension in self.extensions.values_mut() {
+            extension.build_multipath_route(payment, params, routes);
         }
     }
 }